@@ -0,0 +1,196 @@
+#ifndef MOSAIC_H
+#define MOSAIC_H
+
+/* This file is generated by cbindgen from src/lib.rs. Do not edit it manually. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// The status of a call.
+typedef enum MosaicStatus {
+  // The call succeeded.
+  MOSAIC_STATUS_OK = 0,
+  // A pointer argument is null.
+  MOSAIC_STATUS_NULL_POINTER = 1,
+  // A string argument is not valid UTF-8.
+  MOSAIC_STATUS_INVALID_STRING = 2,
+  // The crypto library could not be initialized.
+  MOSAIC_STATUS_CRYPTO_INIT = 3,
+  // The client could not be initialized.
+  MOSAIC_STATUS_CLIENT_INIT = 4,
+  // The client could not be restored from its state.
+  MOSAIC_STATUS_CLIENT_RESTORE = 5,
+  // The local model does not have the length expected by the coordinator.
+  MOSAIC_STATUS_MODEL_LENGTH = 6,
+  // The local model contains weights which are not finite numbers.
+  MOSAIC_STATUS_INVALID_MODEL = 7,
+  // No global model is available yet.
+  MOSAIC_STATUS_NO_GLOBAL_MODEL = 8,
+  // The global model could not be fetched from the coordinator.
+  MOSAIC_STATUS_GLOBAL_MODEL_FETCH = 9,
+  // The global model is not representable as floats.
+  MOSAIC_STATUS_GLOBAL_MODEL_DATA_TYPE = 10,
+  // The buffer is too small for the global model.
+  MOSAIC_STATUS_BUFFER_TOO_SMALL = 11,
+  // The call panicked. The client must not be used anymore, except for freeing it.
+  MOSAIC_STATUS_PANIC = 12,
+} MosaicStatus;
+
+// The task of a client in the current round.
+typedef enum MosaicTask {
+  // The client does not take part in the current round.
+  MOSAIC_TASK_NONE = 0,
+  // The client takes part in the sum task.
+  MOSAIC_TASK_SUM = 1,
+  // The client takes part in the update task.
+  MOSAIC_TASK_UPDATE = 2,
+} MosaicTask;
+
+// A client of a mosaic coordinator.
+typedef struct MosaicClient MosaicClient;
+
+// A buffer of bytes owned by the library, which must be freed with [`mosaic_buffer_free()`].
+typedef struct MosaicBuffer {
+  // The bytes of the buffer.
+  uint8_t *data;
+  // The number of bytes of the buffer.
+  size_t len;
+} MosaicBuffer;
+
+// Creates a new client of the coordinator at `url`, which weighs its local models with
+// `scalar` in the aggregation. The client generates its own signing keys.
+//
+// On success, `*client` is set to the new client, which must be destroyed with either
+// [`mosaic_client_free()`] or [`mosaic_client_save()`].
+//
+// # Safety
+//
+// `url` must be a nul terminated string and `client` must be valid for writes.
+enum MosaicStatus mosaic_client_new(const char *url, double scalar, struct MosaicClient **client);
+
+// Restores a client of the coordinator at `url` from the `len` bytes of its serialized
+// `state`, as returned by [`mosaic_client_save()`].
+//
+// On success, `*client` is set to the restored client, which must be destroyed with either
+// [`mosaic_client_free()`] or [`mosaic_client_save()`].
+//
+// # Safety
+//
+// `state` must be valid for `len` bytes, `url` must be a nul terminated string and `client`
+// must be valid for writes.
+enum MosaicStatus mosaic_client_restore(const uint8_t *state,
+                                        size_t len,
+                                        const char *url,
+                                        struct MosaicClient **client);
+
+// Serializes the state of a client and destroys the client.
+//
+// On success, `*state` is set to the serialized state, which must be freed with
+// [`mosaic_buffer_free()`]. The client is destroyed in any case unless it is null.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `state` must be valid for
+// writes.
+enum MosaicStatus mosaic_client_save(struct MosaicClient *client, struct MosaicBuffer *state);
+
+// Destroys a client. Destroying a null client does nothing.
+//
+// # Safety
+//
+// `client` must be null or a client which has not been destroyed yet.
+void mosaic_client_free(struct MosaicClient *client);
+
+// Frees a buffer returned by the library. Freeing an empty buffer does nothing.
+//
+// # Safety
+//
+// `buffer` must be a buffer returned by the library which has not been freed yet.
+void mosaic_buffer_free(struct MosaicBuffer buffer);
+
+// Drives the internal state machine of a client.
+//
+// After calling this function, the caller should check whether the state of the client
+// changed with [`mosaic_client_made_progress()`]. If so, the caller should check the task of
+// the client with [`mosaic_client_task()`] and whether it should set its model with
+// [`mosaic_client_should_set_model()`].
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet.
+enum MosaicStatus mosaic_client_step(struct MosaicClient *client);
+
+// Checks whether the internal state machine of a client made progress during the last step.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `made_progress` must be
+// valid for writes.
+enum MosaicStatus mosaic_client_made_progress(struct MosaicClient *client, bool *made_progress);
+
+// Gets the task of a client in the current round.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `task` must be valid for
+// writes.
+enum MosaicStatus mosaic_client_task(struct MosaicClient *client, enum MosaicTask *task);
+
+// Checks whether a client waits for its local model. If so, the caller should set the model
+// with [`mosaic_client_set_model()`] at some point.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `should_set_model` must be
+// valid for writes.
+enum MosaicStatus mosaic_client_should_set_model(struct MosaicClient *client,
+                                                 bool *should_set_model);
+
+// Checks whether a new global model is available, which can be fetched with
+// [`mosaic_client_global_model()`].
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `new_global_model` must be
+// valid for writes.
+enum MosaicStatus mosaic_client_new_global_model(struct MosaicClient *client,
+                                                 bool *new_global_model);
+
+// Gets the number of weights of the models of the current round, or `0` if it is not known
+// yet.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `len` must be valid for
+// writes.
+enum MosaicStatus mosaic_client_model_length(struct MosaicClient *client, size_t *len);
+
+// Sets the local model of a client from the `len` weights of `weights`, which are copied.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet and `weights` must be valid for
+// `len` floats.
+enum MosaicStatus mosaic_client_set_model(struct MosaicClient *client,
+                                          const float *weights,
+                                          size_t len);
+
+// Fetches the latest global model into the caller-provided buffer `weights` of `capacity`
+// floats.
+//
+// On success, `*len` is set to the number of weights of the global model. If the buffer is
+// too small, [`MosaicStatus::BufferTooSmall`] is returned and `*len` is set to the required
+// capacity instead. If no global model is available yet, [`MosaicStatus::NoGlobalModel`] is
+// returned.
+//
+// # Safety
+//
+// `client` must be a client which has not been destroyed yet, `weights` must be valid for
+// writes of `capacity` floats and `len` must be valid for writes.
+enum MosaicStatus mosaic_client_global_model(struct MosaicClient *client,
+                                             float *weights,
+                                             size_t capacity,
+                                             size_t *len);
+
+#endif /* MOSAIC_H */
@@ -0,0 +1,508 @@
+//! C bindings of the mosaic client SDK for native applications, e.g. on Android and iOS.
+//!
+//! A client is an opaque [`MosaicClient`] handle, which is created with [`mosaic_client_new()`]
+//! or [`mosaic_client_restore()`] and destroyed with either [`mosaic_client_free()`] or
+//! [`mosaic_client_save()`]. Every function returns a [`MosaicStatus`] and hands its results to
+//! the caller via out pointers, which are only written on success. The only exception is
+//! [`mosaic_client_global_model()`], which also writes the required buffer capacity to its `len`
+//! out pointer when it returns [`MosaicStatus::BufferTooSmall`].
+//!
+//! Like the blocking [`mosaic_client_sdk::Client`] it wraps, a client executes the protocol on
+//! its own runtime and it is the caller's responsibility to drive it by calling
+//! [`mosaic_client_step()`]. A handle may be moved between threads but must not be used from
+//! several threads at once.
+//!
+//! The C header `include/mosaic.h` is generated from this crate by its build script.
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use mosaic_client_sdk::settings::MaxMessageSize;
+use mosaic_core::{
+    crypto::SigningKeyPair,
+    model::{FromPrimitives, IntoPrimitives, Model},
+};
+
+/// The status of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MosaicStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer argument is null.
+    NullPointer = 1,
+    /// A string argument is not valid UTF-8.
+    InvalidString = 2,
+    /// The crypto library could not be initialized.
+    CryptoInit = 3,
+    /// The client could not be initialized.
+    ClientInit = 4,
+    /// The client could not be restored from its state.
+    ClientRestore = 5,
+    /// The local model does not have the length expected by the coordinator.
+    ModelLength = 6,
+    /// The local model contains weights which are not finite numbers.
+    InvalidModel = 7,
+    /// No global model is available yet.
+    NoGlobalModel = 8,
+    /// The global model could not be fetched from the coordinator.
+    GlobalModelFetch = 9,
+    /// The global model is not representable as floats.
+    GlobalModelDataType = 10,
+    /// The buffer is too small for the global model.
+    BufferTooSmall = 11,
+    /// The call panicked. The client must not be used anymore, except for freeing it.
+    Panic = 12,
+}
+
+/// The task of a client in the current round.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MosaicTask {
+    /// The client does not take part in the current round.
+    None = 0,
+    /// The client takes part in the sum task.
+    Sum = 1,
+    /// The client takes part in the update task.
+    Update = 2,
+}
+
+impl From<mosaic_client_sdk::Task> for MosaicTask {
+    fn from(task: mosaic_client_sdk::Task) -> Self {
+        match task {
+            mosaic_client_sdk::Task::None => MosaicTask::None,
+            mosaic_client_sdk::Task::Sum => MosaicTask::Sum,
+            mosaic_client_sdk::Task::Update => MosaicTask::Update,
+        }
+    }
+}
+
+/// A buffer of bytes owned by the library, which must be freed with [`mosaic_buffer_free()`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct MosaicBuffer {
+    /// The bytes of the buffer.
+    pub data: *mut u8,
+    /// The number of bytes of the buffer.
+    pub len: usize,
+}
+
+impl From<Vec<u8>> for MosaicBuffer {
+    fn from(bytes: Vec<u8>) -> Self {
+        let len = bytes.len();
+        Self {
+            data: Box::into_raw(bytes.into_boxed_slice()) as *mut u8,
+            len,
+        }
+    }
+}
+
+/// A client of a mosaic coordinator.
+pub struct MosaicClient {
+    inner: mosaic_client_sdk::Client,
+}
+
+/// Executes the body of a call, turning panics into [`MosaicStatus::Panic`] since they must not
+/// unwind into C.
+fn call<F>(body: F) -> MosaicStatus
+where
+    F: FnOnce() -> Result<(), MosaicStatus>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => MosaicStatus::Ok,
+        Ok(Err(status)) => status,
+        Err(_) => MosaicStatus::Panic,
+    }
+}
+
+/// Dereferences a pointer argument.
+///
+/// # Safety
+///
+/// The pointer must be null or valid for the lifetime `'a`.
+unsafe fn deref<'a, T>(pointer: *mut T) -> Result<&'a mut T, MosaicStatus> {
+    pointer.as_mut().ok_or(MosaicStatus::NullPointer)
+}
+
+/// Reads a string argument.
+///
+/// # Safety
+///
+/// The pointer must be null or point to a nul terminated string.
+unsafe fn string<'a>(pointer: *const c_char) -> Result<&'a str, MosaicStatus> {
+    if pointer.is_null() {
+        return Err(MosaicStatus::NullPointer);
+    }
+    CStr::from_ptr(pointer)
+        .to_str()
+        .map_err(|_| MosaicStatus::InvalidString)
+}
+
+/// Reads a buffer argument, which may be null if it is empty.
+///
+/// # Safety
+///
+/// The pointer must be null or valid for `len` elements.
+unsafe fn buffer<'a, T>(pointer: *const T, len: usize) -> Result<&'a [T], MosaicStatus> {
+    if len == 0 {
+        Ok(&[])
+    } else if pointer.is_null() {
+        Err(MosaicStatus::NullPointer)
+    } else {
+        Ok(slice::from_raw_parts(pointer, len))
+    }
+}
+
+fn init_crypto() -> Result<(), MosaicStatus> {
+    sodiumoxide::init().map_err(|_| MosaicStatus::CryptoInit)
+}
+
+/// Creates a new client of the coordinator at `url`, which weighs its local models with
+/// `scalar` in the aggregation. The client generates its own signing keys.
+///
+/// On success, `*client` is set to the new client, which must be destroyed with either
+/// [`mosaic_client_free()`] or [`mosaic_client_save()`].
+///
+/// # Safety
+///
+/// `url` must be a nul terminated string and `client` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_new(
+    url: *const c_char,
+    scalar: f64,
+    client: *mut *mut MosaicClient,
+) -> MosaicStatus {
+    call(|| {
+        let url = string(url)?;
+        let client = deref(client)?;
+        init_crypto()?;
+
+        let mut settings = mosaic_client_sdk::Settings::new();
+        settings.set_url(url.to_string());
+        settings.set_keys(SigningKeyPair::generate());
+        settings.set_scalar(scalar);
+        settings.set_max_message_size(MaxMessageSize::unlimited());
+        let inner =
+            mosaic_client_sdk::Client::new(settings).map_err(|_| MosaicStatus::ClientInit)?;
+
+        *client = Box::into_raw(Box::new(MosaicClient { inner }));
+        Ok(())
+    })
+}
+
+/// Restores a client of the coordinator at `url` from the `len` bytes of its serialized
+/// `state`, as returned by [`mosaic_client_save()`].
+///
+/// On success, `*client` is set to the restored client, which must be destroyed with either
+/// [`mosaic_client_free()`] or [`mosaic_client_save()`].
+///
+/// # Safety
+///
+/// `state` must be valid for `len` bytes, `url` must be a nul terminated string and `client`
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_restore(
+    state: *const u8,
+    len: usize,
+    url: *const c_char,
+    client: *mut *mut MosaicClient,
+) -> MosaicStatus {
+    call(|| {
+        let state = buffer(state, len)?;
+        let url = string(url)?;
+        let client = deref(client)?;
+        init_crypto()?;
+
+        let inner = mosaic_client_sdk::Client::restore(state, url)
+            .map_err(|_| MosaicStatus::ClientRestore)?;
+
+        *client = Box::into_raw(Box::new(MosaicClient { inner }));
+        Ok(())
+    })
+}
+
+/// Serializes the state of a client and destroys the client.
+///
+/// On success, `*state` is set to the serialized state, which must be freed with
+/// [`mosaic_buffer_free()`]. The client is destroyed in any case unless it is null.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `state` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_save(
+    client: *mut MosaicClient,
+    state: *mut MosaicBuffer,
+) -> MosaicStatus {
+    if client.is_null() {
+        return MosaicStatus::NullPointer;
+    }
+    let client = Box::from_raw(client);
+    call(|| {
+        let state = deref(state)?;
+        *state = client.inner.save().into();
+        Ok(())
+    })
+}
+
+/// Destroys a client. Destroying a null client does nothing.
+///
+/// # Safety
+///
+/// `client` must be null or a client which has not been destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_free(client: *mut MosaicClient) {
+    if !client.is_null() {
+        // a panic while dropping the client can't be reported anyway
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| drop(Box::from_raw(client))));
+    }
+}
+
+/// Frees a buffer returned by the library. Freeing an empty buffer does nothing.
+///
+/// # Safety
+///
+/// `buffer` must be a buffer returned by the library which has not been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_buffer_free(buffer: MosaicBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+/// Drives the internal state machine of a client.
+///
+/// After calling this function, the caller should check whether the state of the client
+/// changed with [`mosaic_client_made_progress()`]. If so, the caller should check the task of
+/// the client with [`mosaic_client_task()`] and whether it should set its model with
+/// [`mosaic_client_should_set_model()`].
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_step(client: *mut MosaicClient) -> MosaicStatus {
+    call(|| {
+        deref(client)?.inner.step();
+        Ok(())
+    })
+}
+
+/// Checks whether the internal state machine of a client made progress during the last step.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `made_progress` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_made_progress(
+    client: *mut MosaicClient,
+    made_progress: *mut bool,
+) -> MosaicStatus {
+    call(|| {
+        *deref(made_progress)? = deref(client)?.inner.made_progress();
+        Ok(())
+    })
+}
+
+/// Gets the task of a client in the current round.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `task` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_task(
+    client: *mut MosaicClient,
+    task: *mut MosaicTask,
+) -> MosaicStatus {
+    call(|| {
+        *deref(task)? = deref(client)?.inner.task().into();
+        Ok(())
+    })
+}
+
+/// Checks whether a client waits for its local model. If so, the caller should set the model
+/// with [`mosaic_client_set_model()`] at some point.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `should_set_model` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_should_set_model(
+    client: *mut MosaicClient,
+    should_set_model: *mut bool,
+) -> MosaicStatus {
+    call(|| {
+        *deref(should_set_model)? = deref(client)?.inner.should_set_model();
+        Ok(())
+    })
+}
+
+/// Checks whether a new global model is available, which can be fetched with
+/// [`mosaic_client_global_model()`].
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `new_global_model` must be
+/// valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_new_global_model(
+    client: *mut MosaicClient,
+    new_global_model: *mut bool,
+) -> MosaicStatus {
+    call(|| {
+        *deref(new_global_model)? = deref(client)?.inner.new_global_model();
+        Ok(())
+    })
+}
+
+/// Gets the number of weights of the models of the current round, or `0` if it is not known
+/// yet.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `len` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_model_length(
+    client: *mut MosaicClient,
+    len: *mut usize,
+) -> MosaicStatus {
+    call(|| {
+        *deref(len)? = deref(client)?.inner.local_model_config().len;
+        Ok(())
+    })
+}
+
+/// Sets the local model of a client from the `len` weights of `weights`, which are copied.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet and `weights` must be valid for
+/// `len` floats.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_set_model(
+    client: *mut MosaicClient,
+    weights: *const f32,
+    len: usize,
+) -> MosaicStatus {
+    call(|| {
+        let weights = buffer(weights, len)?;
+        let client = deref(client)?;
+
+        let expected = client.inner.local_model_config().len;
+        if expected != 0 && expected != len {
+            return Err(MosaicStatus::ModelLength);
+        }
+        let model = Model::from_primitives(weights.iter().copied())
+            .map_err(|_| MosaicStatus::InvalidModel)?;
+        client.inner.set_model(model);
+        Ok(())
+    })
+}
+
+/// Fetches the latest global model into the caller-provided buffer `weights` of `capacity`
+/// floats.
+///
+/// On success, `*len` is set to the number of weights of the global model. If the buffer is
+/// too small, [`MosaicStatus::BufferTooSmall`] is returned and `*len` is set to the required
+/// capacity instead. If no global model is available yet, [`MosaicStatus::NoGlobalModel`] is
+/// returned.
+///
+/// # Safety
+///
+/// `client` must be a client which has not been destroyed yet, `weights` must be valid for
+/// writes of `capacity` floats and `len` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn mosaic_client_global_model(
+    client: *mut MosaicClient,
+    weights: *mut f32,
+    capacity: usize,
+    len: *mut usize,
+) -> MosaicStatus {
+    call(|| {
+        let len = deref(len)?;
+        let client = deref(client)?;
+
+        let model = client
+            .inner
+            .global_model()
+            .map_err(|_| MosaicStatus::GlobalModelFetch)?
+            .ok_or(MosaicStatus::NoGlobalModel)?;
+        let model = model
+            .into_primitives()
+            .collect::<Result<Vec<f32>, _>>()
+            .map_err(|_| MosaicStatus::GlobalModelDataType)?;
+        if model.len() > capacity {
+            *len = model.len();
+            return Err(MosaicStatus::BufferTooSmall);
+        }
+        if !model.is_empty() {
+            if weights.is_null() {
+                return Err(MosaicStatus::NullPointer);
+            }
+            ptr::copy_nonoverlapping(model.as_ptr(), weights, model.len());
+        }
+        *len = model.len();
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+
+    use super::*;
+
+    #[test]
+    fn test_null_pointers() {
+        let url = CString::new("http://127.0.0.1:1").unwrap();
+        unsafe {
+            assert_eq!(
+                mosaic_client_new(ptr::null(), 1.0, &mut ptr::null_mut()),
+                MosaicStatus::NullPointer
+            );
+            assert_eq!(
+                mosaic_client_new(url.as_ptr(), 1.0, ptr::null_mut()),
+                MosaicStatus::NullPointer
+            );
+            assert_eq!(
+                mosaic_client_step(ptr::null_mut()),
+                MosaicStatus::NullPointer
+            );
+            assert_eq!(
+                mosaic_client_save(ptr::null_mut(), &mut MosaicBuffer::from(Vec::new())),
+                MosaicStatus::NullPointer
+            );
+            mosaic_client_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn test_unreachable_coordinator() {
+        let url = CString::new("http://127.0.0.1:1").unwrap();
+        let mut client = ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                mosaic_client_new(url.as_ptr(), 1.0, &mut client),
+                MosaicStatus::ClientInit
+            );
+        }
+        assert!(client.is_null());
+    }
+
+    #[test]
+    fn test_buffer_free() {
+        unsafe {
+            mosaic_buffer_free(MosaicBuffer::from(vec![1, 2, 3]));
+            mosaic_buffer_free(MosaicBuffer::from(Vec::new()));
+        }
+    }
+}
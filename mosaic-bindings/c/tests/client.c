@@ -0,0 +1,134 @@
+/*
+ * Runs a round of federated learning with two clients against a local aggregator, which
+ * must be configured like `config.toml`, i.e. for rounds of two participants.
+ *
+ * Usage: client [url]
+ */
+#include <math.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <unistd.h>
+
+#include "mosaic.h"
+
+#define CLIENTS 2
+/* the model length of `config.toml`, for coordinators which don't publish a model schema */
+#define MODEL_LENGTH 8
+#define MAX_STEPS 600
+#define STEP_MICROS 100000
+
+#define CHECK(call)                                                                       \
+    do {                                                                                  \
+        MosaicStatus status = (call);                                                     \
+        if (status != MOSAIC_STATUS_OK) {                                                 \
+            fprintf(stderr, "%s:%d: %s failed with status %d\n", __FILE__, __LINE__,      \
+                    #call, (int)status);                                                  \
+            exit(EXIT_FAILURE);                                                           \
+        }                                                                                 \
+    } while (0)
+
+/* Sets a local model whose weights all equal `value`. */
+static void set_model(MosaicClient *client, float value) {
+    size_t len;
+    CHECK(mosaic_client_model_length(client, &len));
+    if (len == 0) {
+        len = MODEL_LENGTH;
+    }
+    float *weights = malloc(len * sizeof(float));
+    for (size_t i = 0; i < len; i++) {
+        weights[i] = value;
+    }
+    CHECK(mosaic_client_set_model(client, weights, len));
+    free(weights);
+}
+
+/* Saves a client and restores it from its state. */
+static MosaicClient *save_and_restore(MosaicClient *client, const char *url) {
+    MosaicBuffer state;
+    CHECK(mosaic_client_save(client, &state));
+    MosaicClient *restored = NULL;
+    CHECK(mosaic_client_restore(state.data, state.len, url, &restored));
+    mosaic_buffer_free(state);
+    return restored;
+}
+
+/*
+ * Fetches the global model and checks that it is the mean of the local models. Returns false
+ * if there is no global model yet.
+ */
+static bool check_global_model(MosaicClient *client, float expected) {
+    size_t len = 0;
+    MosaicStatus status = mosaic_client_global_model(client, NULL, 0, &len);
+    if (status == MOSAIC_STATUS_NO_GLOBAL_MODEL) {
+        return false;
+    }
+    if (status != MOSAIC_STATUS_BUFFER_TOO_SMALL || len == 0) {
+        fprintf(stderr, "expected a too small buffer, got status %d\n", (int)status);
+        exit(EXIT_FAILURE);
+    }
+
+    float *weights = malloc(len * sizeof(float));
+    CHECK(mosaic_client_global_model(client, weights, len, &len));
+    for (size_t i = 0; i < len; i++) {
+        if (fabsf(weights[i] - expected) > 1e-3f) {
+            fprintf(stderr, "weight %zu is %f instead of %f\n", i, weights[i], expected);
+            exit(EXIT_FAILURE);
+        }
+    }
+    free(weights);
+    printf("global model of %zu weights is correct\n", len);
+    return true;
+}
+
+int main(int argc, char **argv) {
+    const char *url = argc > 1 ? argv[1] : "http://127.0.0.1:8081";
+    const float values[CLIENTS] = {1.0f, 3.0f};
+
+    MosaicClient *clients[CLIENTS];
+    for (int i = 0; i < CLIENTS; i++) {
+        CHECK(mosaic_client_new(url, 1.0 / CLIENTS, &clients[i]));
+    }
+
+    bool restored = false;
+    int models = 0;
+    for (int step = 0; step < MAX_STEPS; step++) {
+        for (int i = 0; i < CLIENTS; i++) {
+            CHECK(mosaic_client_step(clients[i]));
+
+            bool made_progress;
+            CHECK(mosaic_client_made_progress(clients[i], &made_progress));
+            if (!made_progress) {
+                continue;
+            }
+
+            MosaicTask task;
+            CHECK(mosaic_client_task(clients[i], &task));
+            bool should_set_model;
+            CHECK(mosaic_client_should_set_model(clients[i], &should_set_model));
+            if (should_set_model) {
+                if (!restored) {
+                    /* the state survives a restart of the application */
+                    clients[i] = save_and_restore(clients[i], url);
+                    restored = true;
+                }
+                printf("client %d sets its model for task %d\n", i, (int)task);
+                set_model(clients[i], values[i]);
+                models++;
+            }
+
+            bool new_global_model;
+            CHECK(mosaic_client_new_global_model(clients[i], &new_global_model));
+            if (new_global_model && models == CLIENTS &&
+                check_global_model(clients[i], (values[0] + values[1]) / CLIENTS)) {
+                for (int j = 0; j < CLIENTS; j++) {
+                    mosaic_client_free(clients[j]);
+                }
+                return EXIT_SUCCESS;
+            }
+        }
+        usleep(STEP_MICROS);
+    }
+
+    fprintf(stderr, "no global model after %d steps\n", MAX_STEPS);
+    return EXIT_FAILURE;
+}
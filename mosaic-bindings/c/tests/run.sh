@@ -0,0 +1,18 @@
+#!/bin/sh
+# Builds the bindings and the aggregator, and runs the C test program against a local
+# aggregator.
+set -e
+
+cd "$(dirname "$0")/.."
+cargo build
+(cd ../../mosaic && cargo build --bin aggregator)
+
+cc -Wall -Wextra -std=c99 -D_DEFAULT_SOURCE -Iinclude tests/client.c \
+    -Ltarget/debug -lmosaic_c_sdk -lm -o target/debug/client_test
+
+../../mosaic/target/debug/aggregator -c tests/config.toml &
+AGGREGATOR=$!
+trap 'kill $AGGREGATOR 2>/dev/null' EXIT
+sleep 1
+
+LD_LIBRARY_PATH=target/debug ./target/debug/client_test http://127.0.0.1:8081
@@ -6,6 +6,7 @@ setup(
     version="0.2.0",
     rust_extensions=[RustExtension("mosaic_python_sdk.mosaic_python_sdk", binding=Binding.PyO3)],
     packages=["mosaic_python_sdk"],
+    install_requires=["numpy"],
     # rust extensions are not zip safe, just like C-extensions.
     zip_safe=False,
 )
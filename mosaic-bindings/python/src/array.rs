@@ -0,0 +1,183 @@
+//! Exchange of models as numpy arrays.
+//!
+//! Local models are read from numpy arrays which are contiguous in C order without copying their
+//! weights. Other objects which support the buffer protocol are copied once, and any other
+//! sequence is extracted weight by weight. Global models are returned as numpy arrays which own
+//! the converted weights.
+use std::borrow::Cow;
+
+use numpy::{npyffi::NPY_ORDER, IntoPyArray, PyArrayDyn, PyReadonlyArrayDyn};
+use pyo3::buffer::{self, PyBuffer};
+use pyo3::prelude::*;
+use pyo3::{ffi, AsPyPointer};
+
+use mosaic_core::model::{DataType, FromPrimitives, IntoPrimitives, Model};
+
+use crate::{GlobalModelDataTypeMisMatch, LocalModelDataTypeError};
+
+/// Gets the name of the numpy dtype of a data type.
+pub(crate) fn dtype_name(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::F32 => "float32",
+        DataType::F64 => "float64",
+        DataType::I32 => "int32",
+        DataType::I64 => "int64",
+    }
+}
+
+/// The weights of a local model, read from a Python object.
+pub(crate) enum Weights<'py, P: numpy::Element> {
+    /// A numpy array which is contiguous in C order. It stays borrowed as long as its weights
+    /// are in use.
+    Array(PyReadonlyArrayDyn<'py, P>),
+    /// The weights copied from any other object.
+    Copied(Vec<P>),
+}
+
+impl<'py, P> Weights<'py, P>
+where
+    P: buffer::Element + numpy::Element + FromPyObject<'py>,
+{
+    /// Reads the weights of a local model in C order.
+    ///
+    /// Arrays and other buffers must have the expected data type, since casting them implicitly
+    /// would silently lose precision. Other objects, like lists, are extracted weight by weight.
+    fn read(weights: &'py PyAny, data_type: DataType) -> PyResult<Self> {
+        if let Ok(array) = weights.downcast::<PyArrayDyn<P>>() {
+            if array.is_c_contiguous() {
+                return Ok(Weights::Array(array.try_readonly()?));
+            }
+        }
+
+        // safe: the object is a valid pointer as long as we hold the GIL
+        if unsafe { ffi::PyObject_CheckBuffer(weights.as_ptr()) } == 0 {
+            return weights
+                .extract()
+                .map(Weights::Copied)
+                .map_err(|err| LocalModelDataTypeError::new_err(format!("{}", err)));
+        }
+        let buffer = PyBuffer::<P>::get(weights).map_err(|_| {
+            LocalModelDataTypeError::new_err(format!(
+                "expected an array of {}, the data type of the current model",
+                dtype_name(data_type)
+            ))
+        })?;
+        buffer.to_vec(weights.py()).map(Weights::Copied)
+    }
+
+    fn as_slice(&self) -> &[P] {
+        match self {
+            // UNWRAP_SAFE: only arrays which are contiguous are borrowed.
+            Weights::Array(array) => array.as_slice().unwrap(),
+            Weights::Copied(weights) => weights,
+        }
+    }
+}
+
+/// The weights of a local model in one of the primitive data types, read from a Python object.
+pub(crate) enum LocalWeights<'py> {
+    F32(Weights<'py, f32>),
+    F64(Weights<'py, f64>),
+    I32(Weights<'py, i32>),
+    I64(Weights<'py, i64>),
+}
+
+impl<'py> LocalWeights<'py> {
+    /// Reads the weights of a local model in the given data type.
+    pub(crate) fn read(weights: &'py PyAny, data_type: DataType) -> PyResult<Self> {
+        Ok(match data_type {
+            DataType::F32 => LocalWeights::F32(Weights::read(weights, data_type)?),
+            DataType::F64 => LocalWeights::F64(Weights::read(weights, data_type)?),
+            DataType::I32 => LocalWeights::I32(Weights::read(weights, data_type)?),
+            DataType::I64 => LocalWeights::I64(Weights::read(weights, data_type)?),
+        })
+    }
+
+    /// Borrows the weights, so that they can be converted without the GIL.
+    pub(crate) fn primitives(&self) -> Primitives<'_> {
+        match self {
+            LocalWeights::F32(weights) => Primitives::F32(Cow::Borrowed(weights.as_slice())),
+            LocalWeights::F64(weights) => Primitives::F64(Cow::Borrowed(weights.as_slice())),
+            LocalWeights::I32(weights) => Primitives::I32(Cow::Borrowed(weights.as_slice())),
+            LocalWeights::I64(weights) => Primitives::I64(Cow::Borrowed(weights.as_slice())),
+        }
+    }
+}
+
+/// The weights of a model in one of the primitive data types.
+pub(crate) enum Primitives<'a> {
+    F32(Cow<'a, [f32]>),
+    F64(Cow<'a, [f64]>),
+    I32(Cow<'a, [i32]>),
+    I64(Cow<'a, [i64]>),
+}
+
+impl Primitives<'_> {
+    /// Converts the weights of a global model into the given data type.
+    ///
+    /// This doesn't need the GIL.
+    pub(crate) fn from_model(model: Model, data_type: DataType) -> PyResult<Self> {
+        fn convert<P: Clone + 'static>(model: Model) -> PyResult<Cow<'static, [P]>>
+        where
+            Model: IntoPrimitives<P>,
+        {
+            model
+                .into_primitives()
+                .collect::<Result<Vec<_>, _>>()
+                .map(Cow::Owned)
+                .map_err(|_| {
+                    GlobalModelDataTypeMisMatch::new_err(format!(
+                        "the global model is not representable as {}",
+                        std::any::type_name::<P>()
+                    ))
+                })
+        }
+
+        Ok(match data_type {
+            DataType::F32 => Primitives::F32(convert(model)?),
+            DataType::F64 => Primitives::F64(convert(model)?),
+            DataType::I32 => Primitives::I32(convert(model)?),
+            DataType::I64 => Primitives::I64(convert(model)?),
+        })
+    }
+
+    /// Converts the weights of a local model.
+    ///
+    /// This doesn't need the GIL.
+    pub(crate) fn into_model(self) -> PyResult<Model> {
+        let model = match self {
+            Primitives::F32(weights) => Model::from_primitives(weights.iter().copied()).ok(),
+            Primitives::F64(weights) => Model::from_primitives(weights.iter().copied()).ok(),
+            Primitives::I32(weights) => Model::from_primitives(weights.iter().copied()).ok(),
+            Primitives::I64(weights) => Model::from_primitives(weights.iter().copied()).ok(),
+        };
+        model.ok_or_else(|| {
+            LocalModelDataTypeError::new_err(
+                "the local model contains weights which are not finite numbers",
+            )
+        })
+    }
+}
+
+/// Moves the weights of a global model into a numpy array of the given shape.
+pub(crate) fn into_ndarray(py: Python, weights: Primitives, shape: &[usize]) -> PyResult<PyObject> {
+    fn ndarray<P: numpy::Element>(
+        py: Python,
+        weights: Cow<[P]>,
+        shape: &[usize],
+    ) -> PyResult<PyObject> {
+        // the weights are in C order, whatever the memory order of the flat array
+        let array = weights
+            .into_owned()
+            .into_pyarray(py)
+            .reshape_with_order(shape, NPY_ORDER::NPY_CORDER)?;
+        Ok(array.into_py(py))
+    }
+
+    match weights {
+        Primitives::F32(weights) => ndarray(py, weights, shape),
+        Primitives::F64(weights) => ndarray(py, weights, shape),
+        Primitives::I32(weights) => ndarray(py, weights, shape),
+        Primitives::I64(weights) => ndarray(py, weights, shape),
+    }
+}
@@ -1,29 +1,48 @@
+mod array;
+mod settings;
+
 use pyo3::create_exception;
-use pyo3::exceptions::PyException;
-use pyo3::types::PyList;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::types::PyDict;
 use pyo3::{prelude::*, wrap_pyfunction};
 use tracing::debug;
 use tracing_subscriber::FmtSubscriber;
 use tracing_subscriber::*;
 
-use mosaic_client_sdk::settings::MaxMessageSize;
-use mosaic_core::model::{DataType, FromPrimitives, IntoPrimitives, Model};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use mosaic_core::model::{Model, ModelSchema};
+
+use crate::array::{into_ndarray, LocalWeights, Primitives};
+use crate::settings::Settings;
 
 create_exception!(mosaic_python_sdk, CryptoInit, PyException);
+create_exception!(mosaic_python_sdk, InvalidSettings, PyException);
+create_exception!(mosaic_python_sdk, LoggingInit, PyException);
 create_exception!(mosaic_python_sdk, ClientInit, PyException);
 create_exception!(mosaic_python_sdk, ClientRestore, PyException);
 create_exception!(mosaic_python_sdk, UninitializedClient, PyException);
 create_exception!(mosaic_python_sdk, LocalModelLengthMisMatch, PyException);
 create_exception!(mosaic_python_sdk, LocalModelDataTypeError, PyException);
+create_exception!(mosaic_python_sdk, LocalModelSchemaMisMatch, PyException);
 create_exception!(mosaic_python_sdk, GlobalModelUnavailable, PyException);
 create_exception!(mosaic_python_sdk, GlobalModelDataTypeMisMatch, PyException);
 
 #[pymodule]
 fn mosaic_python_sdk(py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Client>()?;
+    m.add_class::<Settings>()?;
+    m.add_class::<Task>()?;
+    m.add_class::<Event>()?;
+    m.add_class::<EventKind>()?;
+    m.add_class::<ErrorKind>()?;
+    m.add_class::<EventIterator>()?;
     m.add_function(wrap_pyfunction!(init_logging, m)?)?;
 
     m.add("CryptoInit", py.get_type::<CryptoInit>())?;
+    m.add("InvalidSettings", py.get_type::<InvalidSettings>())?;
+    m.add("LoggingInit", py.get_type::<LoggingInit>())?;
     m.add("ClientInit", py.get_type::<ClientInit>())?;
     m.add("ClientRestore", py.get_type::<ClientRestore>())?;
     m.add("UninitializedClient", py.get_type::<UninitializedClient>())?;
@@ -35,6 +54,10 @@ fn mosaic_python_sdk(py: Python, m: &PyModule) -> PyResult<()> {
         "LocalModelDataTypeError",
         py.get_type::<LocalModelDataTypeError>(),
     )?;
+    m.add(
+        "LocalModelSchemaMisMatch",
+        py.get_type::<LocalModelSchemaMisMatch>(),
+    )?;
     m.add(
         "GlobalModelUnavailable",
         py.get_type::<GlobalModelUnavailable>(),
@@ -47,6 +70,180 @@ fn mosaic_python_sdk(py: Python, m: &PyModule) -> PyResult<()> {
     Ok(())
 }
 
+/// Initializes the crypto library, which is idempotent.
+pub(crate) fn init_crypto() -> PyResult<()> {
+    sodiumoxide::init().map_err(|_| CryptoInit::new_err("failed to initialize crypto library."))
+}
+
+/// The task of a client in the current round.
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Task {
+    /// The client doesn't take part in the current round.
+    #[pyo3(name = "NONE")]
+    NoTask,
+    /// The client takes part in the sum task.
+    #[pyo3(name = "SUM")]
+    Sum,
+    /// The client takes part in the update task.
+    #[pyo3(name = "UPDATE")]
+    Update,
+}
+
+impl From<mosaic_client_sdk::Task> for Task {
+    fn from(task: mosaic_client_sdk::Task) -> Self {
+        match task {
+            mosaic_client_sdk::Task::None => Task::NoTask,
+            mosaic_client_sdk::Task::Sum => Task::Sum,
+            mosaic_client_sdk::Task::Update => Task::Update,
+        }
+    }
+}
+
+/// The kind of an event emitted by the client internal state machine, see `Event`.
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EventKind {
+    /// A new round starts, whose ID is the `round_id` of the event.
+    #[pyo3(name = "ROUND_STARTED")]
+    RoundStarted,
+    /// The client is selected for the update task.
+    #[pyo3(name = "UPDATE")]
+    Update,
+    /// The client should set its model with `Client.set_model()`.
+    #[pyo3(name = "LOAD_MODEL")]
+    LoadModel,
+    /// The update of the client has been sent to the coordinator.
+    #[pyo3(name = "UPDATE_SENT")]
+    UpdateSent,
+    /// The coordinator rejected the update of the client, for the `reason` of the event.
+    #[pyo3(name = "UPDATE_REJECTED")]
+    UpdateRejected,
+    /// The client is done with its task.
+    #[pyo3(name = "IDLE")]
+    Idle,
+    /// The global model aggregated in the round `round_id` of the event is available.
+    #[pyo3(name = "GLOBAL_MODEL_AVAILABLE")]
+    GlobalModelAvailable,
+    /// The client ran into the `error` of the event, which it recovers from.
+    #[pyo3(name = "ERROR")]
+    Error,
+}
+
+/// The kind of an error the client internal state machine runs into.
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ErrorKind {
+    /// The round parameters could not be fetched from the coordinator.
+    #[pyo3(name = "ROUND_PARAMS")]
+    RoundParams,
+    /// The local model could not be loaded.
+    #[pyo3(name = "LOAD_MODEL")]
+    LoadModel,
+    /// The global model the update is computed against could not be fetched.
+    #[pyo3(name = "GLOBAL_MODEL")]
+    GlobalModel,
+    /// The round requires local differential privacy, which the client does not apply.
+    #[pyo3(name = "PRIVACY")]
+    Privacy,
+    /// The update could not be sent to the coordinator within the retry policy.
+    #[pyo3(name = "SEND_UPDATE")]
+    SendUpdate,
+}
+
+impl From<mosaic_client_sdk::ErrorKind> for ErrorKind {
+    fn from(kind: mosaic_client_sdk::ErrorKind) -> Self {
+        match kind {
+            mosaic_client_sdk::ErrorKind::RoundParams => ErrorKind::RoundParams,
+            mosaic_client_sdk::ErrorKind::LoadModel => ErrorKind::LoadModel,
+            mosaic_client_sdk::ErrorKind::GlobalModel => ErrorKind::GlobalModel,
+            mosaic_client_sdk::ErrorKind::Privacy => ErrorKind::Privacy,
+            mosaic_client_sdk::ErrorKind::SendUpdate => ErrorKind::SendUpdate,
+        }
+    }
+}
+
+/// An event emitted by the client internal state machine as it advances through the protocol.
+/// Depending on its `kind`, an event carries the ID of a round, the reason of a rejection or the
+/// kind of an error.
+#[pyclass]
+#[derive(Clone, Debug)]
+struct Event {
+    /// The kind of the event.
+    #[pyo3(get)]
+    kind: EventKind,
+    /// The round which started, respectively whose global model is available.
+    #[pyo3(get)]
+    round_id: Option<u32>,
+    /// The reason why the coordinator rejected the update.
+    #[pyo3(get)]
+    reason: Option<String>,
+    /// The kind of the error.
+    #[pyo3(get)]
+    error: Option<ErrorKind>,
+}
+
+impl Event {
+    fn new(kind: EventKind) -> Self {
+        Self {
+            kind,
+            round_id: None,
+            reason: None,
+            error: None,
+        }
+    }
+}
+
+impl From<mosaic_client_sdk::Event> for Event {
+    fn from(event: mosaic_client_sdk::Event) -> Self {
+        match event {
+            mosaic_client_sdk::Event::RoundStarted(round_id) => Event {
+                round_id: Some(round_id),
+                ..Event::new(EventKind::RoundStarted)
+            },
+            mosaic_client_sdk::Event::Update => Event::new(EventKind::Update),
+            mosaic_client_sdk::Event::LoadModel => Event::new(EventKind::LoadModel),
+            mosaic_client_sdk::Event::UpdateSent => Event::new(EventKind::UpdateSent),
+            mosaic_client_sdk::Event::UpdateRejected(reason) => Event {
+                reason: Some(reason),
+                ..Event::new(EventKind::UpdateRejected)
+            },
+            mosaic_client_sdk::Event::Idle => Event::new(EventKind::Idle),
+            mosaic_client_sdk::Event::GlobalModelAvailable(round_id) => Event {
+                round_id: Some(round_id),
+                ..Event::new(EventKind::GlobalModelAvailable)
+            },
+            mosaic_client_sdk::Event::Error(kind) => Event {
+                error: Some(kind.into()),
+                ..Event::new(EventKind::Error)
+            },
+        }
+    }
+}
+
+#[pymethods]
+impl Event {
+    fn __repr__(&self) -> String {
+        let mut repr = format!("Event(kind={:?}", self.kind);
+        if let Some(round_id) = self.round_id {
+            repr.push_str(&format!(", round_id={}", round_id));
+        }
+        if let Some(ref reason) = self.reason {
+            repr.push_str(&format!(", reason={:?}", reason));
+        }
+        if let Some(error) = self.error {
+            repr.push_str(&format!(", error={:?}", error));
+        }
+        repr + ")"
+    }
+}
+
+/// Converts a number of seconds into a duration.
+fn seconds(seconds: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|_| PyValueError::new_err(format!("invalid number of seconds {}", seconds)))
+}
+
 #[pyclass]
 struct Client {
     inner: Option<mosaic_client_sdk::Client>,
@@ -54,34 +251,35 @@ struct Client {
 
 #[pymethods]
 impl Client {
+    /// Create a new client with the given settings or, if a `state` is given, restore a client
+    /// from its serialized state. The state includes all the settings except for the URL, the
+    /// task and the TLS settings, which are taken from `settings`.
     #[new]
-    pub fn new(url: String, scalar: f64, state: Option<Vec<u8>>) -> PyResult<Self> {
-        sodiumoxide::init()
-            .map_err(|_| CryptoInit::new_err("failed to initialize crypto library."))?;
+    #[args(state = "None")]
+    pub fn new(py: Python, settings: &Settings, state: Option<Vec<u8>>) -> PyResult<Self> {
+        init_crypto()?;
+        let settings = settings.inner().clone();
 
         let inner = if let Some(state) = state {
             debug!("restoring the client.");
-            mosaic_client_sdk::Client::restore(&state, &url).map_err(|err| {
-                ClientRestore::new_err(format!("failed to restore client: {}.", err))
-            })?
+            py.allow_threads(|| mosaic_client_sdk::Client::restore_with(&state, &settings))
+                .map_err(|err| {
+                    ClientRestore::new_err(format!("failed to restore client: {}.", err))
+                })?
         } else {
             debug!("initializing the client.");
-            let mut settings = mosaic_client_sdk::Settings::new();
-            settings.set_url(url);
-            settings.set_keys(mosaic_core::crypto::SigningKeyPair::generate());
-            settings.set_scalar(scalar);
-            settings.set_max_message_size(MaxMessageSize::unlimited());
-
-            mosaic_client_sdk::Client::new(settings).map_err(|err| {
-                ClientInit::new_err(format!("failed to initialize client: {}.", err))
-            })?
+            py.allow_threads(|| mosaic_client_sdk::Client::new(settings))
+                .map_err(|err| {
+                    ClientInit::new_err(format!("failed to initialize client: {}.", err))
+                })?
         };
 
         Ok(Self { inner: Some(inner) })
     }
 
+    /// Drive the client internal state machine. The GIL is released meanwhile.
     // #[text_signature = "($self)"]
-    pub fn step(&mut self) -> PyResult<()> {
+    pub fn step(&mut self, py: Python) -> PyResult<()> {
         let inner = match self.inner {
             Some(ref mut inner) => inner,
             None => {
@@ -91,12 +289,19 @@ impl Client {
             }
         };
 
-        inner.step();
+        py.allow_threads(|| inner.step());
         Ok(())
     }
 
+    /// Set the local model, either as an array of weights or, if the model is structured, as a
+    /// dict which maps the name of each tensor to its array of weights. The arrays must have the
+    /// data type of the current model, respectively of their tensor, and are read in C order,
+    /// whatever their shape. Lists of weights are accepted as well, but they are slow to read.
+    ///
+    /// Arrays which are contiguous in C order are read without copying them, and the GIL is
+    /// released while the weights are converted.
     // #[text_signature = "($self, local_model)"]
-    pub fn set_model(&mut self, local_model: &PyList) -> PyResult<()> {
+    pub fn set_model(&mut self, py: Python, local_model: &PyAny) -> PyResult<()> {
         let inner = match self.inner {
             Some(ref mut inner) => inner,
             None => {
@@ -108,17 +313,33 @@ impl Client {
 
         let local_model_config = inner.local_model_config();
 
+        if let Ok(tensors) = local_model.downcast::<PyDict>() {
+            let schema = local_model_config.schema.ok_or_else(|| {
+                LocalModelSchemaMisMatch::new_err(
+                    "the model of the current round is not structured",
+                )
+            })?;
+            let tensors = tensors_from(&schema, tensors)?;
+            let primitives = tensors
+                .iter()
+                .map(|(name, tensor)| (name.clone(), tensor.primitives()))
+                .collect();
+            let model = py.allow_threads(|| model_from_tensors(&schema, primitives))?;
+            py.allow_threads(|| inner.set_model(model));
+            return Ok(());
+        }
+
         debug!(
             "converting local model to {:?} datatype.",
             local_model_config.data_type
         );
-
-        match local_model_config.data_type {
-            DataType::F32 => from_primitives!(inner, local_model, f32),
-            DataType::F64 => from_primitives!(inner, local_model, f64),
-            DataType::I32 => from_primitives!(inner, local_model, i32),
-            DataType::I64 => from_primitives!(inner, local_model, i64),
-        }
+        let weights = LocalWeights::read(local_model, local_model_config.data_type)?;
+        let primitives = weights.primitives();
+        py.allow_threads(|| {
+            let model = primitives.into_model()?;
+            inner.set_model(model);
+            Ok(())
+        })
     }
 
     /// Check whether the client internal state machine made progress while
@@ -152,8 +373,9 @@ impl Client {
         Ok(inner.should_set_model())
     }
 
+    /// Return the task of the client in the current round.
     // #[text_signature = "($self)"]
-    pub fn task(&self) -> PyResult<u8> {
+    pub fn task(&self) -> PyResult<Task> {
         let inner = match self.inner {
             Some(ref inner) => inner,
             None => {
@@ -163,13 +385,66 @@ impl Client {
             }
         };
 
-        let task_as_u8 = match inner.task() {
-            mosaic_client_sdk::Task::None => 0,
-            mosaic_client_sdk::Task::Sum => 1,
-            mosaic_client_sdk::Task::Update => 2,
+        Ok(inner.task().into())
+    }
+
+    /// Drive the client internal state machine until it emits an event, and return the event.
+    /// If the state machine can't make progress, it is retried after `tick` seconds. `None` is
+    /// returned if no event is emitted within `timeout` seconds, if any.
+    ///
+    /// The client state is updated for the event before it is returned, e.g. the client should
+    /// set its model after an event of kind `EventKind.LOAD_MODEL`. The GIL is released while the
+    /// state machine is driven.
+    #[pyo3(text_signature = "($self, tick=1.0, timeout=None)")]
+    #[args(tick = "1.0", timeout = "None")]
+    pub fn next_event(
+        &mut self,
+        py: Python,
+        tick: f64,
+        timeout: Option<f64>,
+    ) -> PyResult<Option<Event>> {
+        let inner = match self.inner {
+            Some(ref mut inner) => inner,
+            None => {
+                return Err(UninitializedClient::new_err(
+                    "called 'next_event' on an uninitialized client.",
+                ))
+            }
         };
 
-        Ok(task_as_u8)
+        let tick = seconds(tick)?;
+        let deadline = timeout
+            .map(seconds)
+            .transpose()?
+            .map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(event) = py.allow_threads(|| inner.try_next_event()) {
+                return Ok(Some(event.into()));
+            }
+            // lets a KeyboardInterrupt stop the loop
+            py.check_signals()?;
+            let wait = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => tick.min(remaining),
+                    _ => return Ok(None),
+                },
+                None => tick,
+            };
+            if !inner.made_progress() {
+                py.allow_threads(|| std::thread::sleep(wait));
+            }
+        }
+    }
+
+    /// Return an iterator over the events emitted by the client internal state machine, which
+    /// drives the state machine like `Client.next_event()`. The iterator never ends.
+    #[pyo3(text_signature = "($self, tick=1.0)")]
+    #[args(tick = "1.0")]
+    pub fn events(slf: PyRef<Self>, tick: f64) -> PyResult<EventIterator> {
+        Ok(EventIterator {
+            client: slf.into(),
+            tick: seconds(tick)?.as_secs_f64(),
+        })
     }
 
     pub fn new_global_model(&self) -> PyResult<bool> {
@@ -185,7 +460,13 @@ impl Client {
         Ok(inner.new_global_model())
     }
 
-    pub fn global_model(&mut self, py: Python) -> PyResult<Option<Py<PyList>>> {
+    /// Get the global model, either as a flat array of weights or, if the model is structured,
+    /// as a dict which maps the name of each tensor to its array of weights in the shape of the
+    /// tensor. The arrays have the data type of the current model, respectively of their
+    /// tensor, and take over the converted weights without copying them.
+    ///
+    /// The GIL is released while the global model is fetched and converted.
+    pub fn global_model(&mut self, py: Python) -> PyResult<Option<PyObject>> {
         let inner = match self.inner {
             Some(ref mut inner) => inner,
             None => {
@@ -195,8 +476,8 @@ impl Client {
             }
         };
 
-        let global_model = inner
-            .global_model()
+        let global_model = py
+            .allow_threads(|| inner.global_model())
             .map_err(|_| GlobalModelUnavailable::new_err("failed to fetch global model"))?;
 
         let global_model = match global_model {
@@ -204,12 +485,14 @@ impl Client {
             None => return Ok(None),
         };
 
-        match inner.local_model_config().data_type {
-            DataType::F32 => into_primitives!(py, global_model, f32),
-            DataType::F64 => into_primitives!(py, global_model, f64),
-            DataType::I32 => into_primitives!(py, global_model, i32),
-            DataType::I64 => into_primitives!(py, global_model, i64),
+        let local_model_config = inner.local_model_config();
+        if let Some(schema) = local_model_config.schema {
+            return model_into_tensors(py, &schema, global_model).map(Some);
         }
+        let length = global_model.len();
+        let weights = py
+            .allow_threads(|| Primitives::from_model(global_model, local_model_config.data_type))?;
+        into_ndarray(py, weights, &[length]).map(Some)
     }
 
     // #[text_signature = "($self)"]
@@ -227,42 +510,100 @@ impl Client {
     }
 }
 
-#[macro_export]
-macro_rules! into_primitives {
-    ($py:expr, $global_model:expr, $data_type:ty) => {
-        if let Ok(global_model) = $global_model
-            .into_primitives()
-            .collect::<Result<Vec<$data_type>, _>>()
-        {
-            let py_list = PyList::new($py, global_model.into_iter());
-            Ok(Some(py_list.into()))
-        } else {
-            Err(GlobalModelDataTypeMisMatch::new_err(
-                "the global model data type is incompatible with the data type of the current model.",
-            ))
-        }
-    };
+/// Reads the named tensors of a structured local model in the data types of their tensors.
+fn tensors_from<'py>(
+    schema: &ModelSchema,
+    tensors: &'py PyDict,
+) -> PyResult<HashMap<String, LocalWeights<'py>>> {
+    let mut weights = HashMap::new();
+    for (name, tensor) in tensors.iter() {
+        let name: String = name.extract()?;
+        let spec = schema
+            .tensors()
+            .iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| LocalModelSchemaMisMatch::new_err(format!("unknown tensor {}", name)))?;
+        weights.insert(name, LocalWeights::read(tensor, spec.data_type)?);
+    }
+    Ok(weights)
 }
 
-#[macro_export]
-macro_rules! from_primitives {
-    ($client:expr, $local_model:expr, $data_type:ty) => {{
-            let model: Vec<$data_type> = $local_model.extract()
-                .map_err(|err| LocalModelDataTypeError::new_err(format!("{}", err)))?;
-            let converted_model = Model::from_primitives(model.into_iter());
-            if let Ok(converted_model) = converted_model {
-                $client.set_model(converted_model);
-                Ok(())
-            } else {
-                Err(LocalModelDataTypeError::new_err(
-                    "the local model data type is incompatible with the data type of the current model."
-                ))
-            }}
-    };
+/// Joins the named tensors of a structured local model. This doesn't need the GIL.
+fn model_from_tensors(
+    schema: &ModelSchema,
+    tensors: HashMap<String, Primitives>,
+) -> PyResult<Model> {
+    let mut weights = HashMap::new();
+    for (name, tensor) in tensors {
+        weights.insert(name, tensor.into_model()?.0);
+    }
+    schema
+        .join(weights)
+        .map(Model::from)
+        .map_err(|err| LocalModelSchemaMisMatch::new_err(format!("{}", err)))
+}
+
+/// Splits a structured global model into a dict of its named tensors, converting each of them
+/// into the data type and the shape of its tensor.
+fn model_into_tensors(py: Python, schema: &ModelSchema, model: Model) -> PyResult<PyObject> {
+    let tensors = py.allow_threads(|| {
+        schema
+            .split(&model.0)
+            .map_err(|err| GlobalModelDataTypeMisMatch::new_err(format!("{}", err)))?
+            .into_iter()
+            .map(|(spec, weights)| {
+                let tensor = Primitives::from_model(Model::from(weights.to_vec()), spec.data_type)?;
+                Ok((spec, tensor))
+            })
+            .collect::<PyResult<Vec<_>>>()
+    })?;
+    let dict = PyDict::new(py);
+    for (spec, tensor) in tensors {
+        let shape = spec
+            .shape
+            .iter()
+            .map(|dim| *dim as usize)
+            .collect::<Vec<_>>();
+        dict.set_item(&spec.name, into_ndarray(py, tensor, &shape)?)?;
+    }
+    Ok(dict.into_py(py))
+}
+
+/// An iterator over the events of a client, see `Client.events()`.
+#[pyclass]
+struct EventIterator {
+    client: Py<Client>,
+    tick: f64,
+}
+
+#[pymethods]
+impl EventIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python) -> PyResult<Option<Event>> {
+        self.client
+            .as_ref(py)
+            .try_borrow_mut()?
+            .next_event(py, self.tick, None)
+    }
 }
 
-#[pyfunction]
-fn init_logging() {
+/// Initialize the logging of the client to the standard output. The `filter` selects the logged
+/// events with the syntax of the `RUST_LOG` environment variable, e.g. `"info"` or
+/// `"mosaic_client_sdk=debug,warn"`. By default, the `RUST_LOG` environment variable is used if
+/// it is set.
+#[pyfunction(filter = "None")]
+#[pyo3(text_signature = "(filter=None)")]
+fn init_logging(filter: Option<&str>) -> PyResult<()> {
+    let filter = match filter {
+        Some(filter) => EnvFilter::try_new(filter)
+            .map_err(|err| LoggingInit::new_err(format!("invalid log filter: {}", err)))?,
+        None => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new("modalic=debug,info")),
+    };
+
     let format = fmt::format()
         .with_level(true)
         .with_target(false)
@@ -270,9 +611,10 @@ fn init_logging() {
         .with_thread_names(false)
         .compact();
 
-    let _fmt_subscriber = FmtSubscriber::builder()
+    FmtSubscriber::builder()
         .event_format(format)
-        .with_env_filter("modalic=debug,info")
+        .with_env_filter(filter)
         .with_ansi(true)
-        .init();
+        .try_init()
+        .map_err(|err| LoggingInit::new_err(format!("failed to initialize logging: {}", err)))
 }
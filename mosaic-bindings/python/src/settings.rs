@@ -0,0 +1,256 @@
+//! The settings of a client.
+use std::time::Duration;
+
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+use mosaic_client_sdk::settings::{MaxMessageSize, RetryPolicy, UploadPolicy};
+use mosaic_core::{
+    crypto::{ByteObject, SigningKeyPair, SigningKeySeed},
+    message::{Codec, UpdateKind},
+    model::{PrivacyParams, Quantization, Sparsification, UpdateCodec},
+};
+
+use crate::InvalidSettings;
+
+/// The settings of a client, which mirror the settings of the Rust SDK.
+///
+/// The identity of the client is derived from a key seed, which is generated unless it is
+/// given. The seed should be persisted and passed again to keep the identity of the client
+/// across sessions.
+#[pyclass]
+#[pyo3(text_signature = "(url, key_seed=None)")]
+#[derive(Clone)]
+pub(crate) struct Settings {
+    inner: mosaic_client_sdk::Settings,
+    key_seed: SigningKeySeed,
+}
+
+impl Settings {
+    /// Gets the settings of the Rust SDK.
+    pub(crate) fn inner(&self) -> &mosaic_client_sdk::Settings {
+        &self.inner
+    }
+
+    /// Applies a change to the settings, unless the changed settings are invalid.
+    fn update(&mut self, change: impl FnOnce(&mut mosaic_client_sdk::Settings)) -> PyResult<()> {
+        let mut inner = self.inner.clone();
+        change(&mut inner);
+        inner
+            .check()
+            .map_err(|err| InvalidSettings::new_err(format!("{}", err)))?;
+        self.inner = inner;
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl Settings {
+    #[new]
+    #[args(key_seed = "None")]
+    fn new(url: String, key_seed: Option<&[u8]>) -> PyResult<Self> {
+        crate::init_crypto()?;
+        let key_seed = match key_seed {
+            Some(key_seed) => SigningKeySeed::from_slice(key_seed).ok_or_else(|| {
+                InvalidSettings::new_err(format!(
+                    "the key seed must have {} bytes",
+                    SigningKeySeed::LENGTH
+                ))
+            })?,
+            None => SigningKeySeed::generate(),
+        };
+
+        let mut inner = mosaic_client_sdk::Settings::new();
+        inner.set_url(url);
+        inner.set_keys(SigningKeyPair::derive_from_seed(&key_seed));
+        inner.set_max_message_size(MaxMessageSize::unlimited());
+        Ok(Self { inner, key_seed })
+    }
+
+    /// The seed the signing keys of the client are derived from.
+    #[getter]
+    fn key_seed<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.key_seed.as_slice())
+    }
+
+    /// Sets the training task to take part in, for coordinators that host several tasks.
+    #[pyo3(text_signature = "($self, task)")]
+    fn set_task(&mut self, task: String) {
+        self.inner.set_task(task);
+    }
+
+    /// Sets the scalar used for masking.
+    #[pyo3(text_signature = "($self, scalar)")]
+    fn set_scalar(&mut self, scalar: f64) -> PyResult<()> {
+        self.update(|inner| inner.set_scalar(scalar))
+    }
+
+    /// Sets the maximum size of a message in bytes, above which messages are split into chunks.
+    /// `None` disables the splitting, which is the default.
+    #[pyo3(text_signature = "($self, size)")]
+    fn set_max_message_size(&mut self, size: Option<usize>) -> PyResult<()> {
+        let size = match size {
+            Some(size) => MaxMessageSize::capped(size)
+                .map_err(|err| InvalidSettings::new_err(format!("{}", err)))?,
+            None => MaxMessageSize::unlimited(),
+        };
+        self.inner.set_max_message_size(size);
+        Ok(())
+    }
+
+    /// Sets the codecs messages may be compressed with, in order of preference, out of `"zstd"`
+    /// and `"lz4"`. An empty list disables compression.
+    #[pyo3(text_signature = "($self, codecs)")]
+    fn set_compression(&mut self, codecs: Vec<&str>) -> PyResult<()> {
+        let codecs = codecs
+            .into_iter()
+            .map(|codec| match codec {
+                "zstd" => Ok(Codec::Zstd),
+                "lz4" => Ok(Codec::Lz4),
+                _ => Err(InvalidSettings::new_err(format!("unknown codec {}", codec))),
+            })
+            .collect::<PyResult<_>>()?;
+        self.inner.set_compression(codecs);
+        Ok(())
+    }
+
+    /// Sets the lossy codec the trained models are encoded with: the values are quantized to
+    /// `quantization` bits, `8` or `4`, and only the fraction `top_k` of the values with the
+    /// largest magnitudes, respectively the fraction `random_k` of random values, is sent.
+    #[pyo3(text_signature = "($self, quantization=None, top_k=None, random_k=None)")]
+    #[args(quantization = "None", top_k = "None", random_k = "None")]
+    fn set_update_codec(
+        &mut self,
+        quantization: Option<u8>,
+        top_k: Option<f64>,
+        random_k: Option<f64>,
+    ) -> PyResult<()> {
+        let quantization = match quantization {
+            Some(8) => Some(Quantization::Bits8),
+            Some(4) => Some(Quantization::Bits4),
+            Some(bits) => {
+                return Err(InvalidSettings::new_err(format!(
+                    "values can't be quantized to {} bits",
+                    bits
+                )))
+            }
+            None => None,
+        };
+        let sparsification = match (top_k, random_k) {
+            (Some(_), Some(_)) => {
+                return Err(InvalidSettings::new_err(
+                    "top_k and random_k are mutually exclusive",
+                ))
+            }
+            (Some(ratio), None) => Some(Sparsification::TopK(ratio)),
+            (None, Some(ratio)) => Some(Sparsification::RandomK(ratio)),
+            (None, None) => None,
+        };
+        self.inner.set_update_codec(UpdateCodec {
+            quantization,
+            sparsification,
+        });
+        Ok(())
+    }
+
+    /// Sets whether the full trained models or their deltas against the latest global model are
+    /// sent.
+    #[pyo3(text_signature = "($self, delta)")]
+    fn set_delta_updates(&mut self, delta: bool) {
+        self.inner.set_update_kind(if delta {
+            UpdateKind::Delta
+        } else {
+            UpdateKind::Full
+        });
+    }
+
+    /// Sets how messages that couldn't be sent are retried. The delays are given in seconds and
+    /// `max_attempts=None` retries forever.
+    #[pyo3(
+        text_signature = "($self, initial_delay=1.0, max_delay=60.0, multiplier=2.0, jitter=0.5, max_attempts=10)"
+    )]
+    #[args(
+        initial_delay = "1.0",
+        max_delay = "60.0",
+        multiplier = "2.0",
+        jitter = "0.5",
+        max_attempts = "10"
+    )]
+    fn set_retry_policy(
+        &mut self,
+        initial_delay: f64,
+        max_delay: f64,
+        multiplier: f64,
+        jitter: f64,
+        max_attempts: Option<u32>,
+    ) -> PyResult<()> {
+        let delay = |seconds: f64| {
+            Duration::try_from_secs_f64(seconds)
+                .map_err(|_| InvalidSettings::new_err(format!("invalid retry delay {}", seconds)))
+        };
+        self.inner.set_retry_policy(RetryPolicy {
+            initial_delay: delay(initial_delay)?,
+            max_delay: delay(max_delay)?,
+            multiplier,
+            jitter,
+            max_attempts,
+        });
+        Ok(())
+    }
+
+    /// Sets how the parts of messages that are split are uploaded: up to `max_in_flight` parts
+    /// are sent concurrently, and their size adapts to the network conditions if `adaptive` is
+    /// set.
+    #[pyo3(text_signature = "($self, max_in_flight=4, adaptive=True)")]
+    #[args(max_in_flight = "4", adaptive = "true")]
+    fn set_upload_policy(&mut self, max_in_flight: usize, adaptive: bool) -> PyResult<()> {
+        if max_in_flight == 0 {
+            return Err(InvalidSettings::new_err(
+                "at least one message part must be in flight",
+            ));
+        }
+        self.inner.set_upload_policy(UploadPolicy {
+            max_in_flight,
+            adaptive,
+        });
+        Ok(())
+    }
+
+    /// Sets the local differential privacy applied to the trained models before they are sent.
+    /// The models are clipped to the L2 norm `clip_norm` and perturbed with Gaussian noise if
+    /// `delta` is given, respectively with Laplace noise otherwise. Gaussian noise requires an
+    /// `epsilon` below `1`.
+    #[pyo3(text_signature = "($self, epsilon, clip_norm, delta=None)")]
+    #[args(delta = "None")]
+    fn set_privacy(&mut self, epsilon: f64, clip_norm: f64, delta: Option<f64>) -> PyResult<()> {
+        let privacy = match delta {
+            Some(delta) => PrivacyParams::gaussian(epsilon, delta, clip_norm).ok_or_else(|| {
+                InvalidSettings::new_err("the epsilon of Gaussian noise must be below 1")
+            })?,
+            None => PrivacyParams::laplace(epsilon, clip_norm),
+        };
+        self.update(|inner| inner.set_privacy(privacy))
+    }
+
+    /// Sets the path to the PEM encoded root certificate which authenticates the coordinator
+    /// over TLS.
+    #[pyo3(text_signature = "($self, path)")]
+    fn set_trust_anchor(&mut self, path: String) {
+        self.inner.set_trust_anchor(path);
+    }
+
+    /// Sets the path to the PEM encoded certificate and private key which authenticate the
+    /// client to the coordinator over TLS.
+    #[pyo3(text_signature = "($self, path)")]
+    fn set_client_cert(&mut self, path: String) {
+        self.inner.set_client_cert(path);
+    }
+
+    /// Checks whether the settings are valid.
+    #[pyo3(text_signature = "($self)")]
+    fn check(&self) -> PyResult<()> {
+        self.inner
+            .check()
+            .map_err(|err| InvalidSettings::new_err(format!("{}", err)))
+    }
+}
@@ -0,0 +1,22 @@
+#!/bin/sh
+# Builds the bindings and the aggregator, and runs the Python tests against a local aggregator.
+set -e
+
+cd "$(dirname "$0")/.."
+cargo build
+(cd ../../mosaic && cargo build --bin aggregator)
+
+# the package as it is installed, with the extension module built above
+PACKAGE=target/debug/python/mosaic_python_sdk
+mkdir -p $PACKAGE
+cp mosaic_python_sdk/__init__.py $PACKAGE/
+cp target/debug/libmosaic_python_sdk.so $PACKAGE/mosaic_python_sdk.so
+
+../../mosaic/target/debug/aggregator -c tests/config.toml &
+AGGREGATOR=$!
+trap 'kill $AGGREGATOR 2>/dev/null' EXIT
+sleep 1
+
+# the tests run outside of the sources, whose package lacks the extension module
+cd tests
+PYTHONPATH=../target/debug/python MOSAIC_URL=http://127.0.0.1:8082 python3 -m unittest -v
@@ -0,0 +1,67 @@
+"""Runs a round of federated learning with two clients against a local aggregator, which must be
+configured like `config.toml`, i.e. for rounds of two participants and models of 8 weights.
+"""
+import os
+import time
+import unittest
+
+try:
+    import numpy as np
+except ImportError:
+    np = None
+
+from mosaic_python_sdk.mosaic_python_sdk import Client, LocalModelDataTypeError, Settings
+
+URL = os.environ.get("MOSAIC_URL", "http://127.0.0.1:8082")
+MODEL_LENGTH = 8
+MAX_STEPS = 600
+STEP_SECONDS = 0.1
+
+
+@unittest.skipIf(np is None, "numpy is not installed")
+class TestClient(unittest.TestCase):
+    def test_round(self):
+        local_models = [
+            # read without a copy
+            np.full(MODEL_LENGTH, 1.0, dtype=np.float32),
+            # not contiguous, hence copied
+            np.full(2 * MODEL_LENGTH, 3.0, dtype=np.float32)[::2],
+        ]
+        clients = []
+        for _ in local_models:
+            settings = Settings(URL)
+            settings.set_scalar(1.0 / len(local_models))
+            clients.append(Client(settings))
+
+        models = 0
+        for _ in range(MAX_STEPS):
+            for client, local_model in zip(clients, local_models):
+                client.step()
+                if not client.made_progress():
+                    continue
+
+                if client.should_set_model():
+                    # arrays are never cast implicitly
+                    with self.assertRaises(LocalModelDataTypeError):
+                        client.set_model(local_model.astype(np.float64))
+                    client.set_model(local_model)
+                    models += 1
+
+                if client.new_global_model() and models == len(clients):
+                    global_model = client.global_model()
+                    if global_model is None:
+                        continue
+                    self.assertIsInstance(global_model, np.ndarray)
+                    self.assertEqual(global_model.dtype, np.float32)
+                    self.assertEqual(global_model.shape, (MODEL_LENGTH,))
+                    np.testing.assert_allclose(global_model, 2.0, atol=1e-3)
+                    # the array owns its weights, which may be modified
+                    global_model += 1.0
+                    return
+            time.sleep(STEP_SECONDS)
+
+        self.fail("no global model after {} steps".format(MAX_STEPS))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,106 @@
+"""Tests the settings, the tasks, the events and the logging of the bindings. The clients connect
+to a local aggregator, which must be configured like `config.toml`.
+"""
+import os
+import unittest
+
+from mosaic_python_sdk.mosaic_python_sdk import (
+    Client,
+    ClientInit,
+    Event,
+    EventKind,
+    InvalidSettings,
+    LoggingInit,
+    Settings,
+    Task,
+    init_logging,
+)
+
+URL = os.environ.get("MOSAIC_URL", "http://127.0.0.1:8082")
+
+
+class TestSettings(unittest.TestCase):
+    def test_key_seed(self):
+        key_seed = bytes(range(32))
+        self.assertEqual(Settings(URL, key_seed).key_seed, key_seed)
+        self.assertEqual(len(Settings(URL).key_seed), 32)
+        with self.assertRaises(InvalidSettings):
+            Settings(URL, key_seed[1:])
+
+    def test_invalid_settings_are_not_applied(self):
+        settings = Settings(URL)
+        with self.assertRaises(InvalidSettings):
+            settings.set_scalar(float("nan"))
+        with self.assertRaises(InvalidSettings):
+            # Gaussian noise requires an epsilon below 1
+            settings.set_privacy(2.0, 1.0, delta=1e-5)
+        with self.assertRaises(InvalidSettings):
+            settings.set_privacy(-1.0, 1.0)
+        settings.check()
+        # the client would fail to initialize with the rejected settings
+        Client(settings)
+
+    def test_retry_policy(self):
+        settings = Settings(URL)
+        settings.set_retry_policy(initial_delay=0.5, max_delay=10.0, max_attempts=None)
+        for delay in [-1.0, float("nan"), float("inf"), 1e300]:
+            with self.assertRaises(InvalidSettings):
+                settings.set_retry_policy(initial_delay=delay)
+            with self.assertRaises(InvalidSettings):
+                settings.set_retry_policy(max_delay=delay)
+
+    def test_invalid_options(self):
+        settings = Settings(URL)
+        with self.assertRaises(InvalidSettings):
+            settings.set_compression(["zstd", "brotli"])
+        with self.assertRaises(InvalidSettings):
+            settings.set_update_codec(quantization=3)
+        with self.assertRaises(InvalidSettings):
+            settings.set_update_codec(top_k=0.1, random_k=0.1)
+        with self.assertRaises(InvalidSettings):
+            settings.set_upload_policy(max_in_flight=0)
+        settings.set_compression(["zstd", "lz4"])
+        settings.set_update_codec(quantization=8, top_k=0.1)
+        settings.set_upload_policy(max_in_flight=2, adaptive=False)
+        settings.check()
+
+
+class TestClientState(unittest.TestCase):
+    def test_task(self):
+        self.assertNotEqual(Task.NONE, Task.SUM)
+        self.assertNotEqual(Task.SUM, Task.UPDATE)
+        self.assertEqual(Client(Settings(URL)).task(), Task.NONE)
+
+    def test_event(self):
+        client = Client(Settings(URL))
+        event = client.next_event(tick=0.1, timeout=10.0)
+        self.assertIsInstance(event, Event)
+        self.assertEqual(event.kind, EventKind.ROUND_STARTED)
+        self.assertIsInstance(event.round_id, int)
+        self.assertIsNone(event.reason)
+        self.assertIsNone(event.error)
+        self.assertEqual(
+            repr(event), "Event(kind=RoundStarted, round_id={})".format(event.round_id)
+        )
+
+        with self.assertRaises(ValueError):
+            client.next_event(tick=-1.0)
+
+    def test_unreachable_aggregator(self):
+        # nothing listens on the discard port
+        with self.assertRaises(ClientInit):
+            Client(Settings("http://127.0.0.1:9"))
+
+
+class TestLogging(unittest.TestCase):
+    def test_init_logging(self):
+        with self.assertRaises(LoggingInit):
+            init_logging("mosaic_client_sdk=loud")
+        init_logging("warn")
+        # the logging is initialized once per process
+        with self.assertRaises(LoggingInit):
+            init_logging()
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,475 @@
+//! Asynchronous client implementation
+use std::{convert::TryInto, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use thiserror::Error;
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::sleep,
+};
+use tracing::{debug, warn};
+
+use mosaic_core::{common::IncompatibleCapabilities, model::Model};
+
+use crate::{
+    client::{
+        new_client,
+        settings::{Settings, SettingsError},
+        ClientError,
+    },
+    http_client::HttpClient,
+    LocalModelConfig, ModelStore, MosaicClientTrait, Notify, SerializableState, StateMachine,
+    TransitionOutcome,
+};
+
+/// Event emitted by the participant internal state machine as it advances through the protocol.
+///
+/// Rounds are identified by the version of the global model they produce, i.e. the first round
+/// is round `1`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// Event emitted when the round with the given ID starts
+    RoundStarted(u32),
+    /// Event emitted when the participant is selected for the update task
+    Update,
+    /// Event emitted when the participant should load its model. This only happens if
+    /// the participant has been selected for the update task
+    LoadModel,
+    /// Event emitted when the update of the participant has been sent to the coordinator
+    UpdateSent,
+    /// Event emitted when the coordinator rejected the update of the participant, with the
+    /// reason given by the coordinator
+    UpdateRejected(String),
+    /// Event emitted when the participant is done with its task
+    Idle,
+    /// Event emitted when the global model aggregated in the round with the given ID is
+    /// available
+    GlobalModelAvailable(u32),
+    /// Event emitted when the participant runs into an error, which it recovers from by
+    /// retrying or by waiting for the next round
+    Error(ErrorKind),
+}
+
+/// The kind of an error the participant internal state machine runs into.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The round parameters could not be fetched from the coordinator
+    RoundParams,
+    /// The local model could not be loaded from the model store
+    LoadModel,
+    /// The global model the update is computed against could not be fetched
+    GlobalModel,
+    /// The round requires local differential privacy, which the participant does not apply
+    Privacy,
+    /// The update could not be sent to the coordinator within the retry policy
+    SendUpdate,
+}
+
+/// Event sender that is passed to the participant internal state machine for emitting
+/// notification
+pub struct Notifier(mpsc::UnboundedSender<Event>);
+
+/// A receiver for events emitted by the participant internal state machine
+///
+/// The channel is unbounded, so that no event is lost. It holds at most the events of a few
+/// transitions, as the participant drains it after every transition.
+pub struct Events(mpsc::UnboundedReceiver<Event>);
+
+impl Events {
+    /// Create a new event sender and receiver.
+    fn new() -> (Self, Notifier) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Self(rx), Notifier(tx))
+    }
+
+    /// Pop the next event. If no event has been received, or if the state machine has been
+    /// dropped and all its events have been received, return `None`.
+    fn next(&mut self) -> Option<Event> {
+        self.0.try_recv().ok()
+    }
+}
+
+impl Notify for Notifier {
+    fn notify(&mut self, event: Event) {
+        if let Err(e) = self.0.send(event) {
+            warn!("failed to notify participant: {}", e);
+        }
+    }
+}
+
+/// A store shared between by the participant and its internal state machine. When the
+/// state machine emits a [`Event::LoadModel`] event, the participant is expected to
+/// load its model into the store. See [`AsyncClient::set_model()`].
+#[derive(Clone)]
+struct Store(Arc<Mutex<Option<Model>>>);
+
+impl Store {
+    /// Create a new model store.
+    fn new() -> Self {
+        Self(Arc::new(Mutex::new(None)))
+    }
+}
+
+#[async_trait]
+impl ModelStore for Store {
+    type Model = Model;
+    type Error = std::convert::Infallible;
+
+    async fn load_model(&mut self) -> Result<Option<Self::Model>, Self::Error> {
+        Ok(self.0.lock().await.take())
+    }
+}
+
+/// Represent the participant current task
+#[derive(Clone, Debug, Copy)]
+pub enum Task {
+    /// The participant is taking part in the sum task
+    Sum,
+    /// The participant is taking part in the update task
+    Update,
+    /// The participant is not taking part in any task
+    None,
+}
+
+/// Error that can occur when instantiating a new client, either with [`AsyncClient::new()`] or
+/// [`AsyncClient::restore()`], respectively their blocking counterparts
+#[derive(Error, Debug)]
+pub enum InitError {
+    #[error("failed to deserialize the participant state {:?}", _0)]
+    Deserialization(#[from] Box<bincode::ErrorKind>),
+    #[error("failed to initialize the participant runtime {:?}", _0)]
+    Runtime(std::io::Error),
+    #[error("failed to initialize HTTP client {:?}", _0)]
+    Client(#[from] ClientError),
+    #[error("invalid participant settings {:?}", _0)]
+    InvalidSettings(#[from] SettingsError),
+    #[error("failed to fetch the coordinator capabilities: {}", _0)]
+    Capabilities(crate::http_client::ClientError),
+    #[error("incompatible coordinator: {}", _0)]
+    Incompatible(#[from] IncompatibleCapabilities),
+}
+
+#[derive(Error, Debug)]
+#[error("failed to fetch global model: {}", self.0)]
+pub struct GetGlobalModelError(crate::http_client::ClientError);
+
+/// Asynchronous client
+///
+/// It embeds an internal state machine that executes the protocol on the runtime of the
+/// caller, hence many clients can share a single runtime. The state machine is driven either
+/// by calling [`AsyncClient::step()`] and checking the participant state afterwards, or by
+/// consuming the [`AsyncClient::events()`] stream.
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use futures::StreamExt;
+/// use mosaic_client_sdk::{AsyncClient, Event, Settings};
+/// use mosaic_core::{crypto::SigningKeyPair, model::{FromPrimitives, Model}};
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut settings = Settings::new();
+/// settings.set_url("http://localhost:8080".to_string());
+/// settings.set_keys(SigningKeyPair::generate());
+/// let mut client = AsyncClient::new(settings).await?;
+///
+/// loop {
+///     let event = client.events(Duration::from_secs(1)).next().await;
+///     if event == Some(Event::LoadModel) {
+///         let model = Model::from_primitives(vec![0.0_f32; 100].into_iter())?;
+///         client.set_model(model).await;
+///     }
+/// }
+/// # }
+/// ```
+pub struct AsyncClient {
+    /// Internal state machine
+    state_machine: Option<StateMachine>,
+    /// Receiver for the events emitted by the state machine
+    events: Events,
+    /// Model store where the participant should load its model, when
+    /// `self.should_set_model` is `true`.
+    store: Store,
+    /// HTTP client
+    http_client: HttpClient<reqwest::Client>,
+    /// Whether the participant state changed after the last call to
+    /// [`AsyncClient::step()`]
+    made_progress: bool,
+    /// Whether the participant should load its model into the store.
+    should_set_model: bool,
+    /// Whether a new global model is available.
+    new_global_model: bool,
+    /// If clients idle & awaiting task.
+    awaitening: bool,
+    /// The participant current task
+    task: Task,
+}
+
+impl AsyncClient {
+    /// Create a new participant with the given settings
+    pub async fn new(settings: Settings) -> Result<Self, InitError> {
+        let (trust_anchor, client_cert) = settings.tls();
+        let (url, pet_settings) = settings.try_into()?;
+        let client = new_client(url.as_str(), trust_anchor, client_cert)?;
+        let (events, notifier) = Events::new();
+        let store = Store::new();
+        let state_machine =
+            StateMachine::new(pet_settings, client.clone(), store.clone(), notifier);
+        Self::init(state_machine, client, events, store).await
+    }
+
+    /// Restore a participant from it's serialized state. The coordinator client that
+    /// the participant uses internally is not part of the participant state, so the
+    /// `url` is used to instantiate a new one. A participant of a task other than the default
+    /// task is restored with the URL of its task, `{url}/tasks/{task}`.
+    pub async fn restore(state: &[u8], url: &str) -> Result<Self, InitError> {
+        let state: SerializableState = bincode::deserialize(state)?;
+        let (events, notifier) = Events::new();
+        let store = Store::new();
+        let client = new_client(url, None, None)?;
+        let state_machine = StateMachine::restore(state, client.clone(), store.clone(), notifier);
+        Self::init(state_machine, client, events, store).await
+    }
+
+    /// Restore a participant from it's serialized state, like [`AsyncClient::restore()`], but
+    /// with the URL, the task and the TLS settings of `settings`. The other settings are part of
+    /// the participant state and are ignored.
+    pub async fn restore_with(state: &[u8], settings: &Settings) -> Result<Self, InitError> {
+        let url = settings.endpoint()?;
+        let (trust_anchor, client_cert) = settings.tls();
+        let state: SerializableState = bincode::deserialize(state)?;
+        let (events, notifier) = Events::new();
+        let store = Store::new();
+        let client = new_client(&url, trust_anchor, client_cert)?;
+        let state_machine = StateMachine::restore(state, client.clone(), store.clone(), notifier);
+        Self::init(state_machine, client, events, store).await
+    }
+
+    async fn init(
+        state_machine: StateMachine,
+        mut http_client: HttpClient<reqwest::Client>,
+        events: Events,
+        store: Store,
+    ) -> Result<Self, InitError> {
+        http_client
+            .get_capabilities()
+            .await
+            .map_err(InitError::Capabilities)?
+            .check()?;
+
+        let mut client = Self {
+            state_machine: Some(state_machine),
+            events,
+            store,
+            http_client,
+            task: Task::None,
+            made_progress: true,
+            should_set_model: false,
+            new_global_model: false,
+            awaitening: false,
+        };
+        client.process_events();
+        Ok(client)
+    }
+
+    /// Serialize the participant state and return the corresponding buffer.
+    pub fn save(self) -> Vec<u8> {
+        // UNWRAP_SAFE: the state machine is always set.
+        let state_machine = self.state_machine.unwrap().save();
+        bincode::serialize(&state_machine).unwrap()
+    }
+
+    /// Drive the client internal state machine.
+    ///
+    /// After calling this method, the caller should check whether the participant state
+    /// changed, by calling [`AsyncClient::made_progress()`].  If the state changed, the
+    /// caller should perform the following checks and react appropriately:
+    ///
+    /// - whether the participant is taking part to any task by calling
+    ///   [`AsyncClient::task()`]
+    /// - whether the participant should load its model into the store by calling
+    ///   [`AsyncClient::should_set_model()`]
+    pub async fn step(&mut self) {
+        self.transition().await;
+        self.process_events();
+    }
+
+    /// Makes the internal state machine attempt a single transition.
+    async fn transition(&mut self) {
+        // UNWRAP_SAFE: the state machine is always set.
+        let state_machine = self.state_machine.take().unwrap();
+        let outcome = state_machine.transition().await;
+
+        debug!("\tClient state while progressing: \n{:?}", &outcome);
+        match outcome {
+            TransitionOutcome::Pending(new_state_machine) => {
+                self.made_progress = false;
+                self.state_machine = Some(new_state_machine);
+            }
+            TransitionOutcome::Complete(new_state_machine) => {
+                self.made_progress = true;
+                self.state_machine = Some(new_state_machine)
+            }
+        };
+    }
+
+    /// Gets a stream of the events emitted by the internal state machine, which is driven
+    /// while the stream is polled. If the state machine can't make progress, it is retried
+    /// after `tick`.
+    ///
+    /// The participant state is updated for each event before it is yielded, so the caller can
+    /// react to an event, e.g. with [`AsyncClient::set_model()`], and resume the stream
+    /// afterwards. The stream never ends.
+    pub fn events(&mut self, tick: Duration) -> BoxStream<'_, Event> {
+        stream::unfold(self, move |client| async move {
+            let event = client.next_event(tick).await;
+            Some((event, client))
+        })
+        .boxed()
+    }
+
+    /// Drives the internal state machine until it emits an event.
+    async fn next_event(&mut self, tick: Duration) -> Event {
+        loop {
+            if let Some(event) = self.try_next_event().await {
+                return event;
+            }
+            if !self.made_progress {
+                sleep(tick).await;
+            }
+        }
+    }
+
+    /// Gets the next event emitted by the internal state machine. If there is none yet, the
+    /// state machine attempts a single transition, which may emit one.
+    ///
+    /// Like for the [`AsyncClient::events()`] stream, the participant state is updated for the
+    /// event before it is returned. If `None` is returned, the caller should check whether the
+    /// state machine made progress with [`AsyncClient::made_progress()`] and wait before trying
+    /// again otherwise.
+    pub async fn try_next_event(&mut self) -> Option<Event> {
+        let event = match self.events.next() {
+            Some(event) => Some(event),
+            None => {
+                self.transition().await;
+                self.events.next()
+            }
+        };
+        if let Some(ref event) = event {
+            self.process_event(event);
+        }
+        event
+    }
+
+    fn process_events(&mut self) {
+        while let Some(event) = self.events.next() {
+            self.process_event(&event);
+        }
+    }
+
+    fn process_event(&mut self, event: &Event) {
+        match event {
+            Event::Idle => {
+                self.task = Task::None;
+            }
+            Event::Update => {
+                self.task = Task::Update;
+            }
+            Event::RoundStarted(_) => {
+                self.should_set_model = false;
+            }
+            Event::GlobalModelAvailable(_) => {
+                self.new_global_model = true;
+            }
+            Event::LoadModel => {
+                self.should_set_model = true;
+            }
+            Event::UpdateSent | Event::UpdateRejected(_) | Event::Error(_) => {}
+        }
+    }
+
+    /// Check whether the participant internal state machine made progress while
+    /// executing the PET protocol. If so, the participant state likely changed.
+    pub fn made_progress(&self) -> bool {
+        self.made_progress
+    }
+
+    /// Check whether the participant internal state machine is waiting for the
+    /// participant to load its model into the store. If this method returns `true`, the
+    /// caller should make sure to call [`AsyncClient::set_model()`] at some point.
+    pub fn should_set_model(&self) -> bool {
+        self.should_set_model
+    }
+
+    /// Check whether a new global model is available. If this method returns `true`, the
+    /// caller can call [`AsyncClient::global_model()`] to fetch the new global model.
+    pub fn new_global_model(&self) -> bool {
+        self.new_global_model
+    }
+
+    /// Check wether the client is awaitening a task.
+    pub fn is_awaitening(&self) -> bool {
+        self.awaitening
+    }
+
+    /// Return the participant current task
+    pub fn task(&self) -> Task {
+        self.task
+    }
+
+    /// Load the given model into the store, so that the participant internal state
+    /// machine can process it.
+    pub async fn set_model(&mut self, model: Model) {
+        *self.store.0.lock().await = Some(model);
+        self.should_set_model = false;
+    }
+
+    /// Retrieve the current global model, if available.
+    pub async fn global_model(&mut self) -> Result<Option<Model>, GetGlobalModelError> {
+        let global_model = self
+            .http_client
+            .get_model()
+            .await
+            .map_err(GetGlobalModelError);
+        if global_model.is_ok() {
+            self.new_global_model = false;
+        }
+        global_model
+    }
+
+    /// Return the local model configuration of the model that is expected in the
+    /// [`AsyncClient::set_model`] method.
+    pub fn local_model_config(&self) -> LocalModelConfig {
+        // UNWRAP_SAFE: the state machine is always set.
+        let state_machine = self.state_machine.as_ref().unwrap();
+        state_machine.local_model_config()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_not_dropped() {
+        let (mut events, mut notifier) = Events::new();
+        for round_id in 1..=100 {
+            notifier.notify(Event::RoundStarted(round_id));
+        }
+        for round_id in 1..=100 {
+            assert_eq!(events.next(), Some(Event::RoundStarted(round_id)));
+        }
+        assert_eq!(events.next(), None);
+    }
+
+    #[test]
+    fn test_events_of_dropped_notifier() {
+        let (mut events, mut notifier) = Events::new();
+        notifier.notify(Event::Idle);
+        drop(notifier);
+        assert_eq!(events.next(), Some(Event::Idle));
+        assert_eq!(events.next(), None);
+        assert_eq!(events.next(), None);
+    }
+}
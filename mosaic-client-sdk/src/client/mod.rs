@@ -1,8 +1,12 @@
+mod async_client;
 mod client;
 mod settings;
 
 pub use self::{
-    client::{Client, Event, Events, InitError, Notifier, Task},
+    async_client::{
+        AsyncClient, ErrorKind, Event, Events, GetGlobalModelError, InitError, Notifier, Task,
+    },
+    client::Client,
     settings::{Settings, SettingsError},
 };
 
@@ -2,26 +2,46 @@
 //!
 //! [`Participant`]: crate::Participant
 //!
-use crate::settings::{MaxMessageSize, PetSettings};
+use crate::settings::{MaxMessageSize, PetSettings, RetryPolicy, UploadPolicy};
 use mosaic_core::{
     crypto::SigningKeyPair,
     mask::{FromPrimitive, Scalar},
-    model::PrimitiveCastError,
+    message::{Codec, UpdateKind},
+    model::{PrimitiveCastError, PrivacyParams, UpdateCodec},
 };
 use std::convert::TryInto;
 use thiserror::Error;
+use url::Url;
 
 /// A participant settings
 #[derive(Clone, Debug)]
 pub struct Settings {
     /// Coordinator url.
     url: Option<String>,
+    /// The training task of the coordinator to take part in.
+    task: Option<String>,
     /// The participant signing keys.
     keys: Option<SigningKeyPair>,
     /// The scalar used for masking.
     scalar: Result<Scalar, PrimitiveCastError<f64>>,
     /// The maximum possible size of a message.
     max_message_size: MaxMessageSize,
+    /// The codecs messages may be compressed with, in order of preference.
+    compression: Vec<Codec>,
+    /// The lossy codec the trained models are encoded with.
+    update_codec: UpdateCodec,
+    /// Whether the full trained models or their deltas are sent.
+    update_kind: UpdateKind,
+    /// How messages that couldn't be sent are retried.
+    retry_policy: RetryPolicy,
+    /// How the parts of messages that are split are uploaded.
+    upload_policy: UploadPolicy,
+    /// The local differential privacy applied to the trained models.
+    privacy: Option<PrivacyParams>,
+    /// The path to the root certificate which authenticates the coordinator.
+    trust_anchor: Option<String>,
+    /// The path to the certificate which authenticates the participant.
+    client_cert: Option<String>,
 }
 
 impl Default for Settings {
@@ -35,9 +55,18 @@ impl Settings {
     pub fn new() -> Self {
         Self {
             url: None,
+            task: None,
             keys: None,
             scalar: Ok(Scalar::unit()),
             max_message_size: MaxMessageSize::default(),
+            compression: Codec::ALL.to_vec(),
+            update_codec: UpdateCodec::default(),
+            update_kind: UpdateKind::default(),
+            retry_policy: RetryPolicy::default(),
+            upload_policy: UploadPolicy::default(),
+            privacy: None,
+            trust_anchor: None,
+            client_cert: None,
         }
     }
 
@@ -56,11 +85,82 @@ impl Settings {
         self.url = Some(url);
     }
 
+    /// Sets the training task to take part in, for coordinators that host several tasks. By
+    /// default, the participant takes part in the default task of the coordinator.
+    pub fn set_task(&mut self, task: String) {
+        self.task = Some(task);
+    }
+
     /// Sets the maximum possible size of a message.
     pub fn set_max_message_size(&mut self, size: MaxMessageSize) {
         self.max_message_size = size;
     }
 
+    /// Sets the codecs messages may be compressed with, in order of preference. Each message is
+    /// compressed with the first codec the coordinator accepts. An empty list disables
+    /// compression.
+    pub fn set_compression(&mut self, codecs: Vec<Codec>) {
+        self.compression = codecs;
+    }
+
+    /// Sets the lossy codec the trained models are encoded with. By default, the models are sent
+    /// unchanged.
+    pub fn set_update_codec(&mut self, codec: UpdateCodec) {
+        self.update_codec = codec;
+    }
+
+    /// Sets whether the full trained models or their deltas against the latest global model are
+    /// sent. Deltas fall back to full models when there is no global model yet.
+    pub fn set_update_kind(&mut self, kind: UpdateKind) {
+        self.update_kind = kind;
+    }
+
+    /// Sets how messages that couldn't be sent are retried. By default, the participant backs off
+    /// exponentially and gives up on a message after 10 failed attempts.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
+    /// Sets how the parts of messages that are split are uploaded. By default, up to 4 parts are
+    /// sent concurrently and their size adapts to the network conditions.
+    pub fn set_upload_policy(&mut self, policy: UploadPolicy) {
+        self.upload_policy = policy;
+    }
+
+    /// Sets the local differential privacy applied to the trained models before they are sent.
+    /// The models are clipped and perturbed with noise, and the privacy budget is tightened to
+    /// the requirement of the round, if any. By default, the models are sent as they are, and
+    /// the participant doesn't take part in rounds which require privacy.
+    pub fn set_privacy(&mut self, privacy: PrivacyParams) {
+        self.privacy = Some(privacy);
+    }
+
+    /// Sets the path to the PEM encoded root certificate which authenticates the coordinator
+    /// over TLS. By default, the system root certificates are trusted.
+    pub fn set_trust_anchor(&mut self, path: String) {
+        self.trust_anchor = Some(path);
+    }
+
+    /// Sets the path to the PEM encoded certificate and private key which authenticate the
+    /// participant to the coordinator over TLS.
+    pub fn set_client_cert(&mut self, path: String) {
+        self.client_cert = Some(path);
+    }
+
+    /// Gets the URL of the endpoints of the task to take part in.
+    pub(crate) fn endpoint(&self) -> Result<String, SettingsError> {
+        let url = self.url.clone().ok_or(SettingsError::MissingUrl)?;
+        Ok(match self.task {
+            Some(ref task) => task_url(url, task),
+            None => url,
+        })
+    }
+
+    /// Gets the paths to the trust anchor and to the client certificate, if any.
+    pub(crate) fn tls(&self) -> (Option<String>, Option<String>) {
+        (self.trust_anchor.clone(), self.client_cert.clone())
+    }
+
     /// Check whether the settings are complete and valid
     pub fn check(&self) -> Result<(), SettingsError> {
         if self.url.is_none() {
@@ -69,6 +169,8 @@ impl Settings {
             Err(SettingsError::MissingKeys)
         } else if let Err(e) = &self.scalar {
             Err(e.clone().into())
+        } else if !self.privacy.map_or(true, |privacy| privacy.is_valid()) {
+            Err(SettingsError::InvalidPrivacy)
         } else {
             Ok(())
         }
@@ -84,29 +186,99 @@ pub enum SettingsError {
     MissingKeys,
     #[error("float not within range of scalar: {0}")]
     OutOfScalarRange(#[from] PrimitiveCastError<f64>),
+    #[error("invalid local differential privacy parameters")]
+    InvalidPrivacy,
 }
 
 impl TryInto<(String, PetSettings)> for Settings {
     type Error = SettingsError;
 
     fn try_into(self) -> Result<(String, PetSettings), Self::Error> {
+        let url = self.endpoint()?;
         let Settings {
             keys,
-            url,
             scalar,
             max_message_size,
+            compression,
+            update_codec,
+            update_kind,
+            retry_policy,
+            upload_policy,
+            privacy,
+            ..
         } = self;
 
-        let url = url.ok_or(SettingsError::MissingUrl)?;
         let keys = keys.ok_or(SettingsError::MissingKeys)?;
         let scalar = scalar.map_err(SettingsError::OutOfScalarRange)?;
+        if !privacy.map_or(true, |privacy| privacy.is_valid()) {
+            return Err(SettingsError::InvalidPrivacy);
+        }
 
         let pet_settings = PetSettings {
             keys,
             scalar,
             max_message_size,
+            compression,
+            update_codec,
+            update_kind,
+            retry_policy,
+            upload_policy,
+            privacy,
         };
 
         Ok((url, pet_settings))
     }
 }
+
+/// Gets the URL of the endpoints of a task of the coordinator at `url`.
+///
+/// An invalid `url` is returned unchanged, so that it is reported when the client is created.
+fn task_url(url: String, task: &str) -> String {
+    let mut task_url = match Url::parse(&url) {
+        Ok(task_url) if !task_url.cannot_be_a_base() => task_url,
+        _ => return url,
+    };
+    // UNWRAP_SAFE: the URL can be a base.
+    task_url
+        .path_segments_mut()
+        .unwrap()
+        .pop_if_empty()
+        .extend(&["tasks", task]);
+    task_url.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_task_url() {
+        let url = |url: &str| task_url(url.to_string(), "keyboard");
+        assert_eq!(
+            url("http://localhost:8081"),
+            "http://localhost:8081/tasks/keyboard"
+        );
+        assert_eq!(
+            url("http://localhost:8081/"),
+            "http://localhost:8081/tasks/keyboard"
+        );
+        assert_eq!(
+            url("https://example.com/mosaic/"),
+            "https://example.com/mosaic/tasks/keyboard"
+        );
+        assert_eq!(
+            url("https://example.com/mosaic"),
+            "https://example.com/mosaic/tasks/keyboard"
+        );
+
+        // the name of the task is a single path segment
+        assert_eq!(
+            task_url("http://localhost:8081".to_string(), "key/board"),
+            "http://localhost:8081/tasks/key%2Fboard"
+        );
+
+        // invalid URLs are reported when the client is created
+        assert_eq!(url("localhost:8081"), "localhost:8081");
+        assert_eq!(url("not a url"), "not a url");
+    }
+}
@@ -1,10 +1,11 @@
 use async_trait::async_trait;
+use futures::StreamExt;
 use thiserror::Error;
 use url::Url;
 
-use crate::MosaicClientTrait;
+use crate::{utils::concurrent_futures::ConcurrentFutures, MosaicClientTrait};
 use mosaic_core::{
-    common::RoundParameters,
+    common::{Capabilities, RoundParameters},
     crypto::{ByteObject, PublicSigningKey},
     model::Model,
     SumDict, UpdateSeedDict,
@@ -26,6 +27,9 @@ pub enum ClientError {
 
     #[error("Unexpected response")]
     UnexpectedResponse(u16),
+
+    #[error("the coordinator rejected the message: {0}")]
+    Rejected(String),
 }
 
 #[cfg_attr(not(feature = "reqwest-client"), allow(dead_code))]
@@ -61,7 +65,8 @@ pub trait HttpClientTrait {
     /// response body must be returned
     async fn get(&mut self, url: &str) -> Result<Option<Self::GetResponse>, ClientError>;
 
-    /// Perform an HTTP `POST` on the given URL, with the given body.
+    /// Perform an HTTP `POST` on the given URL, with the given body. A
+    /// response with a client error status is a [`ClientError::Rejected`].
     async fn post(&mut self, url: &str, body: Vec<u8>) -> Result<(), ClientError>;
 }
 
@@ -129,10 +134,18 @@ where
 #[async_trait]
 impl<C> MosaicClientTrait for HttpClient<C>
 where
-    C: HttpClientTrait + Send,
+    C: HttpClientTrait + Clone + Send + 'static,
 {
     type Error = ClientError;
 
+    async fn get_capabilities(&mut self) -> Result<Capabilities, Self::Error> {
+        let url = self.url("capabilities");
+        let capabilities: Option<Capabilities> = self.get(&url).await?;
+        capabilities.ok_or_else(|| {
+            ClientError::Other("failed to fetch capabilities: empty response".to_string())
+        })
+    }
+
     async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error> {
         let url = self.url("params");
         let round_params: Option<RoundParameters> = self.get(&url).await?;
@@ -161,10 +174,51 @@ where
         Ok(self.get(&url).await?)
     }
 
+    async fn get_model_of_version(&mut self, version: u32) -> Result<Option<Model>, Self::Error> {
+        let mut url = self.url("model");
+        url.query_pairs_mut()
+            .append_pair("version", &version.to_string());
+        self.get(&url).await
+    }
+
     async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
         let url = self.url("message");
         self.post(&url, msg).await
     }
+
+    async fn send_messages(
+        &mut self,
+        msgs: Vec<Vec<u8>>,
+        max_in_flight: usize,
+    ) -> Vec<Result<(), Self::Error>> {
+        let url = self.url("message");
+        let mut results: Vec<Option<Result<(), ClientError>>> = msgs.iter().map(|_| None).collect();
+        let mut requests = ConcurrentFutures::new(max_in_flight.max(1));
+        for (index, msg) in msgs.into_iter().enumerate() {
+            let mut client = self.client.clone();
+            let url = url.clone();
+            requests.push(async move { (index, client.post(url.as_str(), msg).await) });
+        }
+        while let Some(response) = requests.next().await {
+            // a request whose task panicked keeps no result and fails below
+            if let Ok((index, result)) = response {
+                results[index] = Some(result);
+            }
+        }
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| Err(ClientError::Other("request task failed".to_string())))
+            })
+            .collect()
+    }
+
+    fn rejection_reason(error: &Self::Error) -> Option<String> {
+        match error {
+            ClientError::Rejected(reason) => Some(reason.clone()),
+            _ => None,
+        }
+    }
 }
 
 #[async_trait]
@@ -189,13 +243,22 @@ impl HttpClientTrait for reqwest::Client {
     }
 
     async fn post(&mut self, url: &str, body: Vec<u8>) -> Result<(), ClientError> {
-        let _resp = reqwest::Client::post(self, url)
+        let resp = reqwest::Client::post(self, url)
             .body(body)
             .send()
             .await
-            .map_err(ClientError::http_error)?
-            .error_for_status()
             .map_err(ClientError::http_error)?;
+        // the coordinator answers messages it rejects with a client error and the reason
+        let status = resp.status();
+        if status.is_client_error() {
+            let reason = resp.text().await.unwrap_or_default();
+            return Err(ClientError::Rejected(if reason.is_empty() {
+                status.to_string()
+            } else {
+                reason
+            }));
+        }
+        resp.error_for_status().map_err(ClientError::http_error)?;
         Ok(())
     }
 }
@@ -67,6 +67,8 @@
 //! - a store from which it can load a model when the participant is
 //!   selected for the update task. This can be any type that
 //!   implements the [`ModelStore`] trait. 
+//!   A [`FileStore`] loads the model from a file, e.g. a checkpoint
+//!   written by the trainer of the participant.
 //!   For this we're going to use the trait implementations on the `reqwest`
 //!   client that is available when compiling with `--features reqwest-client`.
 //! - a notifier that the state machine can use to send
@@ -97,6 +99,7 @@
 //! use mosaic_sdk::{
 //!     client::Client,
 //!     settings::PetSettings,
+//!     Event,
 //!     ModelStore,
 //!     Notify,
 //!     StateMachine,
@@ -115,42 +118,16 @@
 //!     }
 //! }
 //!
-//! #[derive(Debug)]
-//! enum Event {
-//!     // event sent by the state machine when the participant is
-//!     // selected for the update task
-//!     Update,
-//!     // event sent by the state machine when the participant is
-//!     // selected for the sum task
-//!     Sum,
-//!     // event sent by the state machine when a new round starts
-//!     NewRound,
-//!     // event sent by the state machine when the participant
-//!     // becomes inactive (after finishing a task for instance)
-//!     Idle,
-//!     // event sent by the state machine when the participant
-//!     // is supposed to populate the model store
-//!     LoadModel,
-//! }
-//!
-//! // Our notifier is a simple wrapper around a channel.
+//! // Our notifier is a simple wrapper around a channel, which
+//! // forwards the events of the state machine, e.g.
+//! // `Event::RoundStarted(round_id)` when a new round starts or
+//! // `Event::LoadModel` when the participant is supposed to
+//! // populate the model store.
 //! struct Notifier(mpsc::Sender<Event>);
 //!
 //! impl Notify for Notifier {
-//!     fn new_round(&mut self) {
-//!         self.0.send(Event::NewRound).unwrap();
-//!     }
-//!     fn sum(&mut self) {
-//!         self.0.send(Event::Sum).unwrap();
-//!     }
-//!     fn update(&mut self) {
-//!         self.0.send(Event::Update).unwrap();
-//!     }
-//!     fn idle(&mut self) {
-//!         self.0.send(Event::Idle).unwrap();
-//!     }
-//!     fn load_model(&mut self) {
-//!         self.0.send(Event::LoadModel).unwrap();
+//!     fn notify(&mut self, event: Event) {
+//!         self.0.send(event).unwrap();
 //!     }
 //! }
 //!
@@ -199,12 +176,17 @@ mod http_client;
 mod message_encoder;
 pub mod settings;
 mod state_machine;
+mod store;
 mod traits;
 pub(crate) mod utils;
 
 pub(crate) use self::message_encoder::MessageEncoder;
 pub use self::{
-    client::{Client, Event, Events, InitError, Notifier, Settings, SettingsError, Task},
+    client::{
+        AsyncClient, Client, ErrorKind, Event, Events, InitError, Notifier, Settings,
+        SettingsError, Task,
+    },
     traits::{ModelStore, MosaicClientTrait, Notify},
 };
 pub use state_machine::{LocalModelConfig, SerializableState, StateMachine, TransitionOutcome};
+pub use store::{FileStore, ModelFormat, StoreError};
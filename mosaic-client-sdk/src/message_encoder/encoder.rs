@@ -1,29 +1,58 @@
+use std::{
+    io::{self, Write},
+    ops::Range,
+};
+
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use super::Chunker;
 use mosaic_core::{
     crypto::{PublicEncryptKey, SecretSigningKey, SigningKeyPair},
-    message::{Chunk, Message, Payload, Tag, ToBytes},
+    message::{
+        compress_message, Chunk, Codec, Compressor, Message, Payload, PayloadStream, Tag, ToBytes,
+    },
 };
 
+/// Size of the blocks in which a multipart payload is fed to the compressor.
+///
+/// The payload must always be fed in the same blocks, so that it is compressed to the same data
+/// each time.
+const COMPRESSION_BLOCK_SIZE: usize = 64 * 1024;
+
 /// An encoder for multipart messages. It implements
 /// `Iterator<Item=Vec<u8>>`, which yields message parts ready to be
 /// sent over the wire.
+///
+/// The payload is serialized, and compressed if needed, chunk by
+/// chunk, so that it is never held in memory as a whole.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MultipartEncoder {
     keys: SigningKeyPair,
     /// The coordinator public key. It should be the key used to
     /// encrypt the message.
     coordinator_pk: PublicEncryptKey,
-    /// Serialized message payload.
-    data: Vec<u8>,
+    /// The message payload, serialized on demand.
+    payload: PayloadStream,
+    /// The codec the payload is compressed with, if any.
+    compression: Option<Codec>,
+    /// The length of the data to split in chunks, i.e. of the
+    /// payload once it is compressed with `compression`.
+    length: usize,
+    /// The state of the compression of the payload. It is not
+    /// persisted, but compressed again up to the next chunk instead.
+    #[serde(skip)]
+    compressed: Option<CompressedPayload>,
     /// Next chunk ID to be produced by the iterator
     id: u16,
+    /// The highest chunk ID the coordinator accepts.
+    max_chunk_id: u16,
+    /// Position of the next chunk in the data
+    offset: usize,
     /// Message tag
     tag: Tag,
-    /// The maximum size allowed for the payload. `self.data` is split
-    /// in chunks of this size.
+    /// The maximum size allowed for the payload. The data is split
+    /// in chunks of this size, which may change from one chunk to the
+    /// next.
     payload_size: usize,
     /// A random ID common to all the message chunks.
     message_id: u16,
@@ -33,29 +62,59 @@ pub struct MultipartEncoder {
 pub const CHUNK_OVERHEAD: usize = 8;
 pub const MIN_PAYLOAD_SIZE: usize = CHUNK_OVERHEAD + 1;
 
+impl MultipartEncoder {
+    /// Sets the maximum size of the payload of the next chunks.
+    ///
+    /// The size is raised to the minimum size, and so that the
+    /// remaining data fits in the chunk IDs left up to the highest
+    /// ID the coordinator accepts.
+    fn set_payload_size(&mut self, payload_size: usize) {
+        let remaining_ids = self.max_chunk_id.saturating_sub(self.id) as usize + 1;
+        let min_chunk_size = ceiling_div(self.length - self.offset, remaining_ids);
+        self.payload_size = payload_size
+            .max(MIN_PAYLOAD_SIZE + 1)
+            .max(min_chunk_size + CHUNK_OVERHEAD);
+    }
+
+    /// Gets the data of the chunk covering the given range.
+    fn chunk_data(&mut self, range: Range<usize>) -> Vec<u8> {
+        match self.compression {
+            Some(codec) => self
+                .compressed
+                .get_or_insert_with(|| CompressedPayload::new(codec))
+                .read(&self.payload, range),
+            None => self.payload.read(range),
+        }
+    }
+}
+
 impl Iterator for MultipartEncoder {
     type Item = Vec<u8>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let chunker = Chunker::new(&self.data, self.payload_size - CHUNK_OVERHEAD);
-
-        if self.id as usize >= chunker.nb_chunks() {
+        if self.offset >= self.length {
             return None;
         }
 
+        let end = self
+            .length
+            .min(self.offset + self.payload_size - CHUNK_OVERHEAD);
         let chunk = Chunk {
             id: self.id,
             message_id: self.message_id,
-            last: self.id as usize == chunker.nb_chunks() - 1,
-            data: chunker.get_chunk(self.id as usize).to_vec(),
+            last: end == self.length,
+            data: self.chunk_data(self.offset..end),
         };
-        self.id += 1;
+        // the last chunk may have the last ID
+        self.id = self.id.wrapping_add(1);
+        self.offset = end;
 
         let message = Message {
             // The signature is computed when serializing the message
             signature: None,
             participant_pk: self.keys.public,
             is_multipart: true,
+            compression: self.compression,
             tag: self.tag,
             payload: Payload::Chunk(chunk),
             coordinator_pk: self.coordinator_pk,
@@ -65,6 +124,97 @@ impl Iterator for MultipartEncoder {
     }
 }
 
+/// A payload which is compressed as its chunks are read.
+#[derive(Debug)]
+struct CompressedPayload {
+    /// The compressor, until the whole payload has been fed to it.
+    compressor: Option<Compressor<Vec<u8>>>,
+    /// The compressed data which hasn't been read yet.
+    buffer: Vec<u8>,
+    /// The position of the buffer in the compressed data.
+    offset: usize,
+    /// The number of bytes of the payload fed to the compressor.
+    fed: usize,
+}
+
+impl CompressedPayload {
+    fn new(codec: Codec) -> Self {
+        Self {
+            compressor: Some(codec.compressor(Vec::new())),
+            buffer: Vec::new(),
+            offset: 0,
+            fed: 0,
+        }
+    }
+
+    /// Reads the given range of the compressed payload. The ranges
+    /// must be read in increasing order, the data before them is
+    /// compressed and discarded.
+    fn read(&mut self, payload: &PayloadStream, range: Range<usize>) -> Vec<u8> {
+        while self.offset + self.buffer.len() < range.end {
+            // UNWRAP_SAFE: the range is within the compressed payload,
+            // which is complete once the compressor is finished
+            let mut compressor = self.compressor.take().unwrap();
+            if feed(&mut compressor, payload, &mut self.fed) {
+                self.buffer.append(compressor.get_mut());
+                self.compressor = Some(compressor);
+            } else {
+                // UNWRAP_SAFE: writing to a `Vec` cannot fail
+                self.buffer.append(&mut compressor.finish().unwrap());
+            }
+            if self.offset + self.buffer.len() <= range.start {
+                self.offset += self.buffer.len();
+                self.buffer.clear();
+            }
+        }
+        let data = self.buffer[range.start - self.offset..range.end - self.offset].to_vec();
+        self.buffer.drain(..range.end - self.offset);
+        self.offset = range.end;
+        data
+    }
+}
+
+/// Feeds the next block of the payload to the compressor. Returns
+/// `false` if the whole payload has already been fed.
+fn feed<W: Write>(
+    compressor: &mut Compressor<W>,
+    payload: &PayloadStream,
+    fed: &mut usize,
+) -> bool {
+    if *fed == payload.len() {
+        return false;
+    }
+    let end = payload.len().min(*fed + COMPRESSION_BLOCK_SIZE);
+    // UNWRAP_SAFE: the compressors only write to infallible writers
+    compressor.write_all(&payload.read(*fed..end)).unwrap();
+    *fed = end;
+    true
+}
+
+/// Gets the length of the payload compressed with the given codec,
+/// without holding the compressed data in memory.
+fn compressed_length(payload: &PayloadStream, codec: Codec) -> usize {
+    let mut compressor = codec.compressor(ByteCounter(0));
+    let mut fed = 0;
+    while feed(&mut compressor, payload, &mut fed) {}
+    // UNWRAP_SAFE: counting the bytes cannot fail
+    compressor.finish().unwrap().0
+}
+
+/// A writer which only counts the bytes written to it.
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
 /// An encoder for a [`Payload`] representing a sum, update or sum2
 /// message. If the [`Payload`] is small enough, a [`Message`] header
 /// is added, and the message is serialized and signed. If
@@ -76,7 +226,7 @@ pub enum MessageEncoder {
     Simple(Option<Vec<u8>>),
     /// Encoder for a large payload that needs to be split in several
     /// parts.
-    Multipart(MultipartEncoder),
+    Multipart(Box<MultipartEncoder>),
 }
 
 impl Iterator for MessageEncoder {
@@ -102,10 +252,18 @@ impl MessageEncoder {
     // NOTE: the only reason we need to consume the payload is because creating the Message
     // consumes it.
     /// Create a new encoder for the given payload. The `participant`
-    /// is used to sign the message(s). If the serialized payload is
-    /// larger than `max_payload_size`, the message will we split in
-    /// multiple chunks. If `max_payload_size` is `0`, the message
-    /// will not be split.
+    /// is used to sign the message(s). If a `compression` codec is
+    /// given, the payload is compressed with it, unless that doesn't
+    /// make it any smaller. If the serialized payload is larger than
+    /// `max_payload_size`, the message will we split in multiple
+    /// chunks, which are serialized and compressed one at a time. A
+    /// payload which only fits once it is compressed is sent as a
+    /// multipart message of a single chunk. If `max_payload_size` is
+    /// `0`, the message will not be split.
+    ///
+    /// The chunks never use an ID above `max_chunk_id`: if the
+    /// payload doesn't fit in that many chunks of `max_payload_size`,
+    /// the chunks are made larger.
     ///
     /// # Errors
     ///
@@ -117,6 +275,8 @@ impl MessageEncoder {
         payload: Payload,
         coordinator_pk: PublicEncryptKey,
         max_payload_size: usize,
+        max_chunk_id: u16,
+        compression: Option<Codec>,
     ) -> Result<Self, InvalidEncodingInput> {
         // Reject payloads of type Payload::Chunk. It is the job of the encoder to produce those if
         // the payload is deemed to big to be sent in a single message
@@ -128,54 +288,93 @@ impl MessageEncoder {
             return Err(InvalidEncodingInput::PayloadSize);
         }
 
+        let tag = Self::get_tag_from_payload(&payload);
         if max_payload_size != 0 && payload.buffer_length() > max_payload_size {
-            Ok(Self::new_multipart(
+            return Ok(Self::new_multipart(
                 keys,
                 coordinator_pk,
-                payload,
+                tag,
+                PayloadStream::new(payload),
+                compression,
                 max_payload_size,
-            ))
-        } else {
-            Ok(Self::new_simple(keys, coordinator_pk, payload))
+                max_chunk_id,
+            ));
         }
-    }
 
-    fn new_simple(
-        keys: SigningKeyPair,
-        coordinator_pk: PublicEncryptKey,
-        payload: Payload,
-    ) -> Self {
         let message = Message {
             // The signature is computed when serializing the message
             signature: None,
             participant_pk: keys.public,
             is_multipart: false,
+            compression: None,
             coordinator_pk,
-            tag: Self::get_tag_from_payload(&payload),
+            tag,
             payload,
         };
         let data = serialize_message(&message, &keys.secret);
-        Self::Simple(Some(data))
+
+        let data = compression
+            .and_then(|codec| {
+                // UNWRAP_SAFE: the message was just serialized
+                let compressed = compress_message(&data, codec).unwrap();
+                (compressed.len() < data.len()).then(|| compressed)
+            })
+            .unwrap_or(data);
+        Ok(Self::Simple(Some(data)))
     }
 
     fn new_multipart(
         keys: SigningKeyPair,
         coordinator_pk: PublicEncryptKey,
-        payload: Payload,
+        tag: Tag,
+        payload: PayloadStream,
+        compression: Option<Codec>,
         payload_size: usize,
+        max_chunk_id: u16,
     ) -> Self {
-        let tag = Self::get_tag_from_payload(&payload);
-        let mut data = vec![0; payload.buffer_length()];
-        payload.to_bytes(&mut data);
-        Self::Multipart(MultipartEncoder {
+        // The length of the compressed payload is needed upfront to
+        // know the number of chunks, so the payload is compressed a
+        // first time without keeping the compressed data
+        let (compression, length) = compression
+            .map(|codec| (codec, compressed_length(&payload, codec)))
+            .filter(|(_, length)| *length < payload.len())
+            .map_or((None, payload.len()), |(codec, length)| {
+                (Some(codec), length)
+            });
+        let mut encoder = MultipartEncoder {
             keys,
-            data,
+            payload,
+            compression,
+            length,
+            compressed: None,
             id: 0,
+            max_chunk_id,
+            offset: 0,
             tag,
             coordinator_pk,
             payload_size,
             message_id: rand::random::<u16>(),
-        })
+        };
+        encoder.set_payload_size(payload_size);
+        Self::Multipart(Box::new(encoder))
+    }
+
+    /// Get the maximum size of the payload of the next message part,
+    /// or `None` if the message is not split in parts.
+    pub fn payload_size(&self) -> Option<usize> {
+        match self {
+            MessageEncoder::Simple(_) => None,
+            MessageEncoder::Multipart(multipart_encoder) => Some(multipart_encoder.payload_size),
+        }
+    }
+
+    /// Set the maximum size of the payload of the next message parts,
+    /// for instance to adapt it to the network conditions. It has no
+    /// effect if the message is not split in parts.
+    pub fn set_payload_size(&mut self, payload_size: usize) {
+        if let MessageEncoder::Multipart(multipart_encoder) = self {
+            multipart_encoder.set_payload_size(payload_size);
+        }
     }
 
     fn get_tag_from_payload(payload: &Payload) -> Tag {
@@ -192,4 +391,221 @@ fn serialize_message(message: &Message, sk: &SecretSigningKey) -> Vec<u8> {
     let mut buf = vec![0; message.buffer_length()];
     message.to_bytes(&mut buf, sk);
     buf
-}
\ No newline at end of file
+}
+
+/// A helper that performs division with ceil.
+fn ceiling_div(n: usize, d: usize) -> usize {
+    (n + d - 1) / d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::{
+        crypto::{ByteObject, EncryptKeyPair, Signature},
+        message::{decompress_message, FromBytes, MessageBuffer, Sum, Update, UpdateKind},
+        model::{DataType, FromPrimitives, Model, ModelConfig, ModelObject},
+    };
+
+    fn sum_payload() -> Payload {
+        Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        })
+    }
+
+    #[test]
+    fn test_compressed_simple_message() {
+        let keys = SigningKeyPair::generate();
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let mut encoder = MessageEncoder::new(
+            keys,
+            sum_payload(),
+            coordinator_pk,
+            0,
+            u16::MAX,
+            Some(Codec::Zstd),
+        )
+        .unwrap();
+
+        let data = encoder.next().unwrap();
+        assert!(encoder.next().is_none());
+        let message = decompress_message(&data, usize::MAX).unwrap();
+        assert!(message.len() > data.len());
+        let buffer = MessageBuffer::new(&message).unwrap();
+        buffer.as_ref().check_signature().unwrap();
+        assert_eq!(
+            Message::from_byte_slice(&message).unwrap().payload,
+            sum_payload()
+        );
+    }
+
+    #[test]
+    fn test_compressed_multipart_message() {
+        let keys = SigningKeyPair::generate();
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let encoder = MessageEncoder::new(
+            keys,
+            sum_payload(),
+            coordinator_pk,
+            MIN_PAYLOAD_SIZE + 1,
+            u16::MAX,
+            Some(Codec::Lz4),
+        )
+        .unwrap();
+
+        let mut data = Vec::new();
+        for part in encoder {
+            let message = Message::from_byte_slice(&part).unwrap();
+            assert!(message.is_multipart);
+            assert_eq!(message.compression, Some(Codec::Lz4));
+            match message.payload {
+                Payload::Chunk(chunk) => data.extend(chunk.data),
+                _ => panic!("expected a chunk"),
+            }
+        }
+        let payload = Sum::from_byte_slice(&Codec::Lz4.decompress(&data, usize::MAX).unwrap());
+        assert_eq!(Payload::Sum(payload.unwrap()), sum_payload());
+    }
+
+    fn update_payload() -> Payload {
+        let model = Model::from_primitives((0..100_000).map(|i| (i % 100) as f32)).unwrap();
+        Payload::Update(Update {
+            update_signature: Signature::zeroed(),
+            kind: UpdateKind::Full,
+            base_model_version: 1,
+            privacy: None,
+            model_object: ModelObject::new(
+                model.0,
+                ModelConfig {
+                    data_type: DataType::F32,
+                },
+            )
+            .into(),
+        })
+    }
+
+    fn serialized(payload: &Payload) -> Vec<u8> {
+        let mut bytes = vec![0; payload.buffer_length()];
+        payload.to_bytes(&mut bytes);
+        bytes
+    }
+
+    /// Reassembles the data of the chunks of a multipart message.
+    fn chunk_data(parts: Vec<Vec<u8>>, compression: Option<Codec>) -> Vec<u8> {
+        let mut data = Vec::new();
+        for part in parts {
+            let message = Message::from_byte_slice(&part).unwrap();
+            assert_eq!(message.compression, compression);
+            match message.payload {
+                Payload::Chunk(chunk) => data.extend(chunk.data),
+                _ => panic!("expected a chunk"),
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_streamed_update_message() {
+        let update = update_payload();
+        let bytes = serialized(&update);
+
+        for compression in [None, Some(Codec::Zstd), Some(Codec::Lz4)] {
+            let keys = SigningKeyPair::generate();
+            let coordinator_pk = EncryptKeyPair::generate().public;
+            let mut encoder = MessageEncoder::new(
+                keys,
+                update.clone(),
+                coordinator_pk,
+                10_000,
+                u16::MAX,
+                compression,
+            )
+            .unwrap();
+
+            // the encoder is persisted and restored halfway through
+            let mut parts = encoder.by_ref().take(3).collect::<Vec<_>>();
+            let encoder: MessageEncoder =
+                bincode::deserialize(&bincode::serialize(&encoder).unwrap()).unwrap();
+            parts.extend(encoder);
+
+            let mut data = chunk_data(parts, compression);
+            if let Some(codec) = compression {
+                assert!(data.len() < bytes.len());
+                data = codec.decompress(&data, usize::MAX).unwrap();
+            }
+            assert_eq!(data, bytes);
+        }
+    }
+
+    #[test]
+    fn test_variable_payload_size() {
+        let update = update_payload();
+        let bytes = serialized(&update);
+        let keys = SigningKeyPair::generate();
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let mut encoder =
+            MessageEncoder::new(keys, update, coordinator_pk, 10_000, u16::MAX, None).unwrap();
+        assert_eq!(encoder.payload_size(), Some(10_000));
+
+        let mut parts = vec![encoder.next().unwrap()];
+        encoder.set_payload_size(50_000);
+        parts.push(encoder.next().unwrap());
+        encoder.set_payload_size(0);
+        // the remaining data must fit in the remaining chunk IDs
+        assert!(encoder.payload_size().unwrap() > MIN_PAYLOAD_SIZE);
+        parts.push(encoder.next().unwrap());
+        encoder.set_payload_size(100_000);
+        parts.extend(encoder);
+
+        let sizes = parts.iter().map(Vec::len).collect::<Vec<_>>();
+        assert!(sizes[1] > sizes[0] && sizes[2] < sizes[0]);
+        assert_eq!(chunk_data(parts, None), bytes);
+    }
+
+    /// Gets the chunk IDs of the parts of a multipart message.
+    fn chunk_ids(parts: &[Vec<u8>]) -> Vec<u16> {
+        parts
+            .iter()
+            .map(
+                |part| match Message::from_byte_slice(part).unwrap().payload {
+                    Payload::Chunk(chunk) => chunk.id,
+                    _ => panic!("expected a chunk"),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn test_chunk_budget() {
+        let update = update_payload();
+        let bytes = serialized(&update);
+
+        // the parts are shrunk as much as possible after every part, as if sending them kept
+        // failing, but the message still fits in the chunk IDs the coordinator accepts
+        let keys = SigningKeyPair::generate();
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let mut encoder =
+            MessageEncoder::new(keys, update.clone(), coordinator_pk, 10_000, 63, None).unwrap();
+        assert_eq!(encoder.payload_size(), Some(10_000));
+        let mut parts = Vec::new();
+        loop {
+            encoder.set_payload_size(MIN_PAYLOAD_SIZE);
+            match encoder.next() {
+                Some(part) => parts.push(part),
+                None => break,
+            }
+        }
+        assert!(chunk_ids(&parts).into_iter().all(|id| id <= 63));
+        assert_eq!(chunk_data(parts, None), bytes);
+
+        // the parts are made larger if the message doesn't fit in the chunk IDs otherwise
+        let keys = SigningKeyPair::generate();
+        let mut encoder =
+            MessageEncoder::new(keys, update, coordinator_pk, 10_000, 9, None).unwrap();
+        assert!(encoder.payload_size().unwrap() > 10_000);
+        let parts = encoder.by_ref().collect::<Vec<_>>();
+        assert_eq!(chunk_ids(&parts), (0..=9).collect::<Vec<_>>());
+        assert_eq!(chunk_data(parts, None), bytes);
+    }
+}
@@ -1,5 +1,3 @@
-mod chunker;
 mod encoder;
 
-use chunker::Chunker;
 pub use encoder::MessageEncoder;
@@ -1,15 +1,30 @@
 mod max_message_size;
+mod retry_policy;
+mod upload_policy;
 
 use serde::{Deserialize, Serialize};
 
 pub use max_message_size::{InvalidMaxMessageSize, MaxMessageSize, MIN_MESSAGE_SIZE};
-use mosaic_core::{crypto::SigningKeyPair, mask::Scalar};
+use mosaic_core::{
+    crypto::SigningKeyPair,
+    mask::Scalar,
+    message::{Codec, UpdateKind},
+    model::{PrivacyParams, UpdateCodec},
+};
+pub use retry_policy::RetryPolicy;
+pub use upload_policy::UploadPolicy;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct PetSettings {
     pub keys: SigningKeyPair,
     pub scalar: Scalar,
     pub max_message_size: MaxMessageSize,
+    pub compression: Vec<Codec>,
+    pub update_codec: UpdateCodec,
+    pub update_kind: UpdateKind,
+    pub retry_policy: RetryPolicy,
+    pub upload_policy: UploadPolicy,
+    pub privacy: Option<PrivacyParams>,
 }
 
 impl PetSettings {
@@ -18,6 +33,12 @@ impl PetSettings {
             keys,
             scalar: Scalar::unit(),
             max_message_size: MaxMessageSize::default(),
+            compression: Codec::ALL.to_vec(),
+            update_codec: UpdateCodec::default(),
+            update_kind: UpdateKind::default(),
+            retry_policy: RetryPolicy::default(),
+            upload_policy: UploadPolicy::default(),
+            privacy: None,
         }
     }
 }
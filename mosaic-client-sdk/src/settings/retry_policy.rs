@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Represent how a participant retries to send a message part that couldn't be sent.
+///
+/// The delay between two attempts grows exponentially from `initial_delay` by `multiplier` up to
+/// `max_delay`, and a random fraction of up to `jitter` of it is subtracted, so that participants
+/// which failed at the same time don't retry at the same time. After `max_attempts` failed
+/// attempts, the participant gives up on the message and waits for the next round.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// The delay before the first retry.
+    pub initial_delay: Duration,
+    /// The maximum delay between two attempts.
+    pub max_delay: Duration,
+    /// The factor the delay grows by after each failed attempt.
+    pub multiplier: f64,
+    /// The maximum fraction of the delay which is randomly subtracted, between `0.0` and `1.0`.
+    pub jitter: f64,
+    /// The maximum number of attempts to send a message part, or `None` to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.5,
+            max_attempts: Some(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy which retries immediately and forever.
+    pub fn unlimited() -> Self {
+        Self {
+            initial_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            multiplier: 1.0,
+            jitter: 0.0,
+            max_attempts: None,
+        }
+    }
+
+    /// Checks whether a message part may be sent again after `attempts` failed attempts.
+    pub fn should_retry(&self, attempts: u32) -> bool {
+        self.max_attempts.map_or(true, |max| attempts < max)
+    }
+
+    /// Gets the delay before the next attempt after `attempts` failed attempts.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let max_delay = self.max_delay.as_secs_f64();
+        let delay = (self.initial_delay.as_secs_f64() * self.multiplier.max(1.0).powi(exponent))
+            .min(max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0) * rand::thread_rng().gen::<f64>();
+        // `max` and `min` discard the NaNs of nonsensical policies
+        let delay = (delay * (1.0 - jitter)).max(0.0);
+        // the seconds of huge delays are not representable as a duration once rounded to `f64`
+        if delay >= u64::MAX as f64 {
+            self.max_delay
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(100), Duration::from_secs(60));
+        assert!(policy.should_retry(9));
+        assert!(!policy.should_retry(10));
+
+        let policy = RetryPolicy::default();
+        for attempts in 1..10 {
+            let delay = policy.delay(attempts);
+            let max = Duration::from_secs(1 << (attempts - 1)).min(policy.max_delay);
+            assert!(delay <= max && delay >= max / 2);
+        }
+        assert!(RetryPolicy::unlimited().should_retry(u32::MAX));
+
+        let policy = RetryPolicy {
+            initial_delay: Duration::MAX,
+            max_delay: Duration::MAX,
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::MAX);
+        assert_eq!(policy.delay(100), Duration::MAX);
+    }
+}
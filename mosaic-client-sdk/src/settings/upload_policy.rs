@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Represent how a participant uploads the parts of a message which is split in several parts.
+///
+/// Up to `max_in_flight` parts are sent concurrently, so that a large message doesn't take one
+/// round trip per part on high-latency links. If `adaptive` is set, the size of the parts shrinks
+/// when parts fail to be sent and grows back, up to the [`MaxMessageSize`], as long as the
+/// throughput doesn't drop.
+///
+/// [`MaxMessageSize`]: crate::settings::MaxMessageSize
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UploadPolicy {
+    /// The maximum number of message parts sent concurrently.
+    pub max_in_flight: usize,
+    /// Whether the size of the message parts adapts to the observed throughput and failures.
+    pub adaptive: bool,
+}
+
+impl Default for UploadPolicy {
+    fn default() -> Self {
+        Self {
+            max_in_flight: 4,
+            adaptive: true,
+        }
+    }
+}
+
+impl UploadPolicy {
+    /// A policy which sends the message parts one at a time, with a fixed size.
+    pub fn sequential() -> Self {
+        Self {
+            max_in_flight: 1,
+            adaptive: false,
+        }
+    }
+}
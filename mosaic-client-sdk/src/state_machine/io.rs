@@ -1,12 +1,24 @@
 use std::error::Error;
 
 use async_trait::async_trait;
+use thiserror::Error;
 
 use mosaic_core::{
     common::RoundParameters, model::Model, SumDict, SumParticipantPublicKey, UpdateSeedDict,
 };
 
-use crate::{ModelStore, MosaicClientTrait, Notify};
+use crate::{Event, ModelStore, MosaicClientTrait, Notify};
+
+/// Error returned when a message could not be sent to the coordinator.
+#[derive(Debug, Error)]
+pub(crate) enum SendError {
+    /// The coordinator rejected the message, which must not be sent again.
+    #[error("the coordinator rejected the message: {0}")]
+    Rejected(String),
+    /// The message did not reach the coordinator and may be sent again.
+    #[error("{0}")]
+    Failed(Box<dyn Error>),
+}
 
 /// Returned a dynamically dispatched [`IO`] object
 pub(crate) fn boxed_io<X, M, N>(
@@ -49,23 +61,21 @@ pub(crate) trait IO: Send + 'static {
     ) -> Result<Option<UpdateSeedDict>, Box<dyn Error>>;
     /// Fetch the latest global model from the coordinator
     async fn get_model(&mut self) -> Result<Option<Model>, Box<dyn Error>>;
+    /// Fetch the latest global model from the coordinator, if it has the given version
+    async fn get_model_of_version(&mut self, version: u32)
+        -> Result<Option<Model>, Box<dyn Error>>;
     /// Send the given signed and encrypted PET message to the coordinator
-    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Box<dyn Error>>;
-
-    /// Notify the participant that a new round started
-    fn notify_new_round(&mut self);
-    /// Notify the participant that they have been selected for the sum task for the current
-    /// round
-    fn notify_sum(&mut self);
-    /// Notify the participant that it is selected for the update task for the current
-    /// round
-    fn notify_update(&mut self);
-    /// Notify the participant that is done with its current task and it waiting for
-    /// being selected for a task
-    fn notify_idle(&mut self);
-    /// Notify the participant that is is expected to provide a model to the state
-    /// machine by loading it into the store
-    fn notify_load_model(&mut self);
+    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), SendError>;
+    /// Send the given signed and encrypted PET messages to the coordinator, with up to
+    /// `max_in_flight` of them being sent concurrently. The results are in the same order.
+    async fn send_messages(
+        &mut self,
+        msgs: Vec<Vec<u8>>,
+        max_in_flight: usize,
+    ) -> Vec<Result<(), SendError>>;
+
+    /// Notify the participant of an event
+    fn notify(&mut self, event: Event);
 }
 
 /// Internal struct that implements the [`IO`] trait. It is not used as is in the state
@@ -135,31 +145,46 @@ where
             .map_err(|e| Box::new(e) as Box<dyn Error>)
     }
 
-    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    async fn get_model_of_version(
+        &mut self,
+        version: u32,
+    ) -> Result<Option<Model>, Box<dyn Error>> {
         self.mosaic_client
-            .send_message(msg)
+            .get_model_of_version(version)
             .await
             .map_err(|e| Box::new(e) as Box<dyn Error>)
     }
 
-    fn notify_new_round(&mut self) {
-        self.notifier.new_round()
-    }
-
-    fn notify_sum(&mut self) {
-        self.notifier.sum()
+    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), SendError> {
+        self.mosaic_client
+            .send_message(msg)
+            .await
+            .map_err(send_error::<X>)
     }
 
-    fn notify_update(&mut self) {
-        self.notifier.update()
+    async fn send_messages(
+        &mut self,
+        msgs: Vec<Vec<u8>>,
+        max_in_flight: usize,
+    ) -> Vec<Result<(), SendError>> {
+        self.mosaic_client
+            .send_messages(msgs, max_in_flight)
+            .await
+            .into_iter()
+            .map(|result| result.map_err(send_error::<X>))
+            .collect()
     }
 
-    fn notify_idle(&mut self) {
-        self.notifier.idle()
+    fn notify(&mut self, event: Event) {
+        self.notifier.notify(event)
     }
+}
 
-    fn notify_load_model(&mut self) {
-        self.notifier.load_model()
+/// Tells a message the coordinator rejected from a message which did not reach it.
+fn send_error<X: MosaicClientTrait + 'static>(error: X::Error) -> SendError {
+    match X::rejection_reason(&error) {
+        Some(reason) => SendError::Rejected(reason),
+        None => SendError::Failed(Box::new(error)),
     }
 }
 
@@ -190,27 +215,26 @@ impl IO for Box<dyn IO<Model = Box<dyn AsRef<Model> + Send>>> {
         self.as_mut().get_model().await
     }
 
-    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Box<dyn Error>> {
-        self.as_mut().send_message(msg).await
-    }
-
-    fn notify_new_round(&mut self) {
-        self.as_mut().notify_new_round()
-    }
-
-    fn notify_sum(&mut self) {
-        self.as_mut().notify_sum()
+    async fn get_model_of_version(
+        &mut self,
+        version: u32,
+    ) -> Result<Option<Model>, Box<dyn Error>> {
+        self.as_mut().get_model_of_version(version).await
     }
 
-    fn notify_update(&mut self) {
-        self.as_mut().notify_update()
+    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), SendError> {
+        self.as_mut().send_message(msg).await
     }
 
-    fn notify_idle(&mut self) {
-        self.as_mut().notify_idle()
+    async fn send_messages(
+        &mut self,
+        msgs: Vec<Vec<u8>>,
+        max_in_flight: usize,
+    ) -> Vec<Result<(), SendError>> {
+        self.as_mut().send_messages(msgs, max_in_flight).await
     }
 
-    fn notify_load_model(&mut self) {
-        self.as_mut().notify_load_model()
+    fn notify(&mut self, event: Event) {
+        self.as_mut().notify(event)
     }
 }
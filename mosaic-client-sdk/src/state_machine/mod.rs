@@ -12,7 +12,7 @@ mod state_machine;
 #[cfg(test)]
 use self::io::MockIO;
 use self::{
-    io::{boxed_io, IO},
+    io::{boxed_io, SendError, IO},
     phase::{IntoPhase, Phase, PhaseIo, Progress, SharedState, State, Step},
     phases::{Awaiting, NewRound, SendingUpdate, Update},
 };
@@ -6,16 +6,16 @@ use tracing::{debug, error, info};
 
 use super::{Awaiting, NewRound, SendingUpdate, Update, IO};
 use crate::{
-    settings::{MaxMessageSize, PetSettings},
+    settings::{MaxMessageSize, PetSettings, RetryPolicy, UploadPolicy},
     state_machine::{StateMachine, TransitionOutcome},
-    MessageEncoder,
+    ErrorKind, Event, MessageEncoder,
 };
 use mosaic_core::{
     common::{RoundParameters, RoundSeed},
     crypto::{ByteObject, PublicEncryptKey, SigningKeyPair},
     mask::Scalar,
-    message::Payload,
-    model::{self, DataType, Model},
+    message::{Codec, Payload, UpdateKind},
+    model::{self, DataType, Model, ModelSchema, PrivacyParams, UpdateCodec},
 };
 
 #[cfg(feature = "secure")]
@@ -75,6 +75,25 @@ pub struct SharedState {
     /// Maximum message size the participant can send. Messages larger
     /// than `message_size` are split in several parts.
     pub message_size: MaxMessageSize,
+    /// Codecs the participant may compress its messages with, in order
+    /// of preference. The first one the coordinator accepts is used.
+    pub compression: Vec<Codec>,
+    /// Lossy codec the trained models are encoded with.
+    pub update_codec: UpdateCodec,
+    /// Whether the full trained models or their deltas against the
+    /// latest global model are sent.
+    pub update_kind: UpdateKind,
+    /// How message parts that couldn't be sent are retried.
+    pub retry_policy: RetryPolicy,
+    /// How the parts of a message that is split are uploaded.
+    pub upload_policy: UploadPolicy,
+    /// Local differential privacy the participant applies to its
+    /// models, if any.
+    pub privacy: Option<PrivacyParams>,
+    /// Error the update codec introduced in the last sent model. It is
+    /// added to the next model, so that the dropped values are
+    /// eventually sent (error feedback).
+    pub error_feedback: Vec<f64>,
     /// Current round parameters
     pub round_params: RoundParameters,
 }
@@ -95,6 +114,11 @@ fn dummy_round_parameters() -> RoundParameters {
             model_type: mask::ModelType::M3,
         }
         .into(),
+        model_schema: None,
+        codecs: Vec::new(),
+        model_version: 0,
+        privacy: None,
+        max_chunk_id: u16::MAX,
     }
 }
 #[cfg(not(feature = "secure"))]
@@ -105,8 +129,13 @@ fn dummy_round_parameters() -> RoundParameters {
         model_config: ModelConfig {
             data_type: model::DataType::F32,
         },
+        model_schema: None,
         per_round_participants: 0,
         training_rounds: 0,
+        codecs: Vec::new(),
+        model_version: 0,
+        privacy: None,
+        max_chunk_id: u16::MAX,
     }
 }
 
@@ -116,6 +145,13 @@ impl SharedState {
             keys: settings.keys,
             scalar: settings.scalar,
             message_size: settings.max_message_size,
+            compression: settings.compression,
+            update_codec: settings.update_codec,
+            update_kind: settings.update_kind,
+            retry_policy: settings.retry_policy,
+            upload_policy: settings.upload_policy,
+            privacy: settings.privacy,
+            error_feedback: Vec::new(),
             round_params: dummy_round_parameters(),
         }
     }
@@ -179,13 +215,10 @@ where
             RoundFreshness::Unknown => TransitionOutcome::Pending(self.into()),
             RoundFreshness::Outdated => {
                 info!("Started new round: Updating parameters & resetting client state.");
-                self.io.notify_new_round();
                 TransitionOutcome::Complete(
-                    Phase::<NewRound>::new(
-                        State::new(self.state.shared, Box::new(NewRound)),
-                        self.io,
-                    )
-                    .into(),
+                    State::new(self.state.shared, Box::new(NewRound))
+                        .into_phase(self.io)
+                        .into(),
                 )
             }
             RoundFreshness::Fresh => {
@@ -196,11 +229,13 @@ where
     }
 
     /// Check whether the coordinator has published new round parameters. In other
-    /// words, this checks whether a new round has started.
+    /// words, this checks whether a new round has started, which also tells whether a
+    /// new global model has been aggregated.
     async fn check_round_freshness(&mut self) -> RoundFreshness {
         match self.io.get_round_params().await {
             Err(e) => {
                 debug!("failed to fetch round parameters {:?}.", e);
+                self.io.notify(Event::Error(ErrorKind::RoundParams));
                 RoundFreshness::Unknown
             }
             Ok(params) => {
@@ -209,6 +244,10 @@ where
                     RoundFreshness::Fresh
                 } else {
                     debug!("fetched fresh round parameters.");
+                    if params.model_version > self.state.shared.round_params.model_version {
+                        self.io
+                            .notify(Event::GlobalModelAvailable(params.model_version));
+                    }
                     self.state.shared.round_params = params;
                     RoundFreshness::Outdated
                 }
@@ -238,8 +277,14 @@ impl<P> Phase<P> {
     /// Instantiate a message encoder for the given payload.
     ///
     /// The encoder takes care of converting the given `payload` into one or several
-    /// signed and encrypted PET messages.
+    /// signed, compressed and encrypted PET messages.
     pub fn message_encoder(&self, payload: Payload) -> MessageEncoder {
+        let shared = &self.state.shared;
+        let compression = shared
+            .compression
+            .iter()
+            .copied()
+            .find(|codec| shared.round_params.codecs.contains(codec));
         MessageEncoder::new(
             self.state.shared.keys.clone(),
             payload,
@@ -249,6 +294,8 @@ impl<P> Phase<P> {
                 .message_size
                 .max_payload_size()
                 .unwrap_or(0),
+            shared.round_params.max_chunk_id,
+            compression,
         )
         // the encoder rejects Chunk payload, but in the state
         // machine, we never manually create such payloads so
@@ -262,12 +309,20 @@ impl<P> Phase<P> {
         LocalModelConfig {
             data_type: self.state.shared.round_params.mask_config.vect.data_type,
             len: 0,
+            schema: self.state.shared.round_params.model_schema.clone(),
         };
 
         #[cfg(not(feature = "secure"))]
         LocalModelConfig {
             data_type: self.state.shared.round_params.model_config.data_type,
-            len: 0,
+            len: self
+                .state
+                .shared
+                .round_params
+                .model_schema
+                .as_ref()
+                .map_or(0, ModelSchema::weights),
+            schema: self.state.shared.round_params.model_schema.clone(),
         }
     }
 
@@ -290,7 +345,7 @@ impl<P> Phase<P> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 /// The local model configuration of the model that is expected in the update phase.
 pub struct LocalModelConfig {
     /// The expected data type of the local model.
@@ -298,8 +353,11 @@ pub struct LocalModelConfig {
     // the scalar data type and the model data type are different. Therefore, we assume here
     // that the scalar data type is the same as the model data type.
     pub data_type: DataType,
-    /// The expected length of the local model.
+    /// The expected length of the local model, or `0` if it is unknown.
     pub len: usize,
+    /// The named tensors the local model consists of, if the models are structured. The tensors
+    /// are concatenated in this order to form the local model.
+    pub schema: Option<ModelSchema>,
 }
 
 #[derive(Error, Debug)]
@@ -340,3 +398,80 @@ where
         phase.state.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mockall::{predicate::eq, Sequence};
+    use mosaic_core::crypto::EncryptKeyPair;
+
+    use super::*;
+    use crate::state_machine::MockIO;
+
+    /// Creates an awaiting phase of a participant which knows the global model of version `2`.
+    fn awaiting_phase() -> (Phase<Awaiting>, RoundParameters) {
+        let mut shared = SharedState::new(PetSettings::new(SigningKeyPair::generate()));
+        shared.round_params.model_version = 2;
+        let round_params = shared.round_params.clone();
+        let mut io = MockIO::new();
+        io.expect_notify().return_const(());
+        let awaiting = State::new(Box::new(shared), Box::new(Awaiting)).into_phase(Box::new(io));
+        (awaiting, round_params)
+    }
+
+    #[tokio::test]
+    async fn test_new_round_events() {
+        // a round which starts with a new global model
+        let (mut awaiting, mut round_params) = awaiting_phase();
+        round_params.pk = EncryptKeyPair::generate().public;
+        round_params.model_version = 3;
+        awaiting._with_io_mock(move |io| {
+            let mut seq = Sequence::new();
+            io.expect_get_round_params()
+                .times(1)
+                .returning(move || Ok(round_params.clone()));
+            io.expect_notify()
+                .with(eq(Event::GlobalModelAvailable(3)))
+                .times(1)
+                .in_sequence(&mut seq)
+                .return_const(());
+            io.expect_notify()
+                .with(eq(Event::RoundStarted(4)))
+                .times(1)
+                .in_sequence(&mut seq)
+                .return_const(());
+        });
+        match awaiting.step().await {
+            TransitionOutcome::Complete(StateMachine::NewRound(_)) => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+
+        // a round which starts without a new global model
+        let (mut awaiting, mut round_params) = awaiting_phase();
+        round_params.pk = EncryptKeyPair::generate().public;
+        awaiting._with_io_mock(move |io| {
+            io.expect_get_round_params()
+                .times(1)
+                .returning(move || Ok(round_params.clone()));
+            io.expect_notify()
+                .with(eq(Event::RoundStarted(3)))
+                .times(1)
+                .return_const(());
+        });
+        match awaiting.step().await {
+            TransitionOutcome::Complete(StateMachine::NewRound(_)) => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+
+        // the round goes on
+        let (mut awaiting, round_params) = awaiting_phase();
+        awaiting._with_io_mock(move |io| {
+            io.expect_get_round_params()
+                .times(1)
+                .returning(move || Ok(round_params.clone()));
+        });
+        match awaiting.step().await {
+            TransitionOutcome::Pending(StateMachine::Awaiting(_)) => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+}
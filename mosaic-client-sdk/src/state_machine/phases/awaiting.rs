@@ -2,7 +2,10 @@ use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
-use crate::state_machine::{IntoPhase, Phase, PhaseIo, State, Step, TransitionOutcome};
+use crate::{
+    state_machine::{IntoPhase, Phase, PhaseIo, State, Step, TransitionOutcome},
+    Event,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Awaiting;
@@ -17,7 +20,7 @@ impl Step for Phase<Awaiting> {
 impl IntoPhase<Awaiting> for State<Awaiting> {
     fn into_phase(self, mut io: PhaseIo) -> Phase<Awaiting> {
         info!("Client is waiting for next task.");
-        io.notify_idle();
+        io.notify(Event::Idle);
         Phase::<_>::new(self, io)
     }
 }
@@ -3,15 +3,18 @@ use serde::{Deserialize, Serialize};
 use tracing::info;
 use mosaic_core::crypto::{ByteObject, Signature};
 
-use crate::state_machine::{
-    Awaiting,
-    IntoPhase,
-    Phase,
-    PhaseIo,
-    State,
-    Step,
-    TransitionOutcome,
-    Update,
+use crate::{
+    state_machine::{
+        Awaiting,
+        IntoPhase,
+        Phase,
+        PhaseIo,
+        State,
+        Step,
+        TransitionOutcome,
+        Update,
+    },
+    Event,
 };
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,7 +22,10 @@ pub struct NewRound;
 
 impl IntoPhase<NewRound> for State<NewRound> {
     fn into_phase(self, mut io: PhaseIo) -> Phase<NewRound> {
-        io.notify_new_round();
+        // the round produces the next version of the global model
+        io.notify(Event::RoundStarted(
+            self.shared.round_params.model_version + 1,
+        ));
         Phase::<_>::new(self, io)
     }
 }
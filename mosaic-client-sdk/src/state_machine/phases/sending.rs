@@ -1,7 +1,12 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime},
+};
+
 use async_trait::async_trait;
 use paste::paste;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
 use crate::{
     state_machine::{
@@ -10,17 +15,79 @@ use crate::{
         Phase,
         PhaseIo,
         Progress,
+        SendError,
         State,
         Step,
         TransitionOutcome,
         IO,
     },
+    ErrorKind,
+    Event,
     MessageEncoder,
 };
 
+/// The factor the parts of a message may shrink by, compared to the maximum message size.
+const MAX_PART_SHRINK: usize = 16;
+
+/// Adapts the size of the parts of a message to the observed throughput and failures.
+///
+/// The parts start at the maximum size. Their size is halved whenever some parts fail to be
+/// sent, and doubled again after parts were all sent, unless the throughput dropped compared to
+/// the previous parts, in which case they are halved as well. The encoder still makes the parts
+/// large enough for the message to fit in the chunk IDs the coordinator accepts.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct PartSizer {
+    /// The current payload size of the parts.
+    size: usize,
+    /// The minimum payload size of the parts.
+    min: usize,
+    /// The maximum payload size of the parts.
+    max: usize,
+    /// The throughput of the last parts which were all sent, in bytes per second.
+    throughput: Option<f64>,
+}
+
+impl PartSizer {
+    fn new(max: usize) -> Self {
+        Self {
+            size: max,
+            min: max / MAX_PART_SHRINK,
+            max,
+            throughput: None,
+        }
+    }
+
+    /// Records the outcome of sending parts of `bytes` bytes in total, which took `elapsed`.
+    fn record(&mut self, bytes: usize, elapsed: Duration, failed: bool) {
+        if failed {
+            self.size = (self.size / 2).max(self.min);
+            self.throughput = None;
+            return;
+        }
+        let throughput = bytes as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+        self.size = match self.throughput {
+            // a drop below 80% isn't measurement noise anymore
+            Some(previous) if throughput < 0.8 * previous => (self.size / 2).max(self.min),
+            _ => (self.size * 2).min(self.max),
+        };
+        self.throughput = Some(throughput);
+    }
+}
+
 /// Implements the `SendingSum`, `SendingUpdate` and `SendingSum2` phases and transitions.
+///
+/// The events emitted once the message has been sent, once it has been rejected and once
+/// sending it failed for good are built by `$sent`, `$rejected` and `$failed`.
 macro_rules! impl_sending {
-    ($Phase: ty, $Next: ty, $phase: expr, $next: expr) => {
+    (
+        $Phase: ty,
+        $Next: ty,
+        $phase: expr,
+        $next: expr,
+        $sent: expr,
+        $rejected: expr,
+        $failed: expr
+    ) => {
         paste! {
             #[doc = "The state of the " $phase " sending phase."]
             #[derive(Serialize, Deserialize, Debug)]
@@ -28,8 +95,22 @@ macro_rules! impl_sending {
                 /// The message to send.
                 message: MessageEncoder,
 
-                /// Chunk that couldn't be sent and should be tried again.
-                failed: Option<Vec<u8>>,
+                /// Parts of the message which haven't been acknowledged by the coordinator yet,
+                /// by ID. They are sent again after a failure or a restart, whereas the parts
+                /// which have been acknowledged are dropped.
+                pending: BTreeMap<u16, Vec<u8>>,
+
+                /// ID of the next part of the message.
+                next_id: u16,
+
+                /// Number of failed attempts to send the `pending` parts.
+                attempts: u32,
+
+                /// Time before which the `pending` parts aren't tried again.
+                retry_at: Option<SystemTime>,
+
+                /// Size of the parts of a message which is split, if it adapts to the network.
+                sizer: Option<PartSizer>,
 
                 /// State of the phase to transition to, after this one completes.
                 next: $Next,
@@ -38,9 +119,14 @@ macro_rules! impl_sending {
             impl [<Sending $Phase>] {
                 #[doc = "Creates a new " $phase " sending state."]
                 pub fn new(message: MessageEncoder, next: $Next) -> Self {
+                    let sizer = message.payload_size().map(PartSizer::new);
                     Self {
                         message,
-                        failed: None,
+                        pending: BTreeMap::new(),
+                        next_id: 0,
+                        attempts: 0,
+                        retry_at: None,
+                        sizer,
                         next,
                     }
                 }
@@ -59,6 +145,7 @@ macro_rules! impl_sending {
                     self = try_progress!(self.send_next().await);
 
                     debug!("Done sending {} message, going to {} phase.", $phase, $next);
+                    self.io.notify($sent);
                     let phase: Phase<$Next> = self.into();
                     TransitionOutcome::Complete(phase.into())
                 }
@@ -72,46 +159,455 @@ macro_rules! impl_sending {
             }
 
             impl Phase<[<Sending $Phase>]> {
-                #[doc = "Tries to send a " $phase " message and reports back on the progress made."]
-                async fn try_send(mut self, data: Vec<u8>) -> Progress<[<Sending $Phase>]> {
-                    if let Err(e) = self.io.send_message(data.clone()).await {
-                        error!("Failed to send {} message: {:?}", $phase, e);
-                        self.state.private.failed = Some(data);
-                        Progress::Stuck(self)
+                #[doc =
+                    "Tries to send the pending parts of the " $phase " message concurrently and "
+                    "reports back on the progress made.\n"
+                    "\n"
+                    "The parts which couldn't be sent are scheduled to be retried according to the "
+                    "retry policy, or given up on once the policy is exhausted, in which case the "
+                    "participant waits for the next round."
+                ]
+                async fn try_send(mut self) -> Progress<[<Sending $Phase>]> {
+                    let pk = self.state.shared.round_params.pk;
+                    let (ids, mut msgs): (Vec<u16>, Vec<Vec<u8>>) = self
+                        .state
+                        .private
+                        .pending
+                        .iter()
+                        .map(|(id, data)| (*id, pk.encrypt(data.as_slice())))
+                        .unzip();
+                    let bytes = msgs.iter().map(Vec::len).sum();
+
+                    let started = Instant::now();
+                    let results = if let [msg] = &mut msgs[..] {
+                        let result = self.io.send_message(std::mem::take(msg)).await;
+                        vec![result]
                     } else {
-                        Progress::Updated(self.into())
+                        let max_in_flight = self.state.shared.upload_policy.max_in_flight;
+                        self.io.send_messages(msgs, max_in_flight).await
+                    };
+                    let elapsed = started.elapsed();
+
+                    let mut failure = None;
+                    for (id, result) in ids.into_iter().zip(results) {
+                        match result {
+                            Ok(()) => {
+                                self.state.private.pending.remove(&id);
+                            }
+                            Err(SendError::Rejected(reason)) => {
+                                warn!(
+                                    "Coordinator rejected {} message: {}, going to awaiting phase.",
+                                    $phase,
+                                    reason
+                                );
+                                self.io.notify($rejected(reason));
+                                let phase: Phase<Awaiting> =
+                                    State::new(self.state.shared, Box::new(Awaiting)).into_phase(self.io);
+                                return Progress::Updated(phase.into());
+                            }
+                            Err(SendError::Failed(e)) => failure = Some(e),
+                        }
+                    }
+                    if let Some(sizer) = self.state.private.sizer.as_mut() {
+                        sizer.record(bytes, elapsed, failure.is_some());
+                    }
+
+                    match failure {
+                        None => {
+                            self.state.private.attempts = 0;
+                            self.state.private.retry_at = None;
+                            Progress::Updated(self.into())
+                        }
+                        Some(e) => {
+                            error!(
+                                "Failed to send {} of the {} message parts: {:?}",
+                                self.state.private.pending.len(),
+                                $phase,
+                                e
+                            );
+                            let policy = self.state.shared.retry_policy;
+                            let private = &mut self.state.private;
+                            private.attempts += 1;
+                            // a retry beyond the representable time would never happen
+                            let retry_at = policy
+                                .should_retry(private.attempts)
+                                .then(|| {
+                                    SystemTime::now().checked_add(policy.delay(private.attempts))
+                                })
+                                .flatten();
+                            if retry_at.is_none() {
+                                warn!(
+                                    "Giving up on {} message after {} attempts, going to awaiting phase.",
+                                    $phase,
+                                    private.attempts
+                                );
+                                self.io.notify($failed);
+                                let phase: Phase<Awaiting> =
+                                    State::new(self.state.shared, Box::new(Awaiting)).into_phase(self.io);
+                                return Progress::Updated(phase.into());
+                            }
+                            private.retry_at = retry_at;
+                            Progress::Stuck(self)
+                        }
                     }
                 }
 
                 #[doc =
-                    "Sends the next " $phase " message and reports back on the progress made.\n"
+                    "Sends the next parts of the " $phase " message and reports back on the "
+                    "progress made.\n"
                     "\n"
-                    "Retries to send a previously failed message. Otherwise, tries to send the "
-                    "next message."
+                    "Retries to send the parts which previously failed once the backoff elapsed, "
+                    "together with new parts, up to the maximum number of parts in flight."
                 ]
                 async fn send_next(mut self) -> Progress<[<Sending $Phase>]> {
-                    if let Some(data) = self.state.private.failed.take() {
+                    let retry_at = self.state.private.retry_at;
+                    if retry_at.map_or(false, |retry_at| SystemTime::now() < retry_at) {
+                        debug!("Backing off before retrying to send {} message", $phase);
+                        return Progress::Stuck(self);
+                    }
+
+                    let max_in_flight = self.state.shared.upload_policy.max_in_flight.max(1);
+                    let adaptive = self.state.shared.upload_policy.adaptive;
+                    let private = &mut self.state.private;
+                    if !private.pending.is_empty() {
                         debug!(
-                            "Retrying to send {} message that couldn't be sent previously",
+                            "Retrying to send {} {} message parts that couldn't be sent previously",
+                            private.pending.len(),
                             $phase
                         );
-                        self.try_send(data).await
-                    } else {
-                        match self.state.private.message.next() {
+                    }
+                    while private.pending.len() < max_in_flight {
+                        if let (true, Some(sizer)) = (adaptive, private.sizer) {
+                            private.message.set_payload_size(sizer.size);
+                        }
+                        match private.message.next() {
                             Some(data) => {
-                                let data = self.state.shared.round_params.pk.encrypt(data.as_slice());
-                                self.try_send(data).await
-                            }
-                            None => {
-                                debug!("Nothing left to send.");
-                                Progress::Continue(self)
+                                private.pending.insert(private.next_id, data);
+                                private.next_id = private.next_id.wrapping_add(1);
                             }
+                            None => break,
                         }
                     }
+
+                    if private.pending.is_empty() {
+                        debug!("Nothing left to send.");
+                        Progress::Continue(self)
+                    } else {
+                        self.try_send().await
+                    }
                 }
             }
         }
     }
 }
 
-impl_sending!(Update, Awaiting, "update", "awaiting");
+impl_sending!(
+    Update,
+    Awaiting,
+    "update",
+    "awaiting",
+    Event::UpdateSent,
+    Event::UpdateRejected,
+    Event::Error(ErrorKind::SendUpdate)
+);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use mockall::predicate::eq;
+    use mosaic_core::{
+        crypto::{
+            ByteObject,
+            EncryptKeyPair,
+            PublicEncryptKey,
+            SecretEncryptKey,
+            Signature,
+            SigningKeyPair,
+        },
+        message::{Message, Payload, Sum},
+    };
+
+    use super::*;
+    use crate::{
+        settings::{PetSettings, RetryPolicy},
+        state_machine::{phase::SendMessageError, MockIO, SharedState, StateMachine},
+    };
+
+    #[tokio::test]
+    async fn test_rejected_update_is_not_retried() {
+        let keys = SigningKeyPair::generate();
+        let mut shared = SharedState::new(PetSettings::new(keys.clone()));
+        shared.round_params.pk = EncryptKeyPair::generate().public;
+        let payload = Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        });
+        let message =
+            MessageEncoder::new(keys, payload, shared.round_params.pk, 0, u16::MAX, None).unwrap();
+
+        let mut io = MockIO::new();
+        io.expect_send_message()
+            .times(1)
+            .returning(|_| Err(SendError::Rejected("too late".to_string())));
+        io.expect_notify()
+            .with(eq(Event::UpdateRejected("too late".to_string())))
+            .times(1)
+            .return_const(());
+        io.expect_notify()
+            .with(eq(Event::Idle))
+            .times(1)
+            .return_const(());
+        let sending = State::new(
+            Box::new(shared),
+            Box::new(SendingUpdate::new(message, Awaiting)),
+        )
+        .into_phase(Box::new(io));
+
+        match <Phase<SendingUpdate> as Step>::step(sending).await {
+            TransitionOutcome::Complete(StateMachine::Awaiting(_)) => {}
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    /// Gets the chunk IDs of the encrypted message parts.
+    fn chunk_ids(msgs: &[Vec<u8>], pk: &PublicEncryptKey, sk: &SecretEncryptKey) -> Vec<u16> {
+        msgs.iter()
+            .map(|msg| {
+                let msg = sk.decrypt(msg, pk).unwrap();
+                match Message::from_byte_slice(&msg).unwrap().payload {
+                    Payload::Chunk(chunk) => chunk.id,
+                    _ => panic!("expected a chunk"),
+                }
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_only_failed_parts_are_resent() {
+        let keys = SigningKeyPair::generate();
+        let coordinator_keys = EncryptKeyPair::generate();
+        let mut shared = SharedState::new(PetSettings::new(keys.clone()));
+        shared.round_params.pk = coordinator_keys.public;
+        shared.retry_policy = RetryPolicy::unlimited();
+        let payload = Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        });
+        // the sum payload of 96 bytes is split in 8 parts of 12 bytes
+        let message =
+            MessageEncoder::new(keys, payload, shared.round_params.pk, 20, u16::MAX, None).unwrap();
+        let mut sending = State::new(
+            Box::new(shared),
+            Box::new(SendingUpdate::new(message, Awaiting)),
+        )
+        .into_phase(Box::new(MockIO::new()));
+
+        let (pk, sk) = (coordinator_keys.public, coordinator_keys.secret.clone());
+        sending._with_io_mock(move |io| {
+            io.expect_send_messages()
+                .withf(|msgs, max_in_flight| msgs.len() == 4 && *max_in_flight == 4)
+                .times(1)
+                .returning(move |msgs, _| {
+                    assert_eq!(chunk_ids(&msgs, &pk, &sk), vec![0, 1, 2, 3]);
+                    let failed = SendError::Failed(Box::new(SendMessageError));
+                    vec![Ok(()), Err(failed), Ok(()), Ok(())]
+                });
+        });
+        let sending = match sending.send_next().await {
+            Progress::Stuck(sending) => sending,
+            progress => panic!("unexpected progress: {:?}", progress),
+        };
+        let private = &sending.state.private;
+        assert_eq!(private.pending.keys().copied().collect::<Vec<_>>(), vec![1]);
+        assert_eq!(private.sizer.unwrap().size, 10);
+
+        // the pending parts survive a restart
+        let state: SendingUpdate =
+            bincode::deserialize(&bincode::serialize(&sending.state.private).unwrap()).unwrap();
+        let mut sending =
+            State::new(sending.state.shared, Box::new(state)).into_phase(Box::new(MockIO::new()));
+        let (pk, sk) = (coordinator_keys.public, coordinator_keys.secret);
+        sending._with_io_mock(move |io| {
+            io.expect_send_messages()
+                .times(1)
+                .returning(move |msgs, _| {
+                    // the failed part is resent with the next parts, which are smaller
+                    assert_eq!(chunk_ids(&msgs, &pk, &sk), vec![1, 4, 5, 6]);
+                    msgs.iter().map(|_| Ok(())).collect()
+                });
+        });
+        assert!(matches!(
+            sending.send_next().await,
+            Progress::Updated(StateMachine::SendingUpdate(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parts_fit_in_chunk_ids() {
+        let keys = SigningKeyPair::generate();
+        let coordinator_keys = EncryptKeyPair::generate();
+        let mut shared = SharedState::new(PetSettings::new(keys.clone()));
+        shared.round_params.pk = coordinator_keys.public;
+        shared.round_params.max_chunk_id = 11;
+        shared.retry_policy = RetryPolicy::unlimited();
+        let payload = Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        });
+        // the sum payload of 96 bytes is split in 8 parts of 12 bytes, and in no more than 12
+        // parts however small the parts get
+        let message =
+            MessageEncoder::new(keys, payload, shared.round_params.pk, 20, 11, None).unwrap();
+        let mut sending = State::new(
+            Box::new(shared),
+            Box::new(SendingUpdate::new(message, Awaiting)),
+        )
+        .into_phase(Box::new(MockIO::new()));
+
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        for round in 0.. {
+            // the first pending part fails in the first rounds, which shrinks the next parts
+            let fail = round < 6;
+            let pk = coordinator_keys.public;
+            let sk = coordinator_keys.secret.clone();
+            let sk_one = sk.clone();
+            let sent = sent.clone();
+            sending._with_io_mock(move |io| {
+                let sent_one = sent.clone();
+                io.expect_send_message().returning(move |msg| {
+                    let ids = chunk_ids(&[msg], &pk, &sk_one);
+                    sent_one.lock().unwrap().extend(ids);
+                    if fail {
+                        Err(SendError::Failed(Box::new(SendMessageError)))
+                    } else {
+                        Ok(())
+                    }
+                });
+                io.expect_send_messages().returning(move |msgs, _| {
+                    sent.lock().unwrap().extend(chunk_ids(&msgs, &pk, &sk));
+                    (0..msgs.len())
+                        .map(|i| match i {
+                            0 if fail => Err(SendError::Failed(Box::new(SendMessageError))),
+                            _ => Ok(()),
+                        })
+                        .collect()
+                });
+            });
+            sending = match sending.send_next().await {
+                Progress::Stuck(mut sending) => {
+                    sending.state.private.retry_at = None;
+                    sending
+                }
+                Progress::Updated(StateMachine::SendingUpdate(sending)) => sending,
+                Progress::Continue(_) => break,
+                progress => panic!("unexpected progress: {:?}", progress),
+            };
+        }
+
+        let mut sent = sent.lock().unwrap().clone();
+        sent.sort_unstable();
+        sent.dedup();
+        assert_eq!(sent.last(), Some(&11));
+        assert!(sent.len() <= 12);
+    }
+
+    /// Creates the phase of sending an update message in a single part, which fails to be sent
+    /// with the mocked IO.
+    fn failing_sending_phase(retry_policy: RetryPolicy) -> Phase<SendingUpdate> {
+        let keys = SigningKeyPair::generate();
+        let mut shared = SharedState::new(PetSettings::new(keys.clone()));
+        shared.round_params.pk = EncryptKeyPair::generate().public;
+        shared.retry_policy = retry_policy;
+        let payload = Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        });
+        let message =
+            MessageEncoder::new(keys, payload, shared.round_params.pk, 0, u16::MAX, None).unwrap();
+
+        let mut io = MockIO::new();
+        io.expect_send_message()
+            .returning(|_| Err(SendError::Failed(Box::new(SendMessageError))));
+        State::new(
+            Box::new(shared),
+            Box::new(SendingUpdate::new(message, Awaiting)),
+        )
+        .into_phase(Box::new(io))
+    }
+
+    /// Checks that sending the message is given up on.
+    fn assert_gives_up(progress: Progress<SendingUpdate>) {
+        match progress {
+            Progress::Updated(StateMachine::Awaiting(_)) => {}
+            progress => panic!("unexpected progress: {:?}", progress),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_update_is_given_up() {
+        let sending = failing_sending_phase(RetryPolicy {
+            max_attempts: Some(2),
+            ..RetryPolicy::unlimited()
+        });
+        let mut sending = match sending.send_next().await {
+            Progress::Stuck(sending) => sending,
+            progress => panic!("unexpected progress: {:?}", progress),
+        };
+        assert_eq!(sending.state.private.attempts, 1);
+
+        sending._with_io_mock(|io| {
+            io.expect_send_message()
+                .times(1)
+                .returning(|_| Err(SendError::Failed(Box::new(SendMessageError))));
+            io.expect_notify()
+                .with(eq(Event::Error(ErrorKind::SendUpdate)))
+                .times(1)
+                .return_const(());
+            io.expect_notify()
+                .with(eq(Event::Idle))
+                .times(1)
+                .return_const(());
+        });
+        assert_gives_up(sending.send_next().await);
+
+        // a retry which can't be scheduled is given up on as well
+        let mut sending = failing_sending_phase(RetryPolicy {
+            initial_delay: Duration::from_secs(u64::MAX / 2),
+            max_delay: Duration::from_secs(u64::MAX / 2),
+            ..RetryPolicy::unlimited()
+        });
+        sending._with_io_mock(|io| {
+            io.expect_send_message()
+                .times(1)
+                .returning(|_| Err(SendError::Failed(Box::new(SendMessageError))));
+            io.expect_notify().return_const(());
+        });
+        assert_gives_up(sending.send_next().await);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_survives_restart() {
+        let policy = RetryPolicy {
+            initial_delay: Duration::from_secs(3600),
+            max_delay: Duration::from_secs(3600),
+            ..RetryPolicy::unlimited()
+        };
+        let sending = match failing_sending_phase(policy).send_next().await {
+            Progress::Stuck(sending) => sending,
+            progress => panic!("unexpected progress: {:?}", progress),
+        };
+        let retry_at = sending.state.private.retry_at.unwrap();
+        assert!(retry_at > SystemTime::now() + Duration::from_secs(3500));
+
+        let state: SendingUpdate =
+            bincode::deserialize(&bincode::serialize(&sending.state.private).unwrap()).unwrap();
+        assert_eq!(state.attempts, 1);
+        assert_eq!(state.retry_at, Some(retry_at));
+
+        // nothing is sent before the backoff elapsed
+        let sending =
+            State::new(sending.state.shared, Box::new(state)).into_phase(Box::new(MockIO::new()));
+        assert!(matches!(sending.send_next().await, Progress::Stuck(_)));
+    }
+}
@@ -2,13 +2,17 @@ use std::ops::Deref;
 
 use async_trait::async_trait;
 use derive_more::From;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
 use tracing::{debug, warn};
 
 use mosaic_core::{
     crypto::Signature,
-    message::Update as UpdateMessage,
-    model::{Model, ModelObject},
+    message::{Update as UpdateMessage, UpdateKind},
+    model::{
+        EncodedModelObject, FromPrimitives, IntoPrimitives, Model, ModelObject, PrivacyParams,
+    },
     ParticipantTaskSignature,
 };
 
@@ -20,7 +24,7 @@ use crate::{
         Awaiting, IntoPhase, Phase, PhaseIo, Progress, SendingUpdate, State, Step,
         TransitionOutcome, IO,
     },
-    MessageEncoder,
+    ErrorKind, Event, MessageEncoder,
 };
 
 #[derive(From)]
@@ -77,6 +81,9 @@ impl<'de> serde::de::Deserialize<'de> for LocalModel {
 pub struct Update {
     pub update_signature: ParticipantTaskSignature,
     pub model: Option<LocalModel>,
+    /// The version of the global model the delta is computed against and the model itself, if
+    /// deltas are sent.
+    pub base_model: Option<(u32, Model)>,
 }
 
 impl Update {
@@ -85,6 +92,7 @@ impl Update {
         Update {
             update_signature,
             model: None,
+            base_model: None,
         }
     }
 
@@ -135,9 +143,9 @@ impl Update {
 
 impl IntoPhase<Update> for State<Update> {
     fn into_phase(self, mut io: PhaseIo) -> Phase<Update> {
-        io.notify_update();
+        io.notify(Event::Update);
         if !self.private.has_loaded_model() {
-            io.notify_load_model();
+            io.notify(Event::LoadModel);
         }
         Phase::<_>::new(self, io)
     }
@@ -146,7 +154,10 @@ impl IntoPhase<Update> for State<Update> {
 #[async_trait]
 impl Step for Phase<Update> {
     async fn step(mut self) -> TransitionOutcome {
+        self = try_progress!(self.check_privacy());
         self = try_progress!(self.load_model().await);
+        self = try_progress!(self.fetch_base_model().await);
+        self = try_progress!(self.check_delta());
 
         #[cfg(features = "secure")]
         {
@@ -178,6 +189,18 @@ impl From<Phase<Update>> for Phase<Awaiting> {
 }
 
 impl Phase<Update> {
+    /// Gives up on the round if it requires local differential privacy but the participant
+    /// doesn't privatize its models.
+    fn check_privacy(mut self) -> Progress<Update> {
+        if self.state.shared.round_params.privacy.is_none() || self.state.shared.privacy.is_some() {
+            return Progress::Continue(self);
+        }
+        warn!("round requires local differential privacy, which is not configured, skipping it");
+        self.io.notify(Event::Error(ErrorKind::Privacy));
+        let awaiting: Phase<Awaiting> = self.into();
+        Progress::Updated(awaiting.into())
+    }
+
     #[cfg(feature = "secure")]
     pub(crate) async fn fetch_sum_dict(mut self) -> Progress<Update> {
         if self.state.private.has_fetched_sum_dict() {
@@ -227,11 +250,73 @@ impl Phase<Update> {
             }
             Err(e) => {
                 warn!("failed to load model: {:?}", e);
+                self.io.notify(Event::Error(ErrorKind::LoadModel));
+                Progress::Stuck(self)
+            }
+        }
+    }
+
+    /// Fetches the global model the delta is computed against. Nothing is
+    /// fetched if full models are sent. Privatized models are always sent as
+    /// deltas.
+    ///
+    /// Only the global model of the version in the round parameters is
+    /// fetched, since the delta is tagged with the version of its base model.
+    /// Before the first aggregation, this is the initial model, if any.
+    pub(crate) async fn fetch_base_model(mut self) -> Progress<Update> {
+        let version = self.state.shared.round_params.model_version;
+        if (self.state.shared.update_kind == UpdateKind::Full && self.privacy().is_none())
+            || self.state.private.base_model.is_some()
+        {
+            return Progress::Continue(self);
+        }
+
+        debug!("fetching base model of version {}", version);
+        match self.io.get_model_of_version(version).await {
+            Ok(Some(model)) => {
+                self.state.private.base_model = Some((version, model));
+                Progress::Updated(self.into())
+            }
+            Ok(None) if version == 0 => {
+                debug!("there is no initial model to compute a delta against");
+                Progress::Continue(self)
+            }
+            Ok(None) => {
+                // the global model may have been replaced, in which case the round is over
+                debug!("base model is not available");
+                Progress::Stuck(self)
+            }
+            Err(e) => {
+                warn!("failed to fetch base model: {:?}", e);
+                self.io.notify(Event::Error(ErrorKind::GlobalModel));
                 Progress::Stuck(self)
             }
         }
     }
 
+    /// Gives up on the round if the model would be privatized but can't be sent as a delta.
+    ///
+    /// The clipping norm bounds the norm of a delta: clipping a full model to it would destroy
+    /// the model, hence full models are never privatized. The aggregator requires an initial
+    /// model for tasks which require privacy, so that there always is a base model to fetch.
+    fn check_delta(mut self) -> Progress<Update> {
+        if self.privacy().is_none() || self.has_base_model() {
+            return Progress::Continue(self);
+        }
+        warn!("privatized models are sent as deltas, but there is no base model, skipping round");
+        self.io.notify(Event::Error(ErrorKind::Privacy));
+        let awaiting: Phase<Awaiting> = self.into();
+        Progress::Updated(awaiting.into())
+    }
+
+    /// Checks whether a delta can be computed against the base model.
+    fn has_base_model(&self) -> bool {
+        match (&self.state.private.base_model, &self.state.private.model) {
+            (Some((_, base_model)), Some(model)) => base_model.len() == model.as_ref().len(),
+            _ => false,
+        }
+    }
+
     #[cfg(feature = "secure")]
     /// Generate a mask seed and mask a local model.
     pub(crate) fn mask_model(mut self) -> Progress<Update> {
@@ -242,9 +327,9 @@ impl Phase<Update> {
         let config = self.state.shared.round_params.mask_config;
         let masker = Masker::new(config);
         // UNWRAP_SAFE: the model is set, per the `has_masked_model()` check above
-        let model = self.state.private.model.take().unwrap();
+        let model = self.state.private.model.take().unwrap().as_ref().clone();
         let scalar = self.state.shared.scalar.clone();
-        self.state.private.mask = Some(masker.mask(scalar, model.as_ref()));
+        self.state.private.mask = Some(masker.mask(scalar, &model));
 
         Progress::Updated(self.into())
     }
@@ -272,18 +357,118 @@ impl Phase<Update> {
         Progress::Updated(self.into())
     }
 
+    /// Gets the local differential privacy the models are privatized with, i.e. the privacy of
+    /// the participant tightened to the requirement of the round.
+    fn privacy(&self) -> Option<PrivacyParams> {
+        let privacy = self.state.shared.privacy?;
+        Some(match self.state.shared.round_params.privacy {
+            Some(required) => privacy.tighten(&required),
+            None => privacy,
+        })
+    }
+
+    /// Clips the model and adds noise to it, if the participant privatizes its models. The noise
+    /// is drawn from a cryptographically secure generator seeded from the operating system.
+    fn privatize(&self, model: Model) -> Model {
+        let privacy = match self.privacy() {
+            Some(privacy) => privacy,
+            None => return model,
+        };
+        debug!("privatizing model with {:?}", privacy);
+        let mut values = IntoPrimitives::<f64>::to_primitives(&model)
+            .map(|value| value.unwrap_or(0.0))
+            .collect::<Vec<_>>();
+        privacy.privatize(&mut values, &mut ChaCha20Rng::from_entropy());
+        Model::from_primitives_bounded(values.into_iter())
+    }
+
+    /// Encodes the model with the update codec of the participant. The
+    /// error introduced by a lossy codec is kept and added to the next
+    /// model.
+    fn encode_model(&mut self, model: Model) -> EncodedModelObject {
+        let config = self.state.shared.round_params.model_config;
+        let shared = &mut self.state.shared;
+        if shared.update_codec.is_lossless() {
+            return ModelObject::new(model.0, config).into();
+        }
+
+        let mut values = IntoPrimitives::<f64>::to_primitives(&model)
+            .map(|value| value.unwrap_or(0.0))
+            .collect::<Vec<_>>();
+        let error_feedback = std::mem::take(&mut shared.error_feedback);
+        let corrected = !error_feedback.is_empty() && error_feedback.len() == values.len();
+        if corrected {
+            for (value, error) in values.iter_mut().zip(error_feedback) {
+                *value += error;
+            }
+        } else if !error_feedback.is_empty() {
+            warn!(
+                "dropping the error of the previous update, the model length changed from {} to {}",
+                error_feedback.len(),
+                values.len()
+            );
+        }
+
+        match shared
+            .update_codec
+            .encode(&values, config, &mut rand::thread_rng())
+        {
+            Some(model_object) => {
+                // UNWRAP_SAFE: the model object was just encoded
+                let sent = model_object.to_floats().unwrap();
+                shared.error_feedback = values.iter().zip(sent).map(|(v, s)| v - s).collect();
+                model_object.into()
+            }
+            // models which can't be encoded are sent unchanged, along with the error of the
+            // previous update, if any
+            None if corrected => {
+                let model = Model::from_primitives_bounded(values.into_iter());
+                ModelObject::new(model.0, config).into()
+            }
+            None => ModelObject::new(model.0, config).into(),
+        }
+    }
+
     /// Creates and encodes the update message from the update state.
     pub fn compose_message(&mut self) -> MessageEncoder {
         #[cfg(not(features = "secure"))]
         let model = self.state.private.model.take().unwrap().as_ref().clone();
 
-        let model_object = ModelObject::new(
-            model.0,
-            self.state.shared.round_params.model_config,
-        );
+        // a delta is only sent if it can be computed against the base model, and only deltas are
+        // privatized, see `check_delta()`
+        let (kind, model, privacy, base_model_version) = match self.state.private.base_model.take()
+        {
+            Some((version, base_model)) if base_model.len() == model.len() => {
+                let delta = model
+                    .into_iter()
+                    .zip(base_model)
+                    .map(|(w, b)| w - b)
+                    .collect();
+                // the privacy stage runs before the lossy encoding, which only post-processes
+                // its output
+                (
+                    UpdateKind::Delta,
+                    self.privatize(delta),
+                    self.privacy(),
+                    version,
+                )
+            }
+            _ => (
+                UpdateKind::Full,
+                model,
+                None,
+                self.state.shared.round_params.model_version,
+            ),
+        };
+        let model_object = self
+            .encode_model(model)
+            .with_schema(self.state.shared.round_params.model_schema.as_ref());
 
         let update = UpdateMessage {
             update_signature: self.state.private.update_signature,
+            kind,
+            base_model_version,
+            privacy,
             model_object,
         };
 
@@ -291,6 +476,9 @@ impl Phase<Update> {
         let update = UpdateMessage {
             sum_signature: self.state.private.sum_signature,
             update_signature: self.state.private.update_signature,
+            kind: UpdateKind::Full,
+            base_model_version: self.state.shared.round_params.model_version,
+            privacy: None,
             // UNWRAP_SAFE: the mask is set in `mask_model()` which is called before this method
             masked_model: self.state.private.mask.take().unwrap().1,
             // UNWRAP_SAFE: the dict is set in `build_seed_dict()` which is called before this method
@@ -299,3 +487,139 @@ impl Phase<Update> {
         self.message_encoder(update.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use mockall::predicate::eq;
+    use mosaic_core::{
+        crypto::{ByteObject, EncryptKeyPair, SigningKeyPair},
+        message::{Message, Payload},
+        model::EncodedModelObject,
+    };
+
+    use super::*;
+    use crate::{
+        settings::PetSettings,
+        state_machine::{MockIO, SharedState, StateMachine},
+    };
+
+    /// Creates an update phase of a participant which privatizes its model of norm `5`.
+    fn update_phase(base_model: Option<(u32, Model)>) -> Phase<Update> {
+        let mut shared = SharedState::new(PetSettings::new(SigningKeyPair::generate()));
+        shared.round_params.pk = EncryptKeyPair::generate().public;
+        shared.privacy = PrivacyParams::gaussian(0.5, 1e-5, 1.0);
+        let mut update = Update::new(Signature::zeroed());
+        update.model = Some(
+            Model::from_primitives(vec![3_f32, 4.0].into_iter())
+                .unwrap()
+                .into(),
+        );
+        update.base_model = base_model;
+
+        let mut io = MockIO::new();
+        io.expect_notify().return_const(());
+        State::new(Box::new(shared), Box::new(update)).into_phase(Box::new(io))
+    }
+
+    #[test]
+    fn test_full_model_is_not_privatized() {
+        // without a base model, the round is skipped
+        let mut update = update_phase(None);
+        update._with_io_mock(|io| {
+            io.expect_notify()
+                .with(eq(Event::Error(ErrorKind::Privacy)))
+                .times(1)
+                .return_const(());
+            io.expect_notify()
+                .with(eq(Event::Idle))
+                .times(1)
+                .return_const(());
+        });
+        match update.check_delta() {
+            Progress::Updated(StateMachine::Awaiting(_)) => {}
+            progress => panic!("unexpected progress: {:?}", progress),
+        }
+
+        // a full model which slips through is sent unchanged
+        let base_model = Model::from_primitives(vec![1_f32].into_iter()).unwrap();
+        let mut update = update_phase(Some((1, base_model)));
+        let message = update.compose_message().next().unwrap();
+        let update = match Message::from_byte_slice(&message).unwrap().payload {
+            Payload::Update(update) => update,
+            payload => panic!("unexpected payload: {:?}", payload),
+        };
+        assert_eq!(update.kind, UpdateKind::Full);
+        assert_eq!(update.privacy, None);
+        match update.model_object {
+            EncodedModelObject::Dense(object) => assert_eq!(
+                object.data,
+                Model::from_primitives(vec![3_f32, 4.0].into_iter())
+                    .unwrap()
+                    .0
+            ),
+            object => panic!("unexpected model object: {:?}", object),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delta_is_tagged_with_base_model_version() {
+        let base_model = Model::from_primitives(vec![1_f32, 1.0].into_iter()).unwrap();
+        let mut update = update_phase(None);
+        update.state.shared.round_params.model_version = 2;
+        update._with_io_mock(|io| {
+            let base_model = base_model.clone();
+            io.expect_get_model_of_version()
+                .with(eq(2))
+                .times(1)
+                .return_once(move |_| Ok(Some(base_model)));
+        });
+        let mut update = match update.fetch_base_model().await {
+            Progress::Updated(StateMachine::Update(update)) => update,
+            progress => panic!("unexpected progress: {:?}", progress),
+        };
+        assert_eq!(update.state.private.base_model, Some((2, base_model)));
+
+        // the round parameters may have moved on since the base model was fetched
+        update.state.shared.round_params.model_version = 3;
+        let message = update.compose_message().next().unwrap();
+        let update = match Message::from_byte_slice(&message).unwrap().payload {
+            Payload::Update(update) => update,
+            payload => panic!("unexpected payload: {:?}", payload),
+        };
+        assert_eq!(update.kind, UpdateKind::Delta);
+        assert_eq!(update.base_model_version, 2);
+    }
+
+    #[tokio::test]
+    async fn test_delta_against_initial_model() {
+        // before the first aggregation, deltas are computed against the initial model
+        let initial_model = Model::from_primitives(vec![1_f32, 1.0].into_iter()).unwrap();
+        let mut update = update_phase(None);
+        update._with_io_mock(|io| {
+            let initial_model = initial_model.clone();
+            io.expect_get_model_of_version()
+                .with(eq(0))
+                .times(1)
+                .return_once(move |_| Ok(Some(initial_model)));
+        });
+        let update = match update.fetch_base_model().await {
+            Progress::Updated(StateMachine::Update(update)) => update,
+            progress => panic!("unexpected progress: {:?}", progress),
+        };
+        assert_eq!(update.state.private.base_model, Some((0, initial_model)));
+        assert!(matches!(update.check_delta(), Progress::Continue(_)));
+
+        // without an initial model, there is nothing to fetch
+        let mut update = update_phase(None);
+        update._with_io_mock(|io| {
+            io.expect_get_model_of_version()
+                .with(eq(0))
+                .times(1)
+                .return_once(|_| Ok(None));
+        });
+        assert!(matches!(
+            update.fetch_base_model().await,
+            Progress::Continue(_)
+        ));
+    }
+}
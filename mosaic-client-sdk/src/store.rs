@@ -0,0 +1,211 @@
+//! File-backed model store.
+//!
+//! The [`FileStore`] loads the local model from a file that the participant writes outside of the
+//! SDK, e.g. a checkpoint of an on-device trainer. The file is memory-mapped and converted into a
+//! [`Model`] only when the state machine loads the model, so that the weights aren't held twice
+//! in memory.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use memmap2::Mmap;
+use thiserror::Error;
+
+use mosaic_core::model::{FormatError, Model, SchemaError};
+
+use crate::{LocalModelConfig, ModelStore};
+
+/// The format of a model file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModelFormat {
+    /// A NumPy `.npy` file of an array in row-major order, see [`Model::from_npy()`].
+    Npy,
+    /// A safetensors file, see [`Model::from_safetensors()`]. The tensors are joined in the
+    /// order of the model schema, if any.
+    Safetensors,
+    /// A raw buffer of little-endian weights, see [`Model::from_raw()`].
+    Raw,
+}
+
+/// Error returned when a [`FileStore`] fails to load the model.
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error("failed to map the model file: {0}")]
+    Io(#[from] io::Error),
+    #[error("failed to read the model file: {0}")]
+    Format(#[from] FormatError),
+    #[error("the model does not conform to the schema: {0}")]
+    Schema(#[from] SchemaError),
+    #[error("the model has {actual} weights but {expected} are expected")]
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+/// A [`ModelStore`] which loads the local model lazily from a file.
+///
+/// The weights are validated against the [`LocalModelConfig`] of the store: they must have its
+/// data type, respectively the data types of its schema, and its length, if it is known. If the
+/// file doesn't exist, the model is not yet available.
+///
+/// The file is read whenever the state machine loads the model and must not be modified while
+/// it is mapped. It should be replaced atomically instead, e.g. by writing the new model to a
+/// temporary file which is then renamed.
+///
+/// # Examples
+/// ```no_run
+/// # use mosaic_core::model::DataType;
+/// # use mosaic_client_sdk::{FileStore, LocalModelConfig, ModelFormat};
+/// let config = LocalModelConfig {
+///     data_type: DataType::F32,
+///     len: 1_000,
+///     schema: None,
+/// };
+/// let store = FileStore::new("checkpoint.npy", ModelFormat::Npy, config);
+/// ```
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    format: ModelFormat,
+    config: LocalModelConfig,
+}
+
+impl FileStore {
+    /// Creates a store for the model file at `path`.
+    pub fn new(path: impl Into<PathBuf>, format: ModelFormat, config: LocalModelConfig) -> Self {
+        Self {
+            path: path.into(),
+            format,
+            config,
+        }
+    }
+
+    /// Gets the path of the model file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the model from the file, if it exists.
+    fn read(&self) -> Result<Option<Model>, StoreError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        // SAFETY: the mapping is only read while the model is converted, and the file must not be
+        // modified in the meantime, as documented on the store
+        let bytes = unsafe { Mmap::map(&file)? };
+
+        // the data type and the length are checked against the header before the weights are
+        // converted, which is expensive for large models
+        let data_type = self.config.data_type;
+        let schema = self.config.schema.as_ref();
+        let len = match self.format {
+            ModelFormat::Npy => Model::npy_len(&bytes, data_type)?,
+            ModelFormat::Safetensors => Model::safetensors_len(&bytes, data_type, schema)?,
+            ModelFormat::Raw => bytes.len() / data_type.bytes_per_number(),
+        };
+        if let Some(schema) = schema {
+            schema.validate(len)?;
+        }
+        if self.config.len != 0 && self.config.len != len {
+            return Err(StoreError::LengthMismatch {
+                expected: self.config.len,
+                actual: len,
+            });
+        }
+
+        let model = match self.format {
+            ModelFormat::Npy => Model::from_npy(&bytes, data_type)?,
+            ModelFormat::Safetensors => Model::from_safetensors(&bytes, data_type, schema)?,
+            ModelFormat::Raw => Model::from_raw(&bytes, data_type)?,
+        };
+        Ok(Some(model))
+    }
+}
+
+#[async_trait]
+impl ModelStore for FileStore {
+    type Model = Model;
+    type Error = StoreError;
+
+    async fn load_model(&mut self) -> Result<Option<Self::Model>, Self::Error> {
+        self.read()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use mosaic_core::model::{DataType, FromPrimitives, ModelSchema, TensorSpec};
+
+    use super::*;
+
+    fn config(len: usize, schema: Option<ModelSchema>) -> LocalModelConfig {
+        LocalModelConfig {
+            data_type: DataType::F32,
+            len,
+            schema,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("mosaic-store-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let model = Model::from_primitives(vec![0.5_f32, -1.0, 2.0, 4.0].into_iter()).unwrap();
+        let schema = ModelSchema::new(vec![
+            TensorSpec {
+                name: "kernel".to_string(),
+                shape: vec![3],
+                data_type: DataType::F32,
+            },
+            TensorSpec {
+                name: "bias".to_string(),
+                shape: vec![],
+                data_type: DataType::F32,
+            },
+        ])
+        .unwrap();
+
+        let path = dir.join("model.npy");
+        let mut store = FileStore::new(&path, ModelFormat::Npy, config(4, None));
+        assert!(store.load_model().await.unwrap().is_none());
+        fs::write(&path, model.to_npy(DataType::F32).unwrap()).unwrap();
+        assert_eq!(store.load_model().await.unwrap().unwrap(), model);
+
+        let path = dir.join("model.safetensors");
+        let bytes = model.to_safetensors(DataType::F32, Some(&schema)).unwrap();
+        fs::write(&path, bytes).unwrap();
+        let mut store = FileStore::new(&path, ModelFormat::Safetensors, config(0, Some(schema)));
+        assert_eq!(store.load_model().await.unwrap().unwrap(), model);
+
+        let path = dir.join("model.bin");
+        fs::write(&path, model.to_raw(DataType::F32).unwrap()).unwrap();
+        let mut store = FileStore::new(&path, ModelFormat::Raw, config(5, None));
+        assert!(matches!(
+            store.load_model().await,
+            Err(StoreError::LengthMismatch {
+                expected: 5,
+                actual: 4
+            })
+        ));
+        let mut store = FileStore::new(
+            dir.join("model.npy"),
+            ModelFormat::Npy,
+            LocalModelConfig {
+                data_type: DataType::F64,
+                ..config(4, None)
+            },
+        );
+        assert!(matches!(
+            store.load_model().await,
+            Err(StoreError::Format(FormatError::DataTypeMismatch { .. }))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
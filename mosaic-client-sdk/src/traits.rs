@@ -1,29 +1,21 @@
 use async_trait::async_trait;
 
 use mosaic_core::{
-    common::RoundParameters, model::Model, SumDict, SumParticipantPublicKey, UpdateSeedDict,
+    common::{Capabilities, RoundParameters},
+    model::Model, SumDict, SumParticipantPublicKey, UpdateSeedDict,
 };
 
+use crate::Event;
+
 /// A trait used by the [`StateMachine`] to emit notifications upon
 /// certain events.
 ///
 /// [`StateMachine`]: crate::StateMachine
 pub trait Notify {
-    /// Emit a notification when a new round of federated learning
-    /// starts
-    fn new_round(&mut self) {}
-    /// Emit a notification when the participant has been selected for
-    /// the sum task
-    fn sum(&mut self) {}
-    /// Emit a notification when the participant has been selected for
-    /// the update task
-    fn update(&mut self) {}
-    /// Emit a notification when the participant is not selected for
-    /// any task and is waiting for another round to start
-    fn idle(&mut self) {}
-    /// Emit a notification when the participant should populate the
-    /// model store (see [`ModelStore`]).
-    fn load_model(&mut self) {}
+    /// Emit a notification of the given event. When the event is
+    /// [`Event::LoadModel`], the participant should populate the model
+    /// store (see [`ModelStore`]).
+    fn notify(&mut self, _event: Event) {}
 }
 
 /// A trait used by the [`StateMachine`] to load the model trained by
@@ -45,7 +37,11 @@ pub trait ModelStore {
 /// [`StateMachine`]: crate::StateMachine
 #[async_trait]
 pub trait MosaicClientTrait {
-    type Error: std::error::Error;
+    type Error: std::error::Error + Send;
+
+    /// Retrieve the protocol version and the features supported by
+    /// the aggregator.
+    async fn get_capabilities(&mut self) -> Result<Capabilities, Self::Error>;
 
     /// Retrieve the current round parameters
     async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error>;
@@ -63,6 +59,37 @@ pub trait MosaicClientTrait {
     /// Retrieve the current global model, if available.
     async fn get_model(&mut self) -> Result<Option<Model>, Self::Error>;
 
+    /// Retrieve the current global model, if available and of the
+    /// given version.
+    async fn get_model_of_version(&mut self, version: u32) -> Result<Option<Model>, Self::Error>;
+
     /// Send an encrypted and signed message to the aggregator.
     async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Self::Error>;
+
+    /// Send several encrypted and signed messages to the aggregator,
+    /// with up to `max_in_flight` of them being sent concurrently, and
+    /// return their results in the same order. The default
+    /// implementation sends them one at a time.
+    async fn send_messages(
+        &mut self,
+        msgs: Vec<Vec<u8>>,
+        _max_in_flight: usize,
+    ) -> Vec<Result<(), Self::Error>>
+    where
+        Self: Send,
+    {
+        let mut results = Vec::with_capacity(msgs.len());
+        for msg in msgs {
+            results.push(self.send_message(msg).await);
+        }
+        results
+    }
+
+    /// Get the reason why the aggregator rejected a message, if the
+    /// error of [`MosaicClientTrait::send_message()`] is a rejection.
+    /// Rejected messages are not sent again, unlike messages which
+    /// failed to reach the aggregator.
+    fn rejection_reason(_error: &Self::Error) -> Option<String> {
+        None
+    }
 }
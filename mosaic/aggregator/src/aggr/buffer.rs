@@ -22,6 +22,8 @@ pub struct FedBuffer {
     pub counter: MessageCounter,
     /// Buffered [`MaskObject`].
     pub local_models: Vec<Model>,
+    /// Buffered delta updates, which are applied to the global model as pseudo-gradients.
+    pub pseudo_gradients: Vec<Model>,
 }
 
 #[cfg(feature = "secure")]
@@ -0,0 +1,105 @@
+//! The file formats in which global models are exported and imported.
+use std::{fmt, path::Path, str::FromStr};
+
+use displaydoc::Display;
+use serde::Deserialize;
+use thiserror::Error;
+
+use mosaic_core::model::{FormatError, Model, ModelConfig, ModelSchema};
+
+/// Errors which can occur while converting a model from or into a file format.
+#[derive(Debug, Display, Error)]
+pub enum ModelFormatError {
+    /// Serializing the model failed: {0}.
+    Serialization(#[from] bincode::Error),
+    /// Converting the model failed: {0}.
+    Conversion(#[from] FormatError),
+}
+
+/// The file format of a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    /// The serialization in which the aggregator serves the global model, which keeps the exact
+    /// weights.
+    Bincode,
+    /// A NumPy `.npy` file holding the weights as a flat array.
+    Npy,
+    /// A safetensors file holding the tensors of the model schema, or a single tensor if the
+    /// models are not structured.
+    Safetensors,
+}
+
+impl ModelFormat {
+    /// Guesses the format of a model file from its extension. Files without a known extension
+    /// are expected to hold the aggregator serialization.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("npy") => Self::Npy,
+            Some("safetensors") => Self::Safetensors,
+            _ => Self::Bincode,
+        }
+    }
+
+    /// Converts a model into this format. The weights are stored in the data type of the models,
+    /// respectively of each tensor of the schema.
+    ///
+    /// # Errors
+    /// Fails if the model does not conform to the schema or if a weight is not representable in
+    /// its data type.
+    pub fn encode(
+        self,
+        model: &Model,
+        config: &ModelConfig,
+        schema: Option<&ModelSchema>,
+    ) -> Result<Vec<u8>, ModelFormatError> {
+        Ok(match self {
+            Self::Bincode => bincode::serialize(model)?,
+            Self::Npy => model.to_npy(config.data_type)?,
+            Self::Safetensors => model.to_safetensors(config.data_type, schema)?,
+        })
+    }
+
+    /// Converts a model from this format.
+    ///
+    /// # Errors
+    /// Fails if the bytes are malformed or don't match the data types and the schema.
+    pub fn decode(
+        self,
+        bytes: &[u8],
+        config: &ModelConfig,
+        schema: Option<&ModelSchema>,
+    ) -> Result<Model, ModelFormatError> {
+        Ok(match self {
+            Self::Bincode => bincode::deserialize(bytes)?,
+            Self::Npy => Model::from_npy(bytes, config.data_type)?,
+            Self::Safetensors => Model::from_safetensors(bytes, config.data_type, schema)?,
+        })
+    }
+}
+
+impl fmt::Display for ModelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bincode => write!(f, "bincode"),
+            Self::Npy => write!(f, "npy"),
+            Self::Safetensors => write!(f, "safetensors"),
+        }
+    }
+}
+
+impl FromStr for ModelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bincode" => Ok(Self::Bincode),
+            "npy" => Ok(Self::Npy),
+            "safetensors" => Ok(Self::Safetensors),
+            _ => Err(format!(
+                "unknown model format {}, expected bincode, npy or safetensors",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! A bounded history of the latest global models.
+//!
+//! Delta updates are computed against a specific version of the global model, which must still
+//! be known to the aggregator to reconstruct the local model.
+use std::{collections::VecDeque, sync::Arc};
+
+use mosaic_core::model::Model;
+
+#[derive(Debug, Clone)]
+/// The latest global models along with their versions, the oldest model being evicted first.
+pub struct ModelHistory {
+    /// The maximum number of models kept.
+    capacity: usize,
+    /// The models, ordered by increasing version.
+    models: VecDeque<(u32, Arc<Model>)>,
+}
+
+impl ModelHistory {
+    /// Creates an empty history which keeps at most `capacity` models.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            models: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds the global model of the given version, evicting the oldest models if the history is
+    /// full. A model of the same version is replaced.
+    pub fn insert(&mut self, version: u32, model: Arc<Model>) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.models.retain(|(v, _)| *v != version);
+        while self.models.len() >= self.capacity {
+            self.models.pop_front();
+        }
+        self.models.push_back((version, model));
+    }
+
+    /// Gets the global model of the given version, if it has not been evicted.
+    pub fn get(&self, version: u32) -> Option<&Arc<Model>> {
+        self.models
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, model)| model)
+    }
+
+    /// Gets the latest global model.
+    pub fn latest(&self) -> Option<&Arc<Model>> {
+        self.models.back().map(|(_, model)| model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eviction() {
+        let mut history = ModelHistory::new(2);
+        assert!(history.latest().is_none());
+        for version in 1..=3 {
+            history.insert(version, Arc::new(Model::zeros(&(version as usize))));
+        }
+        assert!(history.get(1).is_none());
+        assert_eq!(history.get(2).unwrap().len(), 2);
+        assert_eq!(history.latest().unwrap().len(), 3);
+    }
+}
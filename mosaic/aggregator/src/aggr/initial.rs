@@ -0,0 +1,129 @@
+//! The initial global model which the training starts from.
+//!
+//! An initial model is stored in the same serialization in which the aggregator serves the global
+//! model to the participants, hence a model fetched from one aggregator can be used to seed the
+//! training of another one. Model files may also be NumPy `.npy` or safetensors files.
+use std::{fs, io, path::Path};
+
+use displaydoc::Display;
+use thiserror::Error;
+
+use crate::aggr::format::{ModelFormat, ModelFormatError};
+use mosaic_core::model::{
+    DataType, IntoPrimitives, Model, ModelCastError, ModelConfig, ModelSchema,
+};
+
+/// Errors which can occur while loading an initial model.
+#[derive(Debug, Display, Error)]
+pub enum InitialModelError {
+    /// Reading the model file failed: {0}.
+    Read(#[from] io::Error),
+    /// Decoding the model failed: {0}.
+    Decode(#[from] bincode::Error),
+    /// Converting the model file failed: {0}.
+    Format(#[from] ModelFormatError),
+    /// The model has {actual} weights instead of {expected}.
+    Length { expected: usize, actual: usize },
+    /// The model does not match the model schema: {0}.
+    Schema(String),
+    /// The model does not match the model data type: {0}.
+    DataType(#[from] ModelCastError),
+}
+
+/// Decodes a serialized initial model.
+///
+/// # Errors
+/// Fails if the bytes are not a serialized [`Model`].
+pub fn decode_model(bytes: &[u8]) -> Result<Model, InitialModelError> {
+    Ok(bincode::deserialize(bytes)?)
+}
+
+/// Loads an initial model from the file at the given path, in the format implied by its
+/// extension.
+///
+/// # Errors
+/// Fails if the file can't be read or doesn't hold a model of the data types and the schema.
+pub fn load_model(
+    path: impl AsRef<Path>,
+    config: &ModelConfig,
+    schema: Option<&ModelSchema>,
+) -> Result<Model, InitialModelError> {
+    let format = ModelFormat::from_path(&path);
+    Ok(format.decode(&fs::read(path)?, config, schema)?)
+}
+
+/// Checks that a model can be published as the global model, i.e. that it matches the model
+/// length and schema, if any, and that its weights are representable in the data type of the
+/// models.
+///
+/// # Errors
+/// Fails if the model has the wrong number of weights or if a weight is out of range.
+pub fn validate_model(
+    model: &Model,
+    length: Option<usize>,
+    config: &ModelConfig,
+    schema: Option<&ModelSchema>,
+) -> Result<(), InitialModelError> {
+    if let Some(expected) = length.filter(|expected| *expected != model.len()) {
+        return Err(InitialModelError::Length {
+            expected,
+            actual: model.len(),
+        });
+    }
+    if let Some(schema) = schema {
+        schema
+            .validate(model.len())
+            .map_err(|err| InitialModelError::Schema(err.to_string()))?;
+    }
+    let cast_error = match config.data_type {
+        DataType::F32 => first_cast_error::<f32>(model),
+        DataType::F64 => first_cast_error::<f64>(model),
+        DataType::I32 => first_cast_error::<i32>(model),
+        DataType::I64 => first_cast_error::<i64>(model),
+    };
+    cast_error.map_or(Ok(()), |err| Err(err.into()))
+}
+
+/// Gets the error of the first weight which can't be converted into the primitive type `P`.
+fn first_cast_error<P: 'static>(model: &Model) -> Option<ModelCastError>
+where
+    Model: IntoPrimitives<P>,
+{
+    model.to_primitives().find_map(Result::err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::model::FromPrimitives;
+
+    #[test]
+    fn test_decode_and_validate() {
+        let config = ModelConfig {
+            data_type: DataType::I32,
+        };
+
+        let model = Model::from_primitives(vec![1_i64, 2, 3].into_iter()).unwrap();
+        let bytes = bincode::serialize(&model).unwrap();
+        let decoded = decode_model(&bytes).unwrap();
+        assert_eq!(decoded, model);
+        assert!(validate_model(&decoded, Some(3), &config, None).is_ok());
+        assert!(matches!(
+            validate_model(&decoded, Some(4), &config, None),
+            Err(InitialModelError::Length {
+                expected: 4,
+                actual: 3
+            })
+        ));
+
+        let model = Model::from_primitives(vec![i64::MAX].into_iter()).unwrap();
+        assert!(matches!(
+            validate_model(&model, None, &config, None),
+            Err(InitialModelError::DataType(_))
+        ));
+        assert!(matches!(
+            decode_model(&[1, 2, 3]),
+            Err(InitialModelError::Decode(_))
+        ));
+    }
+}
@@ -1,8 +1,10 @@
 use serde::{Deserialize, Serialize};
 
 use crate::settings::{
+    CompressionSettings,
     MaskSettings,
     ModelSettings,
+    MultipartSettings,
     ProtocolSettings,
 };
 
@@ -17,9 +19,15 @@ use mosaic_core::mask::MaskConfig;
 use mosaic_core::model::ModelConfig;
 
 pub mod buffer;
+pub mod format;
+pub mod history;
+pub mod initial;
 pub mod protocol;
 
-pub use self::protocol::{Aggregation, AggregationError};
+pub use self::{
+    history::ModelHistory,
+    protocol::{Aggregation, AggregationError, DeltaAggregation},
+};
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Aggregator {
@@ -35,7 +43,13 @@ pub struct Aggregator {
 }
 
 impl Aggregator {
-    pub fn new(_mask_settings: MaskSettings, model_settings: ModelSettings, protocol_settings: &ProtocolSettings) -> Self {
+    pub fn new(
+        _mask_settings: MaskSettings,
+        model_settings: ModelSettings,
+        protocol_settings: &ProtocolSettings,
+        compression_settings: &CompressionSettings,
+        multipart_settings: &MultipartSettings,
+    ) -> Self {
         let keys = EncryptKeyPair::generate();
 
         #[cfg(feature = "secure")]
@@ -45,22 +59,35 @@ impl Aggregator {
             // update: pet_settings.update.prob,
             seed: RoundSeed::zeroed(),
             mask_config: MaskConfig::from(mask_settings).into(),
+            model_schema: model_settings.schema(),
             // model_length: model_settings.length,
+            codecs: compression_settings.codecs.clone(),
+            model_version: 0,
+            privacy: model_settings.privacy,
+            max_chunk_id: multipart_settings.max_chunk_id,
         };
         #[cfg(not(feature = "secure"))]
         let round_params = RoundParameters {
             pk: keys.public,
             seed: RoundSeed::zeroed(),
+            model_schema: model_settings.schema(),
+            privacy: model_settings.privacy,
             model_config: ModelConfig::from(model_settings),
             per_round_participants: protocol_settings.participants,
             training_rounds: protocol_settings.training_rounds,
+            codecs: compression_settings.codecs.clone(),
+            model_version: 0,
+            max_chunk_id: multipart_settings.max_chunk_id,
         };
 
         Self {
             keys,
             round_id: 0,
             round_params,
-            params: AggrParams::default(),
+            params: AggrParams {
+                delta_aggregation: protocol_settings.delta_aggregation,
+                ..AggrParams::default()
+            },
         }
     }
     /// Sets the round ID to the given value.
@@ -84,17 +111,27 @@ pub struct AggrParams {
     /// According to [Nguyen et al. 2021](https://arxiv.org/abs/2106.06639) k = 10 seems to be
     /// a good fit that needs no further tuning.
     pub k: u32,
+    /// How delta updates are aggregated. Defaults to [`DeltaAggregation::Reconstruct`].
+    pub delta_aggregation: DeltaAggregation,
 }
 
 impl AggrParams {
     /// Creates new [`AggrParams`] which allows altering the default parameters.
     pub fn new(eta: f64, k: u32) -> Self {
-        Self { eta, k }
+        Self {
+            eta,
+            k,
+            ..Self::default()
+        }
     }
 }
 
 impl Default for AggrParams {
     fn default() -> Self {
-        Self { eta: 1e-1, k: 10 }
+        Self {
+            eta: 1e-1,
+            k: 10,
+            delta_aggregation: DeltaAggregation::Reconstruct,
+        }
     }
 }
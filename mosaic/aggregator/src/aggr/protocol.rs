@@ -1,4 +1,5 @@
 use std::ops::{Add, Mul};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::error;
 
@@ -23,6 +24,18 @@ pub enum AggregationError {
     ScalarMismatch,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// How the aggregator consumes delta updates, i.e. updates which hold the difference between a
+/// local model and the global model it was trained from.
+pub enum DeltaAggregation {
+    /// The local model is reconstructed by adding the delta to its base model and then averaged
+    /// with the full local models.
+    Reconstruct,
+    /// The averaged deltas are applied to the global model as a pseudo-gradient, scaled by the
+    /// server learning rate.
+    PseudoGradient,
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Aggregation {
     // pub global_model: Model,
@@ -58,4 +71,27 @@ impl Aggregation {
 
         Ok(global_model)
     }
+
+    /// Applies the average of the `pseudo_gradients` to `model`, scaled by the learning rate
+    /// `eta`.
+    pub fn apply_pseudo_gradients(
+        &mut self,
+        model: Model,
+        pseudo_gradients: &Vec<Model>,
+        eta: f64,
+    ) -> Result<Model, AggregationError> {
+        let gradient = self.aggregate(pseudo_gradients)?;
+        if gradient.len() != model.len() {
+            error!("Pseudo-gradients do not match the length of the global model.");
+            return Err(AggregationError::ModelMismatch);
+        }
+        let eta = Ratio::<BigInt>::from_float(eta).ok_or(AggregationError::InvalidObject)?;
+
+        Ok(model
+            .0
+            .iter()
+            .zip(&gradient.0)
+            .map(|(w, g)| w.add(g.mul(&eta)))
+            .collect())
+    }
 }
@@ -1,20 +1,31 @@
 use std::fmt::Debug;
-use std::{path::PathBuf, process};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
 
-use structopt::StructOpt;
+use futures::future::{join, join_all};
+use structopt::{clap::ErrorKind, StructOpt};
 use tokio::signal;
-use tracing::warn;
+use tracing::{info, info_span, warn, Instrument};
 use tracing_subscriber::*;
 
 #[cfg(feature = "metrics")]
 use aggregator::{metrics, settings::InfluxSettings};
 
 use aggregator::{
-    rest::{serve, RestError},
-    services,
-    settings::{LoggingSettings, Settings},
-    state_engine::init::StateEngineInitializer,
-    storage::{Storage, Store},
+    aggr::{format::ModelFormat, initial},
+    rest::{serve, RestError, TaskServices},
+    services::{self, health::ReadinessProbe},
+    settings::{LoggingSettings, Settings, Task},
+    state_engine::{control::ControlRequest, init::StateEngineInitializer},
+    storage::{AggregatorStorage, ModelStorage, Storage, Store},
+};
+use mosaic_core::{
+    crypto::{ByteObject, EncryptKeyPair, Sha256, SigningKeyPair},
+    model::{IntoPrimitives, ModelConfig},
 };
 
 #[cfg(feature = "redis")]
@@ -26,31 +37,202 @@ use aggregator::{settings::S3Settings, storage::model_storage::s3};
 #[derive(Debug, StructOpt)]
 #[structopt(name = "Aggregator")]
 struct CliConf {
-    /// Path of the configuration file
-    #[structopt(short, parse(from_os_str))]
-    config_path: PathBuf,
+    /// Path of the configuration file, the default settings are used if it is omitted
+    #[structopt(short, long = "config", parse(from_os_str), global = true)]
+    config_path: Option<PathBuf>,
+    /// Runs the aggregator if no command is given
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Runs the aggregator
+    Serve,
+    /// Validates the configuration and reports every invalid setting
+    ValidateConfig,
+    /// Prints the default configuration as a TOML file
+    PrintDefaultConfig,
+    /// Prints the length, the data type, the norm, the range and the hash of a model file
+    InspectModel {
+        /// Name of the task whose model settings apply, the first task by default
+        #[structopt(long)]
+        task: Option<String>,
+        /// Format of the model file (bincode, npy or safetensors), guessed from its extension by
+        /// default
+        #[structopt(long)]
+        format: Option<ModelFormat>,
+        /// Path of the model file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+    },
+    /// Generates a key pair and prints its base64 encoded public and secret keys
+    Keygen {
+        /// Kind of the key pair (signing or encrypt)
+        #[structopt(long, default_value = "signing")]
+        kind: KeyKind,
+    },
+    /// Exports a global model from the model storage of a task
+    Export {
+        /// Name of the task, the first task by default
+        #[structopt(long)]
+        task: Option<String>,
+        /// Id of the global model, the latest global model by default
+        #[structopt(long)]
+        id: Option<String>,
+        /// Format of the exported model (bincode, npy or safetensors), guessed from the
+        /// extension of the output file by default
+        #[structopt(long)]
+        format: Option<ModelFormat>,
+        /// Path of the exported model
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+    },
+    /// Imports a model file as the initial model of a task
+    Import {
+        /// Name of the task, the first task by default
+        #[structopt(long)]
+        task: Option<String>,
+        /// Format of the model file (bincode, npy or safetensors), guessed from its extension by
+        /// default
+        #[structopt(long)]
+        format: Option<ModelFormat>,
+        /// Path of the model file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Path of the initial model, the `initial_model` setting of the task by default
+        #[structopt(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+/// The kind of a generated key pair.
+#[derive(Debug, Clone, Copy)]
+enum KeyKind {
+    /// An `Ed25519` key pair with which participants sign their messages.
+    Signing,
+    /// A `C25519` key pair with which messages are encrypted.
+    Encrypt,
+}
+
+impl FromStr for KeyKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "signing" => Ok(Self::Signing),
+            "encrypt" => Ok(Self::Encrypt),
+            _ => Err(format!(
+                "unknown key kind {}, expected signing or encrypt",
+                s
+            )),
+        }
+    }
+}
+
+/// The exit codes of the aggregator, following the conventions of `sysexits.h`.
+mod exit_code {
+    /// The command line is invalid.
+    pub const USAGE: i32 = 64;
+    /// An input file is malformed.
+    pub const DATA: i32 = 65;
+    /// An input file does not exist or can't be read.
+    pub const NO_INPUT: i32 = 66;
+    /// A required resource, e.g. a stored model, is unavailable.
+    pub const UNAVAILABLE: i32 = 69;
+    /// The aggregator failed internally.
+    pub const SOFTWARE: i32 = 70;
+    /// An output file can't be written.
+    pub const CANT_CREATE: i32 = 73;
+    /// The configuration is invalid.
+    pub const CONFIG: i32 = 78;
+}
+
+/// An error which terminates the aggregator with the given exit code.
+#[derive(Debug)]
+struct CliError {
+    code: i32,
+    message: String,
+}
+
+impl CliError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    let path_buf = match CliConf::from_args_safe() {
-        Ok(path_buf) => Some(path_buf.config_path),
-        Err(_) => {
-            println!("\n\tWARN: Aggregator runs without external configuration, default values are used.\n");
-            None
+    let conf = CliConf::from_args_safe().unwrap_or_else(|err| match err.kind {
+        ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => err.exit(),
+        _ => {
+            eprintln!("{}", err.message);
+            process::exit(exit_code::USAGE);
         }
-    };
-
-    let settings = Settings::new(path_buf).unwrap_or_else(|err| {
-        eprintln!("{}", err);
-        process::exit(1);
     });
+
+    let command = conf.command.unwrap_or(Command::Serve);
+    if let Err(err) = execute(command, conf.config_path.as_deref()).await {
+        eprintln!("{}", err.message);
+        process::exit(err.code);
+    }
+}
+
+/// Executes a command. The settings are only loaded for the commands which depend on them.
+async fn execute(command: Command, config_path: Option<&Path>) -> Result<(), CliError> {
+    match command {
+        Command::PrintDefaultConfig => print_default_config(),
+        Command::Keygen { kind } => keygen(kind),
+        Command::Serve => run(load_settings(config_path)?).await,
+        Command::ValidateConfig => validate_config(&load_settings(config_path)?),
+        Command::InspectModel {
+            task,
+            format,
+            input,
+        } => inspect_model(&load_settings(config_path)?, task, format, input),
+        Command::Export {
+            task,
+            id,
+            format,
+            output,
+        } => export_model(load_settings(config_path)?, task, id, format, output).await,
+        Command::Import {
+            task,
+            format,
+            input,
+            output,
+        } => import_model(load_settings(config_path)?, task, format, input, output),
+    }
+}
+
+/// Loads the settings from the configuration file, or the default settings if no file is given.
+fn load_settings(path: Option<&Path>) -> Result<Settings, CliError> {
+    if path.is_none() {
+        eprintln!("WARN: Aggregator runs without external configuration, default values are used.");
+    }
+    Settings::new(path).map_err(|err| {
+        let mut message = String::from("The configuration is invalid:");
+        for detail in err.details() {
+            message.push_str("\n\t");
+            message.push_str(&detail);
+        }
+        CliError::new(exit_code::CONFIG, message)
+    })
+}
+
+/// Runs the state engines and the REST server of all tasks until one of them terminates.
+async fn run(settings: Settings) -> Result<(), CliError> {
+    // the data of a single unnamed task is kept outside of any storage namespace
+    let namespaced = !settings.tasks.is_empty();
+    let tasks = settings.tasks();
     let Settings {
-        mask: mask_settings,
         api: api_settings,
         log: log_settings,
-        model: model_settings,
-        protocol: protocol_settings,
+        multipart: multipart_settings,
+        compression: compression_settings,
         // redis: redis_settings,
         ..
     } = settings;
@@ -60,51 +242,364 @@ async fn main() {
     // This should already called internally when instantiating the
     // state machine but it doesn't hurt making sure the crypto layer
     // is correctly initialized
-    sodiumoxide::init().unwrap();
+    sodiumoxide::init().map_err(|_| {
+        CliError::new(
+            exit_code::SOFTWARE,
+            "Failed to initialize the crypto layer.",
+        )
+    })?;
 
     #[cfg(feature = "metrics")]
     init_metrics(settings.metrics.influxdb);
 
-    let store = init_store(
-        #[cfg(feature = "redis")]
-        redis_settings,
-        #[cfg(feature = "model-persistence")]
-        settings.s3,
-    )
-    .await;
+    // the tasks share the threads and the memory for partial multipart messages
+    let resources = services::messages::SharedResources::new(multipart_settings);
+    let mut state_machines = Vec::with_capacity(tasks.len());
+    let mut services = Vec::with_capacity(tasks.len());
+    for Task {
+        name,
+        mask,
+        model,
+        protocol,
+    } in tasks
+    {
+        let store = init_store(
+            namespaced.then(|| name.as_str()),
+            #[cfg(feature = "redis")]
+            redis_settings.clone(),
+            #[cfg(feature = "model-persistence")]
+            settings.s3.clone(),
+        )
+        .await?;
 
-    let (state_machine, requests_tx, event_subscriber) = StateEngineInitializer::new(
-        mask_settings,
-        model_settings,
-        protocol_settings,
-        #[cfg(feature = "model-persistence")]
-        settings.restore,
-        store,
-    )
-    .init()
-    .await
-    .expect("Failed to initialize state engine.");
+        let (state_machine, requests_tx, control, event_subscriber) = StateEngineInitializer::new(
+            mask,
+            model.clone(),
+            protocol,
+            compression_settings.clone(),
+            multipart_settings,
+            #[cfg(feature = "model-persistence")]
+            settings.restore.clone(),
+            store.clone(),
+        )
+        .init()
+        .await
+        .map_err(|err| {
+            CliError::new(
+                exit_code::SOFTWARE,
+                format!(
+                    "Failed to initialize state engine of task {}: {}",
+                    name, err
+                ),
+            )
+        })?;
 
-    let fetcher = services::fetchers::fetcher(&event_subscriber);
-    let message_handler =
-        services::messages::PetMessageHandler::new(&event_subscriber, requests_tx);
+        let fetcher = services::fetchers::fetcher(&event_subscriber);
+        let message_handler = services::messages::PetMessageHandler::new(
+            &event_subscriber,
+            requests_tx,
+            mask,
+            &model,
+            compression_settings.clone(),
+            &resources,
+        );
+        info!("Hosting task {}.", name);
+        state_machines.push(
+            state_machine
+                .run()
+                .instrument(info_span!("task", name = %name)),
+        );
+        services.push((
+            name,
+            TaskServices {
+                fetcher,
+                message_handler,
+                control,
+                readiness: ReadinessProbe::new(&event_subscriber, store),
+            },
+        ));
+    }
+
+    let controls: Vec<_> = services
+        .iter()
+        .map(|(_, services)| services.control.clone())
+        .collect();
+    let state_machines = join_all(state_machines);
+    tokio::pin!(state_machines);
 
     tokio::select! {
         biased;
 
-        _ =  signal::ctrl_c() => {}
-        _ = state_machine.run() => {
-            warn!("Shutting down: Service terminated.");
+        _ =  signal::ctrl_c() => {
+            warn!("Shutting down: Interrupted, waiting for the services of all tasks to terminate.");
+            // the state engines which already stopped ignore the request, the others must keep
+            // running to handle it
+            let shutdown = controls
+                .iter()
+                .map(|control| control.request(ControlRequest::Shutdown));
+            join(join_all(shutdown), state_machines).await;
+        }
+        _ = &mut state_machines => {
+            warn!("Shutting down: Services of all tasks terminated.");
         }
-        result = serve(api_settings, fetcher, message_handler) => {
+        result = serve(api_settings, services) => {
             match result {
                 Ok(()) => warn!("Shutting down: REST server terminated."),
                 Err(RestError::InvalidTlsConfig) => {
                     warn!("Shutting down: Invalid TLS settings for REST server.");
+                    return Err(CliError::new(
+                        exit_code::CONFIG,
+                        "The TLS settings of the REST server are invalid.",
+                    ));
                 },
             }
         }
     }
+    Ok(())
+}
+
+/// Reports whether the settings are valid. Invalid settings are already rejected while loading
+/// them, hence this only summarizes the tasks of valid settings.
+fn validate_config(settings: &Settings) -> Result<(), CliError> {
+    let tasks = settings
+        .tasks()
+        .into_iter()
+        .map(|task| task.name)
+        .collect::<Vec<_>>();
+    println!("The configuration is valid, tasks: {}.", tasks.join(", "));
+    Ok(())
+}
+
+/// Prints the default settings as a TOML file.
+fn print_default_config() -> Result<(), CliError> {
+    let config = Settings::default_config()
+        .map_err(|err| CliError::new(exit_code::SOFTWARE, err.to_string()))?;
+    print!("{}", config);
+    Ok(())
+}
+
+/// Prints the statistics of a model file, which is read with the model settings of a task.
+fn inspect_model(
+    settings: &Settings,
+    task: Option<String>,
+    format: Option<ModelFormat>,
+    input: PathBuf,
+) -> Result<(), CliError> {
+    let task = select_task(settings, task.as_deref())?;
+    let bytes = fs::read(&input).map_err(|err| {
+        CliError::new(
+            exit_code::NO_INPUT,
+            format!("Reading {} failed: {}", input.display(), err),
+        )
+    })?;
+    let config = ModelConfig::from(task.model.clone());
+    let schema = task.model.schema();
+    let format = format.unwrap_or_else(|| ModelFormat::from_path(&input));
+    let model = format
+        .decode(&bytes, &config, schema.as_ref())
+        .map_err(|err| CliError::new(exit_code::DATA, err.to_string()))?;
+
+    // the statistics are approximated in f64 since the weights are arbitrary precision rationals
+    let weights = model
+        .to_primitives()
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|err| CliError::new(exit_code::DATA, err.to_string()))?;
+    let norm = weights
+        .iter()
+        .map(|weight| weight * weight)
+        .sum::<f64>()
+        .sqrt();
+    let min = weights.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = weights.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let serialized = bincode::serialize(&model)
+        .map_err(|err| CliError::new(exit_code::SOFTWARE, err.to_string()))?;
+
+    println!("format:    {}", format);
+    println!("length:    {}", model.len());
+    println!("data type: {:?}", config.data_type);
+    if let Some(schema) = schema.as_ref() {
+        println!("tensors:   {}", schema.tensors().len());
+    }
+    println!("l2 norm:   {}", norm);
+    if !weights.is_empty() {
+        println!("min:       {}", min);
+        println!("max:       {}", max);
+    }
+    println!(
+        "sha256:    {}",
+        hex::encode(Sha256::hash(&serialized).as_slice())
+    );
+
+    initial::validate_model(&model, task.model.length, &config, schema.as_ref()).map_err(|err| {
+        CliError::new(
+            exit_code::DATA,
+            format!("The model is invalid for task {}: {}", task.name, err),
+        )
+    })
+}
+
+/// Generates a key pair and prints its keys.
+fn keygen(kind: KeyKind) -> Result<(), CliError> {
+    sodiumoxide::init().map_err(|_| {
+        CliError::new(
+            exit_code::SOFTWARE,
+            "Failed to initialize the crypto layer.",
+        )
+    })?;
+    let (public, secret) = match kind {
+        KeyKind::Signing => {
+            let keys = SigningKeyPair::generate();
+            (
+                base64::encode(keys.public.as_slice()),
+                base64::encode(keys.secret.as_slice()),
+            )
+        }
+        KeyKind::Encrypt => {
+            let keys = EncryptKeyPair::generate();
+            (
+                base64::encode(keys.public.as_slice()),
+                base64::encode(keys.secret.as_slice()),
+            )
+        }
+    };
+    println!("public: {}", public);
+    println!("secret: {}", secret);
+    Ok(())
+}
+
+/// Gets the task of the given name, or the first task if no name is given.
+fn select_task(settings: &Settings, name: Option<&str>) -> Result<Task, CliError> {
+    let mut tasks = settings.tasks().into_iter();
+    match name {
+        Some(name) => tasks
+            .find(|task| task.name == name)
+            .ok_or_else(|| CliError::new(exit_code::USAGE, format!("There is no task {}.", name))),
+        // UNWRAP SAFE: there is always at least one task
+        None => Ok(tasks.next().unwrap()),
+    }
+}
+
+/// Exports a global model of a task from its model storage.
+async fn export_model(
+    settings: Settings,
+    task: Option<String>,
+    id: Option<String>,
+    format: Option<ModelFormat>,
+    output: PathBuf,
+) -> Result<(), CliError> {
+    let namespaced = !settings.tasks.is_empty();
+    let task = select_task(&settings, task.as_deref())?;
+    let mut store = init_store(
+        namespaced.then(|| task.name.as_str()),
+        #[cfg(feature = "redis")]
+        settings.redis.clone(),
+        #[cfg(feature = "model-persistence")]
+        settings.s3.clone(),
+    )
+    .await?;
+
+    let unavailable = |message: String| CliError::new(exit_code::UNAVAILABLE, message);
+    let id = match id {
+        Some(id) => id,
+        None => store
+            .latest_global_model_id()
+            .await
+            .map_err(|err| {
+                unavailable(format!(
+                    "Fetching the latest global model id failed: {}",
+                    err
+                ))
+            })?
+            .ok_or_else(|| {
+                unavailable(format!(
+                    "No global model of task {} has been stored.",
+                    task.name
+                ))
+            })?,
+    };
+    let model = store
+        .global_model(&id)
+        .await
+        .map_err(|err| unavailable(format!("Fetching the global model failed: {}", err)))?
+        .ok_or_else(|| unavailable(format!("The global model {} does not exist.", id)))?;
+
+    let format = format.unwrap_or_else(|| ModelFormat::from_path(&output));
+    let bytes = format
+        .encode(
+            &model,
+            &ModelConfig::from(task.model.clone()),
+            task.model.schema().as_ref(),
+        )
+        .map_err(|err| CliError::new(exit_code::DATA, err.to_string()))?;
+    fs::write(&output, bytes).map_err(|err| {
+        CliError::new(
+            exit_code::CANT_CREATE,
+            format!("Writing {} failed: {}", output.display(), err),
+        )
+    })?;
+    println!(
+        "Exported the global model {} of task {} as {} to {}.",
+        id,
+        task.name,
+        format,
+        output.display()
+    );
+    Ok(())
+}
+
+/// Imports a model file as the initial model of a task, i.e. checks it against the model settings
+/// of the task and writes it in the aggregator serialization.
+fn import_model(
+    settings: Settings,
+    task: Option<String>,
+    format: Option<ModelFormat>,
+    input: PathBuf,
+    output: Option<PathBuf>,
+) -> Result<(), CliError> {
+    let task = select_task(&settings, task.as_deref())?;
+    let output = output
+        .or_else(|| task.model.initial_model.clone())
+        .ok_or_else(|| {
+            CliError::new(
+                exit_code::USAGE,
+                format!(
+                    "Task {} has no initial model setting, the output path is required.",
+                    task.name
+                ),
+            )
+        })?;
+
+    let bytes = fs::read(&input).map_err(|err| {
+        CliError::new(
+            exit_code::NO_INPUT,
+            format!("Reading {} failed: {}", input.display(), err),
+        )
+    })?;
+    let config = ModelConfig::from(task.model.clone());
+    let schema = task.model.schema();
+    let format = format.unwrap_or_else(|| ModelFormat::from_path(&input));
+    let model = format
+        .decode(&bytes, &config, schema.as_ref())
+        .map_err(|err| CliError::new(exit_code::DATA, err.to_string()))?;
+    initial::validate_model(&model, task.model.length, &config, schema.as_ref())
+        .map_err(|err| CliError::new(exit_code::DATA, err.to_string()))?;
+
+    let bytes = ModelFormat::Bincode
+        .encode(&model, &config, schema.as_ref())
+        .map_err(|err| CliError::new(exit_code::SOFTWARE, err.to_string()))?;
+    fs::write(&output, bytes).map_err(|err| {
+        CliError::new(
+            exit_code::CANT_CREATE,
+            format!("Writing {} failed: {}", output.display(), err),
+        )
+    })?;
+    println!(
+        "Imported {} as the initial model of task {} to {}.",
+        input.display(),
+        task.name,
+        output.display()
+    );
+    Ok(())
 }
 
 fn init_tracing(settings: LoggingSettings) {
@@ -132,9 +627,10 @@ fn init_metrics(settings: InfluxSettings) {
 }
 
 async fn init_store(
+    namespace: Option<&str>,
     #[cfg(feature = "redis")] redis_settings: RedisSettings,
     #[cfg(feature = "model-persistence")] s3_settings: S3Settings,
-) -> impl Storage {
+) -> Result<impl Storage, CliError> {
     // let aggregator_store = redis::Client::new(redis_settings.url)
     //     .await
     //     .expect("failed to establish a connection to Redis");
@@ -149,8 +645,16 @@ async fn init_store(
         {
             let aggregator_store = redis::Client::new(redis_settings.url)
                 .await
-                .expect("failed to establish a connection to Redis");
-            aggregator_store
+                .map_err(|err| {
+                    CliError::new(
+                        exit_code::UNAVAILABLE,
+                        format!("Establishing a connection to Redis failed: {}", err),
+                    )
+                })?;
+            match namespace {
+                Some(namespace) => aggregator_store.with_namespace(namespace),
+                None => aggregator_store,
+            }
         }
     };
 
@@ -162,6 +666,9 @@ async fn init_store(
     //     warn!("Unable to establish connection to Redis. Learning proceeds without in-memory data storage.")
     // }
 
+    #[cfg(not(any(feature = "redis", feature = "model-persistence")))]
+    let _ = namespace;
+
     let model_store = {
         #[cfg(not(feature = "model-persistence"))]
         {
@@ -170,13 +677,46 @@ async fn init_store(
 
         #[cfg(feature = "model-persistence")]
         {
-            let s3 = s3::Client::new(s3_settings).expect("failed to create S3 client");
-            s3.create_global_models_bucket()
-                .await
-                .expect("failed to create bucket for global models");
-            s3
+            let s3 = s3::Client::new(s3_settings).map_err(|err| {
+                CliError::new(
+                    exit_code::UNAVAILABLE,
+                    format!("Creating the S3 client failed: {}", err),
+                )
+            })?;
+            s3.create_global_models_bucket().await.map_err(|err| {
+                CliError::new(
+                    exit_code::UNAVAILABLE,
+                    format!("Creating the bucket for global models failed: {}", err),
+                )
+            })?;
+            match namespace {
+                Some(namespace) => s3.with_namespace(namespace),
+                None => s3,
+            }
         }
     };
 
-    Store::new(aggregator_store, model_store)
+    Ok(Store::new(aggregator_store, model_store))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate_default_config() {
+        // the printed default configuration is a valid configuration file
+        let path = std::env::temp_dir().join(format!("aggregator-{}.toml", process::id()));
+        fs::write(&path, Settings::default_config().unwrap()).unwrap();
+        let result = execute(Command::ValidateConfig, Some(&path)).await;
+
+        fs::write(&path, "[compression]\nmax_message_length = 0\n").unwrap();
+        let error = execute(Command::ValidateConfig, Some(&path)).await;
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok());
+        let error = error.unwrap_err();
+        assert_eq!(error.code, exit_code::CONFIG);
+        assert!(error.message.contains("compression.max_message_length"));
+    }
 }
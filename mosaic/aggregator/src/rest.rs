@@ -1,11 +1,13 @@
 //! A HTTP API for the PET protocol interactions.
 
-use std::convert::Infallible;
+use std::{collections::HashMap, convert::Infallible, sync::Arc};
 #[cfg(feature = "tls")]
 use std::path::PathBuf;
 
 use bytes::Bytes;
+use futures::future::join_all;
 use serde::{Deserialize, Serialize};
+use sodiumoxide::utils::memcmp;
 use thiserror::Error;
 use tracing::{error, debug, warn};
 use warp::{
@@ -17,97 +19,390 @@ use warp::{
 use warp::{Server, TlsServer};
 
 use crate::{
-    services::{fetchers::Fetcher, messages::PetMessageHandler},
+    aggr::{format::ModelFormat, initial},
+    services::{
+        fetchers::Fetcher,
+        health::{ReadinessProbe, TaskReadiness},
+        messages::{PetMessageHandler, ServiceError},
+    },
     settings::ApiSettings,
+    state_engine::{
+        channel::RequestError,
+        control::{ControlError, ControlRequest, ControlSender},
+    },
+    storage::Storage,
 };
-use mosaic_core::{crypto::ByteObject, ParticipantPublicKey};
+use mosaic_core::{common::Capabilities, crypto::ByteObject, model::Model, ParticipantPublicKey};
 
 #[derive(Deserialize, Serialize)]
-struct SeedDictQuery {
+struct PublicKeyQuery {
     pk: String,
 }
 
+#[derive(Deserialize)]
+struct ModelQuery {
+    /// The format in which the global model is served, the aggregator serialization by default.
+    format: Option<ModelFormat>,
+    /// The version the global model must have to be served, any version by default.
+    version: Option<u32>,
+}
+
+/// The services of a training task hosted by the aggregator.
+#[derive(Clone)]
+pub struct TaskServices<F, S> {
+    /// The fetcher for responding to data requests.
+    pub fetcher: F,
+    /// The handler for responding to PET messages.
+    pub message_handler: PetMessageHandler,
+    /// The sender for the control requests of the admin API.
+    pub control: ControlSender,
+    /// The probe for the readiness of the task.
+    pub readiness: ReadinessProbe<S>,
+}
+
+/// The readiness of the aggregator.
+#[derive(Serialize)]
+struct Readiness {
+    /// Whether all the tasks are ready.
+    ready: bool,
+    /// The readiness of the tasks by name.
+    tasks: HashMap<String, TaskReadiness>,
+}
+
 /// Starts a HTTP server at the given address, listening to GET requests for
 /// data and POST requests containing PET messages.
 ///
+/// The endpoints of a task are served under `/tasks/{name}/`, for example
+/// `/tasks/{name}/params`. The endpoints of the first task are also served without the prefix.
+///
+/// The global model is served under `model` in the aggregator serialization. It is converted
+/// into a NumPy `.npy` or a safetensors file with the query `model?format=npy`, respectively
+/// `model?format=safetensors`. With the query `model?version={version}`, the model is only
+/// served if it has the given version, so that participants know the model they fetched.
+///
+/// The liveness and the readiness of the aggregator are served under `/healthz` and `/readyz`.
+/// The aggregator is ready if all its tasks are ready, the readiness of their components is
+/// reported in a JSON body.
+///
+/// If an admin token is configured, the admin API of a task is served under `/admin/`. Its
+/// requests must be authenticated with the token as bearer token:
+/// - `POST admin/pause` and `POST admin/resume` pause and resume the collection of messages
+/// - `POST admin/abort` aborts the current round and discards its buffered updates
+/// - `POST admin/aggregate` aggregates the buffered updates right away
+/// - `POST admin/config` changes the protocol settings at the next round boundary, the body is a
+///   JSON object with the optional fields `participants` and `training_rounds`
+/// - `POST admin/model` replaces the initial global model before the training starts, the body
+///   is the model in the serialization in which it is served under `model`
+/// - `POST admin/shutdown` shuts the task down gracefully
+///
 /// * `api_settings`: address of the server and optional certificate and key for TLS server
 ///   authentication as well as trusted anchors for TLS client authentication.
-/// * `fetcher`: fetcher for responding to data requests.
-/// * `pet_message_handler`: handler for responding to PET messages.
+/// * `tasks`: the names and services of the hosted tasks.
 ///
 /// # Errors
 /// Fails if the TLS settings are invalid.
-pub async fn serve<F>(
+///
+/// # Panics
+/// Panics if no task is given.
+pub async fn serve<F, S>(
     api_settings: ApiSettings,
-    fetcher: F,
-    pet_message_handler: PetMessageHandler,
+    tasks: Vec<(String, TaskServices<F, S>)>,
 ) -> Result<(), RestError>
 where
     F: Fetcher + Sync + Send + 'static + Clone,
-{   
+    S: Storage,
+{
     let log = warp::log::custom(|info| {
         debug!(
-            "{} {} from {} with {}.",
-            info.remote_addr().unwrap(),
+            "{:?} {} from {} with {}.",
+            info.remote_addr(),
             info.method(),
             info.path(),
             info.status(),
         );
     });
 
-    let message = warp::path!("message")
+    let routes = routes(tasks, api_settings.admin_token.clone()).with(log);
+
+    #[cfg(not(feature = "tls"))]
+    return run_http(routes, api_settings)
+        .await
+        .map_err(RestError::from);
+    #[cfg(feature = "tls")]
+    return run_https(routes, api_settings).await;
+}
+
+/// Creates the routes of the aggregator for the given tasks.
+///
+/// # Panics
+/// Panics if no task is given.
+fn routes<F, S>(
+    tasks: Vec<(String, TaskServices<F, S>)>,
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone
+where
+    F: Fetcher + Sync + Send + 'static + Clone,
+    S: Storage,
+{
+    let default_task = tasks.first().expect("no task to serve").1.clone();
+    let probes: Vec<_> = tasks
+        .iter()
+        .map(|(name, task)| (name.clone(), task.readiness.clone()))
+        .collect();
+    let tasks: Arc<HashMap<String, TaskServices<F, S>>> = Arc::new(tasks.into_iter().collect());
+
+    let health = warp::path!("healthz")
+        .and(warp::get())
+        .map(|| warp::reply::json(&HashMap::from([("status", "ok")])));
+
+    let readiness = warp::path!("readyz")
+        .and(warp::get())
+        .and(warp::any().map(move || probes.clone()))
+        .and_then(handle_readiness);
+
+    let named_task = warp::path("tasks")
+        .and(warp::path::param::<String>())
+        .and_then(move |name: String| {
+            let task = tasks.get(&name).cloned();
+            async move { task.ok_or_else(warp::reject::not_found) }
+        });
+    let default_task = warp::any().and_then(move || {
+        let task = default_task.clone();
+        async move { Ok::<_, warp::Rejection>(task) }
+    });
+
+    let admin_token = admin_token.map(Arc::new);
+    health
+        .or(readiness)
+        .or(task_routes(named_task, admin_token.clone()))
+        .or(task_routes(default_task, admin_token))
+        .recover(handle_reject)
+}
+
+/// Creates the routes of the task extracted by the `task` filter.
+fn task_routes<F, S, T>(
+    task: T,
+    admin_token: Option<Arc<String>>,
+) -> impl Filter<Extract = (impl Reply,), Error = warp::Rejection> + Clone
+where
+    F: Fetcher + Sync + Send + 'static + Clone,
+    S: Storage,
+    T: Filter<Extract = (TaskServices<F, S>,), Error = warp::Rejection>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    let fetcher = task.clone().map(|task: TaskServices<F, S>| task.fetcher);
+    let message_handler = task
+        .clone()
+        .map(|task: TaskServices<F, S>| task.message_handler);
+    let admin = task
+        .map(|task: TaskServices<F, S>| task.control)
+        .and(warp::path("admin"))
+        .and(warp::post())
+        .and(authorization(admin_token));
+
+    let message = message_handler
+        .clone()
+        .and(warp::path!("message"))
         .and(warp::post())
         .and(warp::body::bytes())
-        .and(with_message_handler(pet_message_handler.clone()))
         .and_then(handle_message);
 
-    let sum_dict = warp::path!("sums")
+    let sum_dict = fetcher
+        .clone()
+        .and(warp::path!("sums"))
         .and(warp::get())
-        .and(with_fetcher(fetcher.clone()))
         .and_then(handle_sums);
 
-    let seed_dict = warp::path!("seeds")
+    let seed_dict = fetcher
+        .clone()
+        .and(warp::path!("seeds"))
         .and(warp::get())
-        .and(warp::query::<SeedDictQuery>())
-        .and_then(part_pk)
-        .and(with_fetcher(fetcher.clone()))
+        .and(warp::query::<PublicKeyQuery>().and_then(part_pk))
         .and_then(handle_seeds);
 
-    let round_params = warp::path!("params")
+    let chunks = message_handler
+        .and(warp::path!("message" / u16 / "chunks"))
+        .and(warp::get())
+        .and(warp::query::<PublicKeyQuery>().and_then(part_pk))
+        .and_then(handle_chunks);
+
+    let round_params = fetcher
+        .clone()
+        .and(warp::path!("params"))
         .and(warp::get())
-        .and(with_fetcher(fetcher.clone()))
         .and_then(handle_params);
 
-    let model = warp::path!("model")
+    let capabilities = fetcher
+        .clone()
+        .and(warp::path!("capabilities"))
+        .and(warp::get())
+        .and_then(handle_capabilities);
+
+    let model = fetcher
+        .and(warp::path!("model"))
         .and(warp::get())
-        .and(with_fetcher(fetcher.clone()))
+        .and(warp::query::<ModelQuery>())
         .and_then(handle_model);
 
-    let routes = message
+    let control = admin
+        .clone()
+        .and(warp::path!("pause"))
+        .map(|control| (control, ControlRequest::Pause))
+        .or(admin
+            .clone()
+            .and(warp::path!("resume"))
+            .map(|control| (control, ControlRequest::Resume)))
+        .unify()
+        .or(admin
+            .clone()
+            .and(warp::path!("abort"))
+            .map(|control| (control, ControlRequest::AbortRound)))
+        .unify()
+        .or(admin
+            .clone()
+            .and(warp::path!("aggregate"))
+            .map(|control| (control, ControlRequest::Aggregate)))
+        .unify()
+        .or(admin
+            .clone()
+            .and(warp::path!("shutdown"))
+            .map(|control| (control, ControlRequest::Shutdown)))
+        .unify()
+        .or(admin
+            .clone()
+            .and(warp::path!("config"))
+            .and(warp::body::json())
+            .map(|control, reconfiguration| {
+                (control, ControlRequest::Reconfigure(reconfiguration))
+            }))
+        .unify()
+        .or(admin
+            .and(warp::path!("model"))
+            .and(warp::body::bytes().and_then(initial_model))
+            .map(|control, model| (control, ControlRequest::SetInitialModel(model))))
+        .unify()
+        .and_then(|(control, req)| handle_control(control, req));
+
+    message
+        .or(chunks)
         .or(round_params)
+        .or(capabilities)
         .or(sum_dict)
         .or(seed_dict)
         .or(model)
-        .recover(handle_reject)
-        .with(log);
+        .or(control)
+}
 
-    #[cfg(not(feature = "tls"))]
-    return run_http(routes, api_settings)
-        .await
-        .map_err(RestError::from);
-    #[cfg(feature = "tls")]
-    return run_https(routes, api_settings).await;
+/// Handles and responds to a request for the readiness of the aggregator.
+async fn handle_readiness<S: Storage>(
+    probes: Vec<(String, ReadinessProbe<S>)>,
+) -> Result<impl warp::Reply, Infallible> {
+    let tasks: HashMap<_, _> = join_all(probes.into_iter().map(|(name, mut probe)| async move {
+        (name, probe.check().await)
+    }))
+    .await
+    .into_iter()
+    .collect();
+    let ready = tasks.values().all(|task| task.ready);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        warn!("Aggregator is not ready.");
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&Readiness { ready, tasks }),
+        status,
+    ))
+}
+
+/// Handles and responds to a control request of the admin API.
+async fn handle_control(
+    control: ControlSender,
+    req: ControlRequest,
+) -> Result<impl warp::Reply, Infallible> {
+    let name = req.to_string();
+    Ok(match control.request(req).await {
+        Ok(()) => warp::reply::with_status(String::new(), StatusCode::OK),
+        Err(e) => {
+            warn!("Failed to handle control request {}: {}", name, e);
+            // the error message is part of the body, as the admin API is used by operators
+            warp::reply::with_status(e.to_string(), control_status(&e))
+        }
+    })
+}
+
+/// Gets the status code of the response to a control request the state engine rejected.
+fn control_status(error: &ControlError) -> StatusCode {
+    match error {
+        ControlError::EngineStopped => StatusCode::SERVICE_UNAVAILABLE,
+        ControlError::NothingToAggregate
+        | ControlError::TrainingStarted
+        | ControlError::NotCollecting => StatusCode::CONFLICT,
+        ControlError::InvalidConfiguration(_) | ControlError::InvalidModel(_) => {
+            StatusCode::BAD_REQUEST
+        }
+        ControlError::SaveModel(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    }
 }
 
 /// Handles and responds to a PET message.
+///
+/// A message the aggregator rejects is answered with a client error status and the reason in
+/// the body, so that the participant doesn't retry it.
 async fn handle_message(
-    body: Bytes,
     mut handler: PetMessageHandler,
+    body: Bytes,
 ) -> Result<impl warp::Reply, Infallible> {
-    let _ = handler.handle_message(body.to_vec()).await.map_err(|e| {
-        warn!("failed to handle message: {:?}", e);
-    });
-    Ok(warp::reply())
+    Ok(match handler.handle_message(body.to_vec()).await {
+        Ok(()) => warp::reply::with_status(String::new(), StatusCode::OK),
+        Err(e) => {
+            warn!("failed to handle message: {:?}", e);
+            warp::reply::with_status(e.to_string(), message_status(&e))
+        }
+    })
+}
+
+/// Gets the status code of the response to a PET message the aggregator failed to handle.
+fn message_status(error: &ServiceError) -> StatusCode {
+    match error {
+        ServiceError::InternalError(_)
+        | ServiceError::StateEngine(
+            RequestError::InternalError(_)
+            | RequestError::CoordinatorStorage(_)
+            | RequestError::LocalSeedDictAdd(_)
+            | RequestError::SumPartAdd(_)
+            | RequestError::MaskScoreIncr(_),
+        ) => StatusCode::INTERNAL_SERVER_ERROR,
+        // the message may be accepted once the collection resumes, respectively once other
+        // multipart messages complete
+        ServiceError::StateEngine(RequestError::CollectionPaused)
+        | ServiceError::MultipartCapacity(_) => StatusCode::SERVICE_UNAVAILABLE,
+        ServiceError::UnexpectedMessage
+        | ServiceError::StateEngine(
+            RequestError::MessageRejected | RequestError::MessageDiscarded,
+        ) => StatusCode::CONFLICT,
+        _ => StatusCode::BAD_REQUEST,
+    }
+}
+
+/// Handles and responds to a request for the chunk IDs the aggregator received so far for a
+/// multipart message, so that an interrupted upload can be resumed.
+async fn handle_chunks(
+    handler: PetMessageHandler,
+    message_id: u16,
+    pk: ParticipantPublicKey,
+) -> Result<impl warp::Reply, Infallible> {
+    Ok(match handler.chunk_ids(pk, message_id) {
+        Some(chunk_ids) => serialized_response(&chunk_ids),
+        None => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .body(Vec::new())
+            .unwrap(),
+    })
 }
 
 /// Handles and responds to a request for the sum dictionary.
@@ -124,21 +419,14 @@ async fn handle_sums<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Inf
             .status(StatusCode::NO_CONTENT)
             .body(Vec::new())
             .unwrap(),
-        Ok(Some(dict)) => {
-            let bytes = bincode::serialize(dict.as_ref()).unwrap();
-            Response::builder()
-                .header("Content-Type", "application/octet-stream")
-                .status(StatusCode::OK)
-                .body(bytes)
-                .unwrap()
-        }
+        Ok(Some(dict)) => serialized_response(dict.as_ref()),
     })
 }
 
 /// Handles and responds to a request for the seed dictionary.
 async fn handle_seeds<F: Fetcher>(
-    pk: ParticipantPublicKey,
     mut fetcher: F,
+    pk: ParticipantPublicKey,
 ) -> Result<impl warp::Reply, Infallible> {
     Ok(match fetcher.seed_dict().await {
         Err(e) => {
@@ -148,15 +436,14 @@ async fn handle_seeds<F: Fetcher>(
                 .body(Vec::new())
                 .unwrap()
         }
-        Ok(Some(dict)) if dict.get(&pk).is_some() => {
-            let bytes = bincode::serialize(dict.as_ref().get(&pk).unwrap()).unwrap();
-            Response::builder()
-                .header("Content-Type", "application/octet-stream")
-                .status(StatusCode::OK)
-                .body(bytes)
-                .unwrap()
-        }
-        _ => Response::builder()
+        Ok(Some(dict)) => match dict.get(&pk) {
+            Some(seeds) => serialized_response(seeds),
+            None => Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .body(Vec::new())
+                .unwrap(),
+        },
+        Ok(None) => Response::builder()
             .status(StatusCode::NO_CONTENT)
             .body(Vec::new())
             .unwrap(),
@@ -164,12 +451,19 @@ async fn handle_seeds<F: Fetcher>(
 }
 
 /// Handles and responds to a request for the global model.
-async fn handle_model<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Infallible> {
-    Ok(match fetcher.model().await {
-        Ok(Some(model)) => Response::builder()
-            .status(StatusCode::OK)
-            .body(bincode::serialize(model.as_ref()).unwrap())
-            .unwrap(),
+async fn handle_model<F: Fetcher>(
+    mut fetcher: F,
+    query: ModelQuery,
+) -> Result<impl warp::Reply, Infallible> {
+    let model = fetcher
+        .model()
+        .await
+        .map(|model| model.filter(|(version, _)| query.version.map_or(true, |v| v == *version)));
+    Ok(match model {
+        Ok(Some((_, model))) => match query.format {
+            None | Some(ModelFormat::Bincode) => serialized_response(model.as_ref()),
+            Some(format) => converted_response(&mut fetcher, &model, format).await,
+        },
         Ok(None) => Response::builder()
             .status(StatusCode::NO_CONTENT)
             .body(Vec::new())
@@ -184,13 +478,39 @@ async fn handle_model<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, In
     })
 }
 
+/// Converts the global model into the requested format, with the data types and the schema of
+/// the current round parameters.
+async fn converted_response<F: Fetcher>(
+    fetcher: &mut F,
+    model: &Model,
+    format: ModelFormat,
+) -> Response<Vec<u8>> {
+    let converted = match fetcher.round_params().await {
+        Ok(params) => format
+            .encode(model, &params.model_config, params.model_schema.as_ref())
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match converted {
+        Ok(bytes) => Response::builder()
+            .header("Content-Type", "application/octet-stream")
+            .status(StatusCode::OK)
+            .body(bytes)
+            .unwrap(),
+        Err(e) => {
+            warn!("Failed to convert the model into the format {}: {}", format, e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Vec::new())
+                .unwrap()
+        }
+    }
+}
+
 /// Handles and responds to a request for the round parameters.
 async fn handle_params<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Infallible> {
     Ok(match fetcher.round_params().await {
-        Ok(params) => Response::builder()
-            .status(StatusCode::OK)
-            .body(bincode::serialize(&params).unwrap())
-            .unwrap(),
+        Ok(params) => serialized_response(&params),
         Err(e) => {
             warn!("Failed to handle round parameters request: {:?}.", e);
             Response::builder()
@@ -201,22 +521,65 @@ async fn handle_params<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, I
     })
 }
 
-/// Converts a PET message handler into a `warp` filter.
-fn with_message_handler(
-    handler: PetMessageHandler,
-) -> impl Filter<Extract = (PetMessageHandler,), Error = Infallible> + Clone {
-    warp::any().map(move || handler.clone())
+/// Handles and responds to a request for the capabilities of the aggregator.
+async fn handle_capabilities<F: Fetcher>(mut fetcher: F) -> Result<impl warp::Reply, Infallible> {
+    Ok(match fetcher.round_params().await {
+        Ok(params) => serialized_response(&Capabilities::new(&params)),
+        Err(e) => {
+            warn!("Failed to handle capabilities request: {:?}.", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Vec::new())
+                .unwrap()
+        }
+    })
+}
+
+/// Serializes `data` into an `OK` response, or into an `INTERNAL_SERVER_ERROR` response if the
+/// serialization fails.
+fn serialized_response<T: Serialize + ?Sized>(data: &T) -> Response<Vec<u8>> {
+    match bincode::serialize(data) {
+        Ok(bytes) => Response::builder()
+            .header("Content-Type", "application/octet-stream")
+            .status(StatusCode::OK)
+            .body(bytes)
+            .unwrap(),
+        Err(e) => {
+            error!("Failed to serialize response: {:?}.", e);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Vec::new())
+                .unwrap()
+        }
+    }
 }
 
-/// Converts a data fetcher into a `warp` filter.
-fn with_fetcher<F: Fetcher + Sync + Send + 'static + Clone>(
-    fetcher: F,
-) -> impl Filter<Extract = (F,), Error = Infallible> + Clone {
-    warp::any().map(move || fetcher.clone())
+/// Checks that a request is authenticated with the admin token as bearer token. Without an
+/// admin token, all the requests are rejected as if the admin API did not exist.
+fn authorization(
+    admin_token: Option<Arc<String>>,
+) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let admin_token = admin_token.clone();
+            async move {
+                let admin_token = admin_token.ok_or_else(warp::reject::not_found)?;
+                let token = header
+                    .as_deref()
+                    .and_then(|header| header.strip_prefix("Bearer "))
+                    .ok_or_else(|| warp::reject::custom(Unauthorized))?;
+                if memcmp(token.as_bytes(), admin_token.as_bytes()) {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(Unauthorized))
+                }
+            }
+        })
+        .untuple_one()
 }
 
 /// Extracts a participant public key from the url query string
-async fn part_pk(query: SeedDictQuery) -> Result<ParticipantPublicKey, warp::Rejection> {
+async fn part_pk(query: PublicKeyQuery) -> Result<ParticipantPublicKey, warp::Rejection> {
     match base64::decode(query.pk.as_bytes()) {
         Ok(bytes) => {
             if let Some(pk) = ParticipantPublicKey::from_slice(&bytes[..]) {
@@ -234,12 +597,41 @@ struct InvalidPublicKey;
 
 impl warp::reject::Reject for InvalidPublicKey {}
 
+/// Decodes a model sent to the admin API.
+async fn initial_model(body: Bytes) -> Result<Arc<Model>, warp::Rejection> {
+    initial::decode_model(&body).map(Arc::new).map_err(|e| {
+        warn!("Failed to decode the model: {}", e);
+        warp::reject::custom(InvalidModel)
+    })
+}
+
+#[derive(Debug)]
+struct InvalidModel;
+
+impl warp::reject::Reject for InvalidModel {}
+
+#[derive(Debug)]
+struct Unauthorized;
+
+impl warp::reject::Reject for Unauthorized {}
+
 /// Handles `warp` rejections of bad requests.
 async fn handle_reject(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
     let code = if err.is_not_found() {
         StatusCode::NOT_FOUND
     } else if let Some(InvalidPublicKey) = err.find() {
         StatusCode::BAD_REQUEST
+    } else if let Some(InvalidModel) = err.find() {
+        StatusCode::BAD_REQUEST
+    } else if err.find::<warp::reject::InvalidQuery>().is_some() {
+        StatusCode::BAD_REQUEST
+    } else if let Some(Unauthorized) = err.find() {
+        StatusCode::UNAUTHORIZED
+    } else if err
+        .find::<warp::filters::body::BodyDeserializeError>()
+        .is_some()
+    {
+        StatusCode::BAD_REQUEST
     } else {
         error!("Unhandled rejection: {:?}", err);
         StatusCode::INTERNAL_SERVER_ERROR
@@ -323,3 +715,250 @@ where
     .await;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggr::DeltaAggregation,
+        services::{fetchers::fetcher, messages::SharedResources},
+        settings::{
+            CompressionSettings, MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings,
+        },
+        state_engine::init::StateEngineInitializer,
+        storage::{
+            aggr_storage::noop::AggrNoOp, model_storage::noop::ModelNoOp, trust_anchor::noop::NoOp,
+            Store,
+        },
+    };
+    use mosaic_core::{
+        common::RoundParameters,
+        mask::{BoundType, GroupType, ModelType},
+        model::{DataType, FromPrimitives},
+    };
+
+    type TestStore = Store<AggrNoOp, ModelNoOp, NoOp>;
+
+    /// Creates the services of a task training for `training_rounds` rounds and runs its state
+    /// engine.
+    async fn task(
+        training_rounds: u32,
+    ) -> TaskServices<impl Fetcher + Sync + Send + Clone + 'static, TestStore> {
+        let mask_settings = MaskSettings {
+            group_type: GroupType::Prime,
+            data_type: DataType::F32,
+            bound_type: BoundType::B0,
+            model_type: ModelType::M3,
+        };
+        let model_settings = ModelSettings {
+            length: Some(4),
+            data_type: DataType::F32,
+            ..ModelSettings::default()
+        };
+        let store = Store::new(AggrNoOp, ModelNoOp);
+        let (state_engine, requests_tx, control, subscriber) = StateEngineInitializer::new(
+            mask_settings,
+            model_settings.clone(),
+            ProtocolSettings {
+                training_rounds,
+                participants: 1,
+                delta_aggregation: DeltaAggregation::Reconstruct,
+                model_history: 1,
+            },
+            CompressionSettings::default(),
+            MultipartSettings::default(),
+            #[cfg(feature = "model-persistence")]
+            crate::settings::RestoreSettings { enable: false },
+            store.clone(),
+        )
+        .init()
+        .await
+        .unwrap();
+        tokio::spawn(state_engine.run());
+
+        TaskServices {
+            fetcher: fetcher(&subscriber),
+            message_handler: PetMessageHandler::new(
+                &subscriber,
+                requests_tx,
+                mask_settings,
+                &model_settings,
+                CompressionSettings::default(),
+                &SharedResources::new(MultipartSettings::default()),
+            ),
+            control,
+            readiness: ReadinessProbe::new(&subscriber, store),
+        }
+    }
+
+    /// Gets the training rounds of the task whose round parameters are served under `path`, or
+    /// `None` if there is no such task.
+    async fn rounds(
+        routes: &(impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone + 'static),
+        path: &str,
+    ) -> Option<u32> {
+        let response = warp::test::request().path(path).reply(routes).await;
+        if response.status() == StatusCode::NOT_FOUND {
+            return None;
+        }
+        assert_eq!(response.status(), StatusCode::OK);
+        let params: RoundParameters = bincode::deserialize(response.body()).unwrap();
+        Some(params.training_rounds)
+    }
+
+    #[tokio::test]
+    async fn test_task_routing() {
+        let tasks = vec![
+            ("first".to_string(), task(1).await),
+            ("second".to_string(), task(2).await),
+        ];
+        let routes = routes(tasks, None);
+
+        assert_eq!(rounds(&routes, "/params").await, Some(1));
+        assert_eq!(rounds(&routes, "/tasks/first/params").await, Some(1));
+        assert_eq!(rounds(&routes, "/tasks/second/params").await, Some(2));
+        assert_eq!(rounds(&routes, "/tasks/third/params").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_admin() {
+        let routes = routes(
+            vec![("task".to_string(), task(1).await)],
+            Some("secret".into()),
+        );
+        let admin = |path: &str, token: Option<&str>| {
+            let request = warp::test::request().method("POST").path(path);
+            match token {
+                Some(token) => request.header("authorization", format!("Bearer {}", token)),
+                None => request,
+            }
+        };
+
+        let response = admin("/admin/pause", None).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = admin("/admin/pause", Some("guess")).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = admin("/tasks/task/admin/pause", Some("secret"))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // the state engine answers the requests
+        let response = admin("/admin/config", Some("secret"))
+            .json(&HashMap::from([("participants", 0)]))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = admin("/admin/aggregate", Some("secret"))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(
+            response.body(),
+            ControlError::NothingToAggregate.to_string().as_bytes()
+        );
+
+        // without an admin token, there is no admin API
+        let routes = super::routes(vec![("task".to_string(), task(1).await)], None);
+        let response = admin("/admin/pause", Some("secret")).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_set_initial_model() {
+        let routes = routes(
+            vec![("task".to_string(), task(1).await)],
+            Some("secret".into()),
+        );
+        let set_model = |model: &Model| {
+            warp::test::request()
+                .method("POST")
+                .path("/admin/model")
+                .header("authorization", "Bearer secret")
+                .body(bincode::serialize(model).unwrap())
+        };
+        let get_model = |path: &str| warp::test::request().path(path);
+
+        let response = get_model("/model").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let model = Model::from_primitives(vec![1_f32, 2.0, 3.0, 4.0].into_iter()).unwrap();
+        let response = set_model(&model).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        for path in ["/model", "/model?version=0", "/tasks/task/model"] {
+            let response = get_model(path).reply(&routes).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(bincode::deserialize::<Model>(response.body()).unwrap(), model);
+        }
+        let response = get_model("/model?version=1").reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        // the weights must be representable in the data type of the models
+        let invalid = Model::from_primitives(vec![1e300_f64; 4].into_iter()).unwrap();
+        let response = set_model(&invalid).reply(&routes).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = get_model("/model").reply(&routes).await;
+        assert_eq!(bincode::deserialize::<Model>(response.body()).unwrap(), model);
+    }
+
+    #[test]
+    fn test_control_status() {
+        assert_eq!(
+            control_status(&ControlError::EngineStopped),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            control_status(&ControlError::NotCollecting),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            control_status(&ControlError::TrainingStarted),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            control_status(&ControlError::InvalidModel("too short".into())),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            control_status(&ControlError::SaveModel("disk full".into())),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_message_status() {
+        // rejected messages must not be sent again
+        assert_eq!(
+            message_status(&ServiceError::InvalidChunkId(9000)),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            message_status(&ServiceError::MultipartLimit(
+                "too many partial messages for this participant"
+            )),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            message_status(&ServiceError::StateEngine(RequestError::MessageRejected)),
+            StatusCode::CONFLICT
+        );
+
+        // messages the aggregator can't take for now may be sent again
+        assert_eq!(
+            message_status(&ServiceError::MultipartCapacity(
+                "too many bytes of partial messages"
+            )),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            message_status(&ServiceError::StateEngine(RequestError::CollectionPaused)),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(
+            message_status(&ServiceError::StateEngine(RequestError::InternalError(
+                "broken"
+            ))),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+}
@@ -17,8 +17,9 @@ pub struct ModelRequest;
 
 /// [`ModelService`]'s response type.
 ///
-/// The response is `None` when no model is currently available.
-pub type ModelResponse = Option<Arc<Model>>;
+/// The response is the latest global model and its version, or `None` when no model is currently
+/// available.
+pub type ModelResponse = Option<(u32, Arc<Model>)>;
 
 /// A service that serves the latest available global model
 pub struct ModelService(EventListener<ModelUpdate>);
@@ -41,7 +42,7 @@ impl Service<ModelRequest> for ModelService {
     fn call(&mut self, _req: ModelRequest) -> Self::Future {
         future::ready(match self.0.get_latest().event {
             ModelUpdate::Invalidate => Ok(None),
-            ModelUpdate::New(model) => Ok(Some(model)),
+            ModelUpdate::New(version, model) => Ok(Some((version, model))),
         })
         .instrument(error_span!("model_fetch_request"))
     }
@@ -0,0 +1,198 @@
+//! This module provides the readiness checks of the coordinator.
+//!
+//! A task is ready if its state engine is running and if all its storage backends are ready to
+//! process requests.
+
+use futures::future::join3;
+use serde::Serialize;
+
+use crate::{
+    state_engine::{
+        events::{EventListener, EventSubscriber},
+        states::StateName,
+    },
+    storage::{AggregatorStorage, ModelStorage, Storage, StorageResult, TrustAnchor},
+};
+
+/// The status of a component of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ComponentStatus {
+    /// Whether the component is ready.
+    pub ready: bool,
+    /// The reason why the component is not ready.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ComponentStatus {
+    /// Creates the status of a ready component.
+    fn ready() -> Self {
+        Self {
+            ready: true,
+            error: None,
+        }
+    }
+
+    /// Creates the status of a component which is not ready.
+    fn not_ready(error: impl ToString) -> Self {
+        Self {
+            ready: false,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+impl From<StorageResult<()>> for ComponentStatus {
+    fn from(result: StorageResult<()>) -> Self {
+        match result {
+            Ok(()) => Self::ready(),
+            Err(err) => Self::not_ready(err),
+        }
+    }
+}
+
+/// The readiness of a task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TaskReadiness {
+    /// Whether all the components of the task are ready.
+    pub ready: bool,
+    /// The status of the state engine.
+    pub state_engine: ComponentStatus,
+    /// The status of the aggregator storage.
+    pub aggregator_storage: ComponentStatus,
+    /// The status of the model storage.
+    pub model_storage: ComponentStatus,
+    /// The status of the trust anchor.
+    pub trust_anchor: ComponentStatus,
+}
+
+/// A probe for the readiness of a task.
+#[derive(Debug, Clone)]
+pub struct ReadinessProbe<S> {
+    /// A listener for the state of the state engine.
+    state: EventListener<StateName>,
+    /// The store of the task.
+    store: S,
+}
+
+impl<S> ReadinessProbe<S>
+where
+    S: Storage,
+{
+    /// Creates a new readiness probe for the task with the given events and store.
+    pub fn new(event_subscriber: &EventSubscriber, store: S) -> Self {
+        Self {
+            state: event_subscriber.state_listener(),
+            store,
+        }
+    }
+
+    /// Checks the readiness of the task.
+    pub async fn check(&mut self) -> TaskReadiness {
+        let state_engine = if self.state.is_closed() {
+            ComponentStatus::not_ready("the state engine has stopped")
+        } else {
+            match self.state.get_latest().event {
+                state @ (StateName::Failure | StateName::Shutdown) => {
+                    ComponentStatus::not_ready(format!("the state engine is in state {}", state))
+                }
+                _ => ComponentStatus::ready(),
+            }
+        };
+        let mut model_store = self.store.clone();
+        let mut trust_anchor = self.store.clone();
+        let (aggregator_storage, model_storage, trust_anchor) = join3(
+            <S as AggregatorStorage>::is_ready(&mut self.store),
+            <S as ModelStorage>::is_ready(&mut model_store),
+            <S as TrustAnchor>::is_ready(&mut trust_anchor),
+        )
+        .await;
+        let (aggregator_storage, model_storage, trust_anchor) = (
+            ComponentStatus::from(aggregator_storage),
+            ComponentStatus::from(model_storage),
+            ComponentStatus::from(trust_anchor),
+        );
+        TaskReadiness {
+            ready: state_engine.ready
+                && aggregator_storage.ready
+                && model_storage.ready
+                && trust_anchor.ready,
+            state_engine,
+            aggregator_storage,
+            model_storage,
+            trust_anchor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aggr::{Aggregator, DeltaAggregation},
+        settings::{
+            CompressionSettings, MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings,
+        },
+        state_engine::events::{EventPublisher, ModelUpdate},
+        storage::{aggr_storage::noop::AggrNoOp, model_storage::noop::ModelNoOp, Store},
+    };
+    use mosaic_core::{
+        mask::{BoundType, GroupType, ModelType},
+        model::DataType,
+    };
+
+    fn events() -> (EventPublisher, EventSubscriber) {
+        let _ = sodiumoxide::init();
+        let aggr = Aggregator::new(
+            MaskSettings {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            },
+            ModelSettings {
+                length: None,
+                data_type: DataType::F32,
+                tensors: Vec::new(),
+                initial_model: None,
+                max_l2_norm: None,
+                max_linf_norm: None,
+                privacy: None,
+            },
+            &ProtocolSettings {
+                training_rounds: 1,
+                participants: 1,
+                delta_aggregation: DeltaAggregation::Reconstruct,
+                model_history: 1,
+            },
+            &CompressionSettings::default(),
+            &MultipartSettings::default(),
+        );
+        EventPublisher::init(
+            aggr.round_id,
+            aggr.keys,
+            aggr.round_params,
+            StateName::Idle,
+            ModelUpdate::Invalidate,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_readiness() {
+        let (mut publisher, subscriber) = events();
+        let mut probe = ReadinessProbe::new(&subscriber, Store::new(AggrNoOp, ModelNoOp));
+        assert!(probe.check().await.ready);
+
+        publisher.broadcast_state(StateName::Failure);
+        let readiness = probe.check().await;
+        assert!(!readiness.ready);
+        assert!(!readiness.state_engine.ready);
+        assert!(readiness.aggregator_storage.ready);
+
+        drop(publisher);
+        assert_eq!(
+            probe.check().await.state_engine,
+            ComponentStatus::not_ready("the state engine has stopped")
+        );
+    }
+}
@@ -2,7 +2,7 @@ use displaydoc::Display;
 use thiserror::Error;
 
 use crate::state_engine::channel::RequestError;
-use mosaic_core::message::DecodeError;
+use mosaic_core::{message::DecodeError, model::DataType};
 
 /// Errors for the message parsing service.
 #[derive(Debug, Display, Error)]
@@ -11,6 +11,8 @@ pub enum ServiceError {
     Decrypt,
     /// Failed to parse the message: {0}.
     Parsing(DecodeError),
+    /// Unsupported protocol version {0}.
+    ProtocolVersion(u8),
     /// Invalid message signature.
     InvalidMessageSignature,
     /// Invalid coordinator public key.
@@ -21,10 +23,35 @@ pub enum ServiceError {
     // conversion into a service error
     /// The state machine failed to process the request: {0}.
     StateEngine(RequestError),
+    /// Invalid multipart message: chunk ID {0} exceeds the maximum chunk ID.
+    InvalidChunkId(u16),
+    /// Multipart message rejected: {0}.
+    MultipartLimit(&'static str),
+    /// Multipart message postponed, the aggregator is out of capacity: {0}.
+    MultipartCapacity(&'static str),
     /// Participant is not eligible for sum task.
     NotSumEligible,
     /// Participant is not eligible for update task.
     NotUpdateEligible,
+    /// Invalid update: the model has {actual} weights but {expected} are expected.
+    ModelLength { expected: usize, actual: usize },
+    /// Invalid update: the model schema differs from the model schema of the round.
+    ModelSchema,
+    /// Invalid update: the model data type {actual:?} differs from the round's {expected:?}.
+    ModelDataType {
+        expected: DataType,
+        actual: DataType,
+    },
+    /// Invalid update: the weight at index {0} is not representable in the model data type.
+    WeightNotRepresentable(usize),
+    /// Invalid update: the weight at index {0} exceeds the weight bound.
+    WeightOutOfBounds(usize),
+    /// Invalid update: the L2 norm {norm} of the model exceeds the cap {max}.
+    L2NormExceeded { norm: f64, max: f64 },
+    /// Invalid update: the L∞ norm {norm} of the model exceeds the cap {max}.
+    LinfNormExceeded { norm: f64, max: f64 },
+    /// Invalid update: the model is not privatized as required by the round.
+    InsufficientPrivacy,
     /// Internal error: {0}.
     InternalError(String),
 }
@@ -1,6 +1,14 @@
-use std::{convert::TryInto, sync::Arc, task::Poll};
+use std::{
+    convert::{TryFrom, TryInto},
+    sync::Arc,
+    task::Poll,
+};
 
-use futures::{future, task::Context};
+use anyhow::anyhow;
+use futures::{
+    future::{self, poll_fn},
+    task::Context,
+};
 use rayon::ThreadPool;
 use tokio::sync::oneshot;
 use tower::{layer::Layer, limit::concurrency::ConcurrencyLimit, Service, ServiceBuilder};
@@ -8,15 +16,203 @@ use tracing::{debug, trace, warn};
 
 use crate::{
     services::messages::{BoxedServiceFuture, ServiceError},
+    settings::CompressionSettings,
     state_engine::{
         events::{EventListener, EventSubscriber},
         states::StateName,
     },
 };
 use mosaic_core::{
+    common::PROTOCOL_VERSION,
     crypto::{EncryptKeyPair, PublicEncryptKey},
-    message::{FromBytes, Message, MessageBuffer, Tag},
+    message::{
+        decompress_message, Codec, DecodeError, Flags, FromBytes, Message, MessageBuffer, Tag,
+    },
 };
+#[cfg(not(feature = "secure"))]
+use mosaic_core::{message::Payload, model::EncodedModelObject};
+
+/// A service that discards messages built for another protocol
+/// version, before any work is spent on them.
+#[derive(Debug, Clone)]
+struct VersionFilter<S>(S);
+
+impl<T, S> Service<T> for VersionFilter<S>
+where
+    T: AsRef<[u8]> + Send + 'static,
+    S: Service<T, Response = Message, Error = ServiceError>,
+    S::Future: Sync + Send + 'static,
+{
+    type Response = Message;
+    type Error = ServiceError;
+    type Future = BoxedServiceFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: T) -> Self::Future {
+        // Invalid buffers are rejected by the next services
+        if let Ok(buffer) = MessageBuffer::new(req.as_ref()) {
+            if buffer.version() != PROTOCOL_VERSION {
+                warn!(
+                    "Found an unsupported protocol version {}.",
+                    buffer.version()
+                );
+                return Box::pin(future::ready(Err(ServiceError::ProtocolVersion(
+                    buffer.version(),
+                ))));
+            }
+        }
+        Box::pin(self.0.call(req))
+    }
+}
+
+struct VersionFilterLayer;
+
+impl<S> Layer<S> for VersionFilterLayer {
+    type Service = VersionFilter<S>;
+
+    fn layer(&self, service: S) -> VersionFilter<S> {
+        VersionFilter(service)
+    }
+}
+
+/// A buffer that holds a message, restored from its compressed form if
+/// it was sent compressed.
+enum MaybeDecompressed<T> {
+    /// The message as it was received
+    Raw(T),
+    /// The decompressed message
+    Decompressed(Vec<u8>),
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for MaybeDecompressed<T> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            MaybeDecompressed::Raw(bytes) => bytes.as_ref(),
+            MaybeDecompressed::Decompressed(bytes) => bytes.as_ref(),
+        }
+    }
+}
+
+/// A service that decompresses compressed single messages, since their
+/// signature is computed over the uncompressed message. Compressed
+/// multipart messages are decompressed once they are reassembled.
+///
+/// Since this is a CPU-intensive task for large messages, this
+/// service offloads the processing to a `rayon` thread-pool to avoid
+/// overloading the tokio thread-pool with blocking tasks.
+#[derive(Debug, Clone)]
+struct Decompressor<S> {
+    /// The accepted codecs and the maximum length of a decompressed
+    /// message
+    settings: Arc<CompressionSettings>,
+    /// Thread-pool the CPU-intensive tasks are offloaded to.
+    thread_pool: Arc<ThreadPool>,
+    /// Next service to be called
+    next_svc: S,
+}
+
+impl<S> Decompressor<S> {
+    /// Returns the codec of a compressed single message, `None` if
+    /// there is nothing to decompress.
+    fn codec(&self, bytes: &[u8]) -> Result<Option<Codec>, ServiceError> {
+        let buffer = match MessageBuffer::new(bytes) {
+            Ok(buffer) => buffer,
+            // Invalid buffers are rejected by the next service
+            Err(_) => return Ok(None),
+        };
+        let flags = buffer.flags();
+        if !flags.contains(Flags::COMPRESSED) || flags.contains(Flags::MULTIPART) {
+            return Ok(None);
+        }
+        let codec = Codec::try_from(buffer.codec()).map_err(ServiceError::Parsing)?;
+        if self.settings.codecs.contains(&codec) {
+            Ok(Some(codec))
+        } else {
+            Err(ServiceError::Parsing(anyhow!(
+                "codec {:?} is not accepted",
+                codec
+            )))
+        }
+    }
+}
+
+impl<T, S> Service<T> for Decompressor<S>
+where
+    T: AsRef<[u8]> + Sync + Send + 'static,
+    S: Service<MaybeDecompressed<T>, Response = Message, Error = ServiceError>
+        + Clone
+        + Sync
+        + Send
+        + 'static,
+    S::Future: Sync + Send + 'static,
+{
+    type Response = Message;
+    type Error = ServiceError;
+    type Future = BoxedServiceFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // The next service is polled in the returned future, on the
+        // clone that handles the request. Polling it here too would
+        // make each request hold two of its concurrency permits.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: T) -> Self::Future {
+        let mut next_svc = self.next_svc.clone();
+        let codec = match self.codec(req.as_ref()) {
+            Ok(codec) => codec,
+            Err(e) => return Box::pin(future::ready(Err(e))),
+        };
+        if codec.is_none() {
+            return Box::pin(async move {
+                poll_fn(|cx| next_svc.poll_ready(cx)).await?;
+                next_svc.call(MaybeDecompressed::Raw(req)).await
+            });
+        }
+
+        let (tx, rx) = oneshot::channel::<Result<Vec<u8>, ServiceError>>();
+        let max_message_length = self.settings.max_message_length;
+        trace!("Spawning decompression task on thread-pool.");
+        self.thread_pool.spawn(move || {
+            let res = decompress_message(req.as_ref(), max_message_length).map_err(|e| {
+                warn!("Failed to decompress message: {:?}.", e);
+                ServiceError::Parsing(e)
+            });
+            let _ = tx.send(res);
+        });
+
+        Box::pin(async move {
+            let bytes = rx.await.map_err(|_| {
+                ServiceError::InternalError(
+                    "failed to receive response from thread-pool".to_string(),
+                )
+            })??;
+            debug!("Decompressed a {:?} message.", codec);
+            poll_fn(|cx| next_svc.poll_ready(cx)).await?;
+            next_svc.call(MaybeDecompressed::Decompressed(bytes)).await
+        })
+    }
+}
+
+struct DecompressorLayer {
+    settings: Arc<CompressionSettings>,
+    thread_pool: Arc<ThreadPool>,
+}
+
+impl<S> Layer<S> for DecompressorLayer {
+    type Service = Decompressor<S>;
+
+    fn layer(&self, service: S) -> Decompressor<S> {
+        Decompressor {
+            settings: self.settings.clone(),
+            thread_pool: self.thread_pool.clone(),
+            next_svc: service,
+        }
+    }
+}
 
 /// A type that hold a un-parsed message
 struct RawMessage<T> {
@@ -294,8 +490,12 @@ impl<S> Layer<S> for CoordinatorPublicKeyValidatorLayer {
     }
 }
 
+/// A service that parses a message and decodes the model of an update
+/// message, up to `max_length` bytes of model values.
 #[derive(Debug, Clone)]
-struct Parser;
+struct Parser {
+    max_length: usize,
+}
 
 impl<T> Service<RawMessage<T>> for Parser
 where
@@ -311,15 +511,48 @@ where
 
     fn call(&mut self, req: RawMessage<T>) -> Self::Future {
         let bytes = req.buffer.inner();
-        future::ready(Message::from_byte_slice(&bytes).map_err(ServiceError::Parsing))
+        future::ready(
+            Message::from_byte_slice(&bytes)
+                .and_then(|message| decode_update(message, self.max_length))
+                .map_err(ServiceError::Parsing),
+        )
     }
 }
 
+/// Decodes the model of an update message sent with a lossy update codec,
+/// so that only dense models reach the aggregation.
+#[cfg(not(feature = "secure"))]
+pub(crate) fn decode_update(
+    mut message: Message,
+    max_length: usize,
+) -> Result<Message, DecodeError> {
+    if let Payload::Update(ref mut update) = message.payload {
+        if let EncodedModelObject::Lossy(ref model_object) = update.model_object {
+            if model_object.decoded_length() > max_length {
+                return Err(anyhow!(
+                    "decoded model exceeds the maximum length of {} bytes",
+                    max_length
+                ));
+            }
+            update.model_object = model_object.decode()?.into();
+        }
+    }
+    Ok(message)
+}
+
+/// Masked models cannot be encoded with lossy update codecs.
+#[cfg(feature = "secure")]
+pub(crate) fn decode_update(message: Message, _max_length: usize) -> Result<Message, DecodeError> {
+    Ok(message)
+}
+
 // type InnerService = BufferWrapper<
 //     PhaseFilter<ConcurrencyLimit<SignatureVerifier<CoordinatorPublicKeyValidator<Parser>>>>,
 // >;
-type InnerService = BufferWrapper<
-    ConcurrencyLimit<SignatureVerifier<CoordinatorPublicKeyValidator<Parser>>>,
+type InnerService = VersionFilter<
+    Decompressor<
+        BufferWrapper<ConcurrencyLimit<SignatureVerifier<CoordinatorPublicKeyValidator<Parser>>>>,
+    >,
 >;
 
 #[derive(Debug, Clone)]
@@ -345,8 +578,18 @@ where
 
 impl MessageParser {
     #[cfg(feature = "secure")]
-    pub fn new(events: &EventSubscriber, thread_pool: Arc<ThreadPool>) -> Self {
+    pub fn new(
+        events: &EventSubscriber,
+        thread_pool: Arc<ThreadPool>,
+        compression_settings: CompressionSettings,
+    ) -> Self {
+        let max_length = compression_settings.max_decompressed_length;
         let inner = ServiceBuilder::new()
+            .layer(VersionFilterLayer)
+            .layer(DecompressorLayer {
+                settings: Arc::new(compression_settings),
+                thread_pool: thread_pool.clone(),
+            })
             .layer(BufferWrapperLayer)
             .layer(PhaseFilterLayer {
                 phase: events.state_listener(),
@@ -355,12 +598,22 @@ impl MessageParser {
             .layer(CoordinatorPublicKeyValidatorLayer {
                 keys: events.keys_listener(),
             })
-            .service(Parser);
+            .service(Parser { max_length });
         Self(inner)
     }
     #[cfg(not(feature = "secure"))]
-    pub fn new(events: &EventSubscriber, thread_pool: Arc<ThreadPool>) -> Self {
+    pub fn new(
+        events: &EventSubscriber,
+        thread_pool: Arc<ThreadPool>,
+        compression_settings: CompressionSettings,
+    ) -> Self {
+        let max_length = compression_settings.max_decompressed_length;
         let inner = ServiceBuilder::new()
+            .layer(VersionFilterLayer)
+            .layer(DecompressorLayer {
+                settings: Arc::new(compression_settings),
+                thread_pool: thread_pool.clone(),
+            })
             .layer(BufferWrapperLayer)
             // .layer(PhaseFilterLayer {
             //     phase: events.state_listener(),
@@ -369,7 +622,266 @@ impl MessageParser {
             .layer(CoordinatorPublicKeyValidatorLayer {
                 keys: events.keys_listener(),
             })
-            .service(Parser);
+            .service(Parser { max_length });
         Self(inner)
     }
 }
+
+#[cfg(test)]
+pub(in crate::services::messages) mod tests {
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+    use rayon::ThreadPoolBuilder;
+
+    use super::*;
+    use crate::{
+        aggr::{Aggregator, DeltaAggregation},
+        settings::{MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings},
+        state_engine::events::{EventPublisher, ModelUpdate},
+    };
+    use mosaic_core::{
+        crypto::{ByteObject, Signature, SigningKeyPair},
+        mask::{BoundType, GroupType, ModelType},
+        message::{compress_message, Flags, Sum, MESSAGE_HEADER_LENGTH},
+        model::DataType,
+    };
+    #[cfg(not(feature = "secure"))]
+    use mosaic_core::{
+        message::{Update, UpdateKind},
+        model::{ModelConfig, Quantization, Sparsification, UpdateCodec},
+    };
+
+    /// Number of random inputs fed through the services by each test.
+    pub const ITERATIONS: usize = 2_000;
+
+    /// Initializes the events of an aggregator that just started.
+    pub fn events() -> (EventPublisher, EventSubscriber, EncryptKeyPair) {
+        let _ = sodiumoxide::init();
+        let aggr = Aggregator::new(
+            MaskSettings {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            },
+            ModelSettings {
+                length: None,
+                data_type: DataType::F32,
+                tensors: Vec::new(),
+                initial_model: None,
+                max_l2_norm: None,
+                max_linf_norm: None,
+                privacy: None,
+            },
+            &ProtocolSettings {
+                training_rounds: 1,
+                participants: 1,
+                delta_aggregation: DeltaAggregation::Reconstruct,
+                model_history: 1,
+            },
+            &CompressionSettings::default(),
+            &MultipartSettings::default(),
+        );
+        let keys = aggr.keys.clone();
+        let (publisher, subscriber) = EventPublisher::init(
+            aggr.round_id,
+            aggr.keys,
+            aggr.round_params,
+            StateName::Update,
+            ModelUpdate::Invalidate,
+        );
+        (publisher, subscriber, keys)
+    }
+
+    /// Builds a message with a valid header and signature but a random tag, random flags and a
+    /// random payload, so that it makes it past the signature check.
+    pub fn signed_garbage(
+        rng: &mut ChaCha20Rng,
+        keys: &SigningKeyPair,
+        coordinator_pk: &PublicEncryptKey,
+    ) -> Vec<u8> {
+        let mut bytes = vec![0; MESSAGE_HEADER_LENGTH + rng.gen_range(0..512)];
+        let length = bytes.len() as u32;
+        let mut writer = MessageBuffer::new_unchecked(&mut bytes);
+        writer
+            .participant_pk_mut()
+            .copy_from_slice(keys.public.as_slice());
+        writer
+            .coordinator_pk_mut()
+            .copy_from_slice(coordinator_pk.as_slice());
+        writer.set_tag(rng.gen_range(0..5));
+        writer.set_flags(Flags::from_bits_truncate(rng.gen()));
+        writer.set_codec(rng.gen_range(0..4));
+        writer.set_version(PROTOCOL_VERSION);
+        writer.set_length(length);
+        rng.fill_bytes(writer.payload_mut());
+        let signature = keys.secret.sign_detached(writer.signed_data_mut());
+        writer
+            .signature_mut()
+            .copy_from_slice(signature.as_slice());
+        bytes
+    }
+
+    async fn parse(parser: &mut MessageParser, data: Vec<u8>) -> Result<Message, ServiceError> {
+        futures::future::poll_fn(|cx| <MessageParser as Service<Vec<u8>>>::poll_ready(parser, cx))
+            .await?;
+        parser.call(data).await
+    }
+
+    fn parser(subscriber: &EventSubscriber) -> MessageParser {
+        parser_with(subscriber, CompressionSettings::default())
+    }
+
+    fn parser_with(
+        subscriber: &EventSubscriber,
+        compression_settings: CompressionSettings,
+    ) -> MessageParser {
+        let thread_pool = Arc::new(ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        MessageParser::new(subscriber, thread_pool, compression_settings)
+    }
+
+    #[tokio::test]
+    async fn test_parse_arbitrary_bytes() {
+        let (_publisher, subscriber, _) = events();
+        let mut parser = parser(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+        for _ in 0..ITERATIONS {
+            let mut data = vec![0; rng.gen_range(0..512)];
+            rng.fill_bytes(&mut data);
+            assert!(parse(&mut parser, data).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_arbitrary_length_field() {
+        let (_publisher, subscriber, _) = events();
+        let mut parser = parser(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+
+        for _ in 0..ITERATIONS {
+            let mut data = vec![0; MESSAGE_HEADER_LENGTH + rng.gen_range(0..64)];
+            rng.fill_bytes(&mut data);
+            // lengths around the header length are the interesting ones
+            let length = rng.gen_range(0..data.len() as u32 + 1);
+            MessageBuffer::new_unchecked(&mut data).set_length(length);
+            assert!(parse(&mut parser, data).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_signed_garbage() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let mut parser = parser(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let keys = SigningKeyPair::generate();
+
+        for _ in 0..ITERATIONS {
+            let data = signed_garbage(&mut rng, &keys, &coordinator_keys.public);
+            // the payload may happen to be valid, we only care about not panicking
+            let _ = parse(&mut parser, data).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_compressed() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let keys = SigningKeyPair::generate();
+        let sum = Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        };
+        let message = Message::new_sum(keys.public, coordinator_keys.public, sum);
+        let mut bytes = vec![0; message.buffer_length()];
+        message.to_bytes(&mut bytes, &keys.secret);
+
+        let mut parser = parser(&subscriber);
+        let expected = parse(&mut parser, bytes.clone()).await.unwrap();
+        for codec in Codec::ALL {
+            let compressed = compress_message(&bytes, codec).unwrap();
+            assert_eq!(parse(&mut parser, compressed).await.unwrap(), expected);
+        }
+
+        // codecs that are not accepted and messages that decompress to more than the maximum
+        // length are rejected
+        let mut parser = parser_with(
+            &subscriber,
+            CompressionSettings {
+                codecs: vec![Codec::Zstd],
+                max_message_length: bytes.len() - 1,
+                ..CompressionSettings::default()
+            },
+        );
+        for codec in Codec::ALL {
+            let compressed = compress_message(&bytes, codec).unwrap();
+            assert!(matches!(
+                parse(&mut parser, compressed).await,
+                Err(ServiceError::Parsing(_))
+            ));
+        }
+    }
+
+    #[cfg(not(feature = "secure"))]
+    #[tokio::test]
+    async fn test_parse_lossy_update() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let keys = SigningKeyPair::generate();
+        let values = (0..600).map(|i| i as f64 / 600.).collect::<Vec<_>>();
+        let codec = UpdateCodec {
+            quantization: Some(Quantization::Bits4),
+            sparsification: Some(Sparsification::TopK(0.1)),
+        };
+        let config = ModelConfig {
+            data_type: DataType::F32,
+        };
+        let model_object = codec
+            .encode(&values, config, &mut ChaCha20Rng::seed_from_u64(4))
+            .unwrap();
+        let update = Update {
+            update_signature: Signature::zeroed(),
+            kind: UpdateKind::Full,
+            base_model_version: 0,
+            privacy: None,
+            model_object: model_object.clone().into(),
+        };
+        let message = Message::new_update(keys.public, coordinator_keys.public, update);
+        let mut bytes = vec![0; message.buffer_length()];
+        message.to_bytes(&mut bytes, &keys.secret);
+
+        let mut parser = parser(&subscriber);
+        match parse(&mut parser, bytes.clone()).await.unwrap().payload {
+            Payload::Update(update) => {
+                assert_eq!(update.model_object, model_object.decode().unwrap().into())
+            }
+            payload => panic!("unexpected payload {:?}", payload),
+        }
+
+        // the decoded model must not exceed the maximum decompressed length
+        let mut parser = parser_with(
+            &subscriber,
+            CompressionSettings {
+                max_decompressed_length: model_object.decoded_length() - 1,
+                ..CompressionSettings::default()
+            },
+        );
+        assert!(matches!(
+            parse(&mut parser, bytes).await,
+            Err(ServiceError::Parsing(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_parse_other_version() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let mut parser = parser(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(3);
+        let keys = SigningKeyPair::generate();
+
+        let mut data = signed_garbage(&mut rng, &keys, &coordinator_keys.public);
+        MessageBuffer::new_unchecked(&mut data).set_version(PROTOCOL_VERSION + 1);
+        assert!(matches!(
+            parse(&mut parser, data).await,
+            Err(ServiceError::ProtocolVersion(version)) if version == PROTOCOL_VERSION + 1
+        ));
+    }
+}
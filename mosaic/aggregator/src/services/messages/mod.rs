@@ -9,35 +9,75 @@ mod message_parser;
 mod multipart;
 mod state_engine;
 mod task_validator;
+mod update_validator;
 
 use std::sync::Arc;
 
 use futures::future::poll_fn;
-use rayon::ThreadPoolBuilder;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 use tower::Service;
-use mosaic_core::message::Message;
+use mosaic_core::{crypto::PublicSigningKey, message::Message};
 
 pub use self::error::ServiceError;
 use self::{
     decryptor::Decryptor,
     message_parser::MessageParser,
-    multipart::MultipartHandler,
+    multipart::{MultipartBudget, MultipartHandler},
     state_engine::StateEngine,
     task_validator::TaskValidator,
+    update_validator::UpdateValidator,
+};
+use crate::{
+    settings::{CompressionSettings, MaskSettings, ModelSettings, MultipartSettings},
+    state_engine::{channel::RequestSender, events::EventSubscriber},
 };
-use crate::state_engine::{events::EventSubscriber, channel::RequestSender};
 
-impl PetMessageHandler {
-    pub fn new(event_subscriber: &EventSubscriber, requests_tx: RequestSender) -> Self {
+/// The resources shared by the message handlers of all the tasks hosted
+/// by the aggregator: the thread pool the CPU intensive work runs on,
+/// and the memory of the partial multipart messages.
+#[derive(Clone)]
+pub struct SharedResources {
+    thread_pool: Arc<ThreadPool>,
+    multipart_settings: MultipartSettings,
+    multipart_budget: MultipartBudget,
+}
+
+impl SharedResources {
+    pub fn new(multipart_settings: MultipartSettings) -> Self {
         // TODO: make this configurable. Users should be able to
         // choose how many threads they want etc.
         //
         // TODO: don't unwrap
         let thread_pool = Arc::new(ThreadPoolBuilder::new().build().unwrap());
+        Self {
+            thread_pool,
+            multipart_settings,
+            multipart_budget: MultipartBudget::default(),
+        }
+    }
+}
+
+impl PetMessageHandler {
+    pub fn new(
+        event_subscriber: &EventSubscriber,
+        requests_tx: RequestSender,
+        mask_settings: MaskSettings,
+        model_settings: &ModelSettings,
+        compression_settings: CompressionSettings,
+        resources: &SharedResources,
+    ) -> Self {
+        let thread_pool = resources.thread_pool.clone();
         let decryptor = Decryptor::new(event_subscriber, thread_pool.clone());
-        let multipart_handler = MultipartHandler::new();
-        let message_parser = MessageParser::new(event_subscriber, thread_pool);
+        let multipart_handler = MultipartHandler::new(
+            resources.multipart_settings,
+            resources.multipart_budget.clone(),
+            compression_settings.clone(),
+        );
+        let message_parser =
+            MessageParser::new(event_subscriber, thread_pool, compression_settings);
         let task_validator = TaskValidator::new(event_subscriber);
+        let update_validator =
+            UpdateValidator::new(event_subscriber, mask_settings, model_settings);
         let state_machine = StateEngine::new(requests_tx);
 
         Self {
@@ -45,6 +85,7 @@ impl PetMessageHandler {
             multipart_handler,
             message_parser,
             task_validator,
+            update_validator,
             state_machine,
         }
     }
@@ -72,17 +113,30 @@ impl PetMessageHandler {
         self.task_validator.call(message).await
     }
 
+    async fn validate_update(&mut self, message: Message) -> Result<Message, ServiceError> {
+        poll_fn(|cx| self.update_validator.poll_ready(cx)).await?;
+        self.update_validator.call(message).await
+    }
+
     async fn process(&mut self, message: Message) -> Result<(), ServiceError> {
         poll_fn(|cx| self.state_machine.poll_ready(cx)).await?;
         self.state_machine.call(message).await
     }
 
+    /// Returns the IDs of the chunks the aggregator received so far for
+    /// the given multipart message, or `None` if it has no such partial
+    /// message.
+    pub fn chunk_ids(&self, participant_pk: PublicSigningKey, message_id: u16) -> Option<Vec<u16>> {
+        self.multipart_handler.chunk_ids(participant_pk, message_id)
+    }
+
     pub async fn handle_message(&mut self, enc_data: Vec<u8>) -> Result<(), ServiceError> {
         let raw_message = self.decrypt(enc_data).await?;
         let message = self.parse(raw_message).await?;
         match self.handle_multipart(message).await? {
             Some(message) => {
                 let message = self.validate_task(message).await?;
+                let message = self.validate_update(message).await?;
                 self.process(message).await
             }
             None => Ok(()),
@@ -93,7 +147,7 @@ impl PetMessageHandler {
 /// A service that processes requests from the beginning to the
 /// end.
 ///
-/// The processing is divided in three phases:
+/// The processing is divided in four phases:
 ///
 /// 1. The raw request (which is just a vector of bytes represented an
 ///    encrypted message) goes through the `MessageParser` service,
@@ -103,16 +157,129 @@ impl PetMessageHandler {
 ///    the message type performs some additional checks. The
 ///    `TaskValidator` may also discard the message
 ///
-/// 3. Finally, the message is handled by the `StateEngine` service.
+/// 3. Update messages are passed to the `UpdateValidator`, which
+///    rejects models that don't match the round or exceed the limits
+///    of the model settings
+///
+/// 4. Finally, the message is handled by the `StateEngine` service.
 #[derive(Clone)]
 pub struct PetMessageHandler {
     decryptor: Decryptor,
     multipart_handler: MultipartHandler,
     message_parser: MessageParser,
     task_validator: TaskValidator,
+    update_validator: UpdateValidator,
     state_machine: StateEngine,
 }
 
 pub type BoxedServiceFuture<Response, Error> = std::pin::Pin<
     Box<dyn futures::Future<Output = Result<Response, Error>> + 'static + Send + Sync>,
 >;
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, RngCore, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::state_engine::channel::{RequestError, RequestReceiver};
+    use message_parser::tests::{events, signed_garbage, ITERATIONS};
+    use mosaic_core::{
+        crypto::SigningKeyPair,
+        mask::{BoundType, GroupType, ModelType},
+        message::{Chunk, Message, Tag},
+        model::DataType,
+    };
+
+    /// Creates a message handler whose requests are all rejected by a dummy state engine, so
+    /// that only internal failures surface as [`ServiceError::InternalError`].
+    fn handler(subscriber: &EventSubscriber) -> PetMessageHandler {
+        let (mut requests_rx, requests_tx) = RequestReceiver::new();
+        tokio::spawn(async move {
+            while let Some((_, _, resp_tx)) = requests_rx.recv().await {
+                let _ = resp_tx.send(Err(RequestError::MessageRejected));
+            }
+        });
+        PetMessageHandler::new(
+            subscriber,
+            requests_tx,
+            MaskSettings {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            },
+            &ModelSettings::default(),
+            CompressionSettings::default(),
+            &SharedResources::new(MultipartSettings::default()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_handle_arbitrary_bytes() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let mut handler = handler(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+
+        for _ in 0..ITERATIONS {
+            let mut data = vec![0; rng.gen_range(0..512)];
+            rng.fill_bytes(&mut data);
+            assert!(handler.handle_message(data.clone()).await.is_err());
+            let enc_data = coordinator_keys.public.encrypt(&data);
+            assert!(handler.handle_message(enc_data).await.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_signed_garbage() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let mut handler = handler(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        // a few participants, so that chunks of different senders interleave
+        let participants = (0..4)
+            .map(|_| SigningKeyPair::generate())
+            .collect::<Vec<_>>();
+
+        for i in 0..ITERATIONS {
+            let keys = &participants[i % participants.len()];
+            let data = signed_garbage(&mut rng, keys, &coordinator_keys.public);
+            let enc_data = coordinator_keys.public.encrypt(&data);
+            // the payload may happen to be valid, we only care about not panicking
+            let res = handler.handle_message(enc_data).await;
+            assert!(!matches!(res, Err(ServiceError::InternalError(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handle_multipart_garbage() {
+        let (_publisher, subscriber, coordinator_keys) = events();
+        let mut handler = handler(&subscriber);
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let keys = SigningKeyPair::generate();
+
+        for message_id in 0..ITERATIONS as u16 {
+            let tag = [Tag::Sum, Tag::Update, Tag::Sum2][rng.gen_range(0..3)];
+            let nb_chunks = rng.gen_range(1..4);
+            for id in 0..nb_chunks {
+                let mut data = vec![0; rng.gen_range(0..128)];
+                rng.fill_bytes(&mut data);
+                let chunk = Chunk {
+                    id,
+                    message_id,
+                    last: id == nb_chunks - 1,
+                    data,
+                };
+                let message =
+                    Message::new_multipart(keys.public, coordinator_keys.public, chunk, tag);
+                let mut buffer = vec![0; message.buffer_length()];
+                message.to_bytes(&mut buffer, &keys.secret);
+                let enc_data = coordinator_keys.public.encrypt(&buffer);
+                // the reassembled payload is garbage, we only care about not panicking. A
+                // panicking multipart service would close its buffer and show up as an
+                // internal error.
+                let res = handler.handle_message(enc_data).await;
+                assert!(!matches!(res, Err(ServiceError::InternalError(_))));
+            }
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use std::{
-    collections::btree_map::{BTreeMap, IntoIter as BTreeMapIter},
+    collections::btree_map::{BTreeMap, IntoIter as BTreeMapIter, Values},
+    io::{self, BufReader, Bytes, Read},
     iter::{ExactSizeIterator, Iterator},
     vec::IntoIter as VecIter,
 };
@@ -64,6 +65,100 @@ impl Iterator for MultipartMessageBuffer {
 
 impl ExactSizeIterator for MultipartMessageBuffer {}
 
+// Like the iterator, the reader drops the chunks as soon as they have
+// been read.
+impl Read for MultipartMessageBuffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(chunk) = self.current_chunk.as_mut() {
+                let n = chunk.len().min(buf.len());
+                if n > 0 {
+                    buf[..n].copy_from_slice(&chunk.as_slice()[..n]);
+                    chunk.nth(n - 1);
+                    self.consumed += n;
+                    return Ok(n);
+                }
+            }
+            match self.remaining_chunks.next() {
+                Some((_, chunk)) => self.current_chunk = Some(chunk.into_iter()),
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// A reader over borrowed message chunks, ordered by ID.
+pub struct ChunkReader<'a> {
+    /// message chunks that haven't been read yet
+    remaining_chunks: Values<'a, u16, Vec<u8>>,
+    /// remainder of the chunk being read
+    current_chunk: &'a [u8],
+}
+
+impl<'a> ChunkReader<'a> {
+    pub fn new(chunks: &'a BTreeMap<u16, Vec<u8>>) -> Self {
+        Self {
+            remaining_chunks: chunks.values(),
+            current_chunk: &[],
+        }
+    }
+}
+
+impl<'a> Read for ChunkReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.current_chunk.is_empty() && !buf.is_empty() {
+            match self.remaining_chunks.next() {
+                Some(chunk) => self.current_chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        self.current_chunk.read(buf)
+    }
+}
+
+/// A byte iterator over a reader whose length is known upfront, for
+/// instance a decompressed message payload.
+///
+/// The iterator ends early if the reader fails, so that the parsing of
+/// the bytes fails as well.
+pub struct ReadBuffer<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    /// number of bytes that haven't been read yet
+    remaining: usize,
+}
+
+impl<R: Read> ReadBuffer<R> {
+    /// Creates a buffer of the first `length` bytes of the reader.
+    pub fn new(reader: R, length: usize) -> Self {
+        Self {
+            bytes: BufReader::new(reader).bytes(),
+            remaining: length,
+        }
+    }
+}
+
+impl<R: Read> Iterator for ReadBuffer<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let byte = self.bytes.next()?.ok()?;
+        self.remaining -= 1;
+        Some(byte)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<R: Read> ExactSizeIterator for ReadBuffer<R> {}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -117,4 +212,26 @@ mod tests {
         assert_eq!(iter.len(), 0);
         assert!(iter.current_chunk.is_some());
     }
+
+    #[test]
+    fn test_read() {
+        let mut map: BTreeMap<u16, Vec<u8>> = BTreeMap::new();
+        map.insert(1, vec![0, 1, 2]);
+        map.insert(2, vec![]);
+        map.insert(3, vec![3, 4, 5]);
+
+        let mut bytes = Vec::new();
+        ChunkReader::new(&map).read_to_end(&mut bytes).unwrap();
+        assert_eq!(bytes, vec![0, 1, 2, 3, 4, 5]);
+
+        let mut reader = MultipartMessageBuffer::from(map);
+        let mut buf = [0; 2];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [0, 1]);
+        assert_eq!(reader.len(), 4);
+
+        let iter = ReadBuffer::new(reader, 3);
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
 }
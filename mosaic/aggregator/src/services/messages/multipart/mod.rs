@@ -1,18 +1,31 @@
 mod buffer;
 mod service;
 
-use std::task::{Context, Poll};
+use std::{
+    sync::{Arc, Mutex, Weak},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
 
 use futures::future::TryFutureExt;
 use tower::{buffer::Buffer, Service, ServiceBuilder};
+use tracing::debug;
 
-use crate::services::messages::ServiceError;
-use mosaic_core::message::Message;
+pub use self::service::MultipartBudget;
+use self::service::{lock, MessageBuilders};
+use crate::{
+    services::messages::ServiceError,
+    settings::{CompressionSettings, MultipartSettings},
+};
+use mosaic_core::{crypto::PublicSigningKey, message::Message};
 
 type Inner = Buffer<service::MultipartHandler, Message>;
 
 #[derive(Clone)]
-pub struct MultipartHandler(Inner);
+pub struct MultipartHandler {
+    inner: Inner,
+    message_builders: Arc<Mutex<MessageBuilders>>,
+}
 
 impl Service<Message> for MultipartHandler {
     type Response = Option<Message>;
@@ -24,20 +37,61 @@ impl Service<Message> for MultipartHandler {
     >;
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        <Inner as Service<Message>>::poll_ready(&mut self.0, cx).map_err(ServiceError::from)
+        <Inner as Service<Message>>::poll_ready(&mut self.inner, cx).map_err(ServiceError::from)
     }
 
     fn call(&mut self, req: Message) -> Self::Future {
-        <<Inner as Service<Message>>::Future>::map_err(self.0.call(req), ServiceError::from)
+        <<Inner as Service<Message>>::Future>::map_err(self.inner.call(req), ServiceError::from)
     }
 }
 
 impl MultipartHandler {
-    pub fn new() -> Self {
-        Self(
-            ServiceBuilder::new()
+    /// Creates a new multipart handler and spawns the task that
+    /// periodically discards the expired partial messages. The task
+    /// stops once all the handles are dropped.
+    ///
+    /// The global limits of the `settings` are enforced on the memory
+    /// of the `budget`, which may be shared with other handlers.
+    pub fn new(
+        settings: MultipartSettings,
+        budget: MultipartBudget,
+        compression_settings: CompressionSettings,
+    ) -> Self {
+        let message_builders = Arc::new(Mutex::new(MessageBuilders::new(settings, budget)));
+        tokio::spawn(sweep(
+            Arc::downgrade(&message_builders),
+            Duration::from_secs(settings.sweep_interval),
+        ));
+        Self {
+            inner: ServiceBuilder::new()
                 .buffer(100)
-                .service(service::MultipartHandler::new()),
-        )
+                .service(service::MultipartHandler::new(
+                    message_builders.clone(),
+                    compression_settings,
+                )),
+            message_builders,
+        }
+    }
+
+    /// Returns the IDs of the chunks received so far for the given
+    /// message, or `None` if the aggregator has no such partial message.
+    pub fn chunk_ids(&self, participant_pk: PublicSigningKey, message_id: u16) -> Option<Vec<u16>> {
+        lock(&self.message_builders).chunk_ids(participant_pk, message_id)
+    }
+}
+
+async fn sweep(message_builders: Weak<Mutex<MessageBuilders>>, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        match message_builders.upgrade() {
+            Some(message_builders) => {
+                let swept = lock(&message_builders).sweep(Instant::now());
+                if swept > 0 {
+                    debug!("discarded {} expired multipart messages", swept);
+                }
+            }
+            None => break,
+        }
     }
 }
@@ -1,8 +1,11 @@
 use std::{
     collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
     task::Poll,
+    time::{Duration, Instant},
 };
 
+use anyhow::anyhow;
 use futures::{
     future::{self, Ready},
     task::Context,
@@ -10,10 +13,17 @@ use futures::{
 use tower::Service;
 use tracing::{debug, trace, warn};
 
-use crate::services::messages::{multipart::buffer::MultipartMessageBuffer, ServiceError};
+use crate::{
+    services::messages::{
+        message_parser::decode_update,
+        multipart::buffer::{ChunkReader, MultipartMessageBuffer, ReadBuffer},
+        ServiceError,
+    },
+    settings::{CompressionSettings, MultipartSettings},
+};
 use mosaic_core::{
     crypto::{PublicEncryptKey, PublicSigningKey},
-    message::{Chunk, DecodeError, FromBytes, Message, Payload, Sum, Sum2, Tag, Update},
+    message::{Chunk, Codec, DecodeError, FromBytes, Message, Payload, Sum, Sum2, Tag, Update},
 };
 
 /// A `MessageBuilder` stores chunks of a multipart message. Once it
@@ -30,22 +40,38 @@ pub struct MessageBuilder {
     coordinator_pk: PublicEncryptKey,
     /// Message type
     tag: Tag,
+    /// The codec the reassembled payload is compressed with
+    compression: Option<Codec>,
     /// The ID of the last chunk is actually the total number of
     /// chunks this message is made of.
     last_chunk_id: Option<u16>,
     /// Chunks, ordered by ID
     data: BTreeMap<u16, Vec<u8>>,
+    /// Total size of the chunks in bytes
+    size: usize,
+    /// Point in time after which the message is discarded if it
+    /// didn't receive any new chunk
+    expires_at: Instant,
 }
 
 impl MessageBuilder {
     /// Create a new [`MessageBuilder`] that contains no chunk.
-    fn new(tag: Tag, participant_pk: PublicSigningKey, coordinator_pk: PublicEncryptKey) -> Self {
+    fn new(
+        tag: Tag,
+        participant_pk: PublicSigningKey,
+        coordinator_pk: PublicEncryptKey,
+        compression: Option<Codec>,
+        expires_at: Instant,
+    ) -> Self {
         MessageBuilder {
             tag,
+            compression,
             participant_pk,
             coordinator_pk,
             data: BTreeMap::new(),
             last_chunk_id: None,
+            size: 0,
+            expires_at,
         }
     }
 
@@ -60,8 +86,15 @@ impl MessageBuilder {
             .unwrap_or(false)
     }
 
+    /// Return the size the message would have after adding `chunk`.
+    fn size_with(&self, chunk: &Chunk) -> usize {
+        let replaced = self.data.get(&chunk.id).map(Vec::len).unwrap_or(0);
+        self.size - replaced + chunk.data.len()
+    }
+
     /// Add a chunk.
     fn add_chunk(&mut self, chunk: Chunk) {
+        self.size = self.size_with(&chunk);
         let Chunk { id, last, data, .. } = chunk;
         if last {
             self.last_chunk_id = Some(id);
@@ -71,13 +104,29 @@ impl MessageBuilder {
 
     /// Aggregate all the chunks. This method should only be called
     /// when all the chunks are here, otherwise the aggregated message
-    /// will be invalid.
-    fn into_message(self) -> Result<Message, DecodeError> {
-        let mut bytes = MultipartMessageBuffer::from(self.data);
-        let payload = match self.tag {
-            Tag::Sum => Sum::from_byte_stream(&mut bytes).map(Into::into)?,
-            Tag::Update => Update::from_byte_stream(&mut bytes).map(Into::into)?,
-            Tag::Sum2 => Sum2::from_byte_stream(&mut bytes).map(Into::into)?,
+    /// will be invalid. A compressed payload is decompressed first, up
+    /// to `max_length` bytes, and the model of an update message sent
+    /// with a lossy update codec is decoded, up to `max_decoded_length`
+    /// bytes.
+    ///
+    /// The payload is parsed as a stream, dropping the chunks as soon
+    /// as they have been read, so that the message is never held in
+    /// memory twice.
+    fn into_message(
+        self,
+        max_length: usize,
+        max_decoded_length: usize,
+    ) -> Result<Message, DecodeError> {
+        let payload = match self.compression {
+            None => parse_payload(self.tag, &mut MultipartMessageBuffer::from(self.data))?,
+            Some(codec) => {
+                // The parser needs the length of the payload upfront, so
+                // the chunks are decompressed twice rather than holding
+                // the decompressed payload in memory
+                let length = codec.decompressed_length(ChunkReader::new(&self.data), max_length)?;
+                let reader = codec.decompressor(MultipartMessageBuffer::from(self.data))?;
+                parse_payload(self.tag, &mut ReadBuffer::new(reader, length))?
+            }
         };
         let message = Message {
             signature: None,
@@ -85,12 +134,25 @@ impl MessageBuilder {
             coordinator_pk: self.coordinator_pk,
             tag: self.tag,
             is_multipart: false,
+            compression: None,
             payload,
         };
-        Ok(message)
+        decode_update(message, max_decoded_length)
     }
 }
 
+/// Parses the payload of a reassembled multipart message.
+fn parse_payload<I>(tag: Tag, bytes: &mut I) -> Result<Payload, DecodeError>
+where
+    I: Iterator<Item = u8> + ExactSizeIterator,
+{
+    Ok(match tag {
+        Tag::Sum => Sum::from_byte_stream(bytes)?.into(),
+        Tag::Update => Update::from_byte_stream(bytes)?.into(),
+        Tag::Sum2 => Sum2::from_byte_stream(bytes)?.into(),
+    })
+}
+
 /// [`MessageId`] uniquely identifies a multipart message by its ID
 /// (which uniquely identify a message _for a given participant_), and
 /// the participant public key.
@@ -100,16 +162,224 @@ pub struct MessageId {
     participant_pk: PublicSigningKey,
 }
 
+/// Number of partial messages and their size in bytes.
+#[derive(Debug, Default, Clone, Copy)]
+struct Usage {
+    messages: usize,
+    bytes: usize,
+}
+
+/// The memory used by the partial messages of all the tasks hosted by
+/// the aggregator. It is shared by their multipart handlers, so that
+/// the global [`MultipartSettings`] limits hold for the aggregator as a
+/// whole rather than for each task.
+#[derive(Debug, Default, Clone)]
+pub struct MultipartBudget(Arc<Mutex<Usage>>);
+
+impl MultipartBudget {
+    fn usage(&self) -> MutexGuard<'_, Usage> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The partial multipart messages held in memory, together with the
+/// bookkeeping needed to enforce the [`MultipartSettings`] limits.
+#[derive(Debug)]
+pub struct MessageBuilders {
+    builders: HashMap<MessageId, MessageBuilder>,
+    /// Memory used by the partial messages of each participant
+    participants: HashMap<PublicSigningKey, Usage>,
+    /// Memory used by all the partial messages
+    total: MultipartBudget,
+    settings: MultipartSettings,
+}
+
+impl MessageBuilders {
+    pub fn new(settings: MultipartSettings, budget: MultipartBudget) -> Self {
+        Self {
+            builders: HashMap::new(),
+            participants: HashMap::new(),
+            total: budget,
+            settings,
+        }
+    }
+
+    /// Add a chunk to the partial message it belongs to, creating the
+    /// message once the chunk is accepted if needed. If the message is
+    /// complete, it is removed and returned.
+    ///
+    /// # Errors
+    /// Fails if the chunk ID is out of bounds, if the chunk is not
+    /// compressed like the other chunks of its message or if accepting
+    /// the chunk would exceed one of the limits. A message that
+    /// outgrows the byte limit of its participant can never complete,
+    /// so it is discarded. A chunk that exceeds the limits of all the
+    /// participants may be accepted once other messages complete, so
+    /// its message is kept.
+    fn add_chunk(
+        &mut self,
+        tag: Tag,
+        participant_pk: PublicSigningKey,
+        coordinator_pk: PublicEncryptKey,
+        compression: Option<Codec>,
+        chunk: Chunk,
+        now: Instant,
+    ) -> Result<Option<MessageBuilder>, ServiceError> {
+        if chunk.id > self.settings.max_chunk_id {
+            return Err(ServiceError::InvalidChunkId(chunk.id));
+        }
+
+        let id = MessageId {
+            message_id: chunk.message_id,
+            participant_pk,
+        };
+        let usage = self
+            .participants
+            .get(&participant_pk)
+            .copied()
+            .unwrap_or_default();
+
+        // The chunk is checked against the partial message it belongs
+        // to, if any, before a new partial message is created for it,
+        // so that a rejected chunk leaves no trace.
+        let is_new = !self.builders.contains_key(&id);
+        let (old_size, new_size) = match self.builders.get(&id) {
+            Some(builder) => {
+                if builder.compression != compression {
+                    return Err(ServiceError::Parsing(anyhow!(
+                        "the chunks of a multipart message are compressed differently"
+                    )));
+                }
+                if let Some(last_chunk_id) = builder.last_chunk_id {
+                    if chunk.id > last_chunk_id {
+                        return Err(ServiceError::InvalidChunkId(chunk.id));
+                    }
+                }
+                (builder.size, builder.size_with(&chunk))
+            }
+            None => {
+                if usage.messages >= self.settings.max_participant_messages {
+                    return Err(ServiceError::MultipartLimit(
+                        "too many partial messages for this participant",
+                    ));
+                }
+                (0, chunk.data.len())
+            }
+        };
+
+        if usage.bytes - old_size + new_size > self.settings.max_participant_bytes {
+            warn!("multipart message too large, discarding it");
+            self.remove(&id);
+            return Err(ServiceError::MultipartLimit(
+                "too many bytes of partial messages for this participant",
+            ));
+        }
+
+        // The global limits are shared with the other tasks, so they are
+        // checked and charged under the same lock.
+        let mut total = self.total.usage();
+        if is_new && total.messages >= self.settings.max_messages {
+            return Err(ServiceError::MultipartCapacity("too many partial messages"));
+        }
+        if total.bytes - old_size + new_size > self.settings.max_bytes {
+            warn!("out of memory for multipart messages, postponing the chunk");
+            return Err(ServiceError::MultipartCapacity(
+                "too many bytes of partial messages",
+            ));
+        }
+        total.messages += is_new as usize;
+        total.bytes = total.bytes - old_size + new_size;
+        drop(total);
+
+        let usage = self.participants.entry(participant_pk).or_default();
+        usage.messages += is_new as usize;
+        usage.bytes = usage.bytes - old_size + new_size;
+
+        let expires_at = now + self.ttl();
+        if is_new {
+            debug!("new multipart message (id = {})", id.message_id);
+        }
+        let builder = self.builders.entry(id.clone()).or_insert_with(|| {
+            MessageBuilder::new(tag, participant_pk, coordinator_pk, compression, expires_at)
+        });
+        builder.add_chunk(chunk);
+        builder.expires_at = expires_at;
+
+        if self.builders[&id].has_all_chunks() {
+            Ok(self.remove(&id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Discard the messages that expired at `now`. Return the number of
+    /// discarded messages.
+    pub fn sweep(&mut self, now: Instant) -> usize {
+        let expired = self
+            .builders
+            .iter()
+            .filter(|(_, builder)| builder.expires_at <= now)
+            .map(|(id, _)| id.clone())
+            .collect::<Vec<_>>();
+        for id in expired.iter() {
+            self.remove(id);
+        }
+        expired.len()
+    }
+
+    /// Return the IDs of the chunks received so far for the given
+    /// message, or `None` if there is no such partial message.
+    pub fn chunk_ids(&self, participant_pk: PublicSigningKey, message_id: u16) -> Option<Vec<u16>> {
+        let id = MessageId {
+            message_id,
+            participant_pk,
+        };
+        self.builders
+            .get(&id)
+            .map(|builder| builder.data.keys().copied().collect())
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.settings.ttl)
+    }
+
+    fn remove(&mut self, id: &MessageId) -> Option<MessageBuilder> {
+        let builder = self.builders.remove(id)?;
+        if let Some(usage) = self.participants.get_mut(&id.participant_pk) {
+            usage.messages -= 1;
+            usage.bytes -= builder.size;
+            if usage.messages == 0 {
+                self.participants.remove(&id.participant_pk);
+            }
+        }
+        let mut total = self.total.usage();
+        total.messages -= 1;
+        total.bytes -= builder.size;
+        Some(builder)
+    }
+}
+
+/// Lock the partial messages. The bookkeeping is updated in a way that
+/// leaves it consistent at every step, so a poisoned lock is still safe
+/// to use.
+pub fn lock(builders: &Mutex<MessageBuilders>) -> MutexGuard<'_, MessageBuilders> {
+    builders.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// A service that handles multipart messages.
 pub struct MultipartHandler {
-    message_builders: HashMap<MessageId, MessageBuilder>,
+    message_builders: Arc<Mutex<MessageBuilders>>,
+    compression_settings: CompressionSettings,
 }
 
 impl MultipartHandler {
-    #[allow(dead_code)]
-    pub fn new() -> Self {
+    pub fn new(
+        message_builders: Arc<Mutex<MessageBuilders>>,
+        compression_settings: CompressionSettings,
+    ) -> Self {
         Self {
-            message_builders: HashMap::new(),
+            message_builders,
+            compression_settings,
         }
     }
 }
@@ -136,45 +406,59 @@ impl Service<Message> for MultipartHandler {
             tag,
             participant_pk,
             coordinator_pk,
+            compression,
             payload: Payload::Chunk(chunk),
             ..
         } = message
         {
-            let id = MessageId {
-                message_id: chunk.message_id,
+            if let Some(codec) = compression {
+                if !self.compression_settings.codecs.contains(&codec) {
+                    warn!("rejected message chunk: codec {:?} is not accepted", codec);
+                    return ready_err(ServiceError::Parsing(anyhow!(
+                        "codec {:?} is not accepted",
+                        codec
+                    )));
+                }
+            }
+            let builder = match lock(&self.message_builders).add_chunk(
+                tag,
                 participant_pk,
+                coordinator_pk,
+                compression,
+                chunk,
+                Instant::now(),
+            ) {
+                Ok(Some(builder)) => builder,
+                Ok(None) => return ready_ok(None),
+                Err(e) => {
+                    warn!("rejected message chunk: {}", e);
+                    return ready_err(e);
+                }
             };
-            // If we don't have a partial message for this ID, create
-            // an empty one.
-            let mp_message = self.message_builders.entry(id.clone()).or_insert_with(|| {
-                debug!("new multipart message (id = {})", id.message_id);
-                MessageBuilder::new(tag, participant_pk, coordinator_pk)
-            });
-            // Add the chunk to the partial message
-            mp_message.add_chunk(chunk);
-
-            // Check if the message is complete, and if so parse it
-            // and return it
-            if mp_message.has_all_chunks() {
-                debug!("received the final message chunk, now parsing the full message");
-                // This entry exists, because `mp_message` above
-                // refers to it, so it's ok to unwrap.
-                match self.message_builders.remove(&id).unwrap().into_message() {
-                    Ok(message) => {
-                        debug!("multipart message succesfully parsed");
-                        ready_ok(Some(message))
-                    }
-                    Err(e) => {
-                        warn!("invalid multipart message: {}", e);
-                        ready_err(ServiceError::Parsing(e))
-                    }
+
+            // The message is complete: parse it and return it. This
+            // happens outside of the lock, parsing large messages is slow.
+            debug!("received the final message chunk, now parsing the full message");
+            match builder.into_message(
+                self.compression_settings.max_message_length,
+                self.compression_settings.max_decompressed_length,
+            ) {
+                Ok(message) => {
+                    debug!("multipart message succesfully parsed");
+                    ready_ok(Some(message))
+                }
+                Err(e) => {
+                    warn!("invalid multipart message: {}", e);
+                    ready_err(ServiceError::Parsing(e))
                 }
-            } else {
-                ready_ok(None)
             }
         } else {
-            // This cannot happen, because parsing have fail
-            panic!("multipart flag is set but payload is not a multipart message");
+            // The parser only produces chunk payloads for multipart
+            // messages, but don't trust the caller with that.
+            warn!("multipart flag is set but payload is not a chunk");
+            ready_err(ServiceError::Parsing(anyhow!(
+                "multipart flag is set but payload is not a chunk"
+            )))
         }
     }
 }
@@ -185,4 +469,258 @@ fn ready_ok<T, E>(t: T) -> Ready<Result<T, E>> {
 
 fn ready_err<T, E>(e: E) -> Ready<Result<T, E>> {
     future::ready(Err(e))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosaic_core::{
+        crypto::{ByteObject, EncryptKeyPair, Signature, SigningKeyPair},
+        message::ToBytes,
+    };
+
+    fn settings() -> MultipartSettings {
+        MultipartSettings {
+            ttl: 10,
+            max_chunk_id: 3,
+            max_participant_messages: 2,
+            max_participant_bytes: 100,
+            max_messages: 3,
+            max_bytes: 150,
+            ..MultipartSettings::default()
+        }
+    }
+
+    fn chunk(message_id: u16, id: u16, last: bool, len: usize) -> Chunk {
+        Chunk {
+            id,
+            message_id,
+            last,
+            data: vec![0; len],
+        }
+    }
+
+    fn add(
+        builders: &mut MessageBuilders,
+        participant_pk: PublicSigningKey,
+        chunk: Chunk,
+        now: Instant,
+    ) -> Result<Option<MessageBuilder>, ServiceError> {
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        builders.add_chunk(
+            Tag::Update,
+            participant_pk,
+            coordinator_pk,
+            None,
+            chunk,
+            now,
+        )
+    }
+
+    #[test]
+    fn test_chunk_ids_and_completion() {
+        let mut builders = MessageBuilders::new(settings(), MultipartBudget::default());
+        let pk = SigningKeyPair::generate().public;
+        let now = Instant::now();
+
+        assert!(add(&mut builders, pk, chunk(7, 2, true, 10), now)
+            .unwrap()
+            .is_none());
+        assert!(add(&mut builders, pk, chunk(7, 0, false, 10), now)
+            .unwrap()
+            .is_none());
+        assert_eq!(builders.chunk_ids(pk, 7), Some(vec![0, 2]));
+        assert_eq!(builders.chunk_ids(pk, 8), None);
+        assert_eq!(builders.total.usage().bytes, 20);
+
+        // chunks beyond the last chunk or the limit are rejected
+        assert!(matches!(
+            add(&mut builders, pk, chunk(7, 3, false, 10), now),
+            Err(ServiceError::InvalidChunkId(3))
+        ));
+        assert!(matches!(
+            add(&mut builders, pk, chunk(8, 4, false, 10), now),
+            Err(ServiceError::InvalidChunkId(4))
+        ));
+
+        let builder = add(&mut builders, pk, chunk(7, 1, false, 10), now)
+            .unwrap()
+            .unwrap();
+        assert_eq!(builder.size, 30);
+        assert_eq!(builders.chunk_ids(pk, 7), None);
+        assert_eq!(builders.total.usage().bytes, 0);
+        assert_eq!(builders.total.usage().messages, 0);
+        assert!(builders.participants.is_empty());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut builders = MessageBuilders::new(settings(), MultipartBudget::default());
+        let pk1 = SigningKeyPair::generate().public;
+        let pk2 = SigningKeyPair::generate().public;
+        let pk3 = SigningKeyPair::generate().public;
+        let now = Instant::now();
+
+        add(&mut builders, pk1, chunk(0, 0, false, 10), now).unwrap();
+        add(&mut builders, pk1, chunk(1, 0, false, 10), now).unwrap();
+        assert!(matches!(
+            add(&mut builders, pk1, chunk(2, 0, false, 10), now),
+            Err(ServiceError::MultipartLimit(_))
+        ));
+
+        // a message outgrowing the participant limit is discarded
+        assert!(matches!(
+            add(&mut builders, pk1, chunk(1, 1, false, 90), now),
+            Err(ServiceError::MultipartLimit(_))
+        ));
+        assert_eq!(builders.chunk_ids(pk1, 1), None);
+        assert_eq!(builders.total.usage().bytes, 10);
+
+        // a chunk exceeding the global limit is postponed, and its message is kept
+        add(&mut builders, pk2, chunk(0, 0, false, 90), now).unwrap();
+        assert!(matches!(
+            add(&mut builders, pk3, chunk(0, 0, false, 60), now),
+            Err(ServiceError::MultipartCapacity(_))
+        ));
+        assert!(matches!(
+            add(&mut builders, pk1, chunk(0, 1, false, 60), now),
+            Err(ServiceError::MultipartCapacity(_))
+        ));
+        assert_eq!(builders.chunk_ids(pk1, 0), Some(vec![0]));
+        assert_eq!(builders.total.usage().messages, 2);
+        assert_eq!(builders.total.usage().bytes, 100);
+
+        add(&mut builders, pk2, chunk(1, 0, false, 10), now).unwrap();
+        assert!(matches!(
+            add(&mut builders, pk1, chunk(2, 0, false, 10), now),
+            Err(ServiceError::MultipartCapacity(_))
+        ));
+    }
+
+    #[test]
+    fn test_shared_budget() {
+        let budget = MultipartBudget::default();
+        let mut task1 = MessageBuilders::new(settings(), budget.clone());
+        let mut task2 = MessageBuilders::new(settings(), budget.clone());
+        let pk = SigningKeyPair::generate().public;
+        let now = Instant::now();
+
+        // the global limits hold for the partial messages of all the tasks
+        add(&mut task1, pk, chunk(0, 0, false, 90), now).unwrap();
+        assert!(matches!(
+            add(&mut task2, pk, chunk(0, 0, false, 90), now),
+            Err(ServiceError::MultipartCapacity(_))
+        ));
+        add(&mut task2, pk, chunk(0, 0, false, 10), now).unwrap();
+        add(&mut task2, pk, chunk(1, 0, false, 10), now).unwrap();
+        assert!(matches!(
+            add(&mut task1, pk, chunk(1, 0, false, 10), now),
+            Err(ServiceError::MultipartCapacity(_))
+        ));
+        assert_eq!(budget.usage().messages, 3);
+        assert_eq!(budget.usage().bytes, 110);
+
+        // the memory of a discarded message is given back to all the tasks
+        assert_eq!(task1.sweep(now + Duration::from_secs(10)), 1);
+        add(&mut task1, pk, chunk(1, 0, false, 10), now).unwrap();
+        assert_eq!(budget.usage().messages, 3);
+        assert_eq!(budget.usage().bytes, 30);
+    }
+
+    #[test]
+    fn test_rejected_chunk_leaves_no_message() {
+        let mut builders = MessageBuilders::new(settings(), MultipartBudget::default());
+        let pk = SigningKeyPair::generate().public;
+        let now = Instant::now();
+
+        assert!(matches!(
+            add(&mut builders, pk, chunk(0, 0, false, 110), now),
+            Err(ServiceError::MultipartLimit(_))
+        ));
+        assert_eq!(builders.chunk_ids(pk, 0), None);
+        assert_eq!(builders.total.usage().messages, 0);
+        assert!(builders.participants.is_empty());
+
+        // the rejected chunk didn't use up one of the partial messages of the participant
+        add(&mut builders, pk, chunk(1, 0, false, 10), now).unwrap();
+        add(&mut builders, pk, chunk(2, 0, false, 10), now).unwrap();
+        assert_eq!(builders.total.usage().messages, 2);
+    }
+
+    #[test]
+    fn test_sweep() {
+        let mut builders = MessageBuilders::new(settings(), MultipartBudget::default());
+        let pk = SigningKeyPair::generate().public;
+        let now = Instant::now();
+
+        add(&mut builders, pk, chunk(0, 0, false, 10), now).unwrap();
+        add(&mut builders, pk, chunk(1, 0, false, 10), now).unwrap();
+        // receiving a chunk extends the lifetime of the message
+        add(
+            &mut builders,
+            pk,
+            chunk(1, 1, false, 10),
+            now + Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert_eq!(builders.sweep(now + Duration::from_secs(9)), 0);
+        assert_eq!(builders.sweep(now + Duration::from_secs(10)), 1);
+        assert_eq!(builders.chunk_ids(pk, 0), None);
+        assert_eq!(builders.chunk_ids(pk, 1), Some(vec![0, 1]));
+        assert_eq!(builders.sweep(now + Duration::from_secs(15)), 1);
+        assert_eq!(builders.total.usage().messages, 0);
+        assert_eq!(builders.total.usage().bytes, 0);
+        assert!(builders.participants.is_empty());
+    }
+
+    #[test]
+    fn test_compressed_message() {
+        let mut builders =
+            MessageBuilders::new(MultipartSettings::default(), MultipartBudget::default());
+        let participant_pk = SigningKeyPair::generate().public;
+        let coordinator_pk = EncryptKeyPair::generate().public;
+        let now = Instant::now();
+
+        let sum = Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        };
+        let mut bytes = vec![0; sum.buffer_length()];
+        sum.to_bytes(&mut bytes);
+        let compressed = Codec::Lz4.compress(&bytes);
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+        let mut add = |compression, id, data: &[u8]| {
+            let chunk = Chunk {
+                id,
+                message_id: 0,
+                last: id == 1,
+                data: data.to_vec(),
+            };
+            builders.add_chunk(
+                Tag::Sum,
+                participant_pk,
+                coordinator_pk,
+                compression,
+                chunk,
+                now,
+            )
+        };
+
+        assert!(add(Some(Codec::Lz4), 0, first).unwrap().is_none());
+        // all the chunks of a message must be compressed with the same codec
+        assert!(matches!(
+            add(None, 1, second),
+            Err(ServiceError::Parsing(_))
+        ));
+        let builder = add(Some(Codec::Lz4), 1, second).unwrap().unwrap();
+
+        assert!(builder
+            .clone()
+            .into_message(bytes.len() - 1, usize::MAX)
+            .is_err());
+        let message = builder.into_message(bytes.len(), usize::MAX).unwrap();
+        assert_eq!(message.payload, Payload::Sum(sum));
+        assert_eq!(message.compression, None);
+    }
+}
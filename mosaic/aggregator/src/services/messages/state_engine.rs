@@ -1,12 +1,12 @@
-use std::task::Poll;
+use std::{convert::TryFrom, task::Poll};
 
-use futures::task::Context;
+use futures::{future, task::Context};
 use tower::Service;
 use mosaic_core::message::Message;
 
 use crate::{
     services::messages::{BoxedServiceFuture, ServiceError},
-    state_engine::channel::RequestSender,
+    state_engine::channel::{RequestSender, StateEngineRequest},
 };
 
 /// A service that hands the requests to the [`StateEngine`] that runs in the background.
@@ -38,10 +38,14 @@ impl Service<Message> for StateEngine {
     }
 
     fn call(&mut self, req: Message) -> Self::Future {
+        let req = match StateEngineRequest::try_from(req) {
+            Ok(req) => req,
+            Err(e) => return Box::pin(future::ready(Err(ServiceError::StateEngine(e)))),
+        };
         let handle = self.handle.clone();
         Box::pin(async move {
             handle
-                .request(req.into(), tracing::Span::none())
+                .request(req, tracing::Span::none())
                 .await
                 .map_err(ServiceError::StateEngine)
         })
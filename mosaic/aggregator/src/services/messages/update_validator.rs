@@ -0,0 +1,298 @@
+use std::task::Poll;
+
+use futures::{future, task::Context};
+use tower::Service;
+
+use crate::{
+    services::messages::ServiceError,
+    settings::{MaskSettings, ModelSettings},
+    state_engine::events::{EventListener, EventSubscriber, ModelUpdate},
+};
+#[cfg(not(feature = "secure"))]
+use mosaic_core::model::{ratio_to_float, DataType, EncodedModelObject, ModelObject, ModelSchema};
+use mosaic_core::{
+    common::RoundParameters,
+    mask::BoundType,
+    message::{Message, Payload, Update},
+};
+#[cfg(not(feature = "secure"))]
+use num::{bigint::BigInt, rational::Ratio, ToPrimitive};
+
+/// A service for performing sanity checks on the models of update messages before they are
+/// handed to the state machine.
+///
+/// An update is rejected if its model doesn't match the length, the schema and the data type of
+/// the round, if a weight is not representable in the data type or exceeds the mask bound, or
+/// if the L2 or L∞ norm of the model exceeds its cap. Masked models can't be inspected and are
+/// passed through unchecked, unless they are not privatized as required by the round.
+#[derive(Clone, Debug)]
+pub struct UpdateValidator {
+    params_listener: EventListener<RoundParameters>,
+    model_listener: EventListener<ModelUpdate>,
+    length: Option<usize>,
+    bound_type: BoundType,
+    max_l2_norm: Option<f64>,
+    max_linf_norm: Option<f64>,
+}
+
+impl UpdateValidator {
+    pub fn new(
+        subscriber: &EventSubscriber,
+        mask_settings: MaskSettings,
+        model_settings: &ModelSettings,
+    ) -> Self {
+        Self {
+            params_listener: subscriber.params_listener(),
+            model_listener: subscriber.model_listener(),
+            length: model_settings.length,
+            bound_type: mask_settings.bound_type,
+            max_l2_norm: model_settings.max_l2_norm,
+            max_linf_norm: model_settings.max_linf_norm,
+        }
+    }
+
+    /// Checks that the update reports a local differential privacy at least as strong as
+    /// required by the round.
+    fn check_privacy(&self, update: &Update) -> Result<(), ServiceError> {
+        match (
+            self.params_listener.get_latest().event.privacy,
+            update.privacy,
+        ) {
+            (Some(required), Some(used)) if used.satisfies(&required) => Ok(()),
+            (Some(_), _) => Err(ServiceError::InsufficientPrivacy),
+            (None, _) => Ok(()),
+        }
+    }
+
+    #[cfg(not(feature = "secure"))]
+    /// Checks the model of an update against the round parameters and the limits.
+    fn validate(&self, model_object: &ModelObject) -> Result<(), ServiceError> {
+        let params = self.params_listener.get_latest().event;
+        if model_object.schema_digest != params.model_schema.as_ref().map(ModelSchema::digest) {
+            return Err(ServiceError::ModelSchema);
+        }
+
+        // the length is implied by the schema or else by the latest global model, which every
+        // update must match no matter which base model a delta is computed against
+        let expected = params
+            .model_schema
+            .as_ref()
+            .map(ModelSchema::weights)
+            .or(self.length)
+            .or_else(|| match self.model_listener.get_latest().event {
+                ModelUpdate::New(_, model) => Some(model.len()),
+                ModelUpdate::Invalidate => None,
+            });
+        let actual = model_object.data.len();
+        if let Some(expected) = expected.filter(|expected| *expected != actual) {
+            return Err(ServiceError::ModelLength { expected, actual });
+        }
+
+        let expected = params.model_config.data_type;
+        let actual = model_object.config.data_type;
+        if actual != expected {
+            return Err(ServiceError::ModelDataType { expected, actual });
+        }
+
+        self.check_weights(&model_object.data, expected)
+    }
+
+    #[cfg(not(feature = "secure"))]
+    /// Checks that the weights are representable in the data type and within the mask bound
+    /// and that their norms don't exceed the caps.
+    fn check_weights(
+        &self,
+        weights: &[Ratio<BigInt>],
+        data_type: DataType,
+    ) -> Result<(), ServiceError> {
+        let bound = match self.bound_type {
+            BoundType::B0 => 1_f64,
+            BoundType::B2 => 100_f64,
+            BoundType::B4 => 10_000_f64,
+            BoundType::B6 => 1_000_000_f64,
+            // bounded by the data type only
+            BoundType::Bmax => f64::INFINITY,
+        };
+
+        let mut l2_norm = 0_f64;
+        let mut linf_norm = 0_f64;
+        for (index, weight) in weights.iter().enumerate() {
+            let weight = to_f64(weight, data_type)
+                .ok_or(ServiceError::WeightNotRepresentable(index))?
+                .abs();
+            if weight > bound {
+                return Err(ServiceError::WeightOutOfBounds(index));
+            }
+            l2_norm += weight * weight;
+            linf_norm = linf_norm.max(weight);
+        }
+
+        let l2_norm = l2_norm.sqrt();
+        if let Some(max) = self.max_l2_norm.filter(|max| l2_norm > *max) {
+            return Err(ServiceError::L2NormExceeded { norm: l2_norm, max });
+        }
+        if let Some(max) = self.max_linf_norm.filter(|max| linf_norm > *max) {
+            return Err(ServiceError::LinfNormExceeded {
+                norm: linf_norm,
+                max,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "secure"))]
+/// Converts a weight into the primitive data type of the models and approximates it as `f64`,
+/// or gets `None` if it is not representable in the data type.
+fn to_f64(weight: &Ratio<BigInt>, data_type: DataType) -> Option<f64> {
+    match data_type {
+        DataType::F32 => ratio_to_float::<f32>(weight).map(f64::from),
+        DataType::F64 => ratio_to_float::<f64>(weight),
+        DataType::I32 => weight.to_integer().to_i32().map(f64::from),
+        DataType::I64 => weight.to_integer().to_i64().map(|weight| weight as f64),
+    }
+}
+
+impl Service<Message> for UpdateValidator {
+    type Response = Message;
+    type Error = ServiceError;
+    type Future = future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, message: Message) -> Self::Future {
+        if let Payload::Update(ref update) = message.payload {
+            if let Err(err) = self.check_privacy(update) {
+                return future::ready(Err(err));
+            }
+            // lossy models are decoded by the message parser and never reach this point
+            #[cfg(not(feature = "secure"))]
+            if let EncodedModelObject::Dense(ref model_object) = update.model_object {
+                if let Err(err) = self.validate(model_object) {
+                    return future::ready(Err(err));
+                }
+            }
+        }
+        future::ready(Ok(message))
+    }
+}
+
+#[cfg(all(test, not(feature = "secure")))]
+mod tests {
+    use super::*;
+    use crate::services::messages::message_parser::tests::events;
+    use mosaic_core::{
+        crypto::{ByteObject, Signature, SigningKeyPair},
+        mask::{GroupType, ModelType},
+        message::UpdateKind,
+        model::{FromPrimitives, Model, ModelConfig, PrivacyParams, PrivacyRequirement},
+    };
+
+    fn validator(subscriber: &EventSubscriber) -> UpdateValidator {
+        let mask_settings = MaskSettings {
+            group_type: GroupType::Prime,
+            data_type: DataType::F32,
+            bound_type: BoundType::B2,
+            model_type: ModelType::M3,
+        };
+        let model_settings = ModelSettings {
+            length: Some(3),
+            data_type: DataType::F32,
+            tensors: Vec::new(),
+            initial_model: None,
+            max_l2_norm: Some(10.0),
+            max_linf_norm: Some(8.0),
+            privacy: None,
+        };
+        UpdateValidator::new(subscriber, mask_settings, &model_settings)
+    }
+
+    fn update(weights: Vec<f64>, data_type: DataType, privacy: Option<PrivacyParams>) -> Message {
+        let (_, _, coordinator_keys) = events();
+        let model = Model::from_primitives(weights.into_iter()).unwrap();
+        let model_object = ModelObject::new(model.into(), ModelConfig { data_type });
+        let update = Update {
+            update_signature: Signature::zeroed(),
+            kind: UpdateKind::Full,
+            base_model_version: 0,
+            privacy,
+            model_object: model_object.into(),
+        };
+        Message::new_update(
+            SigningKeyPair::generate().public,
+            coordinator_keys.public,
+            update,
+        )
+    }
+
+    async fn check(weights: Vec<f64>, data_type: DataType) -> Result<Message, ServiceError> {
+        let (_publisher, subscriber, _keys) = events();
+        validator(&subscriber)
+            .call(update(weights, data_type, None))
+            .await
+    }
+
+    #[tokio::test]
+    async fn test_validate_update() {
+        assert!(check(vec![1.0, -2.0, 3.0], DataType::F32).await.is_ok());
+        assert!(matches!(
+            check(vec![1.0, 2.0], DataType::F32).await,
+            Err(ServiceError::ModelLength {
+                expected: 3,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            check(vec![1.0, 2.0, 3.0], DataType::F64).await,
+            Err(ServiceError::ModelDataType { .. })
+        ));
+        assert!(matches!(
+            check(vec![1.0, f64::MAX, 3.0], DataType::F32).await,
+            Err(ServiceError::WeightNotRepresentable(1))
+        ));
+        assert!(matches!(
+            check(vec![1.0, 2.0, -101.0], DataType::F32).await,
+            Err(ServiceError::WeightOutOfBounds(2))
+        ));
+        assert!(matches!(
+            check(vec![7.0, 7.0, 7.0], DataType::F32).await,
+            Err(ServiceError::L2NormExceeded { .. })
+        ));
+        assert!(matches!(
+            check(vec![0.0, 9.0, 0.0], DataType::F32).await,
+            Err(ServiceError::LinfNormExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_validate_privacy() {
+        let (mut publisher, subscriber, _keys) = events();
+        let mut params = subscriber.params_listener().get_latest().event;
+        params.privacy = Some(PrivacyRequirement {
+            max_epsilon: 1.0,
+            max_delta: 1e-5,
+        });
+        publisher.broadcast_params(params);
+        let mut validator = validator(&subscriber);
+
+        let weights = || vec![1.0, -2.0, 3.0];
+        let privacy = PrivacyParams::gaussian(0.5, 1e-6, 5.0).unwrap();
+        assert!(validator
+            .call(update(weights(), DataType::F32, Some(privacy)))
+            .await
+            .is_ok());
+        let privacy = PrivacyParams::laplace(2.0, 5.0);
+        assert!(matches!(
+            validator
+                .call(update(weights(), DataType::F32, Some(privacy)))
+                .await,
+            Err(ServiceError::InsufficientPrivacy)
+        ));
+        assert!(matches!(
+            validator.call(update(weights(), DataType::F32, None)).await,
+            Err(ServiceError::InsufficientPrivacy)
+        ));
+    }
+}
@@ -7,6 +7,9 @@
 //!   module
 //! - the services for processing PET message are provided by the
 //!   [`messages`] module.
+//!
+//! Besides, the [`health`] module provides the readiness checks of the coordinator.
 
 pub mod fetchers;
+pub mod health;
 pub mod messages;
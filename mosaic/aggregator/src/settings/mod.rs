@@ -3,9 +3,10 @@
 //! Values defined in the configuration file can be overridden by environment variables. Examples of
 //! configuration files can be found in the `configs/` directory located in the repository root.
 //!
-#[cfg(feature = "tls")]
-use std::path::PathBuf;
-use std::{fmt, path::Path};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
 use config::{Config, ConfigError, ValueKind};
 use displaydoc::Display;
@@ -16,14 +17,13 @@ use serde::{
 };
 use thiserror::Error;
 use tracing_subscriber::filter::EnvFilter;
-use validator::{Validate, ValidationErrors};
-
-#[cfg(feature = "tls")]
-use validator::ValidationError;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
 
+use crate::aggr::DeltaAggregation;
 use mosaic_core::{
     mask::{BoundType, GroupType, MaskConfig, ModelType},
-    model::{DataType, ModelConfig},
+    message::Codec,
+    model::{DataType, ModelConfig, ModelSchema, PrivacyRequirement, TensorSpec},
 };
 
 #[cfg(feature = "model-persistence")]
@@ -39,9 +39,54 @@ pub enum SettingsError {
     Loading(#[from] ConfigError),
     /// Validation failed: {0}.
     Validation(#[from] ValidationErrors),
+    /// Serializing the settings failed: {0}.
+    Serialization(#[from] toml::ser::Error),
+}
+
+impl SettingsError {
+    /// Gets the description of every error, one per invalid setting.
+    pub fn details(&self) -> Vec<String> {
+        match self {
+            Self::Validation(errors) => {
+                let mut details = Vec::new();
+                collect_validation_errors(errors, "", &mut details);
+                details.sort();
+                details
+            }
+            err => vec![err.to_string()],
+        }
+    }
+}
+
+/// Collects the descriptions of the nested validation errors along with the paths of the invalid
+/// settings.
+fn collect_validation_errors(errors: &ValidationErrors, path: &str, details: &mut Vec<String>) {
+    for (field, kind) in errors.errors() {
+        let path = match (path, *field) {
+            // the errors of a schema validation refer to the validated section itself
+            ("", "__all__") => "settings".to_string(),
+            (path, "__all__") => path.to_string(),
+            ("", field) => field.to_string(),
+            (path, field) => format!("{}.{}", path, field),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                details.extend(errors.iter().map(|err| format!("{}: {}", path, err)))
+            }
+            ValidationErrorsKind::Struct(errors) => {
+                collect_validation_errors(errors, &path, details)
+            }
+            ValidationErrorsKind::List(errors) => {
+                for (index, errors) in errors {
+                    collect_validation_errors(errors, &format!("{}[{}]", path, index), details);
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Validate, Deserialize)]
+#[validate(schema(function = "validate_tasks"))]
 /// The combined settings.
 ///
 /// Each section in the configuration file corresponds to the identically named settings field.
@@ -50,8 +95,13 @@ pub struct Settings {
     pub protocol: ProtocolSettings,
     pub mask: MaskSettings,
     pub log: LoggingSettings,
+    #[validate]
     pub model: ModelSettings,
     #[validate]
+    pub multipart: MultipartSettings,
+    #[validate]
+    pub compression: CompressionSettings,
+    #[validate]
     pub metrics: MetricsSettings,
     #[cfg(feature = "redis")]
     #[validate]
@@ -64,6 +114,8 @@ pub struct Settings {
     pub restore: RestoreSettings,
     #[serde(default)]
     pub trust_anchor: TrustAnchorSettings,
+    #[serde(default)]
+    pub tasks: Vec<TaskSettings>,
 }
 
 impl Settings {
@@ -77,6 +129,43 @@ impl Settings {
         Ok(settings)
     }
 
+    /// Gets the training tasks hosted by the aggregator.
+    ///
+    /// The sections a task does not override are taken from the top level settings. If no tasks
+    /// are configured, the aggregator hosts a single task named [`DEFAULT_TASK`] which is
+    /// entirely configured by the top level settings.
+    pub fn tasks(&self) -> Vec<Task> {
+        if self.tasks.is_empty() {
+            return vec![Task {
+                name: DEFAULT_TASK.to_string(),
+                mask: self.mask,
+                model: self.model.clone(),
+                protocol: self.protocol.clone(),
+            }];
+        }
+        self.tasks
+            .iter()
+            .map(|task| Task {
+                name: task.name.clone(),
+                mask: task.mask.unwrap_or(self.mask),
+                model: task.model.clone().unwrap_or_else(|| self.model.clone()),
+                protocol: task
+                    .protocol
+                    .clone()
+                    .unwrap_or_else(|| self.protocol.clone()),
+            })
+            .collect()
+    }
+
+    /// Gets the default settings as the content of a TOML configuration file.
+    ///
+    /// # Errors
+    /// Fails if the default settings can't be serialized.
+    pub fn default_config() -> Result<String, SettingsError> {
+        let defaults: toml::Value = Self::set_default().build()?.try_deserialize()?;
+        Ok(toml::to_string_pretty(&defaults)?)
+    }
+
     fn load(path: Option<impl AsRef<Path>>) -> Result<Self, ConfigError> {
         match path {
             None => Self::set_default().build()?.try_deserialize(),
@@ -108,6 +197,13 @@ impl Settings {
             .unwrap_or_default()
             .set_default("protocol.participants", ValueKind::I64(1))
             .unwrap_or_default()
+            .set_default(
+                "protocol.delta_aggregation",
+                ValueKind::String("Reconstruct".to_string()),
+            )
+            .unwrap_or_default()
+            .set_default("protocol.model_history", ValueKind::I64(4))
+            .unwrap_or_default()
             .set_default("mask.group_type", ValueKind::String("Prime".to_string()))
             .unwrap_or_default()
             .set_default("mask.data_type", ValueKind::String("F32".to_string()))
@@ -116,10 +212,45 @@ impl Settings {
             .unwrap_or_default()
             .set_default("mask.model_type", ValueKind::String("M3".to_string()))
             .unwrap_or_default()
-            // .set_default("model.length", ValueKind::I64(0))
-            // .unwrap_or_default()
             .set_default("model.data_type", ValueKind::String("F32".to_string()))
             .unwrap_or_default()
+            .set_default("model.tensors", ValueKind::Array(Vec::new()))
+            .unwrap_or_default()
+            .set_default("multipart.ttl", ValueKind::I64(300))
+            .unwrap_or_default()
+            .set_default("multipart.sweep_interval", ValueKind::I64(30))
+            .unwrap_or_default()
+            .set_default("multipart.max_chunk_id", ValueKind::I64(8191))
+            .unwrap_or_default()
+            .set_default("multipart.max_participant_messages", ValueKind::I64(4))
+            .unwrap_or_default()
+            .set_default(
+                "multipart.max_participant_bytes",
+                ValueKind::I64(64 * 1024 * 1024),
+            )
+            .unwrap_or_default()
+            .set_default("multipart.max_messages", ValueKind::I64(10_000))
+            .unwrap_or_default()
+            .set_default("multipart.max_bytes", ValueKind::I64(1024 * 1024 * 1024))
+            .unwrap_or_default()
+            .set_default(
+                "compression.codecs",
+                ValueKind::Array(vec![
+                    config::Value::new(None, ValueKind::String("zstd".to_string())),
+                    config::Value::new(None, ValueKind::String("lz4".to_string())),
+                ]),
+            )
+            .unwrap_or_default()
+            .set_default(
+                "compression.max_message_length",
+                ValueKind::I64(256 * 1024 * 1024),
+            )
+            .unwrap_or_default()
+            .set_default(
+                "compression.max_decompressed_length",
+                ValueKind::I64(256 * 1024 * 1024),
+            )
+            .unwrap_or_default()
             .set_default(
                 "metrics.influxdb.url",
                 ValueKind::String("http://127.0.0.1:8086".to_string()),
@@ -160,6 +291,113 @@ impl Settings {
     }
 }
 
+/// The name of the task hosted by an aggregator without configured tasks.
+pub const DEFAULT_TASK: &str = "default";
+
+#[derive(Debug, Validate, Deserialize, Clone)]
+/// The settings of a training task.
+///
+/// An aggregator can host several independent tasks. Each task runs its own state engine and
+/// keeps its data in its own storage namespace. Its endpoints are served under
+/// `/tasks/{name}/`.
+pub struct TaskSettings {
+    /// The name of the task. It may only contain ASCII letters, digits, `-` and `_`.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [[tasks]]
+    /// name = "keyboard"
+    /// ```
+    pub name: String,
+    /// The protocol settings of the task. They replace the top level `[protocol]` section, which
+    /// is used if they are left out.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [[tasks]]
+    /// name = "ranking"
+    ///
+    /// [tasks.protocol]
+    /// training_rounds = 50
+    /// participants = 100
+    /// delta_aggregation = "PseudoGradient"
+    /// model_history = 8
+    /// ```
+    pub protocol: Option<ProtocolSettings>,
+    /// The model settings of the task. They replace the top level `[model]` section, which is
+    /// used if they are left out.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [[tasks]]
+    /// name = "spam"
+    ///
+    /// [tasks.model]
+    /// data_type = "F64"
+    /// tensors = []
+    /// ```
+    #[validate]
+    pub model: Option<ModelSettings>,
+    /// The masking settings of the task. They replace the top level `[mask]` section, which is
+    /// used if they are left out.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [[tasks]]
+    /// name = "spam"
+    ///
+    /// [tasks.mask]
+    /// group_type = "Prime"
+    /// data_type = "F64"
+    /// bound_type = "B0"
+    /// model_type = "M3"
+    /// ```
+    pub mask: Option<MaskSettings>,
+}
+
+/// Checks that the tasks are valid and that their names are unique.
+fn validate_tasks(s: &Settings) -> Result<(), ValidationError> {
+    let mut names = std::collections::HashSet::new();
+    for task in &s.tasks {
+        if task.validate().is_err() {
+            return Err(ValidationError::new("invalid task settings"));
+        }
+        let name = &task.name;
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(ValidationError::new("invalid task name"));
+        }
+        if !names.insert(name) {
+            return Err(ValidationError::new("duplicate task name"));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+/// A training task with its resolved settings.
+pub struct Task {
+    /// The name of the task.
+    pub name: String,
+    /// The masking settings of the task.
+    pub mask: MaskSettings,
+    /// The model settings of the task.
+    pub model: ModelSettings,
+    /// The protocol settings of the task.
+    pub protocol: ProtocolSettings,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 #[cfg_attr(
     feature = "tls",
@@ -190,6 +428,23 @@ pub struct ApiSettings {
     /// ```
     pub server_address: std::net::SocketAddr,
 
+    /// The bearer token which authenticates the requests to the admin API. Leave this out to
+    /// disable the admin API.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [api]
+    /// admin_token = "change-me"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__API__ADMIN_TOKEN=change-me
+    /// ```
+    pub admin_token: Option<String>,
+
     #[cfg(feature = "tls")]
     #[cfg_attr(docsrs, doc(cfg(feature = "tls")))]
     /// The path to the server certificate to enable TLS server authentication. Leave this out to
@@ -295,14 +550,47 @@ pub struct ProtocolSettings {
     /// participants = 10
     /// ```
     pub participants: u32,
+    /// How delta updates are aggregated: either `Reconstruct`, to rebuild the local model from
+    /// its base model, or `PseudoGradient`, to apply the averaged deltas to the global model
+    /// scaled by the server learning rate.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [protocol]
+    /// delta_aggregation = "PseudoGradient"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__PROTOCOL__DELTA_AGGREGATION=PseudoGradient
+    /// ```
+    pub delta_aggregation: DeltaAggregation,
+    /// The number of past global models kept to resolve the base model of delta updates. Deltas
+    /// against an older version are rejected.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [protocol]
+    /// model_history = 4
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__PROTOCOL__MODEL_HISTORY=4
+    /// ```
+    pub model_history: usize,
 }
 
 impl std::fmt::Display for ProtocolSettings {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "[process]\n    training_rounds: {}\n    participants: {}\n",
-            self.training_rounds, self.participants
+            "[process]\n    training_rounds: {}\n    participants: {}\n    delta_aggregation: {:?}\n    model_history: {}\n",
+            self.training_rounds, self.participants, self.delta_aggregation, self.model_history
         )
     }
 }
@@ -343,7 +631,8 @@ pub struct MaskSettings {
     /// ```
     pub data_type: DataType,
 
-    /// The bounds of the numbers to be masked.
+    /// The bounds of the numbers to be masked. If the models are not masked, the weights of the
+    /// updates are checked against these bounds instead.
     ///
     /// # Examples
     ///
@@ -394,26 +683,28 @@ impl From<MaskSettings> for MaskConfig {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
-#[cfg_attr(test, derive(PartialEq, Eq))]
+#[derive(Debug, Validate, Deserialize, Clone)]
+#[validate(schema(function = "validate_model"))]
+#[cfg_attr(test, derive(PartialEq))]
 /// Model settings.
 pub struct ModelSettings {
-    // /// The expected length of the model. The model length corresponds to the number of elements.
-    // /// This value is used to validate the uniform length of the submitted models/masks.
-    // ///
-    // /// # Examples
-    // ///
-    // /// **TOML**
-    // /// ```text
-    // /// [model]
-    // /// length = 100
-    // /// ```
-    // ///
-    // /// **Environment variable**
-    // /// ```text
-    // /// MOSAIC__MODEL__LENGTH=100
-    // /// ```
-    // pub length: usize,
+    /// The expected length of the models, i.e. their number of weights. Updates of a different
+    /// length are rejected. If no length is given, it is implied by the tensors, respectively by
+    /// the latest global model, if any.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [model]
+    /// length = 100
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MODEL__LENGTH=100
+    /// ```
+    pub length: Option<usize>,
     /// The data type of the model.
     ///
     /// # Examples
@@ -429,14 +720,388 @@ pub struct ModelSettings {
     /// MOSAIC__MODEL__DATA_TYPE=F32
     /// ```
     pub data_type: DataType,
+    /// The named tensors the model consists of, in the order they appear in the flattened model.
+    /// The models are not structured if no tensors are given, which is the default. Otherwise,
+    /// the number of weights of every update must match the tensors. The data type of a tensor
+    /// must be exactly representable in the data type of the model.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [[model.tensors]]
+    /// name = "dense.weight"
+    /// shape = [128, 10]
+    /// data_type = "F32"
+    ///
+    /// [[model.tensors]]
+    /// name = "dense.bias"
+    /// shape = [10]
+    /// data_type = "F32"
+    /// ```
+    pub tensors: Vec<TensorSpec>,
+    /// The path to a file holding the global model which the training starts from. The model is
+    /// expected in the serialization in which the aggregator serves the global model, unless the
+    /// file is a NumPy `.npy` or safetensors file, as told by its extension. It must match the
+    /// model length, data type and tensors. By default, the training starts without a global
+    /// model.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [model]
+    /// initial_model = "/var/lib/mosaic/initial_model.bin"
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MODEL__INITIAL_MODEL=/var/lib/mosaic/initial_model.bin
+    /// ```
+    pub initial_model: Option<PathBuf>,
+    /// The maximum L2 norm of the updates, i.e. of the weights of full models and of the
+    /// differences of delta updates. By default, the L2 norm is not capped.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [model]
+    /// max_l2_norm = 100.0
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MODEL__MAX_L2_NORM=100.0
+    /// ```
+    #[validate(range(min = 0.0))]
+    pub max_l2_norm: Option<f64>,
+    /// The maximum L∞ norm of the updates, i.e. the maximum absolute weight of full models and
+    /// the maximum absolute difference of delta updates. By default, the L∞ norm is not capped.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [model]
+    /// max_linf_norm = 1.0
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MODEL__MAX_LINF_NORM=1.0
+    /// ```
+    #[validate(range(min = 0.0))]
+    pub max_linf_norm: Option<f64>,
+    /// The minimum local differential privacy the participants must apply to their updates. The
+    /// participants privatize their updates with at most the given `epsilon` and `delta`, and
+    /// updates which report a weaker privacy are rejected. By default, no privacy is required.
+    ///
+    /// Privatized models are sent as deltas against the global model, hence an `initial_model`
+    /// is required along with the privacy requirement.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [model.privacy]
+    /// max_epsilon = 1.0
+    /// max_delta = 1e-5
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MODEL__PRIVACY__MAX_EPSILON=1.0
+    /// MOSAIC__MODEL__PRIVACY__MAX_DELTA=1e-5
+    /// ```
+    pub privacy: Option<PrivacyRequirement>,
+}
+
+impl ModelSettings {
+    /// Gets the schema of the models, if they are structured.
+    ///
+    /// # Panics
+    /// Panics if the tensors are invalid, which is ruled out by the settings validation.
+    pub fn schema(&self) -> Option<ModelSchema> {
+        if self.tensors.is_empty() {
+            return None;
+        }
+        Some(ModelSchema::new(self.tensors.clone()).expect("invalid model schema"))
+    }
+
+    /// Checks the model settings.
+    fn validate_model(&self) -> Result<(), ValidationError> {
+        if let Some(privacy) = self.privacy {
+            if !(privacy.max_epsilon > 0.0 && privacy.max_epsilon.is_finite()) {
+                return Err(ValidationError::new("privacy epsilon must be positive"));
+            }
+            if !(0.0..1.0).contains(&privacy.max_delta) {
+                return Err(ValidationError::new("privacy delta must be in [0, 1)"));
+            }
+            if self.initial_model.is_none() {
+                return Err(ValidationError::new("privacy requires an initial model"));
+            }
+        }
+        if self.tensors.is_empty() {
+            return Ok(());
+        }
+        let schema = ModelSchema::new(self.tensors.clone())
+            .map_err(|_| ValidationError::new("invalid model tensors"))?;
+        if !schema
+            .tensors()
+            .iter()
+            .all(|tensor| is_representable(tensor.data_type, self.data_type))
+        {
+            return Err(ValidationError::new(
+                "tensor data type is not representable in the model data type",
+            ));
+        }
+        match self.length {
+            Some(length) if length != schema.weights() => {
+                Err(ValidationError::new("model length differs from the tensors"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A wrapper for validate derive.
+fn validate_model(s: &ModelSettings) -> Result<(), ValidationError> {
+    s.validate_model()
+}
+
+/// Checks whether every value of the data type `tensor` is exactly representable in the data type
+/// `model`.
+fn is_representable(tensor: DataType, model: DataType) -> bool {
+    tensor == model
+        || matches!(
+            (tensor, model),
+            (DataType::F32, DataType::F64)
+                | (DataType::I32, DataType::I64)
+                | (DataType::I32, DataType::F64)
+        )
 }
 
 impl From<ModelSettings> for ModelConfig {
-    fn from(ModelSettings { data_type }: ModelSettings) -> ModelConfig {
+    fn from(ModelSettings { data_type, .. }: ModelSettings) -> ModelConfig {
         ModelConfig { data_type }
     }
 }
 
+impl Default for ModelSettings {
+    fn default() -> Self {
+        Self {
+            length: None,
+            data_type: DataType::F32,
+            tensors: Vec::new(),
+            initial_model: None,
+            max_l2_norm: None,
+            max_linf_norm: None,
+            privacy: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Clone, Copy)]
+/// Multipart message settings.
+///
+/// Large messages are sent in chunks which the aggregator keeps in memory until the message is
+/// complete. These settings bound how long and how much of that partial data is kept.
+pub struct MultipartSettings {
+    /// The time in seconds after which a partial message that did not receive any new chunk is
+    /// discarded.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// ttl = 300
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__TTL=300
+    /// ```
+    #[validate(range(min = 1))]
+    pub ttl: u64,
+    /// The interval in seconds at which expired partial messages are swept.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// sweep_interval = 30
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__SWEEP_INTERVAL=30
+    /// ```
+    #[validate(range(min = 1))]
+    pub sweep_interval: u64,
+    /// The highest chunk ID a multipart message may use, i.e. a message is made of at most
+    /// `max_chunk_id + 1` chunks.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_chunk_id = 8191
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__MAX_CHUNK_ID=8191
+    /// ```
+    pub max_chunk_id: u16,
+    /// The number of partial messages a single participant may have in flight.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_participant_messages = 4
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__MAX_PARTICIPANT_MESSAGES=4
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_participant_messages: usize,
+    /// The number of bytes of partial messages a single participant may have in flight.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_participant_bytes = 67108864
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__MAX_PARTICIPANT_BYTES=67108864
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_participant_bytes: usize,
+    /// The number of partial messages all participants together may have in flight.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_messages = 10000
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__MAX_MESSAGES=10000
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_messages: usize,
+    /// The number of bytes of partial messages all participants together may have in flight.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [multipart]
+    /// max_bytes = 1073741824
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__MULTIPART__MAX_BYTES=1073741824
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_bytes: usize,
+}
+
+impl Default for MultipartSettings {
+    fn default() -> Self {
+        Self {
+            ttl: 300,
+            sweep_interval: 30,
+            max_chunk_id: 8191,
+            max_participant_messages: 4,
+            max_participant_bytes: 64 * 1024 * 1024,
+            max_messages: 10_000,
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, Clone)]
+/// Message compression settings.
+///
+/// Participants may compress the payload of their messages with one of the accepted codecs. The
+/// aggregator decompresses them before checking their signature.
+pub struct CompressionSettings {
+    /// The codecs the aggregator accepts. They are advertised to the participants in the round
+    /// parameters, an empty list disables compression.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [compression]
+    /// codecs = ["zstd", "lz4"]
+    /// ```
+    pub codecs: Vec<Codec>,
+    /// The maximum length in bytes of a decompressed message. The decompression of a message is
+    /// aborted as soon as it exceeds this length, which protects the aggregator against
+    /// decompression bombs.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [compression]
+    /// max_message_length = 268435456
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__COMPRESSION__MAX_MESSAGE_LENGTH=268435456
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_message_length: usize,
+    /// The maximum length in bytes of the values of a model sent with a lossy update codec, once
+    /// they are decoded. Updates whose model would decode to more values are rejected before they
+    /// are decoded.
+    ///
+    /// # Examples
+    ///
+    /// **TOML**
+    /// ```text
+    /// [compression]
+    /// max_decompressed_length = 268435456
+    /// ```
+    ///
+    /// **Environment variable**
+    /// ```text
+    /// MOSAIC__COMPRESSION__MAX_DECOMPRESSED_LENGTH=268435456
+    /// ```
+    #[validate(range(min = 1))]
+    pub max_decompressed_length: usize,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            codecs: Codec::ALL.to_vec(),
+            max_message_length: 256 * 1024 * 1024,
+            max_decompressed_length: 256 * 1024 * 1024,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Validate)]
 /// Metrics settings.
 pub struct MetricsSettings {
@@ -482,7 +1147,7 @@ pub struct InfluxSettings {
     pub db: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 /// Redis settings.
 pub struct RedisSettings {
     /// The URL where Redis is running.
@@ -587,6 +1252,168 @@ where
     deserializer.deserialize_str(EnvFilterVisitor)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads and validates the settings from the content of a configuration file.
+    fn settings(toml: &str) -> Result<Settings, SettingsError> {
+        let settings: Settings = Settings::set_default()
+            .add_source(config::File::from_str(toml, config::FileFormat::Toml))
+            .build()?
+            .try_deserialize()?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    #[test]
+    fn test_default_task() {
+        let tasks = settings("").unwrap().tasks();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].name, DEFAULT_TASK);
+    }
+
+    #[test]
+    fn test_tasks() {
+        let toml = r#"
+            [protocol]
+            training_rounds = 5
+            participants = 3
+            delta_aggregation = "Reconstruct"
+            model_history = 1
+
+            [[tasks]]
+            name = "keyboard"
+
+            [[tasks]]
+            name = "ranking"
+
+            [tasks.protocol]
+            training_rounds = 50
+            participants = 100
+            delta_aggregation = "PseudoGradient"
+            model_history = 8
+        "#;
+        let tasks = settings(toml).unwrap().tasks();
+        assert_eq!(tasks.len(), 2);
+
+        // a task without overrides takes the top level sections
+        assert_eq!(tasks[0].name, "keyboard");
+        assert_eq!(tasks[0].protocol.training_rounds, 5);
+        assert_eq!(tasks[0].protocol.participants, 3);
+
+        assert_eq!(tasks[1].name, "ranking");
+        assert_eq!(tasks[1].protocol.training_rounds, 50);
+        assert_eq!(tasks[1].protocol.participants, 100);
+        assert_eq!(tasks[1].protocol.model_history, 8);
+    }
+
+    #[test]
+    fn test_validate_tasks() {
+        let error = |names: &[&str]| {
+            let toml: String = names
+                .iter()
+                .map(|name| format!("[[tasks]]\nname = {:?}\n", name))
+                .collect();
+            settings(&toml).err().map(|err| err.details().join("\n"))
+        };
+
+        assert!(error(&["keyboard", "ranking_2", "spam-filter"]).is_none());
+        for name in ["", "key board", "keyboard/1", "clé"] {
+            assert!(error(&[name]).unwrap().contains("invalid task name"));
+        }
+        assert!(error(&["keyboard", "keyboard"])
+            .unwrap()
+            .contains("duplicate task name"));
+    }
+
+    #[test]
+    fn test_default_config() {
+        let config = Settings::default_config().unwrap();
+        assert!(config.contains("[compression]"));
+        let defaults = settings("").unwrap();
+        let reloaded = settings(&config).unwrap();
+        assert_eq!(reloaded.api.server_address, defaults.api.server_address);
+        assert_eq!(reloaded.mask, defaults.mask);
+        assert_eq!(reloaded.model, defaults.model);
+        assert_eq!(
+            reloaded.protocol.training_rounds,
+            defaults.protocol.training_rounds
+        );
+        assert_eq!(reloaded.compression.codecs, defaults.compression.codecs);
+    }
+
+    #[test]
+    fn test_privacy_requires_initial_model() {
+        let toml = "[model.privacy]\nmax_epsilon = 1.0\nmax_delta = 1e-5\n";
+        assert!(settings(toml).unwrap_err().details()[0].contains("requires an initial model"));
+        let toml = format!("[model]\ninitial_model = \"model.bin\"\n{}", toml);
+        assert!(settings(&toml).is_ok());
+    }
+
+    #[test]
+    fn test_settings_error_details() {
+        let toml = r#"
+            [model]
+            max_linf_norm = -1.0
+
+            [compression]
+            max_message_length = 0
+
+            [[tasks]]
+            name = "spam"
+
+            [[tasks]]
+            name = "spam"
+        "#;
+        // one detail per invalid setting, sorted by the path of the setting
+        let details = settings(toml).unwrap_err().details();
+        assert_eq!(details.len(), 3);
+        assert!(details[0].starts_with("compression.max_message_length: "));
+        assert!(details[1].starts_with("model.max_linf_norm: "));
+        assert!(details[2].starts_with("settings: "));
+        assert!(details[2].contains("duplicate task name"));
+
+        // errors other than validation errors have a single detail
+        let error = settings("[protocol]\ntraining_rounds = \"many\"\n").unwrap_err();
+        assert!(matches!(error, SettingsError::Loading(_)));
+        assert_eq!(error.details(), vec![error.to_string()]);
+    }
+
+    #[test]
+    fn test_validate_tensor_data_types() {
+        let error = |model: &str, tensor: &str| {
+            let toml = format!(
+                "[model]\ndata_type = {:?}\n[[model.tensors]]\nname = \"w\"\nshape = [2]\n\
+                 data_type = {:?}\n",
+                model, tensor
+            );
+            settings(&toml).err().map(|err| err.details().join("\n"))
+        };
+
+        let valid = [
+            ("F32", "F32"),
+            ("F64", "F32"),
+            ("I64", "I32"),
+            ("F64", "I32"),
+        ];
+        for (model, tensor) in valid {
+            assert!(error(model, tensor).is_none());
+        }
+        let invalid = [
+            ("F32", "F64"),
+            ("F32", "I32"),
+            ("I32", "F32"),
+            ("F64", "I64"),
+        ];
+        for (model, tensor) in invalid {
+            assert!(error(model, tensor)
+                .unwrap()
+                .contains("tensor data type is not representable"));
+        }
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
@@ -9,7 +9,7 @@ use serde::{
 };
 use validator::{Validate, ValidationError};
 
-#[derive(Debug, Validate, Deserialize)]
+#[derive(Debug, Clone, Validate, Deserialize)]
 pub struct S3Settings {
     /// The [access key ID](https://docs.aws.amazon.com/general/latest/gr/aws-sec-cred-types.html).
     ///
@@ -61,7 +61,7 @@ pub struct S3Settings {
     pub buckets: S3BucketsSettings,
 }
 
-#[derive(Debug, Validate, Deserialize)]
+#[derive(Debug, Clone, Validate, Deserialize)]
 /// S3 buckets settings.
 pub struct S3BucketsSettings {
     /// The bucket name in which the global models are stored.
@@ -157,7 +157,7 @@ where
     deserializer.deserialize_any(S3RegionVisitor)
 }
 
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Clone, Deserialize, Validate)]
 /// Restore settings.
 pub struct RestoreSettings {
     /// If set to `false`, the restoring of coordinator state is prevented.
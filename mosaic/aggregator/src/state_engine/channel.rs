@@ -2,6 +2,7 @@
 //! types.
 
 use std::{
+    convert::TryFrom,
     pin::Pin,
     task::{Context, Poll},
 };
@@ -16,8 +17,8 @@ use tracing::{trace, Span};
 use crate::storage::{LocalSeedDictAddError, MaskScoreIncrError, StorageError, SumPartAddError};
 use mosaic_core::{
     mask::MaskObject,
-    message::{Message, Payload, Update},
-    model::ModelObject,
+    message::{Message, Payload, Update, UpdateKind},
+    model::{EncodedModelObject, ModelObject, SchemaError},
     ParticipantPublicKey, SumParticipantEphemeralPublicKey, SumParticipantPublicKey,
     UpdateParticipantPublicKey,
 };
@@ -31,8 +32,18 @@ pub enum RequestError {
     MessageRejected,
     /// The message was discarded.
     MessageDiscarded,
+    /// The message was rejected because the collection is paused.
+    CollectionPaused,
+    /// The message payload cannot be handled by the state engine.
+    UnexpectedPayload,
     /// Invalid update: the model or scalar sent by the participant could not be aggregated.
     AggregationFailed,
+    /// Invalid update: the base model version {0} of the delta is unknown or has been evicted.
+    UnknownBaseModel(u32),
+    /// Invalid update: the model schema differs from the model schema of the round.
+    SchemaMismatch,
+    /// Invalid update: the model does not conform to its schema: {0}.
+    InvalidModel(#[from] SchemaError),
     /// The request could not be processed due to an internal error: {0}.
     InternalError(&'static str),
     /// Storage request failed: {0}.
@@ -60,6 +71,10 @@ pub struct SumRequest {
 pub struct UpdateRequest {
     /// The public key of the participant.
     pub participant_pk: UpdateParticipantPublicKey,
+    /// Whether the model is a full model or a delta.
+    pub kind: UpdateKind,
+    /// The version of the global model a delta is computed against.
+    pub base_model_version: u32,
     /// The local seed dict that contains the seed used to mask `masked_model`.
     pub local_seed_dict: LocalSeedDict,
     /// The masked model trained by the participant.
@@ -72,6 +87,10 @@ pub struct UpdateRequest {
 pub struct UpdateRequest {
     /// The public key of the participant.
     pub participant_pk: UpdateParticipantPublicKey,
+    /// Whether the model is a full model or a delta.
+    pub kind: UpdateKind,
+    /// The version of the global model a delta is computed against.
+    pub base_model_version: u32,
     /// The masked model trained by the participant.
     pub model_object: ModelObject,
 }
@@ -95,45 +114,65 @@ pub enum StateEngineRequest {
     Sum2(Sum2Request),
 }
 
-impl From<Message> for StateEngineRequest {
-    fn from(message: Message) -> Self {
+impl TryFrom<Message> for StateEngineRequest {
+    type Error = RequestError;
+
+    fn try_from(message: Message) -> Result<Self, Self::Error> {
         let participant_pk = message.participant_pk;
         #[cfg(feature = "secure")]
         match message.payload {
-            Payload::Sum(sum) => StateEngineRequest::Sum(SumRequest {
+            Payload::Sum(sum) => Ok(StateEngineRequest::Sum(SumRequest {
                 participant_pk,
                 ephm_pk: sum.ephm_pk,
-            }),
+            })),
             Payload::Update(update) => {
                 let Update {
+                    kind,
+                    base_model_version,
                     local_seed_dict,
                     masked_model,
                     ..
                 } = update;
-                StateEngineRequest::Update(UpdateRequest {
+                Ok(StateEngineRequest::Update(UpdateRequest {
                     participant_pk,
+                    kind,
+                    base_model_version,
                     local_seed_dict,
                     masked_model,
-                })
+                }))
             }
-            Payload::Sum2(sum2) => StateEngineRequest::Sum2(Sum2Request {
+            Payload::Sum2(sum2) => Ok(StateEngineRequest::Sum2(Sum2Request {
                 participant_pk,
                 model_mask: sum2.model_mask,
-            }),
-            Payload::Chunk(_) => unimplemented!(),
+            })),
+            // Chunks are reassembled by the multipart service and must never reach this point.
+            Payload::Chunk(_) => Err(RequestError::UnexpectedPayload),
         }
         #[cfg(not(feature = "secure"))]
         match message.payload {
-            Payload::Sum(_) => unimplemented!(),
             Payload::Update(update) => {
-                let Update { model_object, .. } = update;
-                StateEngineRequest::Update(UpdateRequest {
+                let Update {
+                    kind,
+                    base_model_version,
+                    model_object,
+                    ..
+                } = update;
+                let model_object = match model_object {
+                    EncodedModelObject::Dense(model_object) => model_object,
+                    // Lossy models are decoded by the message parser.
+                    EncodedModelObject::Lossy(_) => return Err(RequestError::UnexpectedPayload),
+                };
+                Ok(StateEngineRequest::Update(UpdateRequest {
                     participant_pk,
+                    kind,
+                    base_model_version,
                     model_object,
-                })
+                }))
+            }
+            // Sum and sum2 tasks only exist when masking is enabled.
+            Payload::Sum(_) | Payload::Sum2(_) | Payload::Chunk(_) => {
+                Err(RequestError::UnexpectedPayload)
             }
-            Payload::Sum2(_) => unimplemented!(),
-            Payload::Chunk(_) => unimplemented!(),
         }
     }
 }
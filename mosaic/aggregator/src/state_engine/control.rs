@@ -0,0 +1,149 @@
+//! This module provides the `ControlRequest`, `ControlSender` and `ControlReceiver` types.
+//!
+//! Control requests are sent by the operators of the aggregator, for example via the admin API,
+//! and are delivered to the [`StateEngine`] on a channel separate from the participant requests.
+//!
+//! [`StateEngine`]: crate::state_engine::StateEngine
+
+use std::{fmt, sync::Arc};
+
+use derive_more::From;
+use displaydoc::Display;
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+
+use mosaic_core::model::Model;
+
+/// Errors which can occur while the state engine handles a control request.
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum ControlError {
+    /// The state engine has shut down.
+    EngineStopped,
+    /// There are no updates to aggregate in the current round.
+    NothingToAggregate,
+    /// Invalid configuration: {0}.
+    InvalidConfiguration(&'static str),
+    /// The training has already started.
+    TrainingStarted,
+    /// Invalid model: {0}.
+    InvalidModel(String),
+    /// Saving the model failed: {0}.
+    SaveModel(String),
+    /// The state engine is not collecting messages.
+    NotCollecting,
+}
+
+/// A change of the protocol settings, which takes effect at the next round boundary. The
+/// settings which are left out are kept.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub struct Reconfiguration {
+    /// The number of participants one round should at least contain.
+    pub participants: Option<u32>,
+    /// The number of training rounds.
+    pub training_rounds: Option<u32>,
+}
+
+impl Reconfiguration {
+    /// Merges a later reconfiguration into this one.
+    pub fn merge(&mut self, other: Reconfiguration) {
+        self.participants = other.participants.or(self.participants);
+        self.training_rounds = other.training_rounds.or(self.training_rounds);
+    }
+}
+
+/// A control request to the [`StateEngine`].
+///
+/// [`StateEngine`]: crate::state_engine::StateEngine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlRequest {
+    /// Rejects the participant requests until the collection is resumed.
+    Pause,
+    /// Resumes the collection of participant requests.
+    Resume,
+    /// Aborts the current round and discards its buffered updates.
+    AbortRound,
+    /// Aggregates the buffered updates of the current round right away.
+    Aggregate,
+    /// Changes the protocol settings at the next round boundary.
+    Reconfigure(Reconfiguration),
+    /// Replaces the global model which the training starts from. Only possible before the first
+    /// update is accepted.
+    SetInitialModel(Arc<Model>),
+    /// Drains the pending participant requests and shuts the state engine down.
+    Shutdown,
+}
+
+impl fmt::Display for ControlRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // the model is left out, as it may have millions of weights
+            Self::SetInitialModel(model) => write!(f, "SetInitialModel({} weights)", model.len()),
+            Self::Reconfigure(reconfiguration) => write!(f, "Reconfigure({:?})", reconfiguration),
+            req => write!(f, "{:?}", req),
+        }
+    }
+}
+
+/// A channel for the state engine to send the response to a [`ControlRequest`].
+pub(in crate::state_engine) type ControlResponseSender = oneshot::Sender<Result<(), ControlError>>;
+
+/// A handle to send control requests to the [`StateEngine`].
+///
+/// [`StateEngine`]: crate::state_engine::StateEngine
+#[derive(Clone, From, Debug)]
+pub struct ControlSender(mpsc::UnboundedSender<(ControlRequest, ControlResponseSender)>);
+
+impl ControlSender {
+    /// Sends a control request to the [`StateEngine`] and waits until it is handled.
+    ///
+    /// # Errors
+    /// Fails if the [`StateEngine`] rejects the request or has already shut down.
+    ///
+    /// [`StateEngine`]: crate::state_engine::StateEngine
+    pub async fn request(&self, req: ControlRequest) -> Result<(), ControlError> {
+        let (resp_tx, resp_rx) = oneshot::channel();
+        self.0
+            .send((req, resp_tx))
+            .map_err(|_| ControlError::EngineStopped)?;
+        resp_rx.await.map_err(|_| ControlError::EngineStopped)?
+    }
+}
+
+/// The receiver half of the control channel that is used by the [`StateEngine`] to receive
+/// control requests.
+///
+/// [`StateEngine`]: crate::state_engine::StateEngine
+#[derive(From, Debug)]
+pub struct ControlReceiver(mpsc::UnboundedReceiver<(ControlRequest, ControlResponseSender)>);
+
+impl ControlReceiver {
+    /// Creates a new control channel and returns the [`ControlReceiver`] as well as the
+    /// [`ControlSender`] half.
+    pub fn new() -> (Self, ControlSender) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (ControlReceiver::from(rx), ControlSender::from(tx))
+    }
+
+    /// Receives the next control request.
+    ///
+    /// Never resolves once all the senders have been dropped, so that an aggregator without
+    /// operators keeps running.
+    pub async fn recv(&mut self) -> (ControlRequest, ControlResponseSender) {
+        match self.0.recv().await {
+            Some(item) => item,
+            None => futures::future::pending().await,
+        }
+    }
+
+    /// Receives the next pending control request without waiting for one.
+    pub fn try_recv(&mut self) -> Option<(ControlRequest, ControlResponseSender)> {
+        self.0.try_recv().ok()
+    }
+
+    /// Closes the channel, so that further control requests fail right away. The pending
+    /// requests can still be received.
+    pub fn close(&mut self) {
+        self.0.close()
+    }
+}
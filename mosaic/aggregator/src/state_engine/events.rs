@@ -24,7 +24,8 @@ pub struct Event<E> {
 #[derive(Debug, Clone, PartialEq)]
 pub enum ModelUpdate {
     Invalidate,
-    New(Arc<Model>),
+    /// A new global model and its version.
+    New(u32, Arc<Model>),
 }
 
 /// Dictionary update event.
@@ -218,7 +219,12 @@ where
         self.0.borrow().clone()
     }
 
-    #[cfg(test)]
+    /// Checks whether the coordinator stopped emitting events.
+    pub fn is_closed(&self) -> bool {
+        self.0.has_changed().is_err()
+    }
+
+    /// Waits for the coordinator to emit a new event.
     pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
         self.0.changed().await
     }
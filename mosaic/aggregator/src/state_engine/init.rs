@@ -1,23 +1,31 @@
+use std::sync::Arc;
+
 use displaydoc::Display;
 use thiserror::Error;
 #[cfg(feature = "model-persistence")]
-use tracing::{debug, info};
+use tracing::debug;
+use tracing::info;
 
 #[cfg(feature = "model-persistence")]
 use crate::settings::RestoreSettings;
 use crate::{
-    aggr::Aggregator,
-    settings::{MaskSettings, ModelSettings, ProtocolSettings},
+    aggr::{
+        initial::{self, InitialModelError},
+        Aggregator, ModelHistory,
+    },
+    settings::{
+        CompressionSettings, MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings,
+    },
     state_engine::{
         channel::{RequestReceiver, RequestSender},
+        control::{ControlReceiver, ControlSender},
         events::{EventPublisher, EventSubscriber, ModelUpdate},
         states::{Idle, SharedState, StateCondition, StateName},
         StateEngine,
     },
-    storage::{Storage, StorageError},
+    storage::{Storage, StorageError, StorageResult},
 };
-#[cfg(feature = "model-persistence")]
-use mosaic_core::mask::Model;
+use mosaic_core::{common::RoundSeed, crypto::ByteObject, model::Model};
 
 type StateEngineInitializationResult<T> = Result<T, StateEngineInitializationError>;
 
@@ -38,6 +46,10 @@ pub enum StateEngineInitializationError {
     GlobalModelUnavailable(String),
     /// Global model is invalid: {0}.
     GlobalModelInvalid(String),
+    /// Loading the initial model failed: {0}.
+    LoadInitialModel(InitialModelError),
+    /// Saving the initial model failed: {0}.
+    SaveInitialModel(StorageError),
 }
 
 /// The state engine initializer that initializes a new state engine.
@@ -45,6 +57,8 @@ pub struct StateEngineInitializer<T> {
     mask_settings: MaskSettings,
     model_settings: ModelSettings,
     protocol_settings: ProtocolSettings,
+    compression_settings: CompressionSettings,
+    multipart_settings: MultipartSettings,
     #[cfg(feature = "model-persistence")]
     restore_settings: RestoreSettings,
     store: T,
@@ -56,6 +70,8 @@ impl<T> StateEngineInitializer<T> {
         mask_settings: MaskSettings,
         model_settings: ModelSettings,
         protocol_settings: ProtocolSettings,
+        compression_settings: CompressionSettings,
+        multipart_settings: MultipartSettings,
         #[cfg(feature = "model-persistence")] restore_settings: RestoreSettings,
         store: T,
     ) -> Self {
@@ -63,6 +79,8 @@ impl<T> StateEngineInitializer<T> {
             mask_settings,
             model_settings,
             protocol_settings,
+            compression_settings,
+            multipart_settings,
             #[cfg(feature = "model-persistence")]
             restore_settings,
             store,
@@ -74,7 +92,17 @@ impl<T> StateEngineInitializer<T> {
         self,
         aggr: Aggregator,
         global_model: ModelUpdate,
-    ) -> (StateEngine<T>, RequestSender, EventSubscriber) {
+    ) -> (
+        StateEngine<T>,
+        RequestSender,
+        ControlSender,
+        EventSubscriber,
+    ) {
+        let mut models = ModelHistory::new(self.protocol_settings.model_history);
+        if let ModelUpdate::New(version, model) = &global_model {
+            models.insert(*version, model.clone());
+        }
+
         let (event_publisher, event_subscriber) = EventPublisher::init(
             aggr.round_id,
             aggr.keys.clone(),
@@ -84,11 +112,20 @@ impl<T> StateEngineInitializer<T> {
         );
 
         let (request_rx, request_tx) = RequestReceiver::new();
+        let (control_rx, control_tx) = ControlReceiver::new();
 
-        let shared = SharedState::new(aggr, event_publisher, request_rx, self.store);
+        let shared = SharedState::new(
+            aggr,
+            models,
+            self.model_settings.length,
+            event_publisher,
+            request_rx,
+            control_rx,
+            self.store,
+        );
 
         let state_engine = StateEngine::from(StateCondition::<Idle, _>::new(shared));
-        (state_engine, request_tx, event_subscriber)
+        (state_engine, request_tx, control_tx, event_subscriber)
     }
 }
 
@@ -100,7 +137,12 @@ where
     /// Initializes a new [`StateEngine`] with the given settings.
     pub async fn init(
         mut self,
-    ) -> StateEngineInitializationResult<(StateEngine<T>, RequestSender, EventSubscriber)> {
+    ) -> StateEngineInitializationResult<(
+        StateEngine<T>,
+        RequestSender,
+        ControlSender,
+        EventSubscriber,
+    )> {
         // crucial: init must be called before anything else in this module
         sodiumoxide::init().or(Err(StateEngineInitializationError::CryptoInit))?;
 
@@ -118,18 +160,64 @@ where
             .delete_aggregator_data()
             .await
             .map_err(StateEngineInitializationError::DeleteCoordinatorData)?;
-        Ok((
-            // CoordinatorState::new(
-            //     self.pet_settings,
-            //     self.mask_settings,
-            //     self.model_settings.clone(),
-            // ),
-            Aggregator::new(self.mask_settings, self.model_settings.clone(), &self.protocol_settings),
-            ModelUpdate::Invalidate,
+        // CoordinatorState::new(
+        //     self.pet_settings,
+        //     self.mask_settings,
+        //     self.model_settings.clone(),
+        // ),
+        let aggr = Aggregator::new(
+            self.mask_settings,
+            self.model_settings.clone(),
+            &self.protocol_settings,
+            &self.compression_settings,
+            &self.multipart_settings,
+        );
+        let global_model = self.load_initial_model(&aggr).await?;
+        Ok((aggr, global_model))
+    }
+
+    // Loads the initial model from the file given in the model settings, if any, and saves it
+    // as the global model of round 0.
+    async fn load_initial_model(
+        &mut self,
+        aggr: &Aggregator,
+    ) -> StateEngineInitializationResult<ModelUpdate> {
+        let path = match &self.model_settings.initial_model {
+            Some(path) => path,
+            None => return Ok(ModelUpdate::Invalidate),
+        };
+        info!("Loading the initial model from {}.", path.display());
+        let config = &aggr.round_params.model_config;
+        let schema = aggr.round_params.model_schema.as_ref();
+        let model = initial::load_model(path, config, schema)
+            .and_then(|model| {
+                initial::validate_model(&model, self.model_settings.length, config, schema)
+                    .map(|_| model)
+            })
+            .map_err(StateEngineInitializationError::LoadInitialModel)?;
+        save_initial_model(&mut self.store, &model)
+            .await
+            .map_err(StateEngineInitializationError::SaveInitialModel)?;
+        Ok(ModelUpdate::New(
+            aggr.round_params.model_version,
+            Arc::new(model),
         ))
     }
 }
 
+/// Saves an initial model as the latest global model.
+pub(in crate::state_engine) async fn save_initial_model<T: Storage>(
+    store: &mut T,
+    model: &Model,
+) -> StorageResult<()> {
+    // an initial model may be replaced before the training starts and the models of previous
+    // runs are kept, hence the id is made unique with a random seed
+    let id = store
+        .set_global_model(0, &RoundSeed::generate(), model)
+        .await?;
+    store.set_latest_global_model_id(&id).await
+}
+
 #[cfg(feature = "model-persistence")]
 #[cfg_attr(docsrs, doc(cfg(feature = "model-persistence")))]
 impl<T> StateEngineInitializer<T>
@@ -160,7 +248,12 @@ where
     /// - Any network error will cause the initialization to fail.
     pub async fn init(
         mut self,
-    ) -> StateEngineInitializationResult<(StateEngine<T>, RequestSender, EventSubscriber)> {
+    ) -> StateEngineInitializationResult<(
+        StateEngine<T>,
+        RequestSender,
+        ControlSender,
+        EventSubscriber,
+    )> {
         // crucial: init must be called before anything else in this module
         sodiumoxide::init().or(Err(StateEngineInitializationError::CryptoInit))?;
 
@@ -225,9 +318,10 @@ where
             "restore aggregator with global model id: {}",
             global_model_id
         );
+        let version = aggregator_state.round_params.model_version;
         Ok((
             aggregator_state,
-            ModelUpdate::New(std::sync::Arc::new(global_model)),
+            ModelUpdate::New(version, std::sync::Arc::new(global_model)),
         ))
     }
 
@@ -7,6 +7,7 @@
 //! # StateEngine states
 //!
 pub mod channel;
+pub mod control;
 pub mod events;
 pub mod init;
 pub mod states;
@@ -1,11 +1,13 @@
 use async_trait::async_trait;
+use tracing::{info, warn};
 
 use crate::{
-    aggr::buffer::FedBuffer,
+    aggr::{buffer::FedBuffer, DeltaAggregation},
     state_engine::{
         channel::{RequestError, StateEngineRequest, UpdateRequest},
         states::{
-            SharedState, State, StateCondition, StateError, StateHandler, StateName, Update,
+            Idle, ProcessOutcome, SharedState, Shutdown, State, StateCondition, StateError,
+            StateHandler, StateName, Update,
         },
         StateEngine,
     },
@@ -13,9 +15,12 @@ use crate::{
 };
 
 use mosaic_core::{
-    model::ModelObject,
+    message::UpdateKind,
+    model::{Model, ModelObject},
     UpdateParticipantPublicKey,
 };
+#[cfg(not(feature = "secure"))]
+use mosaic_core::model::ModelSchema;
 #[cfg(feature = "secure")]
 use crate::{
     mask::{Aggregation, MaskObject},
@@ -27,6 +32,8 @@ use crate::{
 pub struct Collect {
     /// [`FedBuffer`]
     fed_buffer: FedBuffer,
+    /// How the processing of the requests ended.
+    outcome: ProcessOutcome,
 }
 
 #[async_trait]
@@ -37,13 +44,33 @@ where
     const NAME: StateName = StateName::Collect;
 
     async fn perform(&mut self) -> Result<(), StateError> {
-        self.process().await?;
+        self.private.outcome = self.process().await?;
 
         Ok(())
     }
 
+    fn publish(&mut self) {
+        if self.private.outcome == ProcessOutcome::AbortRound {
+            info!("Aborting training round {}.", self.shared.aggr.round_id);
+            self.shared.aborted_rounds += 1;
+            self.shared.apply_reconfiguration();
+        }
+    }
+
     async fn next(self) -> Option<StateEngine<T>> {
-        Some(StateCondition::<Update, _>::new(self.shared, self.private.fed_buffer).into())
+        match self.private.outcome {
+            ProcessOutcome::Aggregate => {
+                Some(StateCondition::<Update, _>::new(self.shared, self.private.fed_buffer).into())
+            }
+            // the round restarts with fresh keys and seed, such that the participants which
+            // already sent an update notice the new round
+            ProcessOutcome::AbortRound if !self.shared.is_training_complete() => {
+                Some(StateCondition::<Idle, _>::new(self.shared).into())
+            }
+            ProcessOutcome::AbortRound | ProcessOutcome::Shutdown => {
+                Some(StateCondition::<Shutdown, _>::new(self.shared).into())
+            }
+        }
     }
 }
 
@@ -54,6 +81,7 @@ impl<T> StateCondition<Collect, T> {
         Self {
             private: Collect {
                 fed_buffer: FedBuffer::default(),
+                outcome: ProcessOutcome::Aggregate,
             },
             shared,
         }
@@ -72,6 +100,7 @@ where
                 participant_pk,
                 local_seed_dict,
                 masked_model,
+                ..
             }) = req
             {
                 // self.update_seed_dict_and_aggregate_mask(
@@ -90,10 +119,13 @@ where
         {
             if let StateEngineRequest::Update(UpdateRequest {
                 participant_pk,
+                kind,
+                base_model_version,
                 model_object,
             }) = req
             {
-                self.update_fedbuffer(&participant_pk, model_object).await
+                self.update_fedbuffer(&participant_pk, kind, base_model_version, model_object)
+                    .await
             } else {
                 Err(RequestError::MessageRejected)
             }
@@ -143,11 +175,37 @@ where
     async fn update_fedbuffer(
         &mut self,
         _pk: &UpdateParticipantPublicKey,
+        kind: UpdateKind,
+        base_model_version: u32,
         model_object: ModelObject,
     ) -> Result<(), RequestError> {
+        self.check_schema(&model_object)?;
+        let model: Model = model_object.data.into();
         #[cfg(not(feature = "redis"))]
         {
-            self.private.fed_buffer.local_models.push(model_object.data.into());
+            let fed_buffer = &mut self.private.fed_buffer;
+            match (kind, self.shared.aggr.params.delta_aggregation) {
+                (UpdateKind::Full, _) => fed_buffer.local_models.push(model),
+                (UpdateKind::Delta, delta_aggregation) => {
+                    let base_model = self
+                        .shared
+                        .models
+                        .get(base_model_version)
+                        .ok_or(RequestError::UnknownBaseModel(base_model_version))?;
+                    if base_model.len() != model.len() {
+                        warn!("delta does not match its base model, ignoring update message");
+                        return Err(RequestError::AggregationFailed);
+                    }
+                    match delta_aggregation {
+                        DeltaAggregation::Reconstruct => fed_buffer
+                            .local_models
+                            .push(base_model.iter().zip(model).map(|(w, d)| w + d).collect()),
+                        DeltaAggregation::PseudoGradient => {
+                            fed_buffer.pseudo_gradients.push(model)
+                        }
+                    }
+                }
+            }
         }
         #[cfg(feature = "redis")]
         {
@@ -162,6 +220,20 @@ where
         Ok(())
     }
 
+    #[cfg(not(feature = "secure"))]
+    /// Checks that the model carries the digest of the model schema of the round and conforms to
+    /// it.
+    fn check_schema(&self, model_object: &ModelObject) -> Result<(), RequestError> {
+        let schema = &self.shared.aggr.round_params.model_schema;
+        if model_object.schema_digest != schema.as_ref().map(ModelSchema::digest) {
+            return Err(RequestError::SchemaMismatch);
+        }
+        if let Some(schema) = schema {
+            schema.validate(model_object.data.len())?;
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "redis")]
     /// Adds a local seed dictionary to the global seed dictionary.
     ///
@@ -196,3 +268,64 @@ where
         Ok(())
     }
 }
+
+#[cfg(all(test, not(feature = "secure")))]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::state_engine::states::handler::tests::{state_engine, TestStore};
+    use mosaic_core::{crypto::ByteObject, model::FromPrimitives};
+
+    fn model(weights: &[f32]) -> Model {
+        Model::from_primitives(weights.iter().copied()).unwrap()
+    }
+
+    /// Creates the collect state of a state engine which keeps the global model of version `1`.
+    async fn collect(delta_aggregation: DeltaAggregation) -> StateCondition<Collect, TestStore> {
+        let (engine, ..) = state_engine(delta_aggregation).await;
+        match engine.next().await.unwrap() {
+            StateEngine::Collect(mut collect) => {
+                let base_model = Arc::new(model(&[1.0, 2.0, 3.0, 4.0]));
+                collect.shared.models.insert(1, base_model);
+                collect
+            }
+            _ => panic!("expected the collect state"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delta_aggregation() {
+        let pk = UpdateParticipantPublicKey::zeroed();
+        let delta = model(&[1.0; 4]);
+        let cases = [
+            (DeltaAggregation::Reconstruct, model(&[2.0, 3.0, 4.0, 5.0])),
+            (DeltaAggregation::PseudoGradient, delta.clone()),
+        ];
+        for (delta_aggregation, buffered) in cases {
+            let mut collect = collect(delta_aggregation).await;
+            let config = collect.shared.aggr.round_params.model_config;
+            let model_object = ModelObject::new(delta.0.clone(), config);
+            collect
+                .update_fedbuffer(&pk, UpdateKind::Delta, 1, model_object.clone())
+                .await
+                .unwrap();
+
+            let fed_buffer = &collect.private.fed_buffer;
+            let (local_models, pseudo_gradients) = match delta_aggregation {
+                DeltaAggregation::Reconstruct => (vec![buffered], Vec::new()),
+                DeltaAggregation::PseudoGradient => (Vec::new(), vec![buffered]),
+            };
+            assert_eq!(fed_buffer.local_models, local_models);
+            assert_eq!(fed_buffer.pseudo_gradients, pseudo_gradients);
+
+            // a delta against a global model which isn't kept can't be resolved
+            assert!(matches!(
+                collect
+                    .update_fedbuffer(&pk, UpdateKind::Delta, 2, model_object)
+                    .await,
+                Err(RequestError::UnknownBaseModel(2))
+            ));
+        }
+    }
+}
@@ -1,15 +1,20 @@
 use async_trait::async_trait;
-use std::collections::HashMap;
-use tokio::signal;
+use futures::StreamExt;
+use std::{collections::HashMap, sync::Arc};
 use tracing::{debug, info, Span, warn};
 
 use crate::{
+    aggr::initial,
     state_engine::{
         channel::{RequestError, ResponseSender, StateEngineRequest},
-        states::{State, StateCondition, StateError},
+        control::{ControlError, ControlRequest, ControlResponseSender},
+        events::ModelUpdate,
+        init::save_initial_model,
+        states::{SharedState, State, StateCondition, StateError, StateName},
     },
     storage::Storage,
 };
+use mosaic_core::model::Model;
 
 #[derive(Debug, Default, Clone, PartialEq)]
 pub struct MessageCounter {
@@ -39,6 +44,13 @@ impl MessageCounter {
             }
         }
     }
+    /// Gets the number of accepted messages for a specific training round.
+    pub fn accepted(&self, round_id: &u32) -> u32 {
+        self.counter
+            .get(round_id)
+            .map(|counter| counter.accepted)
+            .unwrap_or_default()
+    }
     /// Include the message to the counter.
     pub fn increment(&mut self, req_result: &Result<(), RequestError>, round_id: &u32) {
         if !self.counter.contains_key(round_id) {
@@ -106,34 +118,57 @@ pub trait StateHandler {
     async fn handle_request(&mut self, req: StateEngineRequest) -> Result<(), RequestError>;
 }
 
+/// How the processing of requests ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessOutcome {
+    /// The buffered updates are aggregated, either because enough of them arrived or because the
+    /// aggregation was forced.
+    Aggregate,
+    /// The round was aborted and its buffered updates are discarded.
+    AbortRound,
+    /// The state engine shuts down.
+    Shutdown,
+}
+
 impl<S, T> StateCondition<S, T>
 where
     S: Send,
     T: Storage,
     Self: State<T> + StateHandler,
 {
-    /// Processes requests.
-    pub async fn process(&mut self) -> Result<(), StateError> {
+    /// Processes requests until enough messages arrived or until a control request interrupts
+    /// the processing.
+    pub async fn process(&mut self) -> Result<ProcessOutcome, StateError> {
         let mut counter = MessageCounter::new(self.shared.aggr.round_params.per_round_participants);
 
+        if self.shared.shutdown {
+            return Ok(ProcessOutcome::Shutdown);
+        }
         if self.shared.aggr.round_params.per_round_participants == 0 {
             warn!("Participants per round parameter is 0. Consider setting `participants` in .toml config file.");
-            return Ok(());
+            return Ok(ProcessOutcome::Aggregate);
         }
         loop {
+            info!("Aggregator waiting for the next incoming request.");
+            // the channels are borrowed separately, as both are polled at the same time
+            let SharedState { control, rx, .. } = &mut self.shared;
             tokio::select! {
                 biased;
 
-                _ =  signal::ctrl_c() => {
-                    break Ok(())
+                (req, tx) = control.recv() => {
+                    if let Some(outcome) = self.process_control(req, tx, &counter).await {
+                        break Ok(outcome);
+                    }
                 }
-                next = self.next_request() => {
-                    let (req, span, tx) = next?;
+                next = rx.next() => {
+                    let (req, span, tx) = next.ok_or(StateError::RequestChannel(
+                        "error when receiving next request.",
+                    ))?;
                     self.process_single(req, span, tx, &mut counter).await;
                 }
             }
             if counter.reached_k(&self.shared.aggr.round_id) {
-                break Ok(());
+                break Ok(ProcessOutcome::Aggregate);
             }
         }
     }
@@ -146,8 +181,276 @@ where
         counter: &mut MessageCounter,
     ) {
         let _span_guard = span.enter();
-        let response = self.handle_request(req).await;
+        let response = if self.shared.paused {
+            Err(RequestError::CollectionPaused)
+        } else {
+            self.handle_request(req).await
+        };
         counter.increment(&response, &self.shared.aggr.round_id);
         let _ = tx.send(response);
     }
 }
+
+impl<S, T> StateCondition<S, T>
+where
+    S: Send,
+    T: Storage,
+    Self: State<T>,
+{
+    /// Handles the control requests which arrived while the state engine was not collecting
+    /// messages.
+    ///
+    /// Requests which take effect within a round are rejected, while a shutdown is deferred to
+    /// the next collection. Once the state engine stops, the channel is closed and all the
+    /// requests fail.
+    pub(in crate::state_engine) async fn process_pending_controls(&mut self) {
+        let stopped = matches!(Self::NAME, StateName::Failure | StateName::Shutdown);
+        if stopped {
+            self.shared.control.close();
+        }
+        let counter = MessageCounter::default();
+        while let Some((req, tx)) = self.shared.control.try_recv() {
+            match req {
+                _ if stopped => {
+                    info!("Aggregator rejects the control request {}.", req);
+                    let _ = tx.send(Err(ControlError::EngineStopped));
+                }
+                ControlRequest::AbortRound => {
+                    info!("Aggregator rejects the control request {}.", req);
+                    let _ = tx.send(Err(ControlError::NotCollecting));
+                }
+                req => {
+                    if let Some(ProcessOutcome::Shutdown) =
+                        self.process_control(req, tx, &counter).await
+                    {
+                        self.shared.shutdown = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Processes a control request. Returns the outcome of the processing if the request
+    /// interrupts it.
+    async fn process_control(
+        &mut self,
+        req: ControlRequest,
+        tx: ControlResponseSender,
+        counter: &MessageCounter,
+    ) -> Option<ProcessOutcome> {
+        info!("Aggregator received the control request {}.", req);
+        let (response, outcome) = match req {
+            ControlRequest::Pause => {
+                self.shared.paused = true;
+                (Ok(()), None)
+            }
+            ControlRequest::Resume => {
+                self.shared.paused = false;
+                (Ok(()), None)
+            }
+            ControlRequest::Reconfigure(reconfiguration) => {
+                if reconfiguration.participants == Some(0) {
+                    let err = ControlError::InvalidConfiguration("participants must be positive");
+                    (Err(err), None)
+                } else {
+                    self.shared.reconfiguration.merge(reconfiguration);
+                    (Ok(()), None)
+                }
+            }
+            ControlRequest::Aggregate => {
+                if counter.accepted(&self.shared.aggr.round_id) == 0 {
+                    (Err(ControlError::NothingToAggregate), None)
+                } else {
+                    (Ok(()), Some(ProcessOutcome::Aggregate))
+                }
+            }
+            ControlRequest::SetInitialModel(model) => {
+                (self.set_initial_model(model, counter).await, None)
+            }
+            ControlRequest::AbortRound => (Ok(()), Some(ProcessOutcome::AbortRound)),
+            ControlRequest::Shutdown => (Ok(()), Some(ProcessOutcome::Shutdown)),
+        };
+        let _ = tx.send(response);
+        outcome
+    }
+
+    /// Replaces the global model which the training starts from, as long as no global model has
+    /// been aggregated and no update has been accepted in the current round.
+    async fn set_initial_model(
+        &mut self,
+        model: Arc<Model>,
+        counter: &MessageCounter,
+    ) -> Result<(), ControlError> {
+        let aggr = &self.shared.aggr;
+        if aggr.round_params.model_version != 0 || counter.accepted(&aggr.round_id) != 0 {
+            return Err(ControlError::TrainingStarted);
+        }
+        initial::validate_model(
+            &model,
+            self.shared.model_length,
+            &aggr.round_params.model_config,
+            aggr.round_params.model_schema.as_ref(),
+        )
+        .map_err(|err| ControlError::InvalidModel(err.to_string()))?;
+        save_initial_model(&mut self.shared.store, &model)
+            .await
+            .map_err(|err| ControlError::SaveModel(err.to_string()))?;
+
+        info!("Publishing the replaced initial model.");
+        self.shared.models.insert(0, model.clone());
+        self.shared
+            .publisher
+            .broadcast_model(ModelUpdate::New(0, model));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(in crate::state_engine) mod tests {
+    use futures::FutureExt;
+
+    use super::*;
+    use crate::{
+        aggr::DeltaAggregation,
+        settings::{
+            CompressionSettings, MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings,
+        },
+        state_engine::{
+            channel::RequestSender,
+            control::{ControlSender, Reconfiguration},
+            events::EventSubscriber,
+            init::StateEngineInitializer,
+            StateEngine,
+        },
+        storage::{
+            aggr_storage::noop::AggrNoOp, model_storage::noop::ModelNoOp, trust_anchor::noop::NoOp,
+            Store,
+        },
+    };
+    use mosaic_core::{
+        mask::{BoundType, GroupType, ModelType},
+        model::DataType,
+    };
+
+    pub(in crate::state_engine) type TestStore = Store<AggrNoOp, ModelNoOp, NoOp>;
+
+    type Request = std::pin::Pin<Box<dyn futures::Future<Output = Result<(), ControlError>>>>;
+
+    /// Creates a state engine for models of 4 weights, which is idle.
+    pub(in crate::state_engine) async fn state_engine(
+        delta_aggregation: DeltaAggregation,
+    ) -> (
+        StateEngine<TestStore>,
+        RequestSender,
+        ControlSender,
+        EventSubscriber,
+    ) {
+        StateEngineInitializer::new(
+            MaskSettings {
+                group_type: GroupType::Prime,
+                data_type: DataType::F32,
+                bound_type: BoundType::B0,
+                model_type: ModelType::M3,
+            },
+            ModelSettings {
+                length: Some(4),
+                data_type: DataType::F32,
+                ..ModelSettings::default()
+            },
+            ProtocolSettings {
+                training_rounds: 1,
+                participants: 1,
+                delta_aggregation,
+                model_history: 1,
+            },
+            CompressionSettings::default(),
+            MultipartSettings::default(),
+            #[cfg(feature = "model-persistence")]
+            crate::settings::RestoreSettings { enable: false },
+            Store::new(AggrNoOp, ModelNoOp),
+        )
+        .init()
+        .await
+        .unwrap()
+    }
+
+    /// Sends a control request without waiting for the response.
+    fn send(control: &ControlSender, req: ControlRequest) -> Request {
+        let control = control.clone();
+        let mut request: Request = Box::pin(async move { control.request(req).await });
+        assert!((&mut request).now_or_never().is_none());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_control_outside_collect() {
+        let (engine, _requests_tx, control, _subscriber) =
+            state_engine(DeltaAggregation::Reconstruct).await;
+
+        // the requests are handled by the idle state, before the collection starts
+        let mut pause = send(&control, ControlRequest::Pause);
+        let mut abort = send(&control, ControlRequest::AbortRound);
+        let mut aggregate = send(&control, ControlRequest::Aggregate);
+        let reconfiguration = Reconfiguration {
+            participants: Some(0),
+            ..Reconfiguration::default()
+        };
+        let mut reconfigure = send(&control, ControlRequest::Reconfigure(reconfiguration));
+        let mut shutdown = send(&control, ControlRequest::Shutdown);
+        let engine = engine.next().await.unwrap();
+        assert_eq!((&mut pause).now_or_never(), Some(Ok(())));
+        assert_eq!(
+            (&mut abort).now_or_never(),
+            Some(Err(ControlError::NotCollecting))
+        );
+        assert_eq!(
+            (&mut aggregate).now_or_never(),
+            Some(Err(ControlError::NothingToAggregate))
+        );
+        assert!(matches!(
+            (&mut reconfigure).now_or_never(),
+            Some(Err(ControlError::InvalidConfiguration(_)))
+        ));
+        assert_eq!((&mut shutdown).now_or_never(), Some(Ok(())));
+        match &engine {
+            StateEngine::Collect(state) => assert!(state.shared.paused && state.shared.shutdown),
+            _ => panic!("expected the collect state"),
+        }
+
+        // the requested shutdown ends the collection right away
+        let engine = engine.next().await.unwrap();
+        assert!(matches!(engine, StateEngine::Shutdown(_)));
+        let mut resume = send(&control, ControlRequest::Resume);
+        assert!(engine.next().await.is_none());
+        assert_eq!(
+            (&mut resume).now_or_never(),
+            Some(Err(ControlError::EngineStopped))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abort_round() {
+        let (engine, _requests_tx, control, subscriber) =
+            state_engine(DeltaAggregation::Reconstruct).await;
+        let engine = engine.next().await.unwrap();
+        let params = subscriber.params_listener().get_latest().event;
+
+        // the aborted round restarts with fresh round parameters, and doesn't count as a
+        // performed training round
+        let mut abort = send(&control, ControlRequest::AbortRound);
+        let engine = engine.next().await.unwrap();
+        assert_eq!((&mut abort).now_or_never(), Some(Ok(())));
+        match &engine {
+            StateEngine::Idle(state) => assert!(!state.shared.is_training_complete()),
+            _ => panic!("expected the idle state"),
+        }
+        let engine = engine.next().await.unwrap();
+        let fresh_params = subscriber.params_listener().get_latest().event;
+        assert_ne!(fresh_params.pk, params.pk);
+        assert_ne!(fresh_params.seed, params.seed);
+        match &engine {
+            StateEngine::Collect(state) => assert_eq!(state.shared.aggr.get_round_id(), 2),
+            _ => panic!("expected the collect state"),
+        }
+    }
+}
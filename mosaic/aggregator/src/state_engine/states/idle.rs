@@ -11,7 +11,10 @@ use crate::{
     },
     storage::{Storage, StorageError},
 };
-use mosaic_core::crypto::EncryptKeyPair;
+use mosaic_core::{
+    common::RoundSeed,
+    crypto::{ByteObject, EncryptKeyPair},
+};
 
 /// Errors which can occur during the idle phase.
 #[derive(Debug, Display, Error)]
@@ -41,7 +44,7 @@ where
 
         self.gen_round_keypair();
         // self.update_round_probabilities();
-        // self.update_round_seed();
+        self.update_round_seed();
 
         self.set_aggr_state_to_store().await?;
 
@@ -74,6 +77,15 @@ impl<T> StateCondition<Idle, T> {
         self.shared.aggr.round_params.pk = self.shared.aggr.keys.public;
     }
 
+    /// Draws a fresh seed for the upcoming round.
+    fn update_round_seed(&mut self) {
+        debug!(
+            "updating the round seed for the upcoming round {:?}.",
+            &self.shared.aggr.round_id
+        );
+        self.shared.aggr.round_params.seed = RoundSeed::generate();
+    }
+
     /// Broadcasts the keys.
     fn publish_keys(&mut self) {
         debug!("broadcasting new keys");
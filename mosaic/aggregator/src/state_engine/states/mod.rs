@@ -14,7 +14,7 @@ mod update;
 pub use self::{
     collect::Collect,
     failure::Failure,
-    handler::{MessageCounter, StateHandler},
+    handler::{MessageCounter, ProcessOutcome, StateHandler},
     idle::{Idle, IdleError},
     shutdown::Shutdown,
     state::{SharedState, State, StateCondition, StateError, StateName},
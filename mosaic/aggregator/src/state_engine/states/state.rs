@@ -6,9 +6,10 @@ use tracing::{debug, debug_span, info, warn, Span};
 use tracing_futures::Instrument;
 
 use crate::{
-    aggr::Aggregator,
+    aggr::{Aggregator, ModelHistory},
     state_engine::{
         channel::{RequestReceiver, ResponseSender, StateEngineRequest},
+        control::{ControlReceiver, Reconfiguration},
         events::EventPublisher,
         states::{IdleError, UpdateError},
         Failure, StateEngine,
@@ -96,6 +97,9 @@ where
             }
 
             self.publish();
+            if Self::NAME != StateName::Collect {
+                self.process_pending_controls().await;
+            }
 
             debug!("Transitioning to the next state.");
             self.next().await
@@ -144,24 +148,75 @@ where
 pub struct SharedState<T> {
     /// [`Aggregator`]
     pub(in crate::state_engine) aggr: Aggregator,
+    /// The latest global models, against which delta updates are resolved.
+    pub(in crate::state_engine) models: ModelHistory,
+    /// The expected length of the models, if it is configured.
+    pub(in crate::state_engine) model_length: Option<usize>,
     /// [`RequestReceiver`] for enabling receiving requests from the client.
     ///
     pub(in crate::state_engine) rx: RequestReceiver,
+    /// [`ControlReceiver`] for enabling receiving control requests from the operators.
+    pub(in crate::state_engine) control: ControlReceiver,
     /// [`EventPublisher`] responsible for publishing the latest updates.
     ///
     pub(in crate::state_engine) publisher: EventPublisher,
     /// The store for storing coordinator and model data.
     pub(in crate::state_engine) store: T,
+    /// Whether the collection of participant requests is paused.
+    pub(in crate::state_engine) paused: bool,
+    /// The pending change of the protocol settings, applied at the next round boundary.
+    pub(in crate::state_engine) reconfiguration: Reconfiguration,
+    /// Whether a shutdown was requested while the state engine was not collecting messages.
+    pub(in crate::state_engine) shutdown: bool,
+    /// The number of aborted training rounds, which don't count as performed rounds.
+    pub(in crate::state_engine) aborted_rounds: u32,
 }
 
 impl<T> SharedState<T> {
     /// Init new [`SharedState`] for the aggregation server.
-    pub fn new(aggr: Aggregator, publisher: EventPublisher, rx: RequestReceiver, store: T) -> Self {
+    pub fn new(
+        aggr: Aggregator,
+        models: ModelHistory,
+        model_length: Option<usize>,
+        publisher: EventPublisher,
+        rx: RequestReceiver,
+        control: ControlReceiver,
+        store: T,
+    ) -> Self {
         SharedState {
             aggr,
+            models,
+            model_length,
             rx,
+            control,
             publisher,
             store,
+            paused: false,
+            reconfiguration: Reconfiguration::default(),
+            shutdown: false,
+            aborted_rounds: 0,
+        }
+    }
+
+    /// Applies the pending change of the protocol settings to the round parameters.
+    pub(in crate::state_engine) fn apply_reconfiguration(&mut self) {
+        let Reconfiguration {
+            participants,
+            training_rounds,
+        } = std::mem::take(&mut self.reconfiguration);
+        let round_params = &mut self.aggr.round_params;
+        if let Some(participants) = participants {
+            info!("Setting the participants per round to {}.", participants);
+            round_params.per_round_participants = participants;
         }
+        if let Some(training_rounds) = training_rounds {
+            info!("Setting the training rounds to {}.", training_rounds);
+            round_params.training_rounds = training_rounds;
+        }
+    }
+
+    /// Checks whether all the training rounds have been performed.
+    pub(in crate::state_engine) fn is_training_complete(&self) -> bool {
+        self.aggr.get_round_id() - self.aborted_rounds >= self.aggr.round_params.training_rounds
     }
 }
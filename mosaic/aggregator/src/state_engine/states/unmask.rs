@@ -64,9 +64,10 @@ where
             self.private.global_model.take().expect(
                 "unreachable: never fails when `broadcast()` is called after `end_round()`",
             );
-        self.shared
-            .publisher
-            .broadcast_model(ModelUpdate::New(global_model));
+        self.shared.publisher.broadcast_model(ModelUpdate::New(
+            self.shared.aggr.round_params.model_version,
+            global_model,
+        ));
     }
 
     async fn next(self) -> Option<StateEngine<T>> {
@@ -68,6 +68,8 @@ where
         #[cfg(feature = "model-persistence")]
         self.save_global_model().await?;
 
+        self.shared.apply_reconfiguration();
+
         Ok(())
     }
 
@@ -78,13 +80,17 @@ where
             .global_model
             .take()
             .expect("unreachable: never fails when `publish()` is called after `end_round()`");
+        self.shared.publisher.broadcast_model(ModelUpdate::New(
+            self.shared.aggr.round_params.model_version,
+            global_model,
+        ));
         self.shared
             .publisher
-            .broadcast_model(ModelUpdate::New(global_model));
+            .broadcast_params(self.shared.aggr.round_params.clone());
     }
 
     async fn next(self) -> Option<StateEngine<T>> {
-        if self.shared.aggr.get_round_id() >= self.shared.aggr.round_params.training_rounds {
+        if self.shared.is_training_complete() {
             Some(StateCondition::<Shutdown, _>::new(self.shared).into())
         } else {
             Some(
@@ -147,11 +153,36 @@ where
     }
 
     #[cfg(not(feature = "secure"))]
+    /// Averages the buffered local models and applies the buffered pseudo-gradients. The new
+    /// global model is versioned with the current round ID.
     async fn aggregate_model(&mut self) -> Result<(), AggregationError> {
-        self.private.global_model =
-            Some(Arc::new(self.private.aggr.aggregate(
-                &self.private.fed_buffer.local_models,
-            )?));
+        let FedBuffer {
+            local_models,
+            pseudo_gradients,
+            ..
+        } = &self.private.fed_buffer;
+        let mut global_model = if local_models.is_empty() && !pseudo_gradients.is_empty() {
+            self.shared
+                .models
+                .latest()
+                .map(|model| model.as_ref().clone())
+                .ok_or(AggregationError::NoModels)?
+        } else {
+            self.private.aggr.aggregate(local_models)?
+        };
+        if !pseudo_gradients.is_empty() {
+            global_model = self.private.aggr.apply_pseudo_gradients(
+                global_model,
+                pseudo_gradients,
+                self.shared.aggr.params.eta,
+            )?;
+        }
+
+        let global_model = Arc::new(global_model);
+        let version = self.shared.aggr.get_round_id();
+        self.shared.models.insert(version, global_model.clone());
+        self.shared.aggr.round_params.model_version = version;
+        self.private.global_model = Some(global_model);
 
         Ok(())
     }
@@ -36,6 +36,8 @@
 //!     "latest_global_model_id": global_model_id
 //! }
 //! ```
+//!
+//! If the client has a namespace, every key is prefixed with `"{namespace}:"`.
 
 pub(in crate::storage) mod impls;
 
@@ -70,6 +72,7 @@ use crate::{
     },
 };
 use mosaic_core::{
+    crypto::ByteObject,
     mask::MaskObject,
     LocalSeedDict,
     SeedDict,
@@ -83,6 +86,7 @@ use mosaic_core::{
 #[derive(Clone)]
 pub struct Client {
     connection: ConnectionManager,
+    namespace: Option<String>,
 }
 
 fn to_storage_err(e: RedisError) -> StorageError {
@@ -100,14 +104,43 @@ impl Client {
     pub async fn new<T: IntoConnectionInfo>(url: T) -> Result<Self, RedisError> {
         let client = redis::Client::open(url)?;
         let connection = client.get_tokio_connection_manager().await?;
-        Ok(Self { connection })
+        Ok(Self {
+            connection,
+            namespace: None,
+        })
+    }
+
+    /// Prefixes the keys of the client with `namespace`, so that the data of several tasks can be
+    /// kept in the same Redis instance.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
+    /// Gets the key of `name` in the namespace of the client.
+    fn key(&self, name: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}:{}", namespace, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Gets the key of the seed dict entry of `sum_pk` in the namespace of the client.
+    fn seed_dict_key(&self, sum_pk: &SumParticipantPublicKey) -> Vec<u8> {
+        let mut key = match &self.namespace {
+            Some(namespace) => format!("{}:", namespace).into_bytes(),
+            None => Vec::new(),
+        };
+        key.extend_from_slice(sum_pk.as_slice());
+        key
     }
 
     async fn create_flush_dicts_pipeline(&mut self) -> RedisResult<Pipeline> {
         // https://redis.io/commands/hkeys
         // > Return value:
         //   Array reply: list of fields in the hash, or an empty list when key does not exist.
-        let sum_pks: Vec<PublicSigningKeyRead> = self.connection.hkeys("sum_dict").await?;
+        let sum_pks: Vec<PublicSigningKeyRead> =
+            self.connection.hkeys(self.key("sum_dict")).await?;
         let mut pipe = redis::pipe();
 
         // https://redis.io/commands/del
@@ -118,17 +151,17 @@ impl Client {
         // We ignore the return value because we are not interested in it.
 
         // delete sum dict
-        pipe.del("sum_dict").ignore();
+        pipe.del(self.key("sum_dict")).ignore();
 
         // delete seed dict
-        pipe.del("update_participants").ignore();
+        pipe.del(self.key("update_participants")).ignore();
         for sum_pk in sum_pks {
-            pipe.del(sum_pk).ignore();
+            pipe.del(self.seed_dict_key(&sum_pk.into())).ignore();
         }
 
         // delete mask dict
-        pipe.del("mask_submitted").ignore();
-        pipe.del("mask_dict").ignore();
+        pipe.del(self.key("mask_submitted")).ignore();
+        pipe.del(self.key("mask_dict")).ignore();
         Ok(pipe)
     }
 }
@@ -143,7 +176,7 @@ impl AggregatorStorage for Client {
         // Possible return value in our case:
         // > Simple string reply: OK if SET was executed correctly.
         self.connection
-            .set("coordinator_state", state)
+            .set(self.key("coordinator_state"), state)
             .await
             .map_err(to_storage_err)
     }
@@ -156,7 +189,7 @@ impl AggregatorStorage for Client {
         // > Return value
         //   Bulk string reply: the value of key, or nil when key does not exist.
         self.connection
-            .get("coordinator_state")
+            .get(self.key("coordinator_state"))
             .await
             .map_err(to_storage_err)
     }
@@ -385,8 +418,8 @@ impl AggregatorStorage for Client {
     async fn delete_coordinator_data(&mut self) -> StorageResult<()> {
         debug!("flush coordinator data");
         let mut pipe = self.create_flush_dicts_pipeline().await?;
-        pipe.del("coordinator_state").ignore();
-        pipe.del("latest_global_model_id").ignore();
+        pipe.del(self.key("coordinator_state")).ignore();
+        pipe.del(self.key("latest_global_model_id")).ignore();
         pipe.atomic()
             .query_async(&mut self.connection)
             .await
@@ -412,7 +445,7 @@ impl AggregatorStorage for Client {
         // Possible return value in our case:
         // > Simple string reply: OK if SET was executed correctly.
         self.connection
-            .set("latest_global_model_id", global_model_id)
+            .set(self.key("latest_global_model_id"), global_model_id)
             .await
             .map_err(to_storage_err)
     }
@@ -426,7 +459,7 @@ impl AggregatorStorage for Client {
         // > Return value
         //   Bulk string reply: the value of key, or nil when key does not exist.
         self.connection
-            .get("latest_global_model_id")
+            .get(self.key("latest_global_model_id"))
             .await
             .map_err(to_storage_err)
     }
@@ -456,7 +489,7 @@ impl Client {
         //   Integer reply: the number of fields that were removed from the hash,
         //   not including specified but non existing fields.
         self.connection
-            .hdel("sum_dict", PublicSigningKeyWrite::from(pk))
+            .hdel(self.key("sum_dict"), PublicSigningKeyWrite::from(pk))
             .await
     }
 
@@ -465,7 +498,7 @@ impl Client {
         // https://redis.io/commands/hlen
         // > Return value
         //   Integer reply: number of fields in the hash, or 0 when key does not exist.
-        self.connection.hlen("sum_dict").await
+        self.connection.hlen(self.key("sum_dict")).await
     }
 
     // Returns the [`SumParticipantPublicKey`] of the [`SumDict`] or an empty list when the
@@ -477,7 +510,7 @@ impl Client {
         // > Return value:
         //   Array reply: list of fields in the hash, or an empty list when key does not exist.
         let result: std::collections::HashSet<PublicSigningKeyRead> =
-            self.connection.hkeys("sum_dict").await?;
+            self.connection.hkeys(self.key("sum_dict")).await?;
         let sum_pks = result.into_iter().map(|pk| pk.into()).collect();
 
         Ok(sum_pks)
@@ -490,7 +523,7 @@ impl Client {
     ) -> RedisResult<u64> {
         self.connection
             .srem(
-                "update_participants",
+                self.key("update_participants"),
                 PublicSigningKeyWrite::from(update_pk),
             )
             .await
@@ -498,14 +531,14 @@ impl Client {
 
     pub async fn mask_submitted_set(&mut self) -> RedisResult<Vec<SumParticipantPublicKey>> {
         let result: Vec<PublicSigningKeyRead> =
-            self.connection.smembers("update_submitted").await?;
+            self.connection.smembers(self.key("update_submitted")).await?;
         let sum_pks = result.into_iter().map(|pk| pk.into()).collect();
         Ok(sum_pks)
     }
 
-    // Returns all keys in the current database
+    // Returns all keys in the namespace of the client
     pub async fn keys(&mut self) -> RedisResult<Vec<String>> {
-        self.connection.keys("*").await
+        self.connection.keys(self.key("*")).await
     }
 
     /// Returns the [`SeedDict`] entry for the given ['SumParticipantPublicKey'] or an empty map
@@ -525,7 +558,7 @@ impl Client {
         //   list when key does not exist.
         let result: Vec<(PublicSigningKeyRead, EncryptedMaskSeedRead)> = self
             .connection
-            .hgetall(PublicSigningKeyWrite::from(sum_pk))
+            .hgetall(self.seed_dict_key(sum_pk))
             .await?;
         let seed_dict = result
             .into_iter()
@@ -535,14 +568,18 @@ impl Client {
         Ok(seed_dict)
     }
 
-    /// Deletes all data in the current database.
+    /// Deletes all data in the namespace of the client, respectively in the current database if
+    /// the client has no namespace.
     pub async fn flush_db(&mut self) -> RedisResult<()> {
-        debug!("flush current database");
-        // https://redis.io/commands/flushdb
-        // > This command never fails.
-        redis::cmd("FLUSHDB")
-            .arg("ASYNC")
-            .query_async(&mut self.connection)
-            .await
+        debug!("flush namespace {:?}", self.namespace);
+        // https://redis.io/commands/keys
+        // > Return value
+        //   Array reply: list of keys matching pattern.
+        let keys: Vec<Vec<u8>> = self.connection.keys(self.key("*")).await?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+        // https://redis.io/commands/del
+        self.connection.del(keys).await
     }
 }
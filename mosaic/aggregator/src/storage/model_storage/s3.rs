@@ -66,6 +66,7 @@ pub enum ClientError {
 pub struct Client {
     buckets: Arc<S3BucketsSettings>,
     client: S3Client,
+    namespace: Option<String>,
 }
 
 impl Client {
@@ -99,9 +100,17 @@ impl Client {
         Ok(Self {
             buckets: Arc::new(settings.buckets),
             client: S3Client::new_with(dispatcher, credentials_provider, settings.region),
+            namespace: None,
         })
     }
 
+    /// Prefixes the keys of the global models with `namespace`, so that the models of several
+    /// tasks can be kept in the same bucket.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+
     /// Creates the `global models` bucket.
     /// This method does not fail if the bucket already exists or is already owned by you.
     pub async fn create_global_models_bucket(&self) -> ClientResult<()> {
@@ -180,6 +189,10 @@ impl ModelStorage for Client {
         global_model: &Model,
     ) -> StorageResult<String> {
         let id = Self::create_global_model_id(round_id, round_seed);
+        let id = match &self.namespace {
+            Some(namespace) => format!("{}/{}", namespace, id),
+            None => id,
+        };
 
         debug!("upload global model: {}", id);
         let output = self
@@ -1,12 +1,22 @@
 use serde::{Deserialize, Serialize};
 use sodiumoxide::{self, crypto::box_};
+use thiserror::Error;
 
 #[cfg(feature = "secure")]
 use crate::mask::MaskConfigPair;
 #[cfg(not(feature = "secure"))]
 use crate::model::ModelConfig;
 
-use crate::{crypto::ByteObject, CoordinatorPublicKey};
+use crate::{
+    crypto::ByteObject,
+    message::Codec,
+    model::{DataType, ModelSchema, PrivacyRequirement},
+    CoordinatorPublicKey,
+};
+
+/// The version of the protocol implemented by this crate. It covers the layout of the messages
+/// and of the data served by the coordinator, and is bumped on every incompatible change.
+pub const PROTOCOL_VERSION: u8 = 2;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct RoundParameters {
@@ -20,10 +30,23 @@ pub struct RoundParameters {
     #[cfg(feature = "secure")]
     /// The masking configuration
     pub mask_config: MaskConfigPair,
+    /// The named tensors the models consist of, if they are structured. Every update must carry
+    /// the same schema.
+    pub model_schema: Option<ModelSchema>,
     /// Sets the amount of participants in each iteration.
     pub per_round_participants: u32,
     /// Defines the number of global epochs.
     pub training_rounds: u32,
+    /// The codecs the coordinator accepts for compressed messages.
+    pub codecs: Vec<Codec>,
+    /// The version of the latest global model, i.e. the round it was aggregated in, or `0` if
+    /// there is no global model yet. Delta updates are computed against this version.
+    pub model_version: u32,
+    /// The minimum local differential privacy the updates must be privatized with, if any.
+    pub privacy: Option<PrivacyRequirement>,
+    /// The highest chunk ID the coordinator accepts, i.e. a message is split in at most
+    /// `max_chunk_id + 1` parts.
+    pub max_chunk_id: u16,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -51,3 +74,129 @@ impl ByteObject for RoundSeed {
         self.0.as_ref()
     }
 }
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+/// How the local models are protected before they are sent to the coordinator.
+pub enum MaskingMode {
+    /// The models are sent as they are, i.e. without the `secure` feature.
+    Plain,
+    /// The models are masked, i.e. with the `secure` feature.
+    Secure,
+}
+
+impl MaskingMode {
+    /// Returns the masking mode this crate was built with.
+    pub const fn current() -> Self {
+        if cfg!(feature = "secure") {
+            MaskingMode::Secure
+        } else {
+            MaskingMode::Plain
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+/// The capabilities of a coordinator.
+///
+/// Unlike the [`RoundParameters`], their layout does not depend on the enabled features, so that a
+/// participant can always decode them and tell whether it is compatible with the coordinator.
+pub struct Capabilities {
+    /// The [`PROTOCOL_VERSION`] of the coordinator.
+    pub protocol_version: u8,
+    /// The masking mode of the coordinator.
+    pub masking: MaskingMode,
+    /// The codecs the coordinator accepts for compressed messages.
+    pub codecs: Vec<Codec>,
+    /// The data types of the models the coordinator accepts.
+    pub data_types: Vec<DataType>,
+    /// The highest chunk ID the coordinator accepts for the parts of a message.
+    pub max_chunk_id: u16,
+}
+
+impl Capabilities {
+    /// Returns the capabilities of a coordinator running with the given round parameters.
+    pub fn new(round_params: &RoundParameters) -> Self {
+        #[cfg(not(feature = "secure"))]
+        let data_type = round_params.model_config.data_type;
+        #[cfg(feature = "secure")]
+        let data_type = round_params.mask_config.vect.data_type;
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            masking: MaskingMode::current(),
+            codecs: round_params.codecs.clone(),
+            data_types: vec![data_type],
+            max_chunk_id: round_params.max_chunk_id,
+        }
+    }
+
+    /// Checks whether this crate can take part in the protocol with a coordinator that has these
+    /// capabilities.
+    ///
+    /// # Errors
+    /// Fails if the coordinator implements another protocol version or uses another masking mode.
+    pub fn check(&self) -> Result<(), IncompatibleCapabilities> {
+        if self.protocol_version != PROTOCOL_VERSION {
+            return Err(IncompatibleCapabilities::ProtocolVersion(
+                self.protocol_version,
+            ));
+        }
+        if self.masking != MaskingMode::current() {
+            return Err(IncompatibleCapabilities::Masking(self.masking));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+/// An error that signals that a coordinator and a participant cannot work together.
+pub enum IncompatibleCapabilities {
+    #[error(
+        "the coordinator implements protocol version {0}, but this participant implements version {}",
+        PROTOCOL_VERSION
+    )]
+    ProtocolVersion(u8),
+    #[error(
+        "the coordinator uses the {0:?} masking mode, but this participant uses the {:?} masking mode",
+        MaskingMode::current()
+    )]
+    Masking(MaskingMode),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_capabilities() {
+        let capabilities = Capabilities {
+            protocol_version: PROTOCOL_VERSION,
+            masking: MaskingMode::current(),
+            codecs: Vec::new(),
+            data_types: vec![DataType::F64],
+            max_chunk_id: u16::MAX,
+        };
+        assert_eq!(capabilities.check(), Ok(()));
+
+        let other_version = Capabilities {
+            protocol_version: PROTOCOL_VERSION + 1,
+            ..capabilities.clone()
+        };
+        assert_eq!(
+            other_version.check(),
+            Err(IncompatibleCapabilities::ProtocolVersion(PROTOCOL_VERSION + 1))
+        );
+
+        let other_masking = match MaskingMode::current() {
+            MaskingMode::Plain => MaskingMode::Secure,
+            MaskingMode::Secure => MaskingMode::Plain,
+        };
+        let other_masking = Capabilities {
+            masking: other_masking,
+            ..capabilities
+        };
+        assert_eq!(
+            other_masking.check(),
+            Err(IncompatibleCapabilities::Masking(other_masking.masking))
+        );
+    }
+}
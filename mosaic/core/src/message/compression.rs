@@ -0,0 +1,374 @@
+//! Message compression.
+//!
+//! A message payload can be compressed with one of the [`Codec`]s. A compressed message has the
+//! [`Flags::COMPRESSED`] flag set and the codec stored in the `codec` header field.
+//!
+//! For a single message, the signature is computed over the _uncompressed_ message, which must
+//! therefore be restored with [`decompress_message()`] before its signature can be checked. For a
+//! multipart message, the payload is compressed before it is split in chunks: each chunk carries
+//! the flag and the codec, and the reassembled payload is decompressed with
+//! [`Codec::decompress()`]. A large payload can also be compressed and decompressed as a stream,
+//! with a [`Compressor`] and with [`Codec::decompressor()`], so that it is never held in memory as
+//! a whole.
+use std::{
+    convert::TryFrom,
+    fmt,
+    io::{self, Read, Write},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::message::{DecodeError, Flags, MessageBuffer, MESSAGE_HEADER_LENGTH};
+
+#[derive(Copy, Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// A compression codec for message payloads.
+pub enum Codec {
+    /// The [Zstandard](https://facebook.github.io/zstd/) codec
+    Zstd,
+    /// The [LZ4](https://lz4.github.io/lz4/) frame codec
+    Lz4,
+}
+
+impl Codec {
+    /// All the codecs supported by this implementation.
+    pub const ALL: [Codec; 2] = [Codec::Zstd, Codec::Lz4];
+
+    /// Compresses `data`.
+    pub fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            // Writing to a `Vec` cannot fail
+            Codec::Zstd => zstd::stream::encode_all(data, 0).unwrap(),
+            Codec::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    /// Decompresses `data`.
+    ///
+    /// # Errors
+    /// Fails if `data` is not valid for this codec, or if the decompressed data would be larger
+    /// than `max_length` bytes. The decompression stops as soon as the limit is exceeded, so
+    /// highly compressed data cannot exhaust the memory.
+    pub fn decompress(self, data: &[u8], max_length: usize) -> Result<Vec<u8>, DecodeError> {
+        let mut decompressed = Vec::new();
+        self.decompressor(data)?
+            .take((max_length as u64).saturating_add(1))
+            .read_to_end(&mut decompressed)
+            .with_context(|| format!("invalid {:?} data", self))?;
+        check_length(decompressed.len(), max_length)?;
+        Ok(decompressed)
+    }
+
+    /// Creates a streaming compressor, which writes the compressed data to `writer`.
+    pub fn compressor<W: Write>(self, writer: W) -> Compressor<W> {
+        Compressor(match self {
+            // UNWRAP_SAFE: creating an encoder only fails for an invalid compression level
+            Codec::Zstd => Encoder::Zstd(Box::new(
+                zstd::stream::write::Encoder::new(writer, 0).unwrap(),
+            )),
+            Codec::Lz4 => Encoder::Lz4(Box::new(lz4_flex::frame::FrameEncoder::new(writer))),
+        })
+    }
+
+    /// Creates a streaming decompressor, which reads the compressed data from `reader`.
+    ///
+    /// # Errors
+    /// Fails if the decompressor cannot be created. Invalid data only fails when it is read.
+    pub fn decompressor<'a, R: Read + 'a>(
+        self,
+        reader: R,
+    ) -> Result<Box<dyn Read + 'a>, DecodeError> {
+        Ok(match self {
+            Codec::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+            Codec::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+        })
+    }
+
+    /// Gets the length of the data read from `reader` once it is decompressed, without holding
+    /// the decompressed data in memory.
+    ///
+    /// # Errors
+    /// Fails like [`Codec::decompress()`].
+    pub fn decompressed_length<R: Read>(
+        self,
+        reader: R,
+        max_length: usize,
+    ) -> Result<usize, DecodeError> {
+        let length = io::copy(
+            &mut self
+                .decompressor(reader)?
+                .take((max_length as u64).saturating_add(1)),
+            &mut io::sink(),
+        )
+        .with_context(|| format!("invalid {:?} data", self))?;
+        // the length is at most `max_length + 1`, which fits if it exceeds `max_length` only
+        let length = usize::try_from(length).unwrap_or(usize::MAX);
+        check_length(length, max_length)?;
+        Ok(length)
+    }
+}
+
+/// Checks that decompressed data doesn't exceed the maximum length.
+fn check_length(length: usize, max_length: usize) -> Result<(), DecodeError> {
+    if length > max_length {
+        return Err(anyhow!(
+            "decompressed data exceeds the maximum length of {} bytes",
+            max_length
+        ));
+    }
+    Ok(())
+}
+
+/// A streaming compressor, which writes the data compressed with a [`Codec`] to `W`.
+///
+/// The compressed data written so far is available with [`Compressor::get_mut()`], and it is only
+/// complete once the compressor is finished with [`Compressor::finish()`]. Compressing the same
+/// data with the same writes always yields the same compressed data.
+pub struct Compressor<W: Write>(Encoder<W>);
+
+enum Encoder<W: Write> {
+    Zstd(Box<zstd::stream::write::Encoder<'static, W>>),
+    Lz4(Box<lz4_flex::frame::FrameEncoder<W>>),
+}
+
+impl<W: Write> Compressor<W> {
+    /// Gets the writer the compressed data is written to.
+    pub fn get_mut(&mut self) -> &mut W {
+        match &mut self.0 {
+            Encoder::Zstd(encoder) => encoder.get_mut(),
+            Encoder::Lz4(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// Writes the remaining compressed data and returns the writer.
+    ///
+    /// # Errors
+    /// Fails if the remaining data cannot be written.
+    pub fn finish(self) -> io::Result<W> {
+        match self.0 {
+            Encoder::Zstd(encoder) => encoder.finish(),
+            Encoder::Lz4(encoder) => (*encoder)
+                .finish()
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err)),
+        }
+    }
+}
+
+impl<W: Write> Write for Compressor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Encoder::Zstd(encoder) => encoder.write(buf),
+            Encoder::Lz4(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Encoder::Zstd(encoder) => encoder.flush(),
+            Encoder::Lz4(encoder) => encoder.flush(),
+        }
+    }
+}
+
+impl<W: Write> fmt::Debug for Compressor<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codec = match self.0 {
+            Encoder::Zstd(_) => Codec::Zstd,
+            Encoder::Lz4(_) => Codec::Lz4,
+        };
+        f.debug_tuple("Compressor").field(&codec).finish()
+    }
+}
+
+impl TryFrom<u8> for Codec {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            1 => Codec::Zstd,
+            2 => Codec::Lz4,
+            _ => return Err(anyhow!("invalid codec {}", value)),
+        })
+    }
+}
+
+impl From<Codec> for u8 {
+    fn from(codec: Codec) -> Self {
+        match codec {
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+}
+
+/// Compresses the payload of a serialized single message.
+///
+/// The header is kept as is, except for the flags, the codec and the length fields. In
+/// particular the signature of the uncompressed message is preserved.
+///
+/// # Errors
+/// Fails if `message` is not a valid message buffer, or if it is already compressed or a
+/// multipart message.
+pub fn compress_message(message: &[u8], codec: Codec) -> Result<Vec<u8>, DecodeError> {
+    let reader = MessageBuffer::new(message)?;
+    if reader
+        .flags()
+        .intersects(Flags::COMPRESSED | Flags::MULTIPART)
+    {
+        return Err(anyhow!(
+            "only uncompressed single messages can be compressed"
+        ));
+    }
+    let payload = codec.compress(&reader.payload()[..payload_length(&reader)]);
+    Ok(replace_payload(
+        &reader,
+        &payload,
+        reader.flags() | Flags::COMPRESSED,
+        Some(codec),
+    ))
+}
+
+/// Restores the uncompressed message from a serialized compressed single message.
+///
+/// Messages that are not compressed are returned unchanged.
+///
+/// # Errors
+/// Fails if `message` is not a valid message buffer, if the payload cannot be decompressed with
+/// the codec of the message, or if the uncompressed message would be larger than `max_length`
+/// bytes.
+pub fn decompress_message(message: &[u8], max_length: usize) -> Result<Vec<u8>, DecodeError> {
+    let reader = MessageBuffer::new(message)?;
+    let flags = reader.flags();
+    if !flags.contains(Flags::COMPRESSED) {
+        return Ok(message.to_vec());
+    }
+    if flags.contains(Flags::MULTIPART) {
+        return Err(anyhow!(
+            "multipart messages are decompressed after they are reassembled"
+        ));
+    }
+    let codec = Codec::try_from(reader.codec())?;
+    let payload = codec.decompress(
+        &reader.payload()[..payload_length(&reader)],
+        max_length.saturating_sub(MESSAGE_HEADER_LENGTH),
+    )?;
+    Ok(replace_payload(
+        &reader,
+        &payload,
+        flags - Flags::COMPRESSED,
+        None,
+    ))
+}
+
+/// Returns the length of the payload according to the length field of a checked buffer.
+fn payload_length(reader: &MessageBuffer<&[u8]>) -> usize {
+    reader.length() as usize - MESSAGE_HEADER_LENGTH
+}
+
+/// Copies the header of a checked buffer and appends the given payload to it.
+fn replace_payload(
+    reader: &MessageBuffer<&[u8]>,
+    payload: &[u8],
+    flags: Flags,
+    codec: Option<Codec>,
+) -> Vec<u8> {
+    let mut bytes = vec![0; MESSAGE_HEADER_LENGTH + payload.len()];
+    bytes[..MESSAGE_HEADER_LENGTH].copy_from_slice(&reader.inner()[..MESSAGE_HEADER_LENGTH]);
+    let mut writer = MessageBuffer::new_unchecked(&mut bytes[..]);
+    writer.set_flags(flags);
+    writer.set_codec(codec.map(Into::into).unwrap_or(0));
+    writer.set_length(writer.inner().len() as u32);
+    writer.payload_mut().copy_from_slice(payload);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{ByteObject, EncryptKeyPair, PublicEncryptKey, Signature, SigningKeyPair},
+        message::{Message, Sum},
+    };
+
+    fn signed_sum_message() -> Vec<u8> {
+        let keys = SigningKeyPair::generate();
+        let sum = Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        };
+        let message = Message::new_sum(keys.public, EncryptKeyPair::generate().public, sum);
+        let mut bytes = vec![0; message.buffer_length()];
+        message.to_bytes(&mut bytes, &keys.secret);
+        bytes
+    }
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let data = vec![0x2a; 10_000];
+        for codec in Codec::ALL {
+            let compressed = codec.compress(&data);
+            assert!(compressed.len() < data.len());
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+            assert!(codec.decompress(&compressed, data.len() - 1).is_err());
+            assert!(codec.decompress(&data, data.len()).is_err());
+        }
+        for value in 0..=u8::MAX {
+            if let Ok(codec) = Codec::try_from(value) {
+                assert_eq!(u8::from(codec), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_roundtrip() {
+        let data = (0..10_000_u32)
+            .flat_map(u32::to_le_bytes)
+            .collect::<Vec<_>>();
+        for codec in Codec::ALL {
+            let mut compressor = codec.compressor(Vec::new());
+            for block in data.chunks(1_000) {
+                compressor.write_all(block).unwrap();
+            }
+            let compressed = compressor.finish().unwrap();
+            assert_eq!(codec.decompress(&compressed, data.len()).unwrap(), data);
+            assert_eq!(
+                codec
+                    .decompressed_length(&compressed[..], data.len())
+                    .unwrap(),
+                data.len()
+            );
+            assert!(codec
+                .decompressed_length(&compressed[..], data.len() - 1)
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_message_roundtrip() {
+        let bytes = signed_sum_message();
+        for codec in Codec::ALL {
+            let compressed = compress_message(&bytes, codec).unwrap();
+            let reader = MessageBuffer::new(&compressed[..]).unwrap();
+            assert!(reader.flags().contains(Flags::COMPRESSED));
+            assert_eq!(reader.codec(), u8::from(codec));
+            assert_eq!(reader.length() as usize, compressed.len());
+            // the signature covers the uncompressed message
+            assert!(reader.check_signature().is_err());
+
+            let decompressed = decompress_message(&compressed, bytes.len()).unwrap();
+            assert_eq!(decompressed, bytes);
+            MessageBuffer::new(&decompressed[..])
+                .unwrap()
+                .check_signature()
+                .unwrap();
+            assert!(decompress_message(&compressed, bytes.len() - 1).is_err());
+            assert!(compress_message(&compressed, codec).is_err());
+        }
+        assert_eq!(decompress_message(&bytes, bytes.len()).unwrap(), bytes);
+    }
+}
@@ -7,8 +7,9 @@ use anyhow::{anyhow, Context};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    common::PROTOCOL_VERSION,
     crypto::{ByteObject, PublicEncryptKey, PublicSigningKey, SecretSigningKey, Signature},
-    message::{Chunk, DecodeError, FromBytes, Payload, Sum, Sum2, ToBytes, Update},
+    message::{Chunk, Codec, DecodeError, FromBytes, Payload, Sum, Sum2, ToBytes, Update},
 };
 
 /// The minimum number of accepted `sum`/`sum2` messages for the PET protocol to function correctly.
@@ -38,12 +39,14 @@ pub(crate) mod ranges {
     pub const TAG: usize = LENGTH.end;
     /// Byte range corresponding to the flags in a message header
     pub const FLAGS: usize = TAG + 1;
-    /// Byte range reserved for future use
-    pub const RESERVED: Range<usize> = range(FLAGS + 1, 2);
+    /// Byte range corresponding to the compression codec in a message header
+    pub const CODEC: usize = FLAGS + 1;
+    /// Byte range corresponding to the protocol version in a message header
+    pub const VERSION: usize = CODEC + 1;
 }
 
 /// Length in bytes of a message header
-pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
+pub const HEADER_LENGTH: usize = ranges::VERSION + 1;
 
 /// A wrapper around a buffer that contains a [`Message`].
 ///
@@ -120,7 +123,7 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// |                             length                            |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-/// |      tag      |     flags     |          reserved             |
+/// |      tag      |     flags     |     codec     |    version    |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// |                                                               |
 /// +                    payload (variable length)                  +
@@ -141,15 +144,22 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 ///   messages can be as big as 2^32 = 4,294,967,296 bytes.
 /// - `tag` indicates the type of message (sum, update, sum2 or
 ///   multipart message)
-/// - the `flags` field currently supports a single flag, that
-///   indicates whether this is a multipart message
+/// - the `flags` field indicates whether this is a multipart message
+///   and whether the payload is compressed
+/// - `codec` indicates the [`Codec`] the payload is compressed with,
+///   or is `0` if the payload is not compressed
+/// - `version` is the [`PROTOCOL_VERSION`] the message was built
+///   for. Messages of other versions are rejected when parsed.
 ///
 /// # Examples
 /// ## Reading a sum message
 ///
 /// ```rust
 /// use std::convert::TryFrom;
-/// use mosaic_core::message::{Flags, MessageBuffer, Tag};
+/// use mosaic_core::{
+///     common::PROTOCOL_VERSION,
+///     message::{Flags, MessageBuffer, Tag},
+/// };
 ///
 /// let mut bytes = vec![0x11; 64]; // message signature
 /// bytes.extend(vec![0x22; 32]); // participant public signing key
@@ -157,7 +167,8 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 /// bytes.extend(&200_u32.to_be_bytes()); // Length field
 /// bytes.push(0x01); // tag (sum message)
 /// bytes.push(0x00); // flags (not a multipart message)
-/// bytes.extend(vec![0x00, 0x00]); // reserved
+/// bytes.push(0x00); // codec (not compressed)
+/// bytes.push(PROTOCOL_VERSION);
 ///
 /// // Payload: a sum message contains a signature and an ephemeral public key
 /// bytes.extend(vec![0xaa; 32]); // signature
@@ -169,6 +180,7 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 /// assert_eq!(buffer.coordinator_pk(), vec![0x33; 32].as_slice());
 /// assert_eq!(Tag::try_from(buffer.tag()).unwrap(), Tag::Sum);
 /// assert_eq!(Flags::try_from(buffer.flags()).unwrap(), Flags::empty());
+/// assert_eq!(buffer.version(), PROTOCOL_VERSION);
 /// assert_eq!(
 ///     buffer.payload(),
 ///     [vec![0xaa; 32], vec![0xbb; 32]].concat().as_slice()
@@ -179,7 +191,10 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 ///
 /// ```rust
 /// use std::convert::TryFrom;
-/// use mosaic_core::message::{Flags, MessageBuffer, Tag};
+/// use mosaic_core::{
+///     common::PROTOCOL_VERSION,
+///     message::{Flags, MessageBuffer, Tag},
+/// };
 ///
 /// let mut expected = vec![0x11; 64]; // message signature
 /// expected.extend(vec![0x22; 32]); // participant public signing key
@@ -187,7 +202,8 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 /// expected.extend(&200_u32.to_be_bytes()); // length field
 /// expected.push(0x01); // tag (sum message)
 /// expected.push(0x00); // flags (not a multipart message)
-/// expected.extend(vec![0x00, 0x00]); // reserved
+/// expected.push(0x00); // codec (not compressed)
+/// expected.push(PROTOCOL_VERSION);
 ///
 /// // Payload: a sum message contains a signature and an ephemeral public key
 /// expected.extend(vec![0xaa; 32]); // signature
@@ -207,6 +223,7 @@ pub const HEADER_LENGTH: usize = ranges::RESERVED.end;
 /// buffer.set_length(200 as u32);
 /// buffer.set_tag(Tag::Sum.into());
 /// buffer.set_flags(Flags::empty());
+/// buffer.set_version(PROTOCOL_VERSION);
 /// buffer
 ///     .payload_mut()
 ///     .copy_from_slice([vec![0xaa; 32], vec![0xbb; 32]].concat().as_slice());
@@ -257,6 +274,13 @@ impl<T: AsRef<[u8]>> MessageBuffer<T> {
             ));
         }
         let expected_len = self.length() as usize;
+        if expected_len < HEADER_LENGTH {
+            return Err(anyhow!(
+                "invalid message length: length field says {}, but the header is {} bytes long",
+                expected_len,
+                HEADER_LENGTH
+            ));
+        }
         let actual_len = self.inner.as_ref().len();
         if actual_len < expected_len {
             return Err(anyhow!(
@@ -284,6 +308,22 @@ impl<T: AsRef<[u8]>> MessageBuffer<T> {
         Flags::from_bits_truncate(self.inner.as_ref()[ranges::FLAGS])
     }
 
+    /// Gets the codec field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn codec(&self) -> u8 {
+        self.inner.as_ref()[ranges::CODEC]
+    }
+
+    /// Gets the protocol version field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn version(&self) -> u8 {
+        self.inner.as_ref()[ranges::VERSION]
+    }
+
     /// Gets the length field
     ///
     /// # Panics
@@ -372,6 +412,22 @@ impl<T: AsMut<[u8]> + AsRef<[u8]>> MessageBuffer<T> {
         self.inner.as_mut()[ranges::FLAGS] = value.bits();
     }
 
+    /// Sets the codec field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn set_codec(&mut self, value: u8) {
+        self.inner.as_mut()[ranges::CODEC] = value;
+    }
+
+    /// Sets the protocol version field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn set_version(&mut self, value: u8) {
+        self.inner.as_mut()[ranges::VERSION] = value;
+    }
+
     /// Sets the length field.
     ///
     /// # Panics
@@ -430,6 +486,8 @@ bitflags::bitflags! {
     pub struct Flags: u8 {
         /// Indicates whether this message is a multipart message
         const MULTIPART = 1 << 0;
+        /// Indicates whether the payload of this message is compressed
+        const COMPRESSED = 1 << 1;
     }
 }
 
@@ -467,7 +525,7 @@ impl From<Tag> for u8 {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 /// A header common to all messages.
 pub struct Message {
     /// Message signature. This can be `None` if it hasn't been
@@ -480,6 +538,12 @@ pub struct Message {
     pub coordinator_pk: PublicEncryptKey,
     /// Wether this is a multipart message
     pub is_multipart: bool,
+    /// The codec the payload of a multipart message is compressed
+    /// with, once reassembled. Single messages are compressed as a
+    /// whole, see [`compress_message()`].
+    ///
+    /// [`compress_message()`]: crate::message::compress_message
+    pub compression: Option<Codec>,
     /// The type of message. This information is partially redundant
     /// with the `payload` field.
     pub tag: Tag,
@@ -500,6 +564,7 @@ impl Message {
             participant_pk,
             coordinator_pk,
             is_multipart: false,
+            compression: None,
             tag: Tag::Sum,
             payload: message.into(),
         }
@@ -517,6 +582,7 @@ impl Message {
             participant_pk,
             coordinator_pk,
             is_multipart: false,
+            compression: None,
             tag: Tag::Sum2,
             payload: message.into(),
         }
@@ -534,6 +600,7 @@ impl Message {
             participant_pk,
             coordinator_pk,
             is_multipart: false,
+            compression: None,
             tag: Tag::Update,
             payload: message.into(),
         }
@@ -552,6 +619,7 @@ impl Message {
             participant_pk,
             coordinator_pk,
             is_multipart: true,
+            compression: None,
             tag,
             payload: message.into(),
         }
@@ -562,6 +630,13 @@ impl Message {
     /// [`MessageBuffer.verify_signature`] before parsing the message.
     pub fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
         let reader = MessageBuffer::new(buffer.as_ref())?;
+        if reader.version() != PROTOCOL_VERSION {
+            return Err(anyhow!(
+                "unsupported protocol version {}, expected version {}",
+                reader.version(),
+                PROTOCOL_VERSION
+            ));
+        }
         let signature =
             Signature::from_byte_slice(&reader.signature()).context("failed to parse signature")?;
         let participant_pk = PublicSigningKey::from_byte_slice(&reader.participant_pk())
@@ -571,6 +646,16 @@ impl Message {
 
         let tag = reader.tag().try_into()?;
         let is_multipart = reader.flags().contains(Flags::MULTIPART);
+        let compression = if reader.flags().contains(Flags::COMPRESSED) {
+            if !is_multipart {
+                return Err(anyhow!(
+                    "compressed messages must be decompressed before being parsed"
+                ));
+            }
+            Some(Codec::try_from(reader.codec())?)
+        } else {
+            None
+        };
 
         let payload = if is_multipart {
             Chunk::from_byte_slice(&reader.payload()).map(Into::into)
@@ -589,6 +674,7 @@ impl Message {
             signature: Some(signature),
             payload,
             is_multipart,
+            compression,
             tag,
         })
     }
@@ -606,18 +692,23 @@ impl Message {
         buffer: &mut T,
         sk: &SecretSigningKey,
     ) {
-        let mut writer = MessageBuffer::new(buffer.as_mut()).unwrap();
+        let mut writer = MessageBuffer::new_unchecked(buffer.as_mut());
 
         self.participant_pk
             .to_bytes(&mut writer.participant_pk_mut());
         self.coordinator_pk
             .to_bytes(&mut writer.coordinator_pk_mut());
-        let flags = if self.is_multipart {
+        let mut flags = if self.is_multipart {
             Flags::MULTIPART
         } else {
             Flags::empty()
         };
+        if self.compression.is_some() {
+            flags |= Flags::COMPRESSED;
+        }
         writer.set_flags(flags);
+        writer.set_codec(self.compression.map(Into::into).unwrap_or(0));
+        writer.set_version(PROTOCOL_VERSION);
         self.payload.to_bytes(&mut writer.payload_mut());
         // Determine the tag from the payload type if
         // possible. Otherwise, use the self.tag field.
@@ -10,6 +10,7 @@
 //! - The local seed dictionary stores the encrypted mask seed, which generates the local mask for
 //!   the local model, which is encrypted by the ephemeral public keys of the sum participants.
 //!
+pub(crate) mod compression;
 #[allow(clippy::module_inception)]
 pub(crate) mod message;
 pub(crate) mod payload;
@@ -17,15 +18,17 @@ pub(crate) mod traits;
 pub(crate) mod utils;
 
 pub use self::{
+    compression::{compress_message, decompress_message, Codec, Compressor},
     message::{
         Flags, Message, MessageBuffer, Tag, HEADER_LENGTH as MESSAGE_HEADER_LENGTH, SUM_COUNT_MIN,
         UPDATE_COUNT_MIN,
     },
     payload::{
         chunk::{Chunk, ChunkBuffer},
+        stream::PayloadStream,
         sum::{Sum, SumBuffer},
         sum2::{Sum2, Sum2Buffer},
-        update::{Update, UpdateBuffer},
+        update::{Update, UpdateBuffer, UpdateKind},
         Payload,
     },
     traits::{FromBytes, LengthValueBuffer, ToBytes},
@@ -1,5 +1,6 @@
 //! Message payloads.
 pub(crate) mod chunk;
+pub(crate) mod stream;
 pub(crate) mod sum;
 pub(crate) mod sum2;
 pub(crate) mod update;
@@ -14,7 +15,7 @@ use crate::message::{
 /// The payload of a [`Message`].
 ///
 /// [`Message`]: crate::message::Message
-#[derive(From, Eq, PartialEq, Debug, Clone)]
+#[derive(From, PartialEq, Debug, Clone)]
 pub enum Payload {
     /// The payload of a [`Sum`] message.
     Sum(Sum),
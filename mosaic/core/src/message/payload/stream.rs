@@ -0,0 +1,263 @@
+//! Streamed serialization of message payloads.
+//!
+//! See the [message module] documentation since this is a private module anyways.
+//!
+//! [message module]: crate::message
+use std::ops::Range;
+
+use num::{bigint::BigInt, rational::Ratio, ToPrimitive};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "secure"))]
+use crate::{
+    message::payload::update::MODEL_OBJECT_OFFSET,
+    model::{
+        serialize::{ModelObjectBuffer, DENSE_VALUES_OFFSET},
+        EncodedModelObject,
+    },
+};
+use crate::{
+    message::{traits::ToBytes, Payload},
+    model::{ratio_to_float, DataType},
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A serialized [`Payload`], whose bytes are serialized on demand.
+///
+/// The weights of the dense model of an update make up almost all of its payload. They are kept
+/// as primitive values of their data type and only serialized when the bytes they belong to are
+/// read, so that a large payload can be split in chunks without ever being serialized as a
+/// whole. The other fields are serialized upfront.
+pub struct PayloadStream {
+    /// The serialized fields before the weights.
+    head: Vec<u8>,
+    /// The weights of a dense model, if any.
+    weights: Option<Weights>,
+    /// The serialized fields after the weights.
+    tail: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The weights of a dense model as primitive values of their data type.
+enum Weights {
+    F32(Vec<f32>),
+    F64(Vec<f64>),
+    I32(Vec<i32>),
+    I64(Vec<i64>),
+}
+
+impl Weights {
+    /// Converts the weights into primitive values like a dense model object is serialized, i.e.
+    /// weights which are not representable in the data type become zero.
+    fn new(weights: &[Ratio<BigInt>], data_type: DataType) -> Self {
+        let weights = weights.iter();
+        match data_type {
+            DataType::F32 => Self::F32(weights.map(|w| ratio_to_float(w).unwrap_or(0.0)).collect()),
+            DataType::F64 => Self::F64(weights.map(|w| ratio_to_float(w).unwrap_or(0.0)).collect()),
+            DataType::I32 => Self::I32(
+                weights
+                    .map(|w| w.to_integer().to_i32().unwrap_or(0))
+                    .collect(),
+            ),
+            DataType::I64 => Self::I64(
+                weights
+                    .map(|w| w.to_integer().to_i64().unwrap_or(0))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Gets the number of weights.
+    fn len(&self) -> usize {
+        match self {
+            Self::F32(weights) => weights.len(),
+            Self::F64(weights) => weights.len(),
+            Self::I32(weights) => weights.len(),
+            Self::I64(weights) => weights.len(),
+        }
+    }
+
+    /// Gets the length of a serialized weight.
+    fn bytes_per_number(&self) -> usize {
+        match self {
+            Self::F32(_) | Self::I32(_) => 4,
+            Self::F64(_) | Self::I64(_) => 8,
+        }
+    }
+
+    /// Serializes the weight at the given index into a buffer of its length.
+    fn write(&self, index: usize, buffer: &mut [u8]) {
+        match self {
+            Self::F32(weights) => buffer.copy_from_slice(&weights[index].to_le_bytes()),
+            Self::F64(weights) => buffer.copy_from_slice(&weights[index].to_le_bytes()),
+            Self::I32(weights) => buffer.copy_from_slice(&weights[index].to_le_bytes()),
+            Self::I64(weights) => buffer.copy_from_slice(&weights[index].to_le_bytes()),
+        }
+    }
+}
+
+impl PayloadStream {
+    /// Creates a stream of the serialized payload.
+    pub fn new(payload: Payload) -> Self {
+        match payload {
+            #[cfg(not(feature = "secure"))]
+            Payload::Update(mut update) => {
+                let dense = match &mut update.model_object {
+                    EncodedModelObject::Dense(object) => {
+                        let weights = std::mem::take(&mut object.data);
+                        Some(Weights::new(&weights, object.config.data_type))
+                    }
+                    EncodedModelObject::Lossy(_) => None,
+                };
+                let mut head = serialize(&update);
+                match dense {
+                    Some(weights) => {
+                        // the update is serialized without the weights, which are inserted
+                        // between the fields before and after them
+                        let tail = head.split_off(MODEL_OBJECT_OFFSET + DENSE_VALUES_OFFSET);
+                        ModelObjectBuffer::new_unchecked(&mut head[MODEL_OBJECT_OFFSET..])
+                            .set_numbers(weights.len() as u32);
+                        Self {
+                            head,
+                            weights: Some(weights),
+                            tail,
+                        }
+                    }
+                    None => Self::serialized(head),
+                }
+            }
+            payload => Self::serialized(serialize(&payload)),
+        }
+    }
+
+    /// Creates a stream of an already serialized payload.
+    fn serialized(bytes: Vec<u8>) -> Self {
+        Self {
+            head: bytes,
+            weights: None,
+            tail: Vec::new(),
+        }
+    }
+
+    /// Gets the length of the serialized payload.
+    pub fn len(&self) -> usize {
+        self.head.len() + self.weights_length() + self.tail.len()
+    }
+
+    /// Checks whether the serialized payload is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Serializes the bytes of the payload in the given range.
+    ///
+    /// # Panics
+    /// Panics if the range is out of bounds.
+    pub fn read(&self, range: Range<usize>) -> Vec<u8> {
+        assert!(
+            range.start <= range.end && range.end <= self.len(),
+            "range {:?} is out of bounds of the payload of {} bytes",
+            range,
+            self.len()
+        );
+        let mut bytes = Vec::with_capacity(range.len());
+        let weights_start = self.head.len();
+        let weights_end = weights_start + self.weights_length();
+
+        if range.start < weights_start {
+            bytes.extend_from_slice(&self.head[range.start..range.end.min(weights_start)]);
+        }
+        if let Some(weights) = self
+            .weights
+            .as_ref()
+            .filter(|_| range.start < weights_end && range.end > weights_start)
+        {
+            let bytes_per_number = weights.bytes_per_number();
+            let start = range.start.max(weights_start) - weights_start;
+            let end = range.end.min(weights_end) - weights_start;
+            let mut buffer = vec![0; bytes_per_number];
+            for index in start / bytes_per_number..(end + bytes_per_number - 1) / bytes_per_number {
+                weights.write(index, &mut buffer);
+                let offset = index * bytes_per_number;
+                let from = start.max(offset) - offset;
+                let to = end.min(offset + bytes_per_number) - offset;
+                bytes.extend_from_slice(&buffer[from..to]);
+            }
+        }
+        if range.end > weights_end {
+            bytes.extend_from_slice(
+                &self.tail[range.start.max(weights_end) - weights_end..range.end - weights_end],
+            );
+        }
+        bytes
+    }
+
+    /// Gets the length of the serialized weights.
+    fn weights_length(&self) -> usize {
+        self.weights
+            .as_ref()
+            .map_or(0, |weights| weights.bytes_per_number() * weights.len())
+    }
+}
+
+/// Serializes a payload or a part of it.
+fn serialize<T: ToBytes>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0; value.buffer_length()];
+    value.to_bytes(&mut bytes);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        crypto::{ByteObject, PublicEncryptKey, Signature},
+        message::{Sum, Update, UpdateKind},
+        model::{ModelConfig, ModelObject, ModelSchema, TensorSpec},
+    };
+
+    #[test]
+    fn test_update_stream() {
+        let config = ModelConfig {
+            data_type: DataType::F32,
+        };
+        let weights = (0..10).map(|i| Ratio::new(BigInt::from(i), BigInt::from(4)));
+        let schema = ModelSchema::new(vec![TensorSpec {
+            name: "kernel".to_string(),
+            shape: vec![2, 5],
+            data_type: DataType::F32,
+        }])
+        .unwrap();
+        let update = Payload::Update(Update {
+            update_signature: Signature::zeroed(),
+            kind: UpdateKind::Full,
+            base_model_version: 3,
+            privacy: None,
+            model_object: ModelObject::new(weights.collect(), config)
+                .with_schema(Some(&schema))
+                .into(),
+        });
+        let bytes = serialize(&update);
+
+        let stream = PayloadStream::new(update);
+        assert_eq!(stream.len(), bytes.len());
+        assert_eq!(stream.read(0..bytes.len()), bytes);
+        for size in [1, 3, 7, 64] {
+            let chunks = (0..bytes.len())
+                .step_by(size)
+                .map(|start| stream.read(start..(start + size).min(bytes.len())));
+            assert_eq!(chunks.flatten().collect::<Vec<_>>(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_serialized_stream() {
+        let sum = Payload::Sum(Sum {
+            sum_signature: Signature::zeroed(),
+            ephm_pk: PublicEncryptKey::zeroed(),
+        });
+        let stream = PayloadStream::new(sum.clone());
+        assert_eq!(stream.read(0..stream.len()), serialize(&sum));
+        assert!(stream.read(3..3).is_empty());
+    }
+}
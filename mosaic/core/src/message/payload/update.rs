@@ -1,7 +1,11 @@
 //! Update message payloads.
-use std::ops::Range;
+use std::{
+    convert::{TryFrom, TryInto},
+    ops::Range,
+};
 
 use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     crypto::ByteObject,
@@ -10,7 +14,7 @@ use crate::{
         utils::range,
         DecodeError,
     },
-    model::{serialize::ModelObjectBuffer, ModelObject},
+    model::{serialize::ModelObjectBuffer, EncodedModelObject, PrivacyParams},
     ParticipantTaskSignature,
 };
 #[cfg(feature = "secure")]
@@ -27,6 +31,90 @@ const SUM_SIGNATURE_RANGE: Range<usize> = range(0, 0);
 const SUM_SIGNATURE_RANGE: Range<usize> = range(0, ParticipantTaskSignature::LENGTH);
 const UPDATE_SIGNATURE_RANGE: Range<usize> =
     range(SUM_SIGNATURE_RANGE.end, ParticipantTaskSignature::LENGTH);
+const KIND_FIELD: usize = UPDATE_SIGNATURE_RANGE.end;
+const BASE_MODEL_VERSION_RANGE: Range<usize> = range(KIND_FIELD + 1, 4);
+// noise mechanism (1 byte, `0` if the model isn't privatized), epsilon, delta and clipping norm
+// (8 bytes each)
+const PRIVACY_RANGE: Range<usize> = range(BASE_MODEL_VERSION_RANGE.end, 25);
+/// The offset of the model object, which is the last field of an update.
+#[cfg(not(feature = "secure"))]
+pub(crate) const MODEL_OBJECT_OFFSET: usize = PRIVACY_RANGE.end;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// The kind of model sent in an [`Update`] message.
+pub enum UpdateKind {
+    /// The weights of the trained model.
+    Full,
+    /// The difference between the trained model and the global model it was trained from.
+    Delta,
+}
+
+impl Default for UpdateKind {
+    fn default() -> Self {
+        UpdateKind::Full
+    }
+}
+
+impl TryFrom<u8> for UpdateKind {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(UpdateKind::Full),
+            1 => Ok(UpdateKind::Delta),
+            _ => Err(anyhow!("invalid update kind {}", value)),
+        }
+    }
+}
+
+impl From<UpdateKind> for u8 {
+    fn from(kind: UpdateKind) -> Self {
+        match kind {
+            UpdateKind::Full => 0,
+            UpdateKind::Delta => 1,
+        }
+    }
+}
+
+/// Writes the privacy parameters field.
+fn privacy_to_bytes(privacy: Option<PrivacyParams>, buffer: &mut [u8]) {
+    match privacy {
+        Some(privacy) => {
+            buffer[0] = privacy.mechanism.into();
+            buffer[1..9].copy_from_slice(&privacy.epsilon.to_be_bytes());
+            buffer[9..17].copy_from_slice(&privacy.delta.to_be_bytes());
+            buffer[17..25].copy_from_slice(&privacy.clip_norm.to_be_bytes());
+        }
+        None => buffer.fill(0),
+    }
+}
+
+/// Reads the privacy parameters field.
+///
+/// # Errors
+/// Fails if the field is too short or if the parameters are invalid.
+fn privacy_from_bytes(buffer: &[u8]) -> Result<Option<PrivacyParams>, DecodeError> {
+    if buffer.len() < PRIVACY_RANGE.len() {
+        return Err(anyhow!(
+            "cannot read privacy parameters: byte stream exhausted"
+        ));
+    }
+    if buffer[0] == 0 {
+        return Ok(None);
+    }
+    // UNWRAP_SAFE: the slices are exactly 8 bytes long
+    let float = |range: Range<usize>| f64::from_be_bytes(buffer[range].try_into().unwrap());
+    let privacy = PrivacyParams {
+        mechanism: buffer[0].try_into()?,
+        epsilon: float(1..9),
+        delta: float(9..17),
+        clip_norm: float(17..25),
+    };
+    if !privacy.is_valid() {
+        return Err(anyhow!("invalid privacy parameters {:?}", privacy));
+    }
+    Ok(Some(privacy))
+}
 
 #[derive(Clone, Debug)]
 /// A wrapper around a buffer that contains an [`Update`] message.
@@ -61,12 +149,12 @@ impl<T: AsRef<[u8]>> UpdateBuffer<T> {
     pub fn check_buffer_length(&self) -> Result<(), DecodeError> {
         let len = self.inner.as_ref().len();
         // First, check the fixed size portion of the
-        // header. UPDATE_SIGNATURE_RANGE is the last field.
-        if len < UPDATE_SIGNATURE_RANGE.end {
+        // header. PRIVACY_RANGE is the last field.
+        if len < PRIVACY_RANGE.end {
             return Err(anyhow!(
                 "invalid buffer length: {} < {}",
                 len,
-                UPDATE_SIGNATURE_RANGE.end
+                PRIVACY_RANGE.end
             ));
         }
         #[cfg(not(feature = "secure"))]
@@ -89,9 +177,30 @@ impl<T: AsRef<[u8]>> UpdateBuffer<T> {
         Ok(())
     }
 
+    /// Gets the update kind field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn kind(&self) -> u8 {
+        self.inner.as_ref()[KIND_FIELD]
+    }
+
+    /// Gets the base model version field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn base_model_version(&self) -> u32 {
+        // UNWRAP SAFE: the slice is exactly 4 bytes long
+        u32::from_be_bytes(
+            self.inner.as_ref()[BASE_MODEL_VERSION_RANGE]
+                .try_into()
+                .unwrap(),
+        )
+    }
+
     /// Gets the offset of the (masked) model field.
     fn model_offset(&self) -> usize {
-        UPDATE_SIGNATURE_RANGE.end
+        PRIVACY_RANGE.end
     }
 
     #[cfg(feature = "secure")]
@@ -123,6 +232,14 @@ impl<'a, T: AsRef<[u8]> + ?Sized> UpdateBuffer<&'a T> {
         &self.inner.as_ref()[UPDATE_SIGNATURE_RANGE]
     }
 
+    /// Gets the privacy parameters field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn privacy(&self) -> &'a [u8] {
+        &self.inner.as_ref()[PRIVACY_RANGE]
+    }
+
     #[cfg(not(feature = "secure"))]
     /// Gets a slice that starts at the beginning of the  model object field.
     ///
@@ -171,6 +288,30 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> UpdateBuffer<T> {
         &mut self.inner.as_mut()[UPDATE_SIGNATURE_RANGE]
     }
 
+    /// Sets the update kind field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn set_kind(&mut self, value: u8) {
+        self.inner.as_mut()[KIND_FIELD] = value;
+    }
+
+    /// Sets the base model version field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn set_base_model_version(&mut self, value: u32) {
+        self.inner.as_mut()[BASE_MODEL_VERSION_RANGE].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Gets a mutable reference to the privacy parameters field.
+    ///
+    /// # Panics
+    /// Accessing the field may panic if the buffer has not been checked before.
+    pub fn privacy_mut(&mut self) -> &mut [u8] {
+        &mut self.inner.as_mut()[PRIVACY_RANGE]
+    }
+
     #[cfg(not(feature = "secure"))]
     /// Gets a mutable slice that starts at the beginning of the model object field.
     ///
@@ -203,7 +344,7 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> UpdateBuffer<T> {
 }
 
 #[cfg(not(feature = "secure"))]
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 /// A high level representation of an update message.
 ///
 /// These messages are sent by update participants during the update phase.
@@ -212,22 +353,35 @@ pub struct Update {
     ///
     /// This is used to determine whether a participant is selected for the update task.
     pub update_signature: ParticipantTaskSignature,
+    /// Whether the model holds the trained weights or their difference to the base model.
+    pub kind: UpdateKind,
+    /// The version of the global model the participant trained from, `0` if it trained from
+    /// scratch.
+    pub base_model_version: u32,
+    /// The local differential privacy the participant applied to its model, if any.
+    pub privacy: Option<PrivacyParams>,
     /// A model trained by an update participant.
     ///
-    /// The model is masked with randomness derived from the participant seed.
-    pub model_object: ModelObject,
+    /// The model may be encoded with a lossy [`UpdateCodec`], in which case it is decoded by the
+    /// coordinator before the aggregation.
+    ///
+    /// [`UpdateCodec`]: crate::model::UpdateCodec
+    pub model_object: EncodedModelObject,
 }
 
 #[cfg(not(feature = "secure"))]
 impl ToBytes for Update {
     fn buffer_length(&self) -> usize {
-        UPDATE_SIGNATURE_RANGE.end + self.model_object.buffer_length()
+        PRIVACY_RANGE.end + self.model_object.buffer_length()
     }
 
     fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
         let mut writer = UpdateBuffer::new_unchecked(buffer.as_mut());
         self.update_signature
             .to_bytes(&mut writer.update_signature_mut());
+        writer.set_kind(self.kind.into());
+        writer.set_base_model_version(self.base_model_version);
+        privacy_to_bytes(self.privacy, writer.privacy_mut());
         self.model_object.to_bytes(&mut writer.model_object_mut());
     }
 }
@@ -239,20 +393,35 @@ impl FromBytes for Update {
         Ok(Self {
             update_signature: ParticipantTaskSignature::from_byte_slice(&reader.update_signature())
                 .context("invalid update signature")?,
-            model_object: ModelObject::from_byte_slice(&reader.model_object())
+            kind: reader.kind().try_into()?,
+            base_model_version: reader.base_model_version(),
+            privacy: privacy_from_bytes(reader.privacy())?,
+            model_object: EncodedModelObject::from_byte_slice(&reader.model_object())
                 .context("invalid masked model")?,
         })
     }
 
     fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
-        _iter: &mut I,
+        iter: &mut I,
     ) -> Result<Self, DecodeError> {
-        todo!()
+        Ok(Self {
+            update_signature: ParticipantTaskSignature::from_byte_stream(iter)
+                .context("invalid update signature")?,
+            kind: iter
+                .next()
+                .ok_or_else(|| anyhow!("byte stream exhausted"))?
+                .try_into()?,
+            base_model_version: u32::from_byte_stream(iter)
+                .context("invalid base model version")?,
+            privacy: privacy_from_bytes(&iter.take(PRIVACY_RANGE.len()).collect::<Vec<_>>())?,
+            model_object: EncodedModelObject::from_byte_stream(iter)
+                .context("invalid model object")?,
+        })
     }
 }
 
 #[cfg(feature = "secure")]
-#[derive(Debug, Eq, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 /// A high level representation of an update message.
 ///
 /// These messages are sent by update participants during the update phase.
@@ -265,6 +434,13 @@ pub struct Update {
     ///
     /// This is used to determine whether a participant is selected for the update task.
     pub update_signature: ParticipantTaskSignature,
+    /// Whether the model holds the trained weights or their difference to the base model.
+    pub kind: UpdateKind,
+    /// The version of the global model the participant trained from, `0` if it trained from
+    /// scratch.
+    pub base_model_version: u32,
+    /// The local differential privacy the participant applied to its model, if any.
+    pub privacy: Option<PrivacyParams>,
     /// A model trained by an update participant.
     ///
     /// The model is masked with randomness derived from the participant seed.
@@ -278,9 +454,7 @@ pub struct Update {
 #[cfg(feature = "secure")]
 impl ToBytes for Update {
     fn buffer_length(&self) -> usize {
-        UPDATE_SIGNATURE_RANGE.end
-            + self.masked_model.buffer_length()
-            + self.local_seed_dict.buffer_length()
+        PRIVACY_RANGE.end + self.masked_model.buffer_length() + self.local_seed_dict.buffer_length()
     }
 
     fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
@@ -288,6 +462,9 @@ impl ToBytes for Update {
         self.sum_signature.to_bytes(&mut writer.sum_signature_mut());
         self.update_signature
             .to_bytes(&mut writer.update_signature_mut());
+        writer.set_kind(self.kind.into());
+        writer.set_base_model_version(self.base_model_version);
+        privacy_to_bytes(self.privacy, writer.privacy_mut());
         self.masked_model.to_bytes(&mut writer.masked_model_mut());
         self.local_seed_dict
             .to_bytes(&mut writer.local_seed_dict_mut());
@@ -303,6 +480,9 @@ impl FromBytes for Update {
                 .context("invalid sum signature")?,
             update_signature: ParticipantTaskSignature::from_byte_slice(&reader.update_signature())
                 .context("invalid update signature")?,
+            kind: reader.kind().try_into()?,
+            base_model_version: reader.base_model_version(),
+            privacy: privacy_from_bytes(reader.privacy())?,
             masked_model: MaskObject::from_byte_slice(&reader.masked_model())
                 .context("invalid masked model")?,
             local_seed_dict: LocalSeedDict::from_byte_slice(&reader.local_seed_dict())
@@ -318,6 +498,13 @@ impl FromBytes for Update {
                 .context("invalid sum signature")?,
             update_signature: ParticipantTaskSignature::from_byte_stream(iter)
                 .context("invalid update signature")?,
+            kind: iter
+                .next()
+                .ok_or_else(|| anyhow!("byte stream exhausted"))?
+                .try_into()?,
+            base_model_version: u32::from_byte_stream(iter)
+                .context("invalid base model version")?,
+            privacy: privacy_from_bytes(&iter.take(PRIVACY_RANGE.len()).collect::<Vec<_>>())?,
             masked_model: MaskObject::from_byte_stream(iter).context("invalid masked model")?,
             local_seed_dict: LocalSeedDict::from_byte_stream(iter)
                 .context("invalid local seed dictionary")?,
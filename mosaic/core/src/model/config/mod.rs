@@ -56,7 +56,8 @@ impl TryInto<u8> for DataType {
 }
 
 impl DataType {
-    pub(crate) fn bytes_per_number(&self) -> usize {
+    /// Returns the number of bytes of a primitive value of this data type.
+    pub fn bytes_per_number(&self) -> usize {
         match self {
             DataType::F32 => 4,
             DataType::F64 => 8,
@@ -0,0 +1,176 @@
+//! Model file formats.
+//!
+//! A [`Model`] can be converted to and from the file formats of the machine learning ecosystem:
+//! - NumPy `.npy` files, which hold the weights as a flat array (see [`Model::to_npy()`]).
+//! - safetensors files, which hold the named tensors of a [`ModelSchema`] or a single tensor if
+//!   the model is not structured (see [`Model::to_safetensors()`]).
+//! - raw buffers, which hold the weights as a flat array without any header (see
+//!   [`Model::to_raw()`]). They are meant for memory-mapped files, whose layout the reader knows.
+//!
+//! The weights are stored as little-endian primitive values of the [`DataType`] of the model,
+//! respectively of each tensor. A file is only read if its data types match the expected ones, as
+//! the conversion between primitive data types is lossy.
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+//! [`ModelSchema`]: crate::model::ModelSchema
+pub(crate) mod npy;
+pub(crate) mod safetensors;
+
+use std::{convert::TryInto, fmt::Debug};
+
+use num::{bigint::BigInt, rational::Ratio};
+use thiserror::Error;
+
+use crate::model::{DataType, FromPrimitives, IntoPrimitives, Model, ModelCastError, SchemaError};
+
+#[derive(Debug, Error)]
+/// Errors related to the conversion of models to and from file formats.
+pub enum FormatError {
+    #[error("the {format} file is malformed: {reason}")]
+    Malformed {
+        format: &'static str,
+        reason: String,
+    },
+    #[error("the weights have the data type {actual} but {expected:?} is expected")]
+    DataTypeMismatch { expected: DataType, actual: String },
+    #[error("the tensor {name} has the shape {actual:?} but the schema expects {expected:?}")]
+    ShapeMismatch {
+        name: String,
+        expected: Vec<u32>,
+        actual: Vec<usize>,
+    },
+    #[error("the file holds {0} tensors but the model has no schema to join them")]
+    Unstructured(usize),
+    #[error("a weight is not finite")]
+    NonFinite,
+    #[error(transparent)]
+    Cast(#[from] ModelCastError),
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+}
+
+impl Model {
+    /// Converts the model into a raw buffer, which holds the weights as little-endian primitive
+    /// values of the given data type.
+    ///
+    /// # Errors
+    /// Fails if a weight is not representable in the data type.
+    pub fn to_raw(&self, data_type: DataType) -> Result<Vec<u8>, FormatError> {
+        let mut bytes = Vec::with_capacity(self.len() * data_type.bytes_per_number());
+        write_weights(&self.0, data_type, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Creates a model from a raw buffer of little-endian primitive values of the given data
+    /// type.
+    ///
+    /// # Errors
+    /// Fails if the length of the buffer is not a multiple of the size of the data type or if a
+    /// weight is not finite.
+    pub fn from_raw(bytes: &[u8], data_type: DataType) -> Result<Self, FormatError> {
+        if bytes.len() % data_type.bytes_per_number() != 0 {
+            return Err(FormatError::Malformed {
+                format: "raw",
+                reason: format!(
+                    "the length {} is not a multiple of the size of {:?}",
+                    bytes.len(),
+                    data_type
+                ),
+            });
+        }
+        read_weights(bytes, data_type).map(Into::into)
+    }
+}
+
+/// Appends the weights to the buffer as little-endian primitive values of the data type.
+///
+/// # Errors
+/// Fails if a weight is not representable in the data type.
+pub(crate) fn write_weights(
+    weights: &[Ratio<BigInt>],
+    data_type: DataType,
+    buffer: &mut Vec<u8>,
+) -> Result<(), FormatError> {
+    let model = Model::from(weights.to_vec());
+    match data_type {
+        DataType::F32 => write_primitives(model, buffer, f32::to_le_bytes),
+        DataType::F64 => write_primitives(model, buffer, f64::to_le_bytes),
+        DataType::I32 => write_primitives(model, buffer, i32::to_le_bytes),
+        DataType::I64 => write_primitives(model, buffer, i64::to_le_bytes),
+    }
+}
+
+fn write_primitives<P: 'static, const N: usize>(
+    model: Model,
+    buffer: &mut Vec<u8>,
+    to_le_bytes: fn(P) -> [u8; N],
+) -> Result<(), FormatError>
+where
+    Model: IntoPrimitives<P>,
+{
+    buffer.reserve(model.len() * N);
+    for weight in model.into_primitives() {
+        buffer.extend_from_slice(&to_le_bytes(weight?));
+    }
+    Ok(())
+}
+
+/// Reads the weights from little-endian primitive values of the data type. The length of the
+/// bytes must be a multiple of the size of the data type.
+///
+/// # Errors
+/// Fails if a weight is not finite.
+pub(crate) fn read_weights(
+    bytes: &[u8],
+    data_type: DataType,
+) -> Result<Vec<Ratio<BigInt>>, FormatError> {
+    debug_assert_eq!(bytes.len() % data_type.bytes_per_number(), 0);
+    match data_type {
+        DataType::F32 => read_primitives(bytes, f32::from_le_bytes),
+        DataType::F64 => read_primitives(bytes, f64::from_le_bytes),
+        DataType::I32 => read_primitives(bytes, i32::from_le_bytes),
+        DataType::I64 => read_primitives(bytes, i64::from_le_bytes),
+    }
+}
+
+fn read_primitives<P: Debug, const N: usize>(
+    bytes: &[u8],
+    from_le_bytes: fn([u8; N]) -> P,
+) -> Result<Vec<Ratio<BigInt>>, FormatError>
+where
+    Model: FromPrimitives<P>,
+{
+    // UNWRAP SAFE: the chunks have exactly N bytes
+    let primitives = bytes
+        .chunks_exact(N)
+        .map(|chunk| from_le_bytes(chunk.try_into().unwrap()));
+    Model::from_primitives(primitives)
+        .map(Into::into)
+        .map_err(|_| FormatError::NonFinite)
+}
+
+/// Gets the number of weights of a tensor with the given shape, or `None` if it overflows.
+fn weights(shape: &[usize]) -> Option<usize> {
+    shape
+        .iter()
+        .try_fold(1_usize, |weights, dim| weights.checked_mul(*dim))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let model = Model::from_primitives(vec![1_i64, -2, 3].into_iter()).unwrap();
+        let bytes = model.to_raw(DataType::I64).unwrap();
+        assert_eq!(bytes.len(), 3 * 8);
+        assert_eq!(Model::from_raw(&bytes, DataType::I64).unwrap(), model);
+        assert!(matches!(
+            Model::from_raw(&bytes[..bytes.len() - 1], DataType::I64),
+            Err(FormatError::Malformed { .. })
+        ));
+    }
+}
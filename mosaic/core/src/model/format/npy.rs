@@ -0,0 +1,215 @@
+//! NumPy `.npy` files.
+//!
+//! See the [format module] documentation since this is a private module anyways.
+//!
+//! [format module]: crate::model::format
+use std::{convert::TryInto, iter};
+
+use crate::model::{
+    format::{read_weights, weights, write_weights, FormatError},
+    DataType, Model,
+};
+
+/// The magic string at the start of a `.npy` file.
+const MAGIC: &[u8] = b"\x93NUMPY";
+/// The alignment of the array data.
+const ALIGNMENT: usize = 64;
+
+/// Gets the NumPy type descriptor of a data type.
+fn descr(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::F32 => "<f4",
+        DataType::F64 => "<f8",
+        DataType::I32 => "<i4",
+        DataType::I64 => "<i8",
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> FormatError {
+    FormatError::Malformed {
+        format: "npy",
+        reason: reason.into(),
+    }
+}
+
+/// Gets the literal of the value of a key in the header dictionary, which is followed by the rest
+/// of the header.
+fn header_value<'a>(header: &'a str, key: &str) -> Result<&'a str, FormatError> {
+    let key = format!("'{}':", key);
+    header
+        .find(&key)
+        .map(|start| header[start + key.len()..].trim_start())
+        .ok_or_else(|| malformed(format!("the header has no key {}", key)))
+}
+
+/// Parses the type descriptor, the order and the shape of the array from the header dictionary.
+fn parse_header(header: &str) -> Result<(&str, bool, Vec<usize>), FormatError> {
+    let descr = header_value(header, "descr")?;
+    let descr = descr
+        .strip_prefix('\'')
+        .and_then(|descr| descr.split('\'').next())
+        .ok_or_else(|| malformed("the type descriptor is not a string"))?;
+
+    let fortran_order = header_value(header, "fortran_order")?;
+    let fortran_order = if fortran_order.starts_with("True") {
+        true
+    } else if fortran_order.starts_with("False") {
+        false
+    } else {
+        return Err(malformed("the order is not a boolean"));
+    };
+
+    let shape = header_value(header, "shape")?;
+    let shape = shape
+        .strip_prefix('(')
+        .and_then(|shape| shape.split(')').next())
+        .ok_or_else(|| malformed("the shape is not a tuple"))?
+        .split(',')
+        .map(str::trim)
+        .filter(|dim| !dim.is_empty())
+        .map(|dim| dim.trim_end_matches('L').parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| malformed("the shape has an invalid dimension"))?;
+
+    Ok((descr, fortran_order, shape))
+}
+
+/// Reads the header of a `.npy` file and checks it against the data type and the length of the
+/// array data. Returns the start of the array data and the number of weights.
+fn read_header(bytes: &[u8], data_type: DataType) -> Result<(usize, usize), FormatError> {
+    if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 4 {
+        return Err(malformed("the magic string is missing"));
+    }
+    let (header_length, header_start) = match bytes[MAGIC.len()] {
+        1 => {
+            // UNWRAP SAFE: the slice has exactly 2 bytes
+            let length = u16::from_le_bytes(bytes[8..10].try_into().unwrap());
+            (length as usize, 10)
+        }
+        2 | 3 if bytes.len() >= 12 => {
+            // UNWRAP SAFE: the slice has exactly 4 bytes
+            let length = u32::from_le_bytes(bytes[8..12].try_into().unwrap());
+            (length as usize, 12)
+        }
+        version => return Err(malformed(format!("unsupported version {}", version))),
+    };
+    let data_start = header_start + header_length;
+    let header = bytes
+        .get(header_start..data_start)
+        .and_then(|header| std::str::from_utf8(header).ok())
+        .ok_or_else(|| malformed("the header is truncated"))?;
+
+    let (actual, fortran_order, shape) = parse_header(header)?;
+    if actual != descr(data_type) {
+        return Err(FormatError::DataTypeMismatch {
+            expected: data_type,
+            actual: actual.to_string(),
+        });
+    }
+    if fortran_order && shape.len() > 1 {
+        return Err(malformed("arrays in column-major order are not supported"));
+    }
+    let len = weights(&shape).ok_or_else(|| malformed("the shape overflows"))?;
+    let length = len
+        .checked_mul(data_type.bytes_per_number())
+        .ok_or_else(|| malformed("the shape overflows"))?;
+    if bytes.len() - data_start != length {
+        return Err(malformed(format!(
+            "the array has {} bytes but its shape requires {}",
+            bytes.len() - data_start,
+            length,
+        )));
+    }
+    Ok((data_start, len))
+}
+
+impl Model {
+    /// Converts the model into a NumPy `.npy` file, which holds the weights as a flat array of
+    /// the given data type.
+    ///
+    /// # Errors
+    /// Fails if a weight is not representable in the data type.
+    pub fn to_npy(&self, data_type: DataType) -> Result<Vec<u8>, FormatError> {
+        let mut header = format!(
+            "{{'descr': '{}', 'fortran_order': False, 'shape': ({},), }}",
+            descr(data_type),
+            self.len(),
+        );
+        // the header is padded with spaces and terminated by a newline to align the array data
+        let unpadded = MAGIC.len() + 4 + header.len() + 1;
+        header.extend(iter::repeat(' ').take((ALIGNMENT - unpadded % ALIGNMENT) % ALIGNMENT));
+        header.push('\n');
+
+        let mut bytes = Vec::with_capacity(
+            MAGIC.len() + 4 + header.len() + self.len() * data_type.bytes_per_number(),
+        );
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[1, 0]);
+        // UNWRAP SAFE: the header is much shorter than u16::MAX
+        bytes.extend_from_slice(&u16::try_from(header.len()).unwrap().to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        write_weights(&self.0, data_type, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Creates a model from a NumPy `.npy` file. The array may have any shape, the weights are
+    /// read in row-major order.
+    ///
+    /// # Errors
+    /// Fails if the file is malformed, if the array is stored in column-major order or if its
+    /// data type differs from the given one.
+    pub fn from_npy(bytes: &[u8], data_type: DataType) -> Result<Self, FormatError> {
+        let (data_start, _) = read_header(bytes, data_type)?;
+        Ok(read_weights(&bytes[data_start..], data_type)?.into())
+    }
+
+    /// Gets the number of weights of a NumPy `.npy` file from its header, without reading the
+    /// weights.
+    ///
+    /// # Errors
+    /// Fails like [`Model::from_npy()`], except for weights which are not finite.
+    pub fn npy_len(bytes: &[u8], data_type: DataType) -> Result<usize, FormatError> {
+        read_header(bytes, data_type).map(|(_, len)| len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::FromPrimitives;
+
+    #[test]
+    fn test_npy_roundtrip() {
+        let model = Model::from_primitives(vec![0.5_f32, -1.25, 3.0].into_iter()).unwrap();
+        let bytes = model.to_npy(DataType::F32).unwrap();
+        assert_eq!((bytes.len() - 3 * 4) % ALIGNMENT, 0);
+        assert_eq!(Model::from_npy(&bytes, DataType::F32).unwrap(), model);
+        assert_eq!(Model::npy_len(&bytes, DataType::F32).unwrap(), 3);
+        assert!(matches!(
+            Model::from_npy(&bytes, DataType::F64),
+            Err(FormatError::DataTypeMismatch { .. })
+        ));
+        assert!(matches!(
+            Model::from_npy(&bytes[..bytes.len() - 1], DataType::F32),
+            Err(FormatError::Malformed { .. })
+        ));
+    }
+
+    #[test]
+    fn test_npy_from_numpy() {
+        // np.save(f, np.array([[1, 2], [3, 4]], dtype=np.int32))
+        let mut bytes = MAGIC.to_vec();
+        let header = "{'descr': '<i4', 'fortran_order': False, 'shape': (2, 2), }";
+        let header = format!("{:<117}\n", header);
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for weight in 1_i32..=4 {
+            bytes.extend_from_slice(&weight.to_le_bytes());
+        }
+        assert_eq!(
+            Model::from_npy(&bytes, DataType::I32).unwrap(),
+            Model::from_primitives(1_i32..=4).unwrap(),
+        );
+    }
+}
@@ -0,0 +1,324 @@
+//! safetensors files.
+//!
+//! See the [format module] documentation since this is a private module anyways.
+//!
+//! [format module]: crate::model::format
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryInto,
+};
+
+use num::{bigint::BigInt, rational::Ratio};
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    format::{read_weights, weights, write_weights, FormatError},
+    DataType, Model, ModelSchema, SchemaError,
+};
+
+/// The name of the single tensor of a model without a schema.
+pub const UNSTRUCTURED_TENSOR: &str = "weights";
+/// The key of the free-form metadata in the header.
+const METADATA_KEY: &str = "__metadata__";
+/// The alignment of the tensor data.
+const ALIGNMENT: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+/// The description of a tensor in the header.
+struct TensorInfo {
+    dtype: String,
+    shape: Vec<usize>,
+    data_offsets: (usize, usize),
+}
+
+/// The named tensors of the header.
+type Tensors = Vec<(String, TensorInfo)>;
+
+/// Gets the safetensors name of a data type.
+fn dtype(data_type: DataType) -> &'static str {
+    match data_type {
+        DataType::F32 => "F32",
+        DataType::F64 => "F64",
+        DataType::I32 => "I32",
+        DataType::I64 => "I64",
+    }
+}
+
+fn malformed(reason: impl Into<String>) -> FormatError {
+    FormatError::Malformed {
+        format: "safetensors",
+        reason: reason.into(),
+    }
+}
+
+/// Reads the tensors from the header of the file. Returns them along with the data buffer.
+fn read_header(bytes: &[u8]) -> Result<(Tensors, &[u8]), FormatError> {
+    let header_length = bytes
+        .get(..8)
+        // UNWRAP SAFE: the slice has exactly 8 bytes
+        .map(|length| u64::from_le_bytes(length.try_into().unwrap()))
+        .ok_or_else(|| malformed("the header length is missing"))?;
+    let data_start = usize::try_from(header_length)
+        .ok()
+        .and_then(|length| length.checked_add(8))
+        .filter(|start| *start <= bytes.len())
+        .ok_or_else(|| malformed("the header is truncated"))?;
+    let mut header: HashMap<String, serde_json::Value> =
+        serde_json::from_slice(&bytes[8..data_start])
+            .map_err(|err| malformed(format!("the header is invalid: {}", err)))?;
+    header.remove(METADATA_KEY);
+
+    let tensors = header
+        .into_iter()
+        .map(|(name, info)| {
+            serde_json::from_value::<TensorInfo>(info)
+                .map(|info| (name.clone(), info))
+                .map_err(|err| malformed(format!("the tensor {} is invalid: {}", name, err)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((tensors, &bytes[data_start..]))
+}
+
+/// Checks that a tensor is stored in the data type.
+fn check_dtype(info: &TensorInfo, data_type: DataType) -> Result<(), FormatError> {
+    if info.dtype != dtype(data_type) {
+        return Err(FormatError::DataTypeMismatch {
+            expected: data_type,
+            actual: info.dtype.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Reads the weights of a tensor from the data buffer of the file.
+fn read_tensor(
+    name: &str,
+    info: &TensorInfo,
+    data: &[u8],
+    data_type: DataType,
+) -> Result<Vec<Ratio<BigInt>>, FormatError> {
+    check_dtype(info, data_type)?;
+    let (begin, end) = info.data_offsets;
+    let bytes = data
+        .get(begin..end)
+        .filter(|_| begin <= end)
+        .ok_or_else(|| malformed(format!("the offsets of the tensor {} are invalid", name)))?;
+    let length = weights(&info.shape)
+        .and_then(|weights| weights.checked_mul(data_type.bytes_per_number()))
+        .ok_or_else(|| malformed(format!("the shape of the tensor {} overflows", name)))?;
+    if bytes.len() != length {
+        return Err(malformed(format!(
+            "the tensor {} has {} bytes but its shape requires {}",
+            name,
+            bytes.len(),
+            length,
+        )));
+    }
+    read_weights(bytes, data_type)
+}
+
+impl Model {
+    /// Converts the model into a safetensors file.
+    ///
+    /// If a schema is given, the file holds the named tensors of the schema in their data types.
+    /// Otherwise, it holds the weights as a single flat tensor named `weights` of the given data
+    /// type.
+    ///
+    /// # Errors
+    /// Fails if the model does not conform to the schema or if a weight is not representable in
+    /// the data type.
+    pub fn to_safetensors(
+        &self,
+        data_type: DataType,
+        schema: Option<&ModelSchema>,
+    ) -> Result<Vec<u8>, FormatError> {
+        let tensors = match schema {
+            Some(schema) => schema
+                .split(&self.0)?
+                .into_iter()
+                .map(|(tensor, weights)| {
+                    let shape = tensor.shape.iter().map(|dim| *dim as usize).collect();
+                    (tensor.name.as_str(), shape, tensor.data_type, weights)
+                })
+                .collect(),
+            None => vec![(
+                UNSTRUCTURED_TENSOR,
+                vec![self.len()],
+                data_type,
+                &self.0[..],
+            )],
+        };
+
+        let mut header = BTreeMap::new();
+        let mut data = Vec::new();
+        for (name, shape, data_type, weights) in tensors {
+            let begin = data.len();
+            write_weights(weights, data_type, &mut data)?;
+            let info = TensorInfo {
+                dtype: dtype(data_type).to_string(),
+                shape,
+                data_offsets: (begin, data.len()),
+            };
+            header.insert(name, info);
+        }
+        // UNWRAP SAFE: the header consists of strings and numbers only
+        let mut header = serde_json::to_vec(&header).unwrap();
+        // the header is padded with spaces to align the tensor data
+        header.resize(
+            header.len() + (ALIGNMENT - header.len() % ALIGNMENT) % ALIGNMENT,
+            b' ',
+        );
+
+        let mut bytes = Vec::with_capacity(8 + header.len() + data.len());
+        bytes.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&header);
+        bytes.extend_from_slice(&data);
+        Ok(bytes)
+    }
+
+    /// Creates a model from a safetensors file.
+    ///
+    /// If a schema is given, the tensors of the file are joined in the order of the schema and
+    /// must match the names, shapes and data types of the tensors of the schema. Otherwise, the
+    /// file must hold a single tensor of any shape and of the given data type, whose weights are
+    /// read in row-major order.
+    ///
+    /// # Errors
+    /// Fails if the file is malformed or if its tensors don't match the schema, respectively the
+    /// data type.
+    pub fn from_safetensors(
+        bytes: &[u8],
+        data_type: DataType,
+        schema: Option<&ModelSchema>,
+    ) -> Result<Self, FormatError> {
+        let (mut tensors, data) = read_header(bytes)?;
+
+        let schema = match schema {
+            Some(schema) => schema,
+            None if tensors.len() == 1 => {
+                // UNWRAP SAFE: there is exactly one tensor
+                let (name, info) = tensors.pop().unwrap();
+                return Ok(read_tensor(&name, &info, data, data_type)?.into());
+            }
+            None => return Err(FormatError::Unstructured(tensors.len())),
+        };
+        let mut weights = HashMap::with_capacity(tensors.len());
+        for (name, info) in tensors {
+            let tensor = schema
+                .tensors()
+                .iter()
+                .find(|tensor| tensor.name == name)
+                .ok_or_else(|| SchemaError::UnknownTensor(name.clone()))?;
+            let shape_matches = tensor.shape.len() == info.shape.len()
+                && tensor
+                    .shape
+                    .iter()
+                    .zip(info.shape.iter())
+                    .all(|(expected, actual)| *expected as usize == *actual);
+            if !shape_matches {
+                return Err(FormatError::ShapeMismatch {
+                    name,
+                    expected: tensor.shape.clone(),
+                    actual: info.shape,
+                });
+            }
+            let tensor_weights = read_tensor(&name, &info, data, tensor.data_type)?;
+            weights.insert(name, tensor_weights);
+        }
+        Ok(schema.join(weights)?.into())
+    }
+
+    /// Gets the number of weights of a safetensors file from its header, without reading the
+    /// weights. The data types of the tensors are checked against the schema, respectively the
+    /// data type, but their shapes are not.
+    ///
+    /// # Errors
+    /// Fails if the header is malformed or if the data type of a tensor doesn't match.
+    pub fn safetensors_len(
+        bytes: &[u8],
+        data_type: DataType,
+        schema: Option<&ModelSchema>,
+    ) -> Result<usize, FormatError> {
+        let (tensors, _) = read_header(bytes)?;
+        if schema.is_none() && tensors.len() != 1 {
+            return Err(FormatError::Unstructured(tensors.len()));
+        }
+        tensors.iter().try_fold(0_usize, |len, (name, info)| {
+            let data_type = match schema {
+                Some(schema) => {
+                    schema
+                        .tensors()
+                        .iter()
+                        .find(|tensor| tensor.name == *name)
+                        .ok_or_else(|| SchemaError::UnknownTensor(name.clone()))?
+                        .data_type
+                }
+                None => data_type,
+            };
+            check_dtype(info, data_type)?;
+            weights(&info.shape)
+                .and_then(|weights| len.checked_add(weights))
+                .ok_or_else(|| malformed(format!("the shape of the tensor {} overflows", name)))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{FromPrimitives, TensorSpec};
+
+    fn schema() -> ModelSchema {
+        ModelSchema::new(vec![
+            TensorSpec {
+                name: "dense.weight".to_string(),
+                shape: vec![2, 2],
+                data_type: DataType::F32,
+            },
+            TensorSpec {
+                name: "dense.bias".to_string(),
+                shape: vec![2],
+                data_type: DataType::I64,
+            },
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_safetensors_roundtrip() {
+        let model =
+            Model::from_primitives(vec![0.5_f64, -1.0, 2.0, 4.0, 7.0, -3.0].into_iter()).unwrap();
+        let schema = schema();
+        let bytes = model.to_safetensors(DataType::F32, Some(&schema)).unwrap();
+        assert_eq!(
+            Model::from_safetensors(&bytes, DataType::F32, Some(&schema)).unwrap(),
+            model
+        );
+        assert_eq!(
+            Model::safetensors_len(&bytes, DataType::F32, Some(&schema)).unwrap(),
+            6
+        );
+        assert!(matches!(
+            Model::from_safetensors(&bytes, DataType::F32, None),
+            Err(FormatError::Unstructured(2))
+        ));
+
+        let bytes = model.to_safetensors(DataType::F64, None).unwrap();
+        assert_eq!(
+            Model::from_safetensors(&bytes, DataType::F64, None).unwrap(),
+            model
+        );
+        assert!(matches!(
+            Model::from_safetensors(&bytes, DataType::F32, None),
+            Err(FormatError::DataTypeMismatch { .. })
+        ));
+        assert!(matches!(
+            Model::safetensors_len(&bytes, DataType::F32, None),
+            Err(FormatError::DataTypeMismatch { .. })
+        ));
+        assert!(matches!(
+            Model::from_safetensors(&bytes, DataType::F64, Some(&schema)),
+            Err(FormatError::Schema(_))
+        ));
+    }
+}
@@ -0,0 +1,581 @@
+//! Lossy update codecs.
+//!
+//! An update participant can trade precision for bandwidth by encoding its model with an
+//! [`UpdateCodec`]. Only a fraction of the values is sent when the model is sparsified, and the
+//! sent values are reduced to a few bits each when the model is quantized. The resulting
+//! [`LossyModelObject`] is decoded back into a dense [`ModelObject`] by the coordinator before
+//! the aggregation.
+//!
+//! The participant is expected to keep track of the error it introduced (see
+//! [`LossyModelObject::to_floats()`]) and to add it to its next model, so that the dropped
+//! information is eventually sent.
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+use std::{cmp::Ordering, convert::TryInto};
+
+use anyhow::{anyhow, Context};
+use num::{bigint::BigInt, rational::Ratio};
+use rand::{seq::index, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    crypto::Sha256,
+    message::DecodeError,
+    model::{model::float_to_ratio_bounded, DataType, ModelConfig, ModelObject},
+};
+
+/// The number of consecutive values that share a quantization scale.
+pub const QUANTIZATION_BLOCK_SIZE: u32 = 256;
+
+/// The codec ID of a dense model object.
+pub(crate) const DENSE_CODEC_ID: u8 = 0;
+/// The codec ID flag of a sparse model object. The lower bits hold the quantization bit width.
+const SPARSE_CODEC_FLAG: u8 = 0x10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// The bit width of quantized values.
+pub enum Quantization {
+    /// Values are quantized to 8 bits.
+    Bits8,
+    /// Values are quantized to 4 bits.
+    Bits4,
+}
+
+impl Quantization {
+    /// Gets the number of bits per quantized value.
+    pub fn bits(self) -> u8 {
+        match self {
+            Quantization::Bits8 => 8,
+            Quantization::Bits4 => 4,
+        }
+    }
+
+    /// Gets the largest quantization level. Values are quantized to the integers in
+    /// `-max_level..=max_level`, which are stored with an offset of `max_level`.
+    fn max_level(self) -> u8 {
+        (1 << (self.bits() - 1)) - 1
+    }
+
+    /// Gets the number of bytes of `count` packed quantized values.
+    pub(crate) fn packed_length(self, count: usize) -> Option<usize> {
+        count
+            .checked_mul(self.bits() as usize)
+            .map(|bits| (bits + 7) / 8)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// The selection of the values sent by an update participant.
+///
+/// The fraction of kept values is clamped to `[0, 1]`, and at least one value is kept.
+pub enum Sparsification {
+    /// Keeps the given fraction of the values, with the largest magnitudes.
+    TopK(f64),
+    /// Keeps the given fraction of the values, chosen uniformly at random.
+    RandomK(f64),
+}
+
+impl Sparsification {
+    /// Selects the indices of the values to keep, in ascending order.
+    fn select<R: Rng>(self, values: &[f64], rng: &mut R) -> Vec<u32> {
+        let ratio = match self {
+            Sparsification::TopK(ratio) | Sparsification::RandomK(ratio) => ratio,
+        };
+        let n = values.len();
+        let k = ((n as f64 * ratio.clamp(0.0, 1.0)).ceil() as usize).clamp(n.min(1), n);
+
+        let mut indices = match self {
+            Sparsification::TopK(_) => {
+                let mut indices = (0..n).collect::<Vec<_>>();
+                if k < n {
+                    indices.select_nth_unstable_by(k, |a, b| {
+                        values[*b]
+                            .abs()
+                            .partial_cmp(&values[*a].abs())
+                            .unwrap_or(Ordering::Equal)
+                    });
+                    indices.truncate(k);
+                }
+                indices
+            }
+            Sparsification::RandomK(_) => index::sample(rng, n, k).into_vec(),
+        };
+        indices.sort_unstable();
+        indices.into_iter().map(|i| i as u32).collect()
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+/// A lossy encoding of the models sent by update participants.
+///
+/// The default codec is lossless, i.e. models are sent as dense [`ModelObject`]s.
+pub struct UpdateCodec {
+    /// The bit width the sent values are quantized to, if any.
+    pub quantization: Option<Quantization>,
+    /// The selection of the sent values, if not all of them are sent.
+    pub sparsification: Option<Sparsification>,
+}
+
+impl UpdateCodec {
+    /// Checks whether this codec sends the models unchanged.
+    pub fn is_lossless(&self) -> bool {
+        self.quantization.is_none() && self.sparsification.is_none()
+    }
+
+    /// Encodes the values of a model with the given configuration.
+    ///
+    /// Quantized values are rounded stochastically, so that they are unbiased estimates of the
+    /// original values. Non-finite values are encoded as zeros. The values are not quantized if
+    /// the scale of a block exceeds the range of `f32`, which happens for huge `F64` values.
+    ///
+    /// Returns `None` if this codec is lossless, if the model doesn't hold floating point
+    /// numbers or if it is neither quantized nor sparsified in the end, in which case the model
+    /// is sent as a dense [`ModelObject`].
+    pub fn encode<R: Rng>(
+        &self,
+        values: &[f64],
+        config: ModelConfig,
+        rng: &mut R,
+    ) -> Option<LossyModelObject> {
+        if self.is_lossless() || !is_float(config.data_type) {
+            return None;
+        }
+
+        let indices = self
+            .sparsification
+            .map(|sparsification| sparsification.select(values, rng));
+        let selected = match indices {
+            Some(ref indices) => indices
+                .iter()
+                .map(|i| finite_or_zero(values[*i as usize]))
+                .collect::<Vec<_>>(),
+            None => values.iter().copied().map(finite_or_zero).collect(),
+        };
+
+        let quantized = self.quantization.and_then(|quantization| {
+            quantize(quantization, &selected, rng)
+                .map(|(scales, encoded)| (quantization, scales, encoded))
+        });
+        let (quantization, block_size, scales, encoded) = match quantized {
+            Some((quantization, scales, encoded)) => {
+                (Some(quantization), QUANTIZATION_BLOCK_SIZE, scales, encoded)
+            }
+            None if indices.is_none() => return None,
+            None => {
+                let encoded = selected
+                    .iter()
+                    .flat_map(|value| float_to_bytes(*value, config.data_type))
+                    .collect();
+                (None, 0, Vec::new(), encoded)
+            }
+        };
+
+        Some(LossyModelObject {
+            config,
+            length: values.len() as u32,
+            quantization,
+            indices,
+            block_size,
+            scales,
+            values: encoded,
+            schema_digest: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A model object encoded with a lossy [`UpdateCodec`].
+pub struct LossyModelObject {
+    /// The configuration of the encoded model.
+    pub config: ModelConfig,
+    /// The number of values of the encoded model.
+    pub length: u32,
+    /// The bit width of the encoded values, if they are quantized.
+    pub quantization: Option<Quantization>,
+    /// The positions of the encoded values in ascending order, if the model is sparse.
+    /// Otherwise, all the values are encoded.
+    pub indices: Option<Vec<u32>>,
+    /// The number of consecutive values that share a scale, if they are quantized.
+    pub block_size: u32,
+    /// The scale of each block of quantized values.
+    pub scales: Vec<f32>,
+    /// The encoded values, either packed quantization levels or numbers of the model data type.
+    pub values: Vec<u8>,
+    /// The digest of the schema of the encoded model, if it is structured.
+    pub schema_digest: Option<Sha256>,
+}
+
+// The equality is reflexive as long as no scale is NaN, which holds for the objects created by
+// `UpdateCodec::encode()`. Decoded objects may hold any scale until they are converted with
+// `to_floats()`, which rejects the non-finite ones.
+impl Eq for LossyModelObject {}
+
+impl LossyModelObject {
+    /// Gets the ID of the codec of this object, as found on the wire.
+    pub fn codec_id(&self) -> u8 {
+        codec_id(self.quantization, self.indices.is_some())
+    }
+
+    /// Gets the number of encoded values.
+    pub fn encoded_values(&self) -> usize {
+        match self.indices {
+            Some(ref indices) => indices.len(),
+            None => self.length as usize,
+        }
+    }
+
+    /// Gets the number of bytes of the values of the decoded model.
+    pub fn decoded_length(&self) -> usize {
+        (self.length as usize).saturating_mul(self.config.bytes_per_number())
+    }
+
+    /// Restores the dense values of the encoded model. The values that were not sent are zeros.
+    ///
+    /// # Errors
+    /// Fails if the object is inconsistent, e.g. if an index is out of bounds or if the number
+    /// of values doesn't match the number of indices.
+    pub fn to_floats(&self) -> Result<Vec<f64>, DecodeError> {
+        let data_type = self.config.data_type;
+        if !is_float(data_type) {
+            return Err(anyhow!(
+                "lossy model objects require a floating point data type, found {:?}",
+                data_type
+            ));
+        }
+        let count = self.encoded_values();
+        let values = match self.quantization {
+            Some(quantization) => dequantize(
+                quantization,
+                self.block_size,
+                &self.scales,
+                &self.values,
+                count,
+            )?,
+            None => {
+                let bytes_per_number = data_type.bytes_per_number();
+                if self.values.len() != count * bytes_per_number {
+                    return Err(anyhow!(
+                        "expected {} encoded values but found {} bytes",
+                        count,
+                        self.values.len()
+                    ));
+                }
+                self.values
+                    .chunks(bytes_per_number)
+                    .map(|bytes| bytes_to_float(bytes, data_type))
+                    .collect()
+            }
+        };
+
+        match self.indices {
+            Some(ref indices) => {
+                let mut dense = vec![0.0; self.length as usize];
+                let mut previous = None;
+                for (index, value) in indices.iter().zip(values) {
+                    if previous.map_or(false, |previous| previous >= *index) {
+                        return Err(anyhow!(
+                            "indices of sparse model objects must be increasing"
+                        ));
+                    }
+                    *dense
+                        .get_mut(*index as usize)
+                        .with_context(|| format!("index {} out of bounds", index))? = value;
+                    previous = Some(*index);
+                }
+                Ok(dense)
+            }
+            None => Ok(values),
+        }
+    }
+
+    /// Decodes this object into a dense model object.
+    ///
+    /// # Errors
+    /// Fails if the object is inconsistent, see [`to_floats()`].
+    ///
+    /// [`to_floats()`]: LossyModelObject::to_floats
+    pub fn decode(&self) -> Result<ModelObject, DecodeError> {
+        let data = self
+            .to_floats()?
+            .into_iter()
+            .map(|value| to_ratio(value, self.config.data_type))
+            .collect();
+        Ok(ModelObject {
+            config: self.config,
+            data,
+            schema_digest: self.schema_digest,
+        })
+    }
+}
+
+/// Gets the codec ID of a lossy model object.
+pub(crate) fn codec_id(quantization: Option<Quantization>, sparse: bool) -> u8 {
+    let bits = quantization.map(Quantization::bits).unwrap_or(0);
+    if sparse {
+        bits | SPARSE_CODEC_FLAG
+    } else {
+        bits
+    }
+}
+
+/// Parses the codec ID of a lossy model object into its quantization and sparsity.
+pub(crate) fn parse_codec_id(id: u8) -> Result<(Option<Quantization>, bool), DecodeError> {
+    let quantization = match id & !SPARSE_CODEC_FLAG {
+        0 => None,
+        8 => Some(Quantization::Bits8),
+        4 => Some(Quantization::Bits4),
+        _ => return Err(anyhow!("invalid model object codec {}", id)),
+    };
+    let sparse = id & SPARSE_CODEC_FLAG != 0;
+    if quantization.is_none() && !sparse {
+        return Err(anyhow!("invalid lossy model object codec {}", id));
+    }
+    Ok((quantization, sparse))
+}
+
+/// Quantizes the values with stochastic rounding and a scale per block of values.
+///
+/// Returns `None` if a scale is not representable as a finite `f32`.
+fn quantize<R: Rng>(
+    quantization: Quantization,
+    values: &[f64],
+    rng: &mut R,
+) -> Option<(Vec<f32>, Vec<u8>)> {
+    let max_level = quantization.max_level() as f64;
+    let scales = values
+        .chunks(QUANTIZATION_BLOCK_SIZE as usize)
+        .map(|block| block.iter().fold(0_f64, |max, value| max.max(value.abs())) as f32)
+        .collect::<Vec<_>>();
+    if scales.iter().any(|scale| !scale.is_finite()) {
+        return None;
+    }
+
+    let mut levels = Vec::with_capacity(values.len());
+    for (block, scale) in values
+        .chunks(QUANTIZATION_BLOCK_SIZE as usize)
+        .zip(scales.iter().copied())
+    {
+        for value in block {
+            let level = if scale > 0.0 {
+                let exact = value / scale as f64 * max_level;
+                let floor = exact.floor();
+                let rounded = if rng.gen::<f64>() < exact - floor {
+                    floor + 1.0
+                } else {
+                    floor
+                };
+                rounded.clamp(-max_level, max_level)
+            } else {
+                0.0
+            };
+            levels.push((level + max_level) as u8);
+        }
+    }
+
+    let packed = match quantization {
+        Quantization::Bits8 => levels,
+        Quantization::Bits4 => levels
+            .chunks(2)
+            .map(|pair| pair[0] | pair.get(1).map_or(0, |high| high << 4))
+            .collect(),
+    };
+    Some((scales, packed))
+}
+
+/// Restores `count` quantized values.
+fn dequantize(
+    quantization: Quantization,
+    block_size: u32,
+    scales: &[f32],
+    packed: &[u8],
+    count: usize,
+) -> Result<Vec<f64>, DecodeError> {
+    if block_size == 0 {
+        return Err(anyhow!("quantization blocks must not be empty"));
+    }
+    let nb_blocks = (count + block_size as usize - 1) / block_size as usize;
+    if scales.len() != nb_blocks {
+        return Err(anyhow!(
+            "expected {} quantization scales but found {}",
+            nb_blocks,
+            scales.len()
+        ));
+    }
+    if scales.iter().any(|scale| !scale.is_finite()) {
+        return Err(anyhow!("quantization scales must be finite"));
+    }
+    if Some(packed.len()) != quantization.packed_length(count) {
+        return Err(anyhow!(
+            "expected {} quantized values but found {} bytes",
+            count,
+            packed.len()
+        ));
+    }
+
+    let max_level = quantization.max_level();
+    let levels: Box<dyn Iterator<Item = u8> + '_> = match quantization {
+        Quantization::Bits8 => Box::new(packed.iter().copied()),
+        Quantization::Bits4 => Box::new(packed.iter().flat_map(|byte| [byte & 0x0f, byte >> 4])),
+    };
+    levels
+        .take(count)
+        .enumerate()
+        .map(|(i, level)| {
+            if level > 2 * max_level {
+                return Err(anyhow!("invalid quantization level {}", level));
+            }
+            let scale = scales[i / block_size as usize] as f64;
+            Ok((level as f64 - max_level as f64) / max_level as f64 * scale)
+        })
+        .collect()
+}
+
+fn is_float(data_type: DataType) -> bool {
+    matches!(data_type, DataType::F32 | DataType::F64)
+}
+
+fn finite_or_zero(value: f64) -> f64 {
+    if value.is_finite() {
+        value
+    } else {
+        0.0
+    }
+}
+
+fn float_to_bytes(value: f64, data_type: DataType) -> Vec<u8> {
+    match data_type {
+        DataType::F32 => (value as f32).to_le_bytes().to_vec(),
+        _ => value.to_le_bytes().to_vec(),
+    }
+}
+
+fn bytes_to_float(bytes: &[u8], data_type: DataType) -> f64 {
+    // UNWRAP SAFE: the chunks have the length of the data type
+    match data_type {
+        DataType::F32 => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+        _ => f64::from_le_bytes(bytes.try_into().unwrap()),
+    }
+}
+
+fn to_ratio(value: f64, data_type: DataType) -> Ratio<BigInt> {
+    match data_type {
+        DataType::F32 => float_to_ratio_bounded(value as f32),
+        _ => float_to_ratio_bounded(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    fn values(rng: &mut ChaCha20Rng, length: usize) -> Vec<f64> {
+        (0..length).map(|_| rng.gen_range(-1.0..1.0)).collect()
+    }
+
+    #[test]
+    fn test_quantization() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let values = values(&mut rng, 1000);
+        let config = ModelConfig {
+            data_type: DataType::F32,
+        };
+        for (quantization, precision) in [
+            (Quantization::Bits8, 1. / 127.),
+            (Quantization::Bits4, 1. / 7.),
+        ] {
+            let codec = UpdateCodec {
+                quantization: Some(quantization),
+                sparsification: None,
+            };
+            let object = codec.encode(&values, config, &mut rng).unwrap();
+            assert_eq!(object.scales.len(), 4);
+            assert_eq!(object.values.len(), 1000 * quantization.bits() as usize / 8);
+            let decoded = object.to_floats().unwrap();
+            assert_eq!(decoded.len(), values.len());
+            for (value, decoded) in values.iter().zip(decoded) {
+                assert!((value - decoded).abs() <= precision + f32::EPSILON as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sparsification() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let values = values(&mut rng, 100);
+        let config = ModelConfig {
+            data_type: DataType::F64,
+        };
+
+        let codec = UpdateCodec {
+            quantization: None,
+            sparsification: Some(Sparsification::TopK(0.1)),
+        };
+        let object = codec.encode(&values, config, &mut rng).unwrap();
+        let decoded = object.to_floats().unwrap();
+        let mut magnitudes = values.iter().map(|value| value.abs()).collect::<Vec<_>>();
+        magnitudes.sort_by(|a, b| b.partial_cmp(a).unwrap());
+        for (value, decoded) in values.iter().zip(decoded) {
+            if value.abs() >= magnitudes[9] {
+                assert_eq!(*value, decoded);
+            } else {
+                assert_eq!(decoded, 0.0);
+            }
+        }
+
+        let codec = UpdateCodec {
+            quantization: Some(Quantization::Bits4),
+            sparsification: Some(Sparsification::RandomK(0.25)),
+        };
+        let object = codec.encode(&values, config, &mut rng).unwrap();
+        assert_eq!(object.encoded_values(), 25);
+        assert_eq!(object.decode().unwrap().data.len(), 100);
+    }
+
+    #[test]
+    fn test_lossless_and_integer_models() {
+        let mut rng = ChaCha20Rng::seed_from_u64(2);
+        let config = ModelConfig {
+            data_type: DataType::F32,
+        };
+        assert!(UpdateCodec::default()
+            .encode(&[1.0], config, &mut rng)
+            .is_none());
+        let codec = UpdateCodec {
+            quantization: Some(Quantization::Bits8),
+            sparsification: None,
+        };
+        let config = ModelConfig {
+            data_type: DataType::I64,
+        };
+        assert!(codec.encode(&[1.0], config, &mut rng).is_none());
+
+        // values beyond the range of the f32 scales are not quantized
+        let config = ModelConfig {
+            data_type: DataType::F64,
+        };
+        let values = [1e300, -2.0];
+        assert!(codec.encode(&values, config, &mut rng).is_none());
+        let codec = UpdateCodec {
+            sparsification: Some(Sparsification::TopK(0.5)),
+            ..codec
+        };
+        let object = codec.encode(&values, config, &mut rng).unwrap();
+        assert_eq!(object.quantization, None);
+        assert_eq!(object.to_floats().unwrap(), vec![1e300, 0.0]);
+    }
+
+    #[test]
+    fn test_codec_id() {
+        for id in 0..=u8::MAX {
+            if let Ok((quantization, sparse)) = parse_codec_id(id) {
+                assert_ne!(id, DENSE_CODEC_ID);
+                assert_eq!(codec_id(quantization, sparse), id);
+            }
+        }
+    }
+}
@@ -19,15 +19,23 @@
 //! ```
 //!
 pub(crate) mod config;
+pub(crate) mod format;
+pub(crate) mod lossy;
 pub(crate) mod model;
 pub(crate) mod object;
+pub(crate) mod privacy;
+pub(crate) mod schema;
 pub(crate) mod serialize;
 
 pub use self::{
     config::{DataType, ModelConfig},
+    format::{safetensors::UNSTRUCTURED_TENSOR, FormatError},
+    lossy::{LossyModelObject, Quantization, Sparsification, UpdateCodec, QUANTIZATION_BLOCK_SIZE},
     model::{
-        bytes_to_ratio, ratio_to_bytes, FromPrimitives, IntoPrimitives, Model, ModelCastError,
-        PrimitiveCastError,
+        bytes_to_ratio, ratio_to_bytes, ratio_to_float, FromPrimitives, IntoPrimitives, Model,
+        ModelCastError, PrimitiveCastError,
     },
-    object::ModelObject,
+    object::{EncodedModelObject, ModelObject},
+    privacy::{NoiseMechanism, PrivacyParams, PrivacyRequirement},
+    schema::{ModelSchema, SchemaError, TensorSpec},
 };
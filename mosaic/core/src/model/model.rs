@@ -316,8 +316,14 @@ pub fn bytes_to_ratio(bytes: &[u8], dtype: &DataType) -> Ratio<BigInt> {
             let chunk: [u8; 8] = bytes.try_into().unwrap();
             float_to_ratio_bounded(f64::from_le_bytes(chunk))
         }
-        DataType::I32 => unimplemented!(),
-        DataType::I64 => unimplemented!(),
+        DataType::I32 => {
+            let chunk: [u8; 4] = bytes.try_into().unwrap();
+            Ratio::from_integer(BigInt::from(i32::from_le_bytes(chunk)))
+        }
+        DataType::I64 => {
+            let chunk: [u8; 8] = bytes.try_into().unwrap();
+            Ratio::from_integer(BigInt::from(i64::from_le_bytes(chunk)))
+        }
     }
 }
 
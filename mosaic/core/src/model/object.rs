@@ -1,23 +1,85 @@
+use derive_more::From;
 use num::{bigint::BigInt, rational::Ratio};
 use serde::{Deserialize, Serialize};
 
-use crate::model::ModelConfig;
+use crate::{
+    crypto::Sha256,
+    message::DecodeError,
+    model::{LossyModelObject, ModelConfig, ModelSchema},
+};
 
 #[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
 /// A [`ModelObject`] which represents a model and some attached meta data.
 pub struct ModelObject {
     pub config: ModelConfig,
     pub data: Vec<Ratio<BigInt>>,
+    /// The digest of the schema of the model, if it is structured.
+    pub schema_digest: Option<Sha256>,
 }
 
 impl ModelObject {
     pub fn new(data: Vec<Ratio<BigInt>>, config: ModelConfig) -> Self {
-        Self { config, data }
+        Self {
+            config,
+            data,
+            schema_digest: None,
+        }
     }
     pub fn empty(config: ModelConfig) -> Self {
         Self {
             data: Vec::new(),
             config,
+            schema_digest: None,
+        }
+    }
+    /// Attaches the digest of a schema to the model.
+    pub fn with_schema(mut self, schema: Option<&ModelSchema>) -> Self {
+        self.schema_digest = schema.map(ModelSchema::digest);
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, From)]
+/// A model object as sent by an update participant, either dense or encoded with a lossy
+/// [`UpdateCodec`].
+///
+/// [`UpdateCodec`]: crate::model::UpdateCodec
+pub enum EncodedModelObject {
+    /// A model object with all its values.
+    Dense(ModelObject),
+    /// A model object encoded with a lossy codec.
+    Lossy(LossyModelObject),
+}
+
+impl EncodedModelObject {
+    /// Gets the digest of the schema of the model, if it is structured.
+    pub fn schema_digest(&self) -> Option<Sha256> {
+        match self {
+            EncodedModelObject::Dense(object) => object.schema_digest,
+            EncodedModelObject::Lossy(object) => object.schema_digest,
+        }
+    }
+
+    /// Attaches the digest of a schema to the model.
+    pub fn with_schema(self, schema: Option<&ModelSchema>) -> Self {
+        match self {
+            EncodedModelObject::Dense(object) => object.with_schema(schema).into(),
+            EncodedModelObject::Lossy(object) => LossyModelObject {
+                schema_digest: schema.map(ModelSchema::digest),
+                ..object
+            }
+            .into(),
+        }
+    }
+
+    /// Gets the dense model object, decoding it if necessary.
+    ///
+    /// # Errors
+    /// Fails if a lossy model object is inconsistent.
+    pub fn decode(self) -> Result<ModelObject, DecodeError> {
+        match self {
+            EncodedModelObject::Dense(object) => Ok(object),
+            EncodedModelObject::Lossy(object) => object.decode(),
         }
     }
 }
@@ -0,0 +1,298 @@
+//! Local differential privacy for model updates.
+//!
+//! An update participant which doesn't trust the coordinator can privatize its model before it
+//! is sent: the model is clipped to an L2 norm and perturbed with noise calibrated to a privacy
+//! budget `(epsilon, delta)`, see [`PrivacyParams`]. The parameters a participant used are
+//! reported in its update, so that the coordinator can reject updates which fall short of the
+//! [`PrivacyRequirement`] of the round.
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use rand::{CryptoRng, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::message::DecodeError;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// The distribution of the noise added to privatized models.
+pub enum NoiseMechanism {
+    /// Gaussian noise, which provides `(epsilon, delta)`-differential privacy.
+    Gaussian,
+    /// Laplace noise, which provides pure `epsilon`-differential privacy.
+    Laplace,
+}
+
+impl TryFrom<u8> for NoiseMechanism {
+    type Error = DecodeError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(NoiseMechanism::Gaussian),
+            2 => Ok(NoiseMechanism::Laplace),
+            _ => Err(anyhow!("invalid noise mechanism {}", value)),
+        }
+    }
+}
+
+impl From<NoiseMechanism> for u8 {
+    fn from(mechanism: NoiseMechanism) -> Self {
+        match mechanism {
+            NoiseMechanism::Gaussian => 1,
+            NoiseMechanism::Laplace => 2,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// The local differential privacy applied by an update participant to its models.
+///
+/// The model is scaled down to an L2 norm of at most `clip_norm`, which bounds the difference
+/// between any two models by `2 * clip_norm`. The noise is calibrated to this sensitivity and to
+/// the privacy budget:
+/// - Gaussian noise has a standard deviation of
+///   `2 * clip_norm * sqrt(2 * ln(1.25 / delta)) / epsilon` per weight. This calibration only
+///   holds for `epsilon < 1`.
+/// - Laplace noise has a scale of `2 * clip_norm * sqrt(n) / epsilon` per weight, where `n` is
+///   the number of weights, since the L2 norm bounds the L1 norm only up to a factor `sqrt(n)`.
+///   `delta` is ignored.
+pub struct PrivacyParams {
+    /// The distribution of the noise.
+    pub mechanism: NoiseMechanism,
+    /// The privacy loss bound. Smaller values give stronger privacy and noisier models.
+    pub epsilon: f64,
+    /// The probability with which the privacy loss bound may be exceeded, `0` for Laplace noise.
+    pub delta: f64,
+    /// The maximum L2 norm of the model.
+    pub clip_norm: f64,
+}
+
+impl PrivacyParams {
+    /// Creates the parameters of Gaussian noise with the given privacy budget.
+    ///
+    /// Returns `None` if `epsilon` is not below `1`, since the noise is not calibrated for it.
+    pub fn gaussian(epsilon: f64, delta: f64, clip_norm: f64) -> Option<Self> {
+        (epsilon < 1.0).then(|| Self {
+            mechanism: NoiseMechanism::Gaussian,
+            epsilon,
+            delta,
+            clip_norm,
+        })
+    }
+
+    /// Creates the parameters of Laplace noise with the given privacy budget.
+    pub fn laplace(epsilon: f64, clip_norm: f64) -> Self {
+        Self {
+            mechanism: NoiseMechanism::Laplace,
+            epsilon,
+            delta: 0.0,
+            clip_norm,
+        }
+    }
+
+    /// Checks whether the parameters describe a meaningful privacy guarantee.
+    ///
+    /// The privacy budget and the clipping norm must be positive and finite. For Gaussian noise,
+    /// `epsilon` must be below `1` and `delta` must be in `(0, 1)`. For Laplace noise, `delta`
+    /// must be `0`.
+    pub fn is_valid(&self) -> bool {
+        let budget = match self.mechanism {
+            NoiseMechanism::Gaussian => self.epsilon < 1.0 && self.delta > 0.0 && self.delta < 1.0,
+            NoiseMechanism::Laplace => self.delta == 0.0,
+        };
+        budget && is_positive(self.epsilon) && is_positive(self.clip_norm)
+    }
+
+    /// Checks whether the parameters are at least as private as the requirement.
+    pub fn satisfies(&self, requirement: &PrivacyRequirement) -> bool {
+        self.is_valid()
+            && self.epsilon <= requirement.max_epsilon
+            && self.delta <= requirement.max_delta
+    }
+
+    /// Tightens the privacy budget to the requirement, if it is weaker.
+    ///
+    /// Gaussian noise falls back to Laplace noise if the requirement doesn't allow any `delta`.
+    pub fn tighten(self, requirement: &PrivacyRequirement) -> Self {
+        let epsilon = self.epsilon.min(requirement.max_epsilon);
+        match self.mechanism {
+            NoiseMechanism::Gaussian if requirement.max_delta > 0.0 => Self::gaussian(
+                epsilon,
+                self.delta.min(requirement.max_delta),
+                self.clip_norm,
+            )
+            // UNWRAP_SAFE: the epsilon of Gaussian noise is below 1 and only gets smaller
+            .unwrap(),
+            _ => Self::laplace(epsilon, self.clip_norm),
+        }
+    }
+
+    /// Gets the standard deviation of Gaussian noise, respectively the scale of Laplace noise,
+    /// added to each of `length` weights.
+    pub fn noise_scale(&self, length: usize) -> f64 {
+        let sensitivity = 2.0 * self.clip_norm;
+        match self.mechanism {
+            NoiseMechanism::Gaussian => {
+                sensitivity * (2.0 * (1.25 / self.delta).ln()).sqrt() / self.epsilon
+            }
+            NoiseMechanism::Laplace => sensitivity * (length as f64).sqrt() / self.epsilon,
+        }
+    }
+
+    /// Clips the values of a model to the L2 norm and adds noise to them.
+    ///
+    /// The randomness must be cryptographically secure, since predictable noise can be removed.
+    /// Non-finite values are treated as zeros.
+    pub fn privatize<R: Rng + CryptoRng>(&self, values: &mut [f64], rng: &mut R) {
+        for value in values.iter_mut().filter(|value| !value.is_finite()) {
+            *value = 0.0;
+        }
+        let norm = values.iter().map(|value| value * value).sum::<f64>().sqrt();
+        if norm > self.clip_norm {
+            let factor = self.clip_norm / norm;
+            for value in values.iter_mut() {
+                *value *= factor;
+            }
+        }
+
+        let scale = self.noise_scale(values.len());
+        for value in values.iter_mut() {
+            *value += scale
+                * match self.mechanism {
+                    NoiseMechanism::Gaussian => standard_normal(rng),
+                    NoiseMechanism::Laplace => standard_laplace(rng),
+                };
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// The minimum local differential privacy a coordinator requires from the updates of a round.
+///
+/// Updates which are not privatized with a budget of at most `max_epsilon` and `max_delta` are
+/// rejected.
+pub struct PrivacyRequirement {
+    /// The largest accepted `epsilon`.
+    pub max_epsilon: f64,
+    /// The largest accepted `delta`.
+    pub max_delta: f64,
+}
+
+/// Checks whether a value is positive and finite.
+fn is_positive(value: f64) -> bool {
+    value > 0.0 && value.is_finite()
+}
+
+/// Samples a uniformly distributed value in `(0, 1]`.
+fn open_unit<R: Rng>(rng: &mut R) -> f64 {
+    1.0 - rng.gen::<f64>()
+}
+
+/// Samples a standard normal value with the Box-Muller transform.
+fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let radius = (-2.0 * open_unit(rng).ln()).sqrt();
+    let angle = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+    radius * angle.cos()
+}
+
+/// Samples a Laplace distributed value of scale `1` as an exponentially distributed value with a
+/// random sign.
+fn standard_laplace<R: Rng>(rng: &mut R) -> f64 {
+    let magnitude = -open_unit(rng).ln();
+    if rng.gen() {
+        magnitude
+    } else {
+        -magnitude
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+
+    fn stats(params: PrivacyParams, values: &[f64]) -> (f64, f64) {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let mut privatized = values.to_vec();
+        params.privatize(&mut privatized, &mut rng);
+        let noise = privatized
+            .iter()
+            .zip(values)
+            .map(|(privatized, value)| privatized - value)
+            .collect::<Vec<_>>();
+        let mean = noise.iter().sum::<f64>() / noise.len() as f64;
+        let variance =
+            noise.iter().map(|n| (n - mean) * (n - mean)).sum::<f64>() / noise.len() as f64;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn test_privatize() {
+        // the values have a norm of 1 and aren't clipped
+        let values = vec![0.01; 10_000];
+
+        let params = PrivacyParams::gaussian(0.5, 1e-5, 1.0).unwrap();
+        let (mean, std_dev) = stats(params, &values);
+        let expected = params.noise_scale(values.len());
+        assert!(mean.abs() < 0.05 * expected);
+        assert!((std_dev - expected).abs() < 0.05 * expected);
+
+        // the standard deviation of Laplace noise is `sqrt(2)` times its scale
+        let params = PrivacyParams::laplace(1.0, 1.0);
+        let (mean, std_dev) = stats(params, &values);
+        let expected = params.noise_scale(values.len()) * 2_f64.sqrt();
+        assert!(mean.abs() < 0.05 * expected);
+        assert!((std_dev - expected).abs() < 0.05 * expected);
+    }
+
+    #[test]
+    fn test_clip() {
+        let mut rng = ChaCha20Rng::seed_from_u64(1);
+        let mut values = vec![3.0, 4.0];
+        let params = PrivacyParams::laplace(f64::MAX, 1.0);
+        params.privatize(&mut values, &mut rng);
+        assert!((values[0] - 0.6).abs() < 1e-9);
+        assert!((values[1] - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_requirement() {
+        let requirement = PrivacyRequirement {
+            max_epsilon: 0.5,
+            max_delta: 1e-5,
+        };
+        let params = PrivacyParams::gaussian(0.9, 1e-3, 1.0).unwrap();
+        assert!(!params.satisfies(&requirement));
+        let params = params.tighten(&requirement);
+        assert_eq!(params, PrivacyParams::gaussian(0.5, 1e-5, 1.0).unwrap());
+        assert!(params.satisfies(&requirement));
+
+        let requirement = PrivacyRequirement {
+            max_epsilon: 0.5,
+            max_delta: 0.0,
+        };
+        let params = params.tighten(&requirement);
+        assert_eq!(params, PrivacyParams::laplace(0.5, 1.0));
+        assert!(params.satisfies(&requirement));
+        assert!(!PrivacyParams::gaussian(0.5, 0.0, 1.0).unwrap().is_valid());
+    }
+
+    #[test]
+    fn test_gaussian_budget() {
+        // the noise of the Gaussian mechanism is only calibrated for epsilon below 1
+        assert!(PrivacyParams::gaussian(1.0, 1e-5, 1.0).is_none());
+        let params = PrivacyParams {
+            mechanism: NoiseMechanism::Gaussian,
+            epsilon: 2.0,
+            delta: 1e-5,
+            clip_norm: 1.0,
+        };
+        assert!(!params.is_valid());
+        assert!(PrivacyParams::laplace(2.0, 1.0).is_valid());
+    }
+}
@@ -0,0 +1,290 @@
+//! Model schemas.
+//!
+//! A [`ModelSchema`] describes a model as an ordered list of named tensors. The model itself is
+//! still a flat vector of weights, which holds the tensors one after the other, each in row-major
+//! order.
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+pub(crate) mod serialize;
+
+use std::{
+    collections::{HashMap, HashSet},
+    convert::TryFrom,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    crypto::{ByteObject, Sha256},
+    message::traits::ToBytes,
+    model::DataType,
+};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+/// Errors related to model schemas.
+pub enum SchemaError {
+    #[error("the schema has no tensors")]
+    Empty,
+    #[error("the schema has more than {} tensors", u16::MAX)]
+    TooManyTensors,
+    #[error("the tensor {0} is defined more than once")]
+    DuplicateTensor(String),
+    #[error(
+        "the tensor {0} has a name longer than {} bytes or more than {} dimensions",
+        u16::MAX,
+        u8::MAX
+    )]
+    InvalidTensor(String),
+    #[error("the number of weights of the tensor {0} overflows")]
+    Overflow(String),
+    #[error("the model has {actual} weights but the schema expects {expected}")]
+    LengthMismatch { expected: usize, actual: usize },
+    #[error("the model has no tensor {0}")]
+    MissingTensor(String),
+    #[error("the model has an unknown tensor {0}")]
+    UnknownTensor(String),
+    #[error("the tensor {name} has {actual} weights but the schema expects {expected}")]
+    TensorLengthMismatch {
+        name: String,
+        expected: usize,
+        actual: usize,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// A named tensor of a model.
+pub struct TensorSpec {
+    /// The name of the tensor, unique within a schema.
+    pub name: String,
+    /// The shape of the tensor. A scalar has an empty shape.
+    pub shape: Vec<u32>,
+    /// The primitive data type participants convert the weights of the tensor from and into.
+    pub data_type: DataType,
+}
+
+impl TensorSpec {
+    /// Gets the number of weights of the tensor, or `None` if it overflows.
+    pub fn weights(&self) -> Option<usize> {
+        self.shape
+            .iter()
+            .try_fold(1_usize, |weights, dim| weights.checked_mul(*dim as usize))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "Vec<TensorSpec>", into = "Vec<TensorSpec>")]
+/// The layout of a model as an ordered list of named tensors.
+pub struct ModelSchema {
+    tensors: Vec<TensorSpec>,
+    /// The total number of weights.
+    weights: usize,
+    /// The digest of the serialized schema.
+    digest: Sha256,
+}
+
+impl TryFrom<Vec<TensorSpec>> for ModelSchema {
+    type Error = SchemaError;
+
+    fn try_from(tensors: Vec<TensorSpec>) -> Result<Self, Self::Error> {
+        Self::new(tensors)
+    }
+}
+
+impl From<ModelSchema> for Vec<TensorSpec> {
+    fn from(schema: ModelSchema) -> Self {
+        schema.tensors
+    }
+}
+
+impl ModelSchema {
+    /// Creates a schema from its tensors, in the order they appear in the model.
+    ///
+    /// # Errors
+    /// Fails if there are no or too many tensors, if a tensor is invalid, if a name is used twice
+    /// or if the number of weights overflows.
+    pub fn new(tensors: Vec<TensorSpec>) -> Result<Self, SchemaError> {
+        if tensors.is_empty() {
+            return Err(SchemaError::Empty);
+        }
+        if tensors.len() > u16::MAX as usize {
+            return Err(SchemaError::TooManyTensors);
+        }
+        let mut names = HashSet::new();
+        let mut weights = 0_usize;
+        for tensor in tensors.iter() {
+            if !names.insert(tensor.name.as_str()) {
+                return Err(SchemaError::DuplicateTensor(tensor.name.clone()));
+            }
+            if tensor.name.len() > u16::MAX as usize || tensor.shape.len() > u8::MAX as usize {
+                return Err(SchemaError::InvalidTensor(tensor.name.clone()));
+            }
+            weights = tensor
+                .weights()
+                .and_then(|tensor_weights| weights.checked_add(tensor_weights))
+                .ok_or_else(|| SchemaError::Overflow(tensor.name.clone()))?;
+        }
+        let mut schema = Self {
+            tensors,
+            weights,
+            digest: Sha256::zeroed(),
+        };
+        let mut bytes = vec![0; schema.buffer_length()];
+        schema.to_bytes(&mut bytes);
+        schema.digest = Sha256::hash(&bytes);
+        Ok(schema)
+    }
+
+    /// Gets the tensors, in the order they appear in the model.
+    pub fn tensors(&self) -> &[TensorSpec] {
+        &self.tensors
+    }
+
+    /// Gets the total number of weights of a model with this schema.
+    pub fn weights(&self) -> usize {
+        self.weights
+    }
+
+    /// Gets the SHA-256 digest of the serialized schema. Model objects carry this digest instead
+    /// of the whole schema.
+    pub fn digest(&self) -> Sha256 {
+        self.digest
+    }
+
+    /// Checks that a flat model of the given length conforms to this schema.
+    ///
+    /// # Errors
+    /// Fails if the length differs from the number of weights of the schema.
+    pub fn validate(&self, length: usize) -> Result<(), SchemaError> {
+        if length != self.weights {
+            return Err(SchemaError::LengthMismatch {
+                expected: self.weights,
+                actual: length,
+            });
+        }
+        Ok(())
+    }
+
+    /// Splits the weights of a flat model into its named tensors.
+    ///
+    /// # Errors
+    /// Fails if the model does not conform to this schema.
+    pub fn split<'a, W>(
+        &'a self,
+        weights: &'a [W],
+    ) -> Result<Vec<(&'a TensorSpec, &'a [W])>, SchemaError> {
+        self.validate(weights.len())?;
+        let mut rest = weights;
+        Ok(self
+            .tensors
+            .iter()
+            .map(|tensor| {
+                // UNWRAP SAFE: the total number of weights was checked when creating the schema
+                let (weights, tail) = rest.split_at(tensor.weights().unwrap());
+                rest = tail;
+                (tensor, weights)
+            })
+            .collect())
+    }
+
+    /// Joins named tensors into the weights of a flat model.
+    ///
+    /// # Errors
+    /// Fails if a tensor is missing or unknown, or if it has the wrong number of weights.
+    pub fn join<W>(&self, mut tensors: HashMap<String, Vec<W>>) -> Result<Vec<W>, SchemaError> {
+        let mut weights = Vec::with_capacity(self.weights);
+        for tensor in self.tensors.iter() {
+            let tensor_weights = tensors
+                .remove(&tensor.name)
+                .ok_or_else(|| SchemaError::MissingTensor(tensor.name.clone()))?;
+            // UNWRAP SAFE: the total number of weights was checked when creating the schema
+            let expected = tensor.weights().unwrap();
+            if tensor_weights.len() != expected {
+                return Err(SchemaError::TensorLengthMismatch {
+                    name: tensor.name.clone(),
+                    expected,
+                    actual: tensor_weights.len(),
+                });
+            }
+            weights.extend(tensor_weights);
+        }
+        match tensors.into_keys().next() {
+            Some(name) => Err(SchemaError::UnknownTensor(name)),
+            None => Ok(weights),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tensor(name: &str, shape: &[u32]) -> TensorSpec {
+        TensorSpec {
+            name: name.to_string(),
+            shape: shape.to_vec(),
+            data_type: DataType::F32,
+        }
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let schema =
+            ModelSchema::new(vec![tensor("weight", &[2, 3]), tensor("bias", &[3])]).unwrap();
+        assert_eq!(schema.weights(), 9);
+        let weights = (0..9).collect::<Vec<u32>>();
+        let tensors = schema.split(&weights).unwrap();
+        assert_eq!(tensors[0].1, &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(tensors[1].1, &[6, 7, 8]);
+        assert!(schema.split(&weights[1..]).is_err());
+
+        let mut tensors = tensors
+            .into_iter()
+            .map(|(tensor, weights)| (tensor.name.clone(), weights.to_vec()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(schema.join(tensors.clone()).unwrap(), weights);
+        tensors.insert("other".to_string(), Vec::new());
+        assert_eq!(
+            schema.join(tensors).unwrap_err(),
+            SchemaError::UnknownTensor("other".to_string())
+        );
+    }
+
+    #[test]
+    fn test_digest() {
+        let schema = ModelSchema::new(vec![tensor("weight", &[2, 3])]).unwrap();
+        assert_eq!(
+            schema.digest(),
+            ModelSchema::new(vec![tensor("weight", &[2, 3])])
+                .unwrap()
+                .digest()
+        );
+        assert_ne!(
+            schema.digest(),
+            ModelSchema::new(vec![tensor("weight", &[3, 2])])
+                .unwrap()
+                .digest()
+        );
+        let mut other = tensor("weight", &[2, 3]);
+        other.data_type = DataType::F64;
+        assert_ne!(
+            schema.digest(),
+            ModelSchema::new(vec![other]).unwrap().digest()
+        );
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        assert_eq!(
+            ModelSchema::new(Vec::new()).unwrap_err(),
+            SchemaError::Empty
+        );
+        assert_eq!(
+            ModelSchema::new(vec![tensor("a", &[1]), tensor("a", &[2])]).unwrap_err(),
+            SchemaError::DuplicateTensor("a".to_string())
+        );
+        assert!(ModelSchema::new(vec![tensor("a", &[u32::MAX, u32::MAX, u32::MAX])]).is_err());
+    }
+}
@@ -0,0 +1,153 @@
+//! Serialization of model schemas.
+//!
+//! A schema holds the number of tensors followed by the tensors. Each tensor holds the length of
+//! its name, its UTF-8 encoded name, its data type, its rank and its dimensions.
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+use std::convert::TryInto;
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    message::{
+        traits::{FromBytes, ToBytes},
+        DecodeError,
+    },
+    model::{
+        schema::{ModelSchema, TensorSpec},
+        DataType,
+    },
+};
+
+impl ToBytes for TensorSpec {
+    fn buffer_length(&self) -> usize {
+        2 + self.name.len() + 2 + 4 * self.shape.len()
+    }
+
+    fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
+        let bytes = (self.name.len() as u16)
+            .to_be_bytes()
+            .into_iter()
+            .chain(self.name.bytes())
+            .chain([self.data_type as u8, self.shape.len() as u8])
+            .chain(self.shape.iter().flat_map(|dim| dim.to_be_bytes()));
+        for (b, byte) in buffer.as_mut().iter_mut().zip(bytes) {
+            *b = byte;
+        }
+    }
+}
+
+impl FromBytes for TensorSpec {
+    fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
+        let mut iter = buffer.as_ref().iter().copied();
+        let tensor = Self::from_byte_stream(&mut iter)?;
+        if iter.len() != 0 {
+            return Err(anyhow!("invalid tensor: {} trailing bytes", iter.len()));
+        }
+        Ok(tensor)
+    }
+
+    fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+        iter: &mut I,
+    ) -> Result<Self, DecodeError> {
+        let name_len = u16::from_byte_stream(iter).context("invalid tensor name length")? as usize;
+        if iter.len() < name_len + 2 {
+            return Err(anyhow!("byte stream exhausted"));
+        }
+        let name =
+            String::from_utf8(iter.take(name_len).collect()).context("invalid tensor name")?;
+        // UNWRAP SAFE: the length was checked above
+        let data_type: DataType = iter
+            .next()
+            .unwrap()
+            .try_into()
+            .context("invalid tensor data type")?;
+        let rank = iter.next().unwrap() as usize;
+        let shape = (0..rank)
+            .map(|_| u32::from_byte_stream(iter))
+            .collect::<Result<Vec<_>, _>>()
+            .context("invalid tensor shape")?;
+        Ok(Self {
+            name,
+            shape,
+            data_type,
+        })
+    }
+}
+
+impl ToBytes for ModelSchema {
+    fn buffer_length(&self) -> usize {
+        2 + self
+            .tensors
+            .iter()
+            .map(|tensor| tensor.buffer_length())
+            .sum::<usize>()
+    }
+
+    fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
+        let buffer = buffer.as_mut();
+        buffer[..2].copy_from_slice(&(self.tensors.len() as u16).to_be_bytes());
+        let mut offset = 2;
+        for tensor in self.tensors.iter() {
+            let end = offset + tensor.buffer_length();
+            tensor.to_bytes(&mut &mut buffer[offset..end]);
+            offset = end;
+        }
+    }
+}
+
+impl FromBytes for ModelSchema {
+    fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
+        let mut iter = buffer.as_ref().iter().copied();
+        let schema = Self::from_byte_stream(&mut iter)?;
+        if iter.len() != 0 {
+            return Err(anyhow!(
+                "invalid model schema: {} trailing bytes",
+                iter.len()
+            ));
+        }
+        Ok(schema)
+    }
+
+    fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+        iter: &mut I,
+    ) -> Result<Self, DecodeError> {
+        let nb_tensors = u16::from_byte_stream(iter).context("invalid number of tensors")?;
+        let tensors = (0..nb_tensors)
+            .map(|_| TensorSpec::from_byte_stream(iter))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(tensors)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_roundtrip() {
+        let schema = ModelSchema::new(vec![
+            TensorSpec {
+                name: "conv.weight".to_string(),
+                shape: vec![8, 3, 3, 3],
+                data_type: DataType::F32,
+            },
+            TensorSpec {
+                name: "step".to_string(),
+                shape: Vec::new(),
+                data_type: DataType::I64,
+            },
+        ])
+        .unwrap();
+        let mut bytes = vec![0; schema.buffer_length()];
+        schema.to_bytes(&mut bytes);
+        assert_eq!(ModelSchema::from_byte_slice(&bytes).unwrap(), schema);
+        assert_eq!(
+            ModelSchema::from_byte_stream(&mut bytes.clone().into_iter()).unwrap(),
+            schema
+        );
+        assert!(ModelSchema::from_byte_slice(&&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -1,22 +1,44 @@
-use std::ops::Range;
+//! Serialization of model objects.
+//!
+//! A model object starts with the model configuration and the ID of its codec. A dense model
+//! object, with codec ID `0`, then holds the number of values followed by the values. A lossy
+//! model object (see [`LossyModelObject`]) holds the number of values of the model, the number
+//! of encoded values and the quantization block size, followed by the indices of the encoded
+//! values if it is sparse, the quantization scales if it is quantized, and the encoded values.
+//! Every model object ends with the length of the digest of its schema, `0` if the model is not
+//! structured, followed by the digest (see [`ModelSchema::digest()`]).
+//!
+//! See the [model module] documentation since this is a private module anyways.
+//!
+//! [model module]: crate::model
+use std::{convert::TryInto, ops::Range};
 
 use anyhow::{anyhow, Context};
+use num::{bigint::BigInt, rational::Ratio};
 
 use crate::{
+    crypto::{ByteObject, Sha256},
     message::{
         traits::{FromBytes, ToBytes},
-        utils::range,
+        utils::{range, ChunkableIterator},
         DecodeError,
     },
     model::{
         bytes_to_ratio,
         config::{serialize::MODEL_CONFIG_BUFFER_LEN, ModelConfig},
-        ratio_to_bytes, ModelObject,
+        lossy::{parse_codec_id, DENSE_CODEC_ID},
+        ratio_to_bytes, EncodedModelObject, LossyModelObject, ModelObject,
     },
 };
 
 const MODEL_CONFIG_FIELD: Range<usize> = range(0, MODEL_CONFIG_BUFFER_LEN);
-const MODEL_LEN_FIELD: Range<usize> = range(MODEL_CONFIG_FIELD.end, 4);
+const CODEC_FIELD: usize = MODEL_CONFIG_FIELD.end;
+const MODEL_LEN_FIELD: Range<usize> = range(CODEC_FIELD + 1, 4);
+/// The offset of the values of a dense model object.
+pub(crate) const DENSE_VALUES_OFFSET: usize = MODEL_LEN_FIELD.end;
+// The remaining header fields only exist in lossy model objects
+const ENCODED_LEN_FIELD: Range<usize> = range(MODEL_LEN_FIELD.end, 4);
+const BLOCK_SIZE_FIELD: Range<usize> = range(ENCODED_LEN_FIELD.end, 4);
 
 #[derive(Debug)]
 /// A buffer for serialized mask objects.
@@ -56,6 +78,13 @@ impl<T: AsRef<[u8]>> ModelObjectBuffer<T> {
                 MODEL_LEN_FIELD.end
             ));
         }
+        if self.codec() != DENSE_CODEC_ID && len < BLOCK_SIZE_FIELD.end {
+            return Err(anyhow!(
+                "invalid buffer length: {} < {}",
+                len,
+                BLOCK_SIZE_FIELD.end
+            ));
+        }
 
         let total_expected_length = self.try_len()?;
         if len < total_expected_length {
@@ -69,13 +98,44 @@ impl<T: AsRef<[u8]>> ModelObjectBuffer<T> {
     }
 
     /// Return the expected length of the underlying byte buffer,
-    /// based on the masking config field of numbers field. This is
-    /// similar to [`len()`] but cannot panic.
+    /// based on the masking config field of numbers field and on the
+    /// schema digest length field. This is similar to [`len()`] but cannot
+    /// panic.
     ///
     /// [`len()`]: MaskVectBuffer::len
     fn try_len(&self) -> Result<usize, DecodeError> {
+        let schema_offset = self.try_data_end()? + 4;
+        let schema_length = self
+            .inner
+            .as_ref()
+            .get(schema_offset - 4..schema_offset)
+            .ok_or_else(|| {
+                anyhow!(
+                    "invalid buffer length: expected at least {} bytes",
+                    schema_offset
+                )
+            })?;
+        // UNWRAP SAFE: the slice is exactly 4 bytes long
+        let schema_length = u32::from_be_bytes(schema_length.try_into().unwrap()) as usize;
+        schema_offset
+            .checked_add(schema_length)
+            .ok_or_else(|| anyhow!("invalid model object buffer: length overflows"))
+    }
+
+    /// Returns the offset of the schema digest length field, which follows
+    /// the values of the model.
+    fn try_data_end(&self) -> Result<usize, DecodeError> {
         let config =
             ModelConfig::from_byte_slice(&self.config()).context("invalid mask vector buffer")?;
+        if self.codec() != DENSE_CODEC_ID {
+            let sections = self.lossy_sections(&config)?;
+            return sections
+                .iter()
+                .try_fold(BLOCK_SIZE_FIELD.end, |len, section| {
+                    len.checked_add(*section)
+                })
+                .ok_or_else(|| anyhow!("invalid lossy model object buffer: length overflows"));
+        }
         let bytes_per_number = config.bytes_per_number();
         let (data_length, overflows) = self.numbers().overflowing_mul(bytes_per_number);
         if overflows {
@@ -83,7 +143,10 @@ impl<T: AsRef<[u8]>> ModelObjectBuffer<T> {
                 "invalid MaskObject buffer: invalid masking config or numbers field"
             ));
         }
-        Ok(MODEL_LEN_FIELD.end + data_length)
+        MODEL_LEN_FIELD
+            .end
+            .checked_add(data_length)
+            .ok_or_else(|| anyhow!("invalid model object buffer: length overflows"))
     }
 
     /// Gets the expected number of bytes of this buffer.
@@ -91,10 +154,47 @@ impl<T: AsRef<[u8]>> ModelObjectBuffer<T> {
     /// # Panics
     /// May panic if this buffer is unchecked.
     pub fn len(&self) -> usize {
-        let config = ModelConfig::from_byte_slice(&self.config()).unwrap();
-        let bytes_per_number = config.bytes_per_number();
-        let data_length = self.numbers() * bytes_per_number;
-        MODEL_LEN_FIELD.end + data_length
+        self.try_len().unwrap()
+    }
+
+    /// Gets the lengths of the indices, the scales and the values of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    fn lossy_sections(&self, config: &ModelConfig) -> Result<[usize; 3], DecodeError> {
+        lossy_sections(
+            self.codec(),
+            self.numbers(),
+            self.encoded_numbers(),
+            self.block_size(),
+            config,
+        )
+    }
+
+    /// Gets the codec ID.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn codec(&self) -> u8 {
+        self.inner.as_ref()[CODEC_FIELD]
+    }
+
+    /// Gets the number of encoded values of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked or not a lossy model object.
+    pub fn encoded_numbers(&self) -> usize {
+        // UNWRAP SAFE: the slice is exactly 4 bytes long
+        u32::from_be_bytes(self.inner.as_ref()[ENCODED_LEN_FIELD].try_into().unwrap()) as usize
+    }
+
+    /// Gets the quantization block size of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked or not a lossy model object.
+    pub fn block_size(&self) -> u32 {
+        // UNWRAP SAFE: the slice is exactly 4 bytes long
+        u32::from_be_bytes(self.inner.as_ref()[BLOCK_SIZE_FIELD].try_into().unwrap())
     }
 
     pub fn numbers(&self) -> usize {
@@ -123,11 +223,57 @@ impl<T: AsRef<[u8]>> ModelObjectBuffer<T> {
     /// # Panics
     /// May panic if this buffer is unchecked.
     pub fn data(&self) -> &[u8] {
-        &self.inner.as_ref()[MODEL_LEN_FIELD.end..self.len()]
+        &self.inner.as_ref()[MODEL_LEN_FIELD.end..self.try_data_end().unwrap()]
+    }
+
+    /// Gets the digest of the schema, which is empty if the model is not
+    /// structured.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn schema_digest(&self) -> &[u8] {
+        &self.inner.as_ref()[self.try_data_end().unwrap() + 4..self.len()]
+    }
+
+    /// Gets the serialized indices, scales and values of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked or not a lossy model object.
+    pub fn lossy_data(&self) -> [&[u8]; 3] {
+        let config = ModelConfig::from_byte_slice(&self.config()).unwrap();
+        let [indices, scales, values] = self.lossy_sections(&config).unwrap();
+        let data = &self.inner.as_ref()[BLOCK_SIZE_FIELD.end..];
+        let (indices_data, data) = data.split_at(indices);
+        let (scales_data, data) = data.split_at(scales);
+        [indices_data, scales_data, &data[..values]]
     }
 }
 
 impl<T: AsRef<[u8]> + AsMut<[u8]>> ModelObjectBuffer<T> {
+    /// Sets the codec ID.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn set_codec(&mut self, value: u8) {
+        self.inner.as_mut()[CODEC_FIELD] = value;
+    }
+
+    /// Sets the number of encoded values of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn set_encoded_numbers(&mut self, value: u32) {
+        self.inner.as_mut()[ENCODED_LEN_FIELD].copy_from_slice(&value.to_be_bytes());
+    }
+
+    /// Sets the quantization block size of a lossy model object.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn set_block_size(&mut self, value: u32) {
+        self.inner.as_mut()[BLOCK_SIZE_FIELD].copy_from_slice(&value.to_be_bytes());
+    }
+
     /// Sets the number of serialized mask vector elements.
     ///
     /// # Panics
@@ -151,37 +297,106 @@ impl<T: AsRef<[u8]> + AsMut<[u8]>> ModelObjectBuffer<T> {
     pub fn data_mut(&mut self, length: usize) -> &mut [u8] {
         &mut self.inner.as_mut()[MODEL_LEN_FIELD.end..length]
     }
+
+    /// Sets the schema digest length field and gets the schema digest
+    /// part. The fields which determine the length of the values must
+    /// be set beforehand.
+    ///
+    /// # Panics
+    /// May panic if this buffer is unchecked.
+    pub fn schema_digest_mut(&mut self, length: u32) -> &mut [u8] {
+        let schema_offset = self.try_data_end().unwrap() + 4;
+        let buffer = self.inner.as_mut();
+        buffer[schema_offset - 4..schema_offset].copy_from_slice(&length.to_be_bytes());
+        &mut buffer[schema_offset..schema_offset + length as usize]
+    }
+}
+
+/// Gets the length of a serialized schema digest, including its length field.
+fn schema_digest_buffer_length(digest: &Option<Sha256>) -> usize {
+    4 + digest.map_or(0, |_| Sha256::LENGTH)
+}
+
+/// Writes the schema digest of a model object, after its other fields.
+fn schema_digest_to_bytes<T: AsRef<[u8]> + AsMut<[u8]>>(
+    digest: &Option<Sha256>,
+    writer: &mut ModelObjectBuffer<T>,
+) {
+    let length = digest.map_or(0, |_| Sha256::LENGTH);
+    let buffer = writer.schema_digest_mut(length as u32);
+    if let Some(digest) = digest {
+        buffer.copy_from_slice(digest.as_slice());
+    }
+}
+
+/// Parses a schema digest, which is empty if the model is not structured.
+fn schema_digest_from_byte_slice(bytes: &[u8]) -> Result<Option<Sha256>, DecodeError> {
+    if bytes.is_empty() {
+        return Ok(None);
+    }
+    Sha256::from_slice(bytes)
+        .ok_or_else(|| anyhow!("invalid model schema digest: {} bytes", bytes.len()))
+        .map(Some)
+}
+
+/// Parses the schema digest of a model object from a byte stream.
+fn schema_digest_from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+    iter: &mut I,
+) -> Result<Option<Sha256>, DecodeError> {
+    let length = u32::from_byte_stream(iter)
+        .context("failed to parse the length of the model schema digest")?;
+    if iter.len() < length as usize {
+        return Err(anyhow!(
+            "model schema digest is {} bytes long but byte stream only has {} bytes",
+            length,
+            iter.len()
+        ));
+    }
+    schema_digest_from_byte_slice(&iter.take(length as usize).collect::<Vec<_>>())
 }
 
 impl ToBytes for ModelObject {
     fn buffer_length(&self) -> usize {
-        MODEL_LEN_FIELD.end + self.config.bytes_per_number() * self.data.len()
+        MODEL_LEN_FIELD.end
+            + self.config.bytes_per_number() * self.data.len()
+            + schema_digest_buffer_length(&self.schema_digest)
     }
 
     fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
         let mut writer = ModelObjectBuffer::new_unchecked(buffer.as_mut());
         self.config.to_bytes(&mut writer.config_mut());
+        writer.set_codec(DENSE_CODEC_ID);
         writer.set_numbers(self.data.len() as u32);
+        schema_digest_to_bytes(&self.schema_digest, &mut writer);
 
-        let mut data = writer.data_mut(self.buffer_length());
+        let mut data =
+            writer.data_mut(MODEL_LEN_FIELD.end + self.config.bytes_per_number() * self.data.len());
 
         let bytes_per_number = self.config.bytes_per_number();
 
         for ratio in self.data.iter() {
-            let bytes = ratio_to_bytes(ratio, self.config.data_type);
-            data[..bytes.len()].copy_from_slice(&bytes[..]);
-
-            for b in data.iter_mut().take(bytes_per_number).skip(bytes.len()) {
-                *b = 0;
-            }
+            write_value(ratio, self.config, &mut data[..bytes_per_number]);
             data = &mut data[bytes_per_number..];
         }
     }
 }
 
+/// Writes a value of a dense model object to a buffer of the size of a number of the
+/// configuration.
+fn write_value(ratio: &Ratio<BigInt>, config: ModelConfig, buffer: &mut [u8]) {
+    let bytes = ratio_to_bytes(ratio, config.data_type);
+    buffer[..bytes.len()].copy_from_slice(&bytes[..]);
+    for b in buffer.iter_mut().skip(bytes.len()) {
+        *b = 0;
+    }
+}
+
 impl FromBytes for ModelObject {
     fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
         let reader = ModelObjectBuffer::new(buffer.as_ref())?;
+        if reader.codec() != DENSE_CODEC_ID {
+            return Err(anyhow!("not a dense model object"));
+        }
 
         let config = ModelConfig::from_byte_slice(&reader.config())?;
         let mut data = Vec::with_capacity(reader.numbers());
@@ -189,13 +404,346 @@ impl FromBytes for ModelObject {
         for chunk in reader.data().chunks(bytes_per_number) {
             data.push(bytes_to_ratio(chunk, &config.data_type));
         }
+        let schema_digest = schema_digest_from_byte_slice(reader.schema_digest())?;
 
-        Ok(ModelObject { data, config })
+        Ok(ModelObject {
+            data,
+            config,
+            schema_digest,
+        })
     }
 
     fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
-        _iter: &mut I,
+        iter: &mut I,
     ) -> Result<Self, DecodeError> {
-        todo!()
+        let config = ModelConfig::from_byte_stream(iter)?;
+        if iter.next() != Some(DENSE_CODEC_ID) {
+            return Err(anyhow!("not a dense model object"));
+        }
+        dense_from_byte_stream(config, iter)
+    }
+}
+
+/// Parses the remainder of a dense model object from a byte stream.
+fn dense_from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+    config: ModelConfig,
+    iter: &mut I,
+) -> Result<ModelObject, DecodeError> {
+    if iter.len() < 4 {
+        return Err(anyhow!("byte stream exhausted"));
+    }
+    let numbers = u32::from_byte_stream(iter)
+        .context("failed to parse the number of items in model object")?;
+    let bytes_per_number = config.bytes_per_number();
+
+    let data_len = numbers as usize * bytes_per_number;
+    if iter.len() < data_len {
+        return Err(anyhow!(
+            "model object is {} bytes long but byte stream only has {} bytes",
+            data_len,
+            iter.len()
+        ));
+    }
+
+    let mut data = Vec::with_capacity(numbers as usize);
+    let mut buf = vec![0; bytes_per_number];
+    for chunk in iter.take(data_len).chunks(bytes_per_number).into_iter() {
+        for (i, b) in chunk.enumerate() {
+            buf[i] = b;
+        }
+        data.push(bytes_to_ratio(&buf, &config.data_type));
+    }
+    let schema_digest = schema_digest_from_byte_stream(iter)?;
+
+    Ok(ModelObject {
+        data,
+        config,
+        schema_digest,
+    })
+}
+
+/// Gets the lengths of the indices, the scales and the values of a lossy model object.
+fn lossy_sections(
+    codec: u8,
+    numbers: usize,
+    encoded_numbers: usize,
+    block_size: u32,
+    config: &ModelConfig,
+) -> Result<[usize; 3], DecodeError> {
+    let (quantization, sparse) = parse_codec_id(codec)?;
+    if !sparse && encoded_numbers != numbers {
+        return Err(anyhow!(
+            "dense lossy model object encodes {} of {} values",
+            encoded_numbers,
+            numbers
+        ));
+    }
+    let indices = if sparse {
+        encoded_numbers.checked_mul(4)
+    } else {
+        Some(0)
+    };
+    let (scales, values) = match quantization {
+        Some(quantization) => {
+            if block_size == 0 {
+                return Err(anyhow!("quantization blocks must not be empty"));
+            }
+            let nb_blocks = (encoded_numbers + block_size as usize - 1) / block_size as usize;
+            (
+                nb_blocks.checked_mul(4),
+                quantization.packed_length(encoded_numbers),
+            )
+        }
+        None => (
+            Some(0),
+            encoded_numbers.checked_mul(config.bytes_per_number()),
+        ),
+    };
+    match (indices, scales, values) {
+        (Some(indices), Some(scales), Some(values)) => Ok([indices, scales, values]),
+        _ => Err(anyhow!(
+            "invalid lossy model object: number of encoded values overflows"
+        )),
+    }
+}
+
+/// Creates a lossy model object from its serialized parts.
+fn lossy_from_parts(
+    config: ModelConfig,
+    codec: u8,
+    length: u32,
+    block_size: u32,
+    [indices, scales, values]: [&[u8]; 3],
+    schema_digest: Option<Sha256>,
+) -> Result<LossyModelObject, DecodeError> {
+    let (quantization, sparse) = parse_codec_id(codec)?;
+    // UNWRAP SAFE: the chunks are exactly 4 bytes long
+    let indices = sparse.then(|| {
+        indices
+            .chunks(4)
+            .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+            .collect()
+    });
+    let scales = scales
+        .chunks(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect();
+    Ok(LossyModelObject {
+        config,
+        length,
+        quantization,
+        indices,
+        block_size: quantization.map_or(0, |_| block_size),
+        scales,
+        values: values.to_vec(),
+        schema_digest,
+    })
+}
+
+impl ToBytes for LossyModelObject {
+    fn buffer_length(&self) -> usize {
+        BLOCK_SIZE_FIELD.end
+            + self.indices.as_ref().map_or(0, |indices| 4 * indices.len())
+            + 4 * self.scales.len()
+            + self.values.len()
+            + schema_digest_buffer_length(&self.schema_digest)
+    }
+
+    fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
+        let mut writer = ModelObjectBuffer::new_unchecked(buffer.as_mut());
+        self.config.to_bytes(&mut writer.config_mut());
+        writer.set_codec(self.codec_id());
+        writer.set_numbers(self.length);
+        writer.set_encoded_numbers(self.encoded_values() as u32);
+        writer.set_block_size(self.block_size);
+        schema_digest_to_bytes(&self.schema_digest, &mut writer);
+
+        let bytes = self
+            .indices
+            .iter()
+            .flatten()
+            .flat_map(|index| index.to_be_bytes())
+            .chain(self.scales.iter().flat_map(|scale| scale.to_le_bytes()))
+            .chain(self.values.iter().copied());
+        for (b, byte) in buffer.as_mut()[BLOCK_SIZE_FIELD.end..]
+            .iter_mut()
+            .zip(bytes)
+        {
+            *b = byte;
+        }
+    }
+}
+
+impl FromBytes for LossyModelObject {
+    fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
+        let reader = ModelObjectBuffer::new(buffer.as_ref())?;
+        if reader.codec() == DENSE_CODEC_ID {
+            return Err(anyhow!("not a lossy model object"));
+        }
+        let config = ModelConfig::from_byte_slice(&reader.config())?;
+        lossy_from_parts(
+            config,
+            reader.codec(),
+            reader.numbers() as u32,
+            reader.block_size(),
+            reader.lossy_data(),
+            schema_digest_from_byte_slice(reader.schema_digest())?,
+        )
+    }
+
+    fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+        iter: &mut I,
+    ) -> Result<Self, DecodeError> {
+        let config = ModelConfig::from_byte_stream(iter)?;
+        match iter.next() {
+            Some(DENSE_CODEC_ID) => Err(anyhow!("not a lossy model object")),
+            Some(codec) => lossy_from_byte_stream(config, codec, iter),
+            None => Err(anyhow!("byte stream exhausted")),
+        }
+    }
+}
+
+/// Parses the remainder of a lossy model object from a byte stream.
+fn lossy_from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+    config: ModelConfig,
+    codec: u8,
+    iter: &mut I,
+) -> Result<LossyModelObject, DecodeError> {
+    if iter.len() < BLOCK_SIZE_FIELD.end - MODEL_LEN_FIELD.start {
+        return Err(anyhow!("byte stream exhausted"));
+    }
+    let numbers = u32::from_byte_stream(iter)
+        .context("failed to parse the number of items in model object")?;
+    let encoded_numbers = u32::from_byte_stream(iter)
+        .context("failed to parse the number of encoded items in model object")?;
+    let block_size =
+        u32::from_byte_stream(iter).context("failed to parse the block size of model object")?;
+
+    let sections = lossy_sections(
+        codec,
+        numbers as usize,
+        encoded_numbers as usize,
+        block_size,
+        &config,
+    )?;
+    let data_len = sections.iter().sum::<usize>();
+    if iter.len() < data_len {
+        return Err(anyhow!(
+            "model object is {} bytes long but byte stream only has {} bytes",
+            data_len,
+            iter.len()
+        ));
+    }
+    let data = iter.take(data_len).collect::<Vec<u8>>();
+    let (indices, data) = data.split_at(sections[0]);
+    let (scales, values) = data.split_at(sections[1]);
+    lossy_from_parts(
+        config,
+        codec,
+        numbers,
+        block_size,
+        [indices, scales, values],
+        schema_digest_from_byte_stream(iter)?,
+    )
+}
+
+impl ToBytes for EncodedModelObject {
+    fn buffer_length(&self) -> usize {
+        match self {
+            EncodedModelObject::Dense(object) => object.buffer_length(),
+            EncodedModelObject::Lossy(object) => object.buffer_length(),
+        }
+    }
+
+    fn to_bytes<T: AsMut<[u8]> + AsRef<[u8]>>(&self, buffer: &mut T) {
+        match self {
+            EncodedModelObject::Dense(object) => object.to_bytes(buffer),
+            EncodedModelObject::Lossy(object) => object.to_bytes(buffer),
+        }
     }
-}
\ No newline at end of file
+}
+
+impl FromBytes for EncodedModelObject {
+    fn from_byte_slice<T: AsRef<[u8]>>(buffer: &T) -> Result<Self, DecodeError> {
+        let reader = ModelObjectBuffer::new(buffer.as_ref())?;
+        if reader.codec() == DENSE_CODEC_ID {
+            ModelObject::from_byte_slice(buffer).map(Into::into)
+        } else {
+            LossyModelObject::from_byte_slice(buffer).map(Into::into)
+        }
+    }
+
+    fn from_byte_stream<I: Iterator<Item = u8> + ExactSizeIterator>(
+        iter: &mut I,
+    ) -> Result<Self, DecodeError> {
+        let config = ModelConfig::from_byte_stream(iter)?;
+        match iter.next() {
+            Some(DENSE_CODEC_ID) => dense_from_byte_stream(config, iter).map(Into::into),
+            Some(codec) => lossy_from_byte_stream(config, codec, iter).map(Into::into),
+            None => Err(anyhow!("byte stream exhausted")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::model::{
+        DataType, ModelSchema, Quantization, Sparsification, TensorSpec, UpdateCodec,
+    };
+
+    fn roundtrip(object: EncodedModelObject) {
+        let mut bytes = vec![0; object.buffer_length()];
+        object.to_bytes(&mut bytes);
+        assert_eq!(
+            ModelObjectBuffer::new(&bytes[..]).unwrap().len(),
+            bytes.len()
+        );
+        assert_eq!(EncodedModelObject::from_byte_slice(&bytes).unwrap(), object);
+        assert_eq!(
+            EncodedModelObject::from_byte_stream(&mut bytes.clone().into_iter()).unwrap(),
+            object
+        );
+        assert!(EncodedModelObject::from_byte_slice(&&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_encoded_model_object_roundtrip() {
+        let mut rng = ChaCha20Rng::seed_from_u64(0);
+        let config = ModelConfig {
+            data_type: DataType::F32,
+        };
+        let values = (0..600).map(|i| i as f64 / 600.).collect::<Vec<_>>();
+        let schema = ModelSchema::new(vec![TensorSpec {
+            name: "weight".to_string(),
+            shape: vec![20, 30],
+            data_type: DataType::F32,
+        }])
+        .unwrap();
+        let object = ModelObject::new(
+            values.iter().map(|value| float_to_ratio(*value)).collect(),
+            config,
+        );
+        roundtrip(object.clone().into());
+        roundtrip(object.with_schema(Some(&schema)).into());
+        for quantization in [None, Some(Quantization::Bits8), Some(Quantization::Bits4)] {
+            for sparsification in [None, Some(Sparsification::TopK(0.3))] {
+                let codec = UpdateCodec {
+                    quantization,
+                    sparsification,
+                };
+                if let Some(object) = codec.encode(&values, config, &mut rng) {
+                    roundtrip(object.clone().into());
+                    roundtrip(EncodedModelObject::from(object).with_schema(Some(&schema)));
+                }
+            }
+        }
+    }
+
+    fn float_to_ratio(value: f64) -> num::rational::Ratio<num::bigint::BigInt> {
+        crate::model::model::float_to_ratio_bounded(value as f32)
+    }
+}
@@ -0,0 +1,86 @@
+use std::{process, time::Duration};
+
+use structopt::StructOpt;
+use tracing_subscriber::EnvFilter;
+
+use simulation::{Simulation, SimulationSettings};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "Simulation")]
+/// Simulates federated learning runs of an aggregator and synthetic participants
+struct CliConf {
+    /// Number of simulated participants
+    #[structopt(short = "n", long, default_value = "20")]
+    participants: u32,
+    /// Number of updates which are aggregated per round
+    #[structopt(short = "k", long, default_value = "10")]
+    per_round_participants: u32,
+    /// Number of training rounds
+    #[structopt(short, long, default_value = "10")]
+    rounds: u32,
+    /// Number of weights of the models
+    #[structopt(short = "l", long, default_value = "100")]
+    model_length: usize,
+    /// Probability with which a participant drops out of a round
+    #[structopt(short, long, default_value = "0.1")]
+    dropout: f64,
+    /// Mean time in milliseconds it takes a participant to train and upload its model
+    #[structopt(long, default_value = "100")]
+    latency: u64,
+    /// Maximum fraction by which the latency of a participant deviates from the mean
+    #[structopt(long, default_value = "0.5")]
+    jitter: f64,
+    /// Maximum deviation of the data of a participant from the common data, per weight
+    #[structopt(long, default_value = "0.5")]
+    heterogeneity: f64,
+    /// Learning rate of the local training
+    #[structopt(long, default_value = "0.1")]
+    learning_rate: f64,
+    /// Number of local training steps per round
+    #[structopt(long, default_value = "5")]
+    local_steps: u32,
+    /// Seed of all random choices of the simulation
+    #[structopt(short, long, default_value = "0")]
+    seed: u64,
+    /// Time in seconds to wait for the aggregator to make progress
+    #[structopt(long, default_value = "30")]
+    timeout: u64,
+}
+
+impl From<CliConf> for SimulationSettings {
+    fn from(conf: CliConf) -> Self {
+        Self {
+            participants: conf.participants,
+            per_round_participants: conf.per_round_participants,
+            rounds: conf.rounds,
+            model_length: conf.model_length,
+            dropout: conf.dropout,
+            latency: Duration::from_millis(conf.latency),
+            jitter: conf.jitter,
+            heterogeneity: conf.heterogeneity,
+            learning_rate: conf.learning_rate,
+            local_steps: conf.local_steps,
+            seed: conf.seed,
+            timeout: Duration::from_secs(conf.timeout),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("warn"));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+
+    let settings = SimulationSettings::from(CliConf::from_args());
+    let report = match Simulation::new(settings) {
+        Ok(simulation) => simulation.run().await,
+        Err(err) => Err(err),
+    };
+    match report {
+        Ok(report) => println!("{}", report),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! The `Simulation`
+//!
+//! Runs the aggregator and a number of simulated participants in a single process, to study how
+//! the training converges under dropouts and latencies without deploying a network of devices.
+//! The participants run the state machine of the client SDK, which talks to the services of the
+//! aggregator via an [`InProcessClient`] instead of the REST API.
+//!
+//! All random choices are derived from a single seed, so that a run can be reproduced exactly.
+pub mod objective;
+pub mod participant;
+pub mod simulation;
+pub mod transport;
+
+pub use self::{
+    simulation::{RoundReport, Simulation, SimulationError, SimulationReport, SimulationSettings},
+    transport::{InProcessClient, InProcessError},
+};
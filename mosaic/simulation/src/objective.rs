@@ -0,0 +1,77 @@
+//! A synthetic federated learning task.
+
+use rand::Rng;
+
+/// A synthetic quadratic objective, whose data is spread over the participants.
+///
+/// Each participant `i` holds a local objective `0.5 * |w - c_i|^2` centered at a point `c_i`.
+/// The centers scatter around a common point by up to `heterogeneity` per weight, which models
+/// non-IID data. The global objective is the mean of the local objectives and is minimal at the
+/// mean of the centers.
+#[derive(Debug, Clone)]
+pub struct Objective {
+    centers: Vec<Vec<f64>>,
+    optimum: Vec<f64>,
+}
+
+impl Objective {
+    /// Generates the objective of `participants` participants for models of `length` weights.
+    pub fn generate<R: Rng>(
+        rng: &mut R,
+        participants: usize,
+        length: usize,
+        heterogeneity: f64,
+    ) -> Self {
+        let common = (0..length)
+            .map(|_| rng.gen_range(-1.0..=1.0))
+            .collect::<Vec<f64>>();
+        let centers = (0..participants)
+            .map(|_| {
+                common
+                    .iter()
+                    .map(|weight| weight + heterogeneity * rng.gen_range(-1.0..=1.0))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let optimum = (0..length)
+            .map(|j| centers.iter().map(|center| center[j]).sum::<f64>() / participants as f64)
+            .collect();
+        Self { centers, optimum }
+    }
+
+    /// Trains the global model on the data of a participant with `steps` steps of gradient
+    /// descent.
+    pub fn train(
+        &self,
+        participant: usize,
+        model: &[f64],
+        learning_rate: f64,
+        steps: u32,
+    ) -> Vec<f64> {
+        let center = &self.centers[participant];
+        let decay = (1.0 - learning_rate).powi(steps as i32);
+        model
+            .iter()
+            .zip(center)
+            .map(|(weight, center)| center + decay * (weight - center))
+            .collect()
+    }
+
+    /// Gets the value of the global objective.
+    pub fn loss(&self, model: &[f64]) -> f64 {
+        self.centers
+            .iter()
+            .map(|center| 0.5 * squared_distance(model, center))
+            .sum::<f64>()
+            / self.centers.len() as f64
+    }
+
+    /// Gets the distance of a model to the optimum of the global objective.
+    pub fn distance(&self, model: &[f64]) -> f64 {
+        squared_distance(model, &self.optimum).sqrt()
+    }
+}
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
@@ -0,0 +1,75 @@
+//! Simulated participants.
+
+use std::{
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+
+use mosaic_client_sdk::{
+    settings::PetSettings, ModelStore, MosaicClientTrait, Notify, StateMachine, TransitionOutcome,
+};
+use mosaic_core::{crypto::SigningKeyPair, model::Model};
+
+/// A model store whose model is set by the simulation.
+#[derive(Clone, Default)]
+struct Slot(Arc<Mutex<Option<Model>>>);
+
+#[async_trait]
+impl ModelStore for Slot {
+    type Model = Model;
+    type Error = Infallible;
+
+    async fn load_model(&mut self) -> Result<Option<Self::Model>, Self::Error> {
+        Ok(self.0.lock().unwrap().take())
+    }
+}
+
+/// A notifier which ignores the notifications, since the simulation drives the participants.
+struct Silent;
+
+impl Notify for Silent {}
+
+/// A participant which runs the state machine of the SDK.
+pub struct Participant {
+    state_machine: Option<StateMachine>,
+    slot: Slot,
+}
+
+impl Participant {
+    /// Creates a participant with the given keys, which talks to the aggregator via `client`.
+    pub fn new<C>(keys: SigningKeyPair, client: C) -> Self
+    where
+        C: MosaicClientTrait + Send + 'static,
+    {
+        let slot = Slot::default();
+        let state_machine = StateMachine::new(PetSettings::new(keys), client, slot.clone(), Silent);
+        Self {
+            state_machine: Some(state_machine),
+            slot,
+        }
+    }
+
+    /// Hands a trained model to the participant, which sends it with its next update.
+    pub fn set_model(&mut self, model: Model) {
+        *self.slot.0.lock().unwrap() = Some(model);
+    }
+
+    /// Drives the state machine until it can't make progress anymore.
+    pub async fn step(&mut self) {
+        let mut state_machine = self
+            .state_machine
+            .take()
+            .expect("unreachable: the state machine is always put back");
+        loop {
+            match state_machine.transition().await {
+                TransitionOutcome::Complete(next) => state_machine = next,
+                TransitionOutcome::Pending(next) => {
+                    self.state_machine = Some(next);
+                    break;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,469 @@
+//! The simulation of federated learning runs.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use displaydoc::Display;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use thiserror::Error;
+use tokio::time;
+use tracing::{debug, info};
+
+use crate::{objective::Objective, participant::Participant, transport::InProcessClient};
+use aggregator::{
+    aggr::DeltaAggregation,
+    services::{
+        fetchers::{fetcher, FetchError, Fetcher},
+        messages::{PetMessageHandler, SharedResources},
+    },
+    settings::{
+        CompressionSettings, MaskSettings, ModelSettings, MultipartSettings, ProtocolSettings,
+    },
+    state_engine::{
+        events::EventListener,
+        init::{StateEngineInitializationError, StateEngineInitializer},
+        states::StateName,
+    },
+    storage::{aggr_storage::noop::AggrNoOp, model_storage::noop::ModelNoOp, Store},
+};
+use mosaic_core::{
+    crypto::{ByteObject, SigningKeyPair, SigningKeySeed},
+    mask::{BoundType, GroupType, ModelType},
+    model::{DataType, FromPrimitives, IntoPrimitives, Model, ModelCastError},
+};
+
+/// Errors which can occur during a simulation.
+#[derive(Debug, Display, Error)]
+pub enum SimulationError {
+    /// Invalid simulation settings: {0}.
+    InvalidSettings(&'static str),
+    /// Initializing the state engine failed: {0}.
+    Init(#[from] StateEngineInitializationError),
+    /// Round {round} stalled: only {arrived} of {required} updates arrived.
+    Stalled {
+        round: u32,
+        arrived: u32,
+        required: u32,
+    },
+    /// The state engine stopped unexpectedly.
+    EngineStopped,
+    /// Timed out while waiting for the state engine.
+    Timeout,
+    /// Fetching the global model failed: {0}.
+    Fetch(FetchError),
+    /// The global model is not representable as `f64`: {0}.
+    ModelCast(#[from] ModelCastError),
+}
+
+/// The settings of a simulation.
+#[derive(Debug, Clone)]
+pub struct SimulationSettings {
+    /// The number of simulated participants.
+    pub participants: u32,
+    /// The number of updates which are aggregated per round.
+    pub per_round_participants: u32,
+    /// The number of training rounds.
+    pub rounds: u32,
+    /// The number of weights of the models.
+    pub model_length: usize,
+    /// The probability with which a participant drops out of a round.
+    pub dropout: f64,
+    /// The mean time it takes a participant to train and upload its model.
+    pub latency: Duration,
+    /// The maximum fraction by which the latency of a participant deviates from the mean.
+    pub jitter: f64,
+    /// How far the data of the participants deviates from each other, see [`Objective`].
+    pub heterogeneity: f64,
+    /// The learning rate of the local training.
+    pub learning_rate: f64,
+    /// The number of local training steps per round.
+    pub local_steps: u32,
+    /// The seed of all random choices of the simulation.
+    pub seed: u64,
+    /// The maximum time to wait for the state engine to make progress.
+    pub timeout: Duration,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            participants: 20,
+            per_round_participants: 10,
+            rounds: 10,
+            model_length: 100,
+            dropout: 0.1,
+            latency: Duration::from_millis(100),
+            jitter: 0.5,
+            heterogeneity: 0.5,
+            learning_rate: 0.1,
+            local_steps: 5,
+            seed: 0,
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+impl SimulationSettings {
+    fn validate(&self) -> Result<(), SimulationError> {
+        let error = if self.per_round_participants == 0 {
+            "at least one update must be aggregated per round"
+        } else if self.participants < self.per_round_participants {
+            "there must be at least as many participants as updates per round"
+        } else if self.rounds == 0 {
+            "there must be at least one round"
+        } else if self.model_length == 0 {
+            "the models must have at least one weight"
+        } else if !(0.0..1.0).contains(&self.dropout) {
+            "the dropout must be in [0, 1)"
+        } else if !(0.0..=1.0).contains(&self.jitter) {
+            "the jitter must be in [0, 1]"
+        } else if !(self.heterogeneity >= 0.0 && self.heterogeneity.is_finite()) {
+            "the heterogeneity must be a non-negative number"
+        } else if !(self.learning_rate > 0.0 && self.learning_rate <= 1.0) {
+            "the learning rate must be in (0, 1]"
+        } else {
+            return Ok(());
+        };
+        Err(SimulationError::InvalidSettings(error))
+    }
+}
+
+/// The outcome of a round.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundReport {
+    /// The ID of the round, starting at `1`.
+    pub round: u32,
+    /// The number of participants which dropped out.
+    pub dropped: u32,
+    /// The number of updates which arrived after the round was complete and were discarded.
+    pub stragglers: u32,
+    /// The simulated time until the last aggregated update arrived.
+    pub duration: Duration,
+    /// The real time it took to run the round.
+    pub wall_time: Duration,
+    /// The global objective of the new global model.
+    pub loss: f64,
+    /// The distance of the new global model to the optimum.
+    pub distance: f64,
+}
+
+impl RoundReport {
+    /// Checks whether two reports describe the same round, disregarding the real time.
+    pub fn same_outcome(&self, other: &Self) -> bool {
+        Self {
+            wall_time: other.wall_time,
+            ..self.clone()
+        } == *other
+    }
+}
+
+/// The outcome of a simulation.
+#[derive(Debug, Clone)]
+pub struct SimulationReport {
+    /// The global objective of the initial global model.
+    pub initial_loss: f64,
+    /// The distance of the initial global model to the optimum.
+    pub initial_distance: f64,
+    /// The outcomes of the rounds.
+    pub rounds: Vec<RoundReport>,
+}
+
+impl SimulationReport {
+    /// Gets the simulated time of all rounds.
+    pub fn duration(&self) -> Duration {
+        self.rounds.iter().map(|round| round.duration).sum()
+    }
+
+    /// Gets the real time of all rounds.
+    pub fn wall_time(&self) -> Duration {
+        self.rounds.iter().map(|round| round.wall_time).sum()
+    }
+
+    /// Gets the global objective of the final global model.
+    pub fn final_loss(&self) -> f64 {
+        self.rounds
+            .last()
+            .map_or(self.initial_loss, |round| round.loss)
+    }
+}
+
+impl fmt::Display for SimulationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>5} {:>7} {:>10} {:>12} {:>12} {:>12} {:>12}",
+            "round", "dropped", "stragglers", "duration", "wall time", "loss", "distance"
+        )?;
+        writeln!(
+            f,
+            "{:>5} {:>7} {:>10} {:>12} {:>12} {:>12.6} {:>12.6}",
+            0, "-", "-", "-", "-", self.initial_loss, self.initial_distance
+        )?;
+        for round in &self.rounds {
+            writeln!(
+                f,
+                "{:>5} {:>7} {:>10} {:>12} {:>12} {:>12.6} {:>12.6}",
+                round.round,
+                round.dropped,
+                round.stragglers,
+                format!("{:.3?}", round.duration),
+                format!("{:.3?}", round.wall_time),
+                round.loss,
+                round.distance
+            )?;
+        }
+        write!(
+            f,
+            "simulated time: {:.3?}, wall time: {:.3?}, loss: {:.6} -> {:.6}",
+            self.duration(),
+            self.wall_time(),
+            self.initial_loss,
+            self.final_loss()
+        )
+    }
+}
+
+/// A simulation of federated learning runs with an in-process aggregator.
+///
+/// The simulation starts a state engine and drives a number of participants, which run the
+/// state machine of the SDK and talk to the aggregator via an [`InProcessClient`]. The
+/// participants train synthetic models on an [`Objective`].
+///
+/// The simulation runs in simulated time, so that it is deterministic for a given seed: in each
+/// round, the participants which don't drop out train the latest global model and their updates
+/// arrive after a random latency. The first `per_round_participants` updates are sent to the
+/// aggregator one after another in the order of their arrival, and the remaining ones are
+/// discarded as stragglers.
+pub struct Simulation {
+    settings: SimulationSettings,
+}
+
+impl Simulation {
+    /// Creates a simulation.
+    pub fn new(settings: SimulationSettings) -> Result<Self, SimulationError> {
+        settings.validate()?;
+        Ok(Self { settings })
+    }
+
+    /// Runs the simulation to completion.
+    pub async fn run(self) -> Result<SimulationReport, SimulationError> {
+        let settings = self.settings;
+        let mut rng = ChaCha20Rng::seed_from_u64(settings.seed);
+
+        let model_settings = ModelSettings {
+            length: Some(settings.model_length),
+            data_type: DataType::F64,
+            ..ModelSettings::default()
+        };
+        let mask_settings = MaskSettings {
+            group_type: GroupType::Prime,
+            data_type: DataType::F64,
+            bound_type: BoundType::Bmax,
+            model_type: ModelType::M3,
+        };
+        let (state_engine, requests_tx, _control, subscriber) = StateEngineInitializer::new(
+            mask_settings,
+            model_settings.clone(),
+            ProtocolSettings {
+                training_rounds: settings.rounds,
+                participants: settings.per_round_participants,
+                delta_aggregation: DeltaAggregation::Reconstruct,
+                model_history: 1,
+            },
+            CompressionSettings::default(),
+            MultipartSettings::default(),
+            Store::new(AggrNoOp, ModelNoOp),
+        )
+        .init()
+        .await?;
+        tokio::spawn(state_engine.run());
+
+        let mut fetcher = fetcher(&subscriber);
+        let message_handler = PetMessageHandler::new(
+            &subscriber,
+            requests_tx,
+            mask_settings,
+            &model_settings,
+            CompressionSettings::default(),
+            &SharedResources::new(MultipartSettings::default()),
+        );
+        let mut participants = (0..settings.participants)
+            .map(|_| {
+                let mut seed = [0; SigningKeySeed::LENGTH];
+                rng.fill(&mut seed[..]);
+                Participant::new(
+                    SigningKeyPair::derive_from_seed(&SigningKeySeed::from_slice_unchecked(&seed)),
+                    InProcessClient::new(fetcher.clone(), message_handler.clone()),
+                )
+            })
+            .collect::<Vec<_>>();
+        let objective = Objective::generate(
+            &mut rng,
+            participants.len(),
+            settings.model_length,
+            settings.heterogeneity,
+        );
+
+        let model = global_model(&mut fetcher, settings.model_length).await?;
+        let mut report = SimulationReport {
+            initial_loss: objective.loss(&model),
+            initial_distance: objective.distance(&model),
+            rounds: Vec::new(),
+        };
+        let mut state_listener = subscriber.state_listener();
+        let mut params_listener = subscriber.params_listener();
+        for round in 1..=settings.rounds {
+            wait_for(&mut state_listener, settings.timeout, |state| {
+                *state == StateName::Collect
+            })
+            .await?;
+            let start = Instant::now();
+
+            // the participants learn about the new round and wait for their models
+            for participant in participants.iter_mut() {
+                participant.step().await;
+            }
+
+            let model = global_model(&mut fetcher, settings.model_length).await?;
+            let mut arrivals = Vec::new();
+            let mut dropped = 0;
+            for index in 0..participants.len() {
+                // both values are drawn for every participant to keep the random choices of the
+                // following participants independent of the dropouts
+                let drops_out = rng.gen::<f64>() < settings.dropout;
+                let deviation = settings.jitter * rng.gen_range(-1.0..=1.0);
+                if drops_out {
+                    dropped += 1;
+                } else {
+                    arrivals.push((settings.latency.as_secs_f64() * (1.0 + deviation), index));
+                }
+            }
+            arrivals.sort_by(|a, b| a.partial_cmp(b).expect("latencies are finite"));
+
+            let required = settings.per_round_participants as usize;
+            if arrivals.len() < required {
+                return Err(SimulationError::Stalled {
+                    round,
+                    arrived: arrivals.len() as u32,
+                    required: required as u32,
+                });
+            }
+            for (_, index) in &arrivals[..required] {
+                let local_model =
+                    objective.train(*index, &model, settings.learning_rate, settings.local_steps);
+                let participant = &mut participants[*index];
+                participant.set_model(Model::from_primitives_bounded(local_model.into_iter()));
+                participant.step().await;
+            }
+
+            wait_for(&mut params_listener, settings.timeout, |params| {
+                params.model_version >= round
+            })
+            .await?;
+            let model = global_model(&mut fetcher, settings.model_length).await?;
+            let round = RoundReport {
+                round,
+                dropped,
+                stragglers: (arrivals.len() - required) as u32,
+                duration: Duration::from_secs_f64(arrivals[required - 1].0),
+                wall_time: start.elapsed(),
+                loss: objective.loss(&model),
+                distance: objective.distance(&model),
+            };
+            info!(
+                "round {} completed with a loss of {}",
+                round.round, round.loss
+            );
+            report.rounds.push(round);
+        }
+
+        Ok(report)
+    }
+}
+
+/// Fetches the latest global model, which consists of zeros before the first round.
+async fn global_model<F: Fetcher>(
+    fetcher: &mut F,
+    length: usize,
+) -> Result<Vec<f64>, SimulationError> {
+    match fetcher.model().await.map_err(SimulationError::Fetch)? {
+        Some((_, model)) => Ok(model.to_primitives().collect::<Result<_, _>>()?),
+        None => Ok(vec![0.0; length]),
+    }
+}
+
+/// Waits until the latest event of the listener satisfies the condition.
+async fn wait_for<E, C>(
+    listener: &mut EventListener<E>,
+    timeout: Duration,
+    condition: C,
+) -> Result<(), SimulationError>
+where
+    E: Clone,
+    C: Fn(&E) -> bool,
+{
+    let deadline = time::Instant::now() + timeout;
+    while !condition(&listener.get_latest().event) {
+        debug!("waiting for the state engine");
+        match time::timeout_at(deadline, listener.changed()).await {
+            Ok(Ok(())) => {}
+            Ok(Err(_)) => return Err(SimulationError::EngineStopped),
+            Err(_) => return Err(SimulationError::Timeout),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_simulation_is_deterministic() {
+        let settings = SimulationSettings {
+            participants: 8,
+            per_round_participants: 4,
+            rounds: 3,
+            model_length: 10,
+            dropout: 0.2,
+            seed: 42,
+            ..SimulationSettings::default()
+        };
+        let run = || async {
+            Simulation::new(settings.clone())
+                .unwrap()
+                .run()
+                .await
+                .unwrap()
+        };
+        let first = run().await;
+        let second = run().await;
+
+        assert_eq!(first.rounds.len(), 3);
+        assert!(first
+            .rounds
+            .iter()
+            .zip(&second.rounds)
+            .all(|(first, second)| first.same_outcome(second)));
+        assert!(first
+            .rounds
+            .windows(2)
+            .all(|rounds| rounds[1].distance < rounds[0].distance));
+        assert!(first.final_loss() < first.initial_loss);
+    }
+
+    #[test]
+    fn test_validate_settings() {
+        let settings = SimulationSettings {
+            per_round_participants: 30,
+            ..SimulationSettings::default()
+        };
+        assert!(matches!(
+            Simulation::new(settings),
+            Err(SimulationError::InvalidSettings(_))
+        ));
+    }
+}
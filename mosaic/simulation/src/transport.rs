@@ -0,0 +1,118 @@
+//! An in-process transport between the SDK and the aggregator.
+
+use async_trait::async_trait;
+use displaydoc::Display;
+use thiserror::Error;
+
+use aggregator::services::{
+    fetchers::{FetchError, Fetcher},
+    messages::{PetMessageHandler, ServiceError},
+};
+use mosaic_client_sdk::MosaicClientTrait;
+use mosaic_core::{
+    common::{Capabilities, RoundParameters},
+    model::Model,
+    SumDict, SumParticipantPublicKey, UpdateSeedDict,
+};
+
+/// Errors which can occur when a participant talks to the aggregator in-process.
+#[derive(Debug, Display, Error)]
+pub enum InProcessError {
+    /// Fetching data from the aggregator failed: {0}.
+    Fetch(FetchError),
+    /// The aggregator rejected the message: {0}.
+    Message(#[from] ServiceError),
+}
+
+/// A client which talks to the services of an aggregator in the same process.
+///
+/// The requests are served by the [`Fetcher`] and the messages are handled by the
+/// [`PetMessageHandler`] directly, which bypasses the REST API and the network. Like the REST
+/// API, the client reports the messages the message handler rejects.
+#[derive(Clone)]
+pub struct InProcessClient<F> {
+    fetcher: F,
+    message_handler: PetMessageHandler,
+}
+
+impl<F> InProcessClient<F>
+where
+    F: Fetcher,
+{
+    /// Creates a client for the services of an aggregator.
+    pub fn new(fetcher: F, message_handler: PetMessageHandler) -> Self {
+        Self {
+            fetcher,
+            message_handler,
+        }
+    }
+}
+
+#[async_trait]
+impl<F> MosaicClientTrait for InProcessClient<F>
+where
+    F: Fetcher + Send,
+{
+    type Error = InProcessError;
+
+    async fn get_capabilities(&mut self) -> Result<Capabilities, Self::Error> {
+        let round_params = self.get_round_params().await?;
+        Ok(Capabilities::new(&round_params))
+    }
+
+    async fn get_round_params(&mut self) -> Result<RoundParameters, Self::Error> {
+        self.fetcher
+            .round_params()
+            .await
+            .map_err(InProcessError::Fetch)
+    }
+
+    async fn get_sums(&mut self) -> Result<Option<SumDict>, Self::Error> {
+        let sum_dict = self
+            .fetcher
+            .sum_dict()
+            .await
+            .map_err(InProcessError::Fetch)?;
+        Ok(sum_dict.map(|sum_dict| sum_dict.as_ref().clone()))
+    }
+
+    async fn get_seeds(
+        &mut self,
+        pk: SumParticipantPublicKey,
+    ) -> Result<Option<UpdateSeedDict>, Self::Error> {
+        let seed_dict = self
+            .fetcher
+            .seed_dict()
+            .await
+            .map_err(InProcessError::Fetch)?;
+        Ok(seed_dict.and_then(|seed_dict| seed_dict.get(&pk).cloned()))
+    }
+
+    async fn get_model(&mut self) -> Result<Option<Model>, Self::Error> {
+        let model = self.fetcher.model().await.map_err(InProcessError::Fetch)?;
+        Ok(model.map(|(_, model)| model.as_ref().clone()))
+    }
+
+    async fn get_model_of_version(&mut self, version: u32) -> Result<Option<Model>, Self::Error> {
+        let model = self.fetcher.model().await.map_err(InProcessError::Fetch)?;
+        Ok(model
+            .filter(|(model_version, _)| *model_version == version)
+            .map(|(_, model)| model.as_ref().clone()))
+    }
+
+    async fn send_message(&mut self, msg: Vec<u8>) -> Result<(), Self::Error> {
+        self.message_handler
+            .handle_message(msg)
+            .await
+            .map_err(InProcessError::from)
+    }
+
+    fn rejection_reason(error: &Self::Error) -> Option<String> {
+        match error {
+            InProcessError::Message(ServiceError::InternalError(_)) | InProcessError::Fetch(_) => {
+                None
+            }
+            InProcessError::Message(e) => Some(e.to_string()),
+        }
+    }
+}